@@ -0,0 +1,212 @@
+use std::io::{self, Read, Write};
+
+use ec_cryptography::consensus::{ConsensusDecode, ConsensusEncode};
+use transactions::{txid::Txid, Transaction};
+
+use crate::{BlockHash, BlockHeader, MerkleProof, MerkleTree, PowError};
+
+/// Why a block isn't valid where it is in the chain
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockError {
+    /// The block doesn't build on the header it's checked against
+    PrevBlockMismatch,
+    /// The hash of the header doesn't meet its target, or the bits are invalid
+    ProofOfWork(PowError),
+    /// The timestamp isn't after the median time past of the previous blocks, this one
+    TimeTooOld(u32),
+    /// The header doesn't commit to the transactions
+    MerkleRootMismatch,
+    /// The block has no transactions, not even a coinbase
+    NoTransactions,
+    /// The first transaction isn't a coinbase
+    FirstNotCoinbase,
+    /// The transaction with this index is a coinbase, which only the first can be
+    UnexpectedCoinbase(usize),
+}
+
+/// A block: its header, and the transactions it commits to, the coinbase first
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub transactions: Vec<Transaction>,
+}
+
+impl Block {
+    /// Reads a block from a stream, consuming exactly its bytes
+    pub fn parse_stream<R: Read>(reader: &mut R) -> io::Result<Block> {
+        Block::consensus_decode(reader)
+    }
+
+    /// Parses a serialized block, as the P2P block message and the getblock RPC carry it.
+    /// The bytes must hold exactly one.
+    pub fn parse(bytes: &[u8]) -> io::Result<Block> {
+        Block::consensus_deserialize(bytes)
+    }
+
+    /// The header, the varint count of the transactions, and each transaction, with its witnesses if it has any
+    pub fn serialize(&self) -> Vec<u8> {
+        self.consensus_serialize()
+    }
+
+    /// The hash of the header, which identifies the block
+    pub fn hash(&self) -> BlockHash {
+        self.header.hash()
+    }
+
+    /// The merkle tree of the txids of the transactions, whose root the header commits to
+    pub fn merkle_tree(&self) -> MerkleTree {
+        let txids: Vec<[u8; 32]> = self.transactions.iter().map(|tx| *tx.txid().as_bytes()).collect();
+        MerkleTree::from_leaves(&txids)
+    }
+
+    /// The proof that the transaction with `txid` is in the block, for a light client that only has the header
+    pub fn merkle_proof(&self, txid: &Txid) -> Option<MerkleProof> {
+        self.merkle_tree().proof(txid)
+    }
+
+    /// Checks the block against the header of the block before it and `mtp`, the median timestamp of the
+    /// 11 blocks up to that one: it must build on it, meet its own target, have a later timestamp than `mtp`,
+    /// commit to its transactions, and have a coinbase first and nowhere else.
+    ///
+    /// Every rule broken is returned. The transactions themselves, and whether the bits are the ones the
+    /// chain requires, aren't checked.
+    pub fn validate(&self, prev_header: &BlockHeader, mtp: u32) -> Result<(), Vec<BlockError>> {
+        let mut errors = vec![];
+
+        if self.header.prev_block != prev_header.hash() {
+            errors.push(BlockError::PrevBlockMismatch);
+        }
+        if let Err(error) = self.header.check_pow() {
+            errors.push(BlockError::ProofOfWork(error));
+        }
+        if self.header.timestamp <= mtp {
+            errors.push(BlockError::TimeTooOld(mtp));
+        }
+        if self.merkle_tree().root() != Some(self.header.merkle_root) {
+            errors.push(BlockError::MerkleRootMismatch);
+        }
+
+        match self.transactions.first() {
+            None => errors.push(BlockError::NoTransactions),
+            Some(first) if !first.is_coinbase() => errors.push(BlockError::FirstNotCoinbase),
+            Some(_) => {}
+        }
+        for (index, tx) in self.transactions.iter().enumerate().skip(1) {
+            if tx.is_coinbase() {
+                errors.push(BlockError::UnexpectedCoinbase(index));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl ConsensusEncode for Block {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.header.consensus_encode(writer)? + self.transactions.consensus_encode(writer)?)
+    }
+}
+
+/// The transactions are read as mainnet ones, a block doesn't say which network it is from
+impl ConsensusDecode for Block {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Block {
+            header: BlockHeader::consensus_decode(reader)?,
+            transactions: Vec::<Transaction>::consensus_decode(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::{from_hex, to_hex};
+
+    use super::*;
+    use crate::{
+        fixtures::{BLOCK_1, GENESIS_BLOCK, SEGWIT_TX},
+        header::HEADER_SIZE,
+    };
+
+    #[test]
+    fn test_parse_genesis() {
+        let bytes = from_hex(GENESIS_BLOCK).unwrap();
+        let block = Block::parse(&bytes).unwrap();
+        assert_eq!(block.hash().to_string(), "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        assert_eq!(block.transactions.len(), 1);
+
+        // the merkle root of a single transaction is its txid
+        let coinbase = &block.transactions[0];
+        assert!(coinbase.is_coinbase());
+        assert_eq!(coinbase.id(), "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+        assert_eq!(coinbase.txid().as_bytes(), &block.header.merkle_root);
+        assert!(block.merkle_proof(&coinbase.txid()).unwrap().verify(&block.header.merkle_root));
+
+        assert_eq!(block.serialize(), bytes);
+        assert!(Block::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Block::parse(&[bytes.as_slice(), &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_parse_segwit() {
+        // the genesis header and coinbase, followed by a segwit transaction
+        let genesis = from_hex(GENESIS_BLOCK).unwrap();
+        let raw = format!("{}02{}{}", to_hex(&genesis[..HEADER_SIZE]), to_hex(&genesis[HEADER_SIZE + 1..]), SEGWIT_TX);
+        let bytes = from_hex(&raw).unwrap();
+
+        let block = Block::parse(&bytes).unwrap();
+        assert_eq!(block.transactions.len(), 2);
+        assert!(!block.transactions[0].has_witness());
+        assert!(block.transactions[1].has_witness());
+        // the merkle tree is of the txids, which leave the witnesses out
+        let txid = block.transactions[1].txid();
+        let proof = block.merkle_proof(&txid).unwrap();
+        assert_eq!(proof.branch, vec![*block.transactions[0].txid().as_bytes()]);
+        assert_eq!(proof.root(), block.merkle_tree().root().unwrap());
+        assert!(block.merkle_proof(&Txid::from_bytes(*block.transactions[1].wtxid().as_bytes())).is_none());
+        assert_eq!(to_hex(&block.transactions[1].serialize()), SEGWIT_TX);
+        assert_eq!(block.serialize(), bytes);
+
+        // blocks are read one after the other from a stream
+        let mut stream = std::io::Cursor::new([bytes.as_slice(), &genesis].concat());
+        assert_eq!(Block::parse_stream(&mut stream).unwrap().transactions.len(), 2);
+        assert_eq!(Block::parse_stream(&mut stream).unwrap().serialize(), genesis);
+        assert!(Block::parse_stream(&mut stream).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let genesis = Block::parse(&from_hex(GENESIS_BLOCK).unwrap()).unwrap();
+        let block = Block::parse(&from_hex(BLOCK_1).unwrap()).unwrap();
+        // the median time past of a chain of one block is its timestamp
+        let mtp = genesis.header.timestamp;
+        assert_eq!(block.validate(&genesis.header, mtp), Ok(()));
+
+        assert_eq!(block.validate(&block.header, mtp), Err(vec![BlockError::PrevBlockMismatch]));
+        let timestamp = block.header.timestamp;
+        assert_eq!(block.validate(&genesis.header, timestamp), Err(vec![BlockError::TimeTooOld(timestamp)]));
+
+        let mut wrong = block.clone();
+        wrong.header.nonce += 1;
+        assert_eq!(wrong.validate(&genesis.header, mtp), Err(vec![BlockError::ProofOfWork(PowError::HashAboveTarget)]));
+
+        // a coinbase that isn't first, after a transaction the header doesn't commit to
+        let mut wrong = block.clone();
+        let segwit_tx = Transaction::parse_hex(SEGWIT_TX, false).unwrap();
+        wrong.transactions.insert(0, segwit_tx);
+        assert_eq!(
+            wrong.validate(&genesis.header, mtp),
+            Err(vec![BlockError::MerkleRootMismatch, BlockError::FirstNotCoinbase, BlockError::UnexpectedCoinbase(1)])
+        );
+
+        let mut wrong = block;
+        wrong.transactions.clear();
+        assert_eq!(
+            wrong.validate(&genesis.header, mtp),
+            Err(vec![BlockError::MerkleRootMismatch, BlockError::NoTransactions])
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use ec_cryptography::{helper::hex_to_32_bytes, network::Network};
+
+use crate::{BlockHash, BlockHeader};
+
+/// The merkle root all the genesis blocks share, of their one coinbase, in internal byte order
+const GENESIS_MERKLE_ROOT: &str = "3ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a";
+
+/// The genesis block of each network, the trusted start of its header chain
+pub trait Genesis {
+    /// The header of the network's first block
+    fn genesis_header(&self) -> BlockHeader;
+
+    /// The hash of the network's first block, as Core hard-codes it
+    fn genesis_hash(&self) -> BlockHash;
+}
+
+impl Genesis for Network {
+    fn genesis_header(&self) -> BlockHeader {
+        // testnet3 and regtest were started on the same day
+        let (timestamp, bits, nonce) = match self {
+            Network::Mainnet => (1231006505, 0x1d00ffff, 2083236893),
+            Network::Testnet => (1296688602, 0x1d00ffff, 414098458),
+            Network::Signet => (1598918400, 0x1e0377ae, 52613770),
+            Network::Regtest => (1296688602, 0x207fffff, 2),
+        };
+        BlockHeader {
+            version: 1,
+            prev_block: BlockHash::default(),
+            merkle_root: hex_to_32_bytes(GENESIS_MERKLE_ROOT).expect("the merkle root is valid hex"),
+            timestamp,
+            bits,
+            nonce,
+        }
+    }
+
+    fn genesis_hash(&self) -> BlockHash {
+        let hash = match self {
+            Network::Mainnet => "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            Network::Testnet => "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+            Network::Signet => "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
+            Network::Regtest => "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+        };
+        hash.parse().expect("the genesis hashes are valid hex")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::to_hex;
+
+    use super::*;
+    use crate::fixtures::{header_hex, GENESIS_BLOCK};
+
+    #[test]
+    fn test_genesis() {
+        for network in [Network::Mainnet, Network::Testnet, Network::Signet, Network::Regtest] {
+            let header = network.genesis_header();
+            assert_eq!(header.hash(), network.genesis_hash());
+            assert_eq!(header.check_pow(), Ok(()));
+        }
+
+        let mainnet = Network::Mainnet.genesis_header();
+        assert_eq!(mainnet.merkle_root, Network::Regtest.genesis_header().merkle_root);
+        assert_eq!(to_hex(&mainnet.serialize()), header_hex(GENESIS_BLOCK));
+        assert_eq!(Network::default().genesis_hash(), Network::Mainnet.genesis_hash());
+    }
+}
@@ -0,0 +1,6 @@
+use ec_cryptography::hash_newtype;
+
+hash_newtype!(
+    /// The hash256 of a block header, which identifies the block and which the next header refers to
+    BlockHash
+);
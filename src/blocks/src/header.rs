@@ -0,0 +1,205 @@
+use std::io::{self, Read, Write};
+
+use ec_cryptography::{
+    consensus::{ConsensusDecode, ConsensusEncode},
+    helper::hash256,
+};
+
+use crate::BlockHash;
+
+/// The size of a serialized block header
+pub const HEADER_SIZE: usize = 80;
+/// The version bit with which miners signalled for segwit (BIP141)
+pub const SEGWIT_BIT: u8 = 1;
+/// The version bit with which miners signalled for taproot (BIP341)
+pub const TAPROOT_BIT: u8 = 2;
+
+/// The header of a block, which its hash commits to. The merkle root is kept in internal byte order,
+/// the reverse of how block explorers display it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: i32,
+    /// The hash of the header of the block this one builds on
+    pub prev_block: BlockHash,
+    /// The root of the merkle tree of the block's transaction ids
+    pub merkle_root: [u8; 32],
+    /// Seconds since the Unix epoch
+    pub timestamp: u32,
+    /// The target the hash of the header must not exceed, in compact encoding
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    /// Reads a header from a stream, consuming its 80 bytes
+    pub fn parse_stream<R: Read>(reader: &mut R) -> io::Result<BlockHeader> {
+        BlockHeader::consensus_decode(reader)
+    }
+
+    /// Parses a serialized header. The bytes must hold exactly one.
+    pub fn parse(bytes: &[u8]) -> io::Result<BlockHeader> {
+        BlockHeader::consensus_deserialize(bytes)
+    }
+
+    /// The 80-byte serialization: the fields in order, the integers little-endian
+    pub fn serialize(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        self.consensus_encode(&mut bytes.as_mut_slice()).expect("a header takes exactly 80 bytes");
+        bytes
+    }
+
+    /// Whether the version is a BIP9 one: its top 3 bits are 001 and the 29 below are signalling bits
+    pub fn is_bip9(&self) -> bool {
+        self.version as u32 >> 29 == 0b001
+    }
+
+    /// Whether the header signals readiness for the soft fork deployed with version bit `bit` (BIP9).
+    /// Only versions with the BIP9 top bits signal, and there are 29 bits.
+    pub fn signals_bit(&self, bit: u8) -> bool {
+        bit < 29 && self.is_bip9() && self.version >> bit & 1 == 1
+    }
+
+    /// Whether the header signals for segwit, which locked in at block 479,707
+    pub fn signals_segwit(&self) -> bool {
+        self.signals_bit(SEGWIT_BIT)
+    }
+
+    /// Whether the header signals for taproot, which locked in at block 687,284
+    pub fn signals_taproot(&self) -> bool {
+        self.signals_bit(TAPROOT_BIT)
+    }
+
+    /// The hash256 of the serialized header, which identifies the block and which the next block's
+    /// `prev_block` refers to
+    pub fn hash(&self) -> BlockHash {
+        let digest = hash256(&self.serialize());
+        BlockHash::from_bytes(digest.try_into().expect("hash256 digests are 32 bytes"))
+    }
+}
+
+impl ConsensusEncode for BlockHeader {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.version.consensus_encode(writer)?
+            + self.prev_block.as_bytes().consensus_encode(writer)?
+            + self.merkle_root.consensus_encode(writer)?
+            + self.timestamp.consensus_encode(writer)?
+            + self.bits.consensus_encode(writer)?
+            + self.nonce.consensus_encode(writer)?)
+    }
+}
+
+impl ConsensusDecode for BlockHeader {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(BlockHeader {
+            version: i32::consensus_decode(reader)?,
+            prev_block: BlockHash::from_bytes(<[u8; 32]>::consensus_decode(reader)?),
+            merkle_root: <[u8; 32]>::consensus_decode(reader)?,
+            timestamp: u32::consensus_decode(reader)?,
+            bits: u32::consensus_decode(reader)?,
+            nonce: u32::consensus_decode(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ec_cryptography::helper::{from_hex, to_hex};
+
+    use super::*;
+    use crate::fixtures::{header_hex, BLOCK_1, GENESIS_BLOCK};
+
+    // a block mined in August 2017, signalling BIP9 soft forks
+    const BLOCK: &str = "020000208ec39428b17323fa0ddec8e887b4a7c53b8c0a0a220cfd0000000000000000005b0750fce0a889502d40508d39576821155e9c9e3f5c3157f961db38fd8b25be1e77a759e93c0118a4ffd71d";
+
+    #[test]
+    fn test_parse() {
+        let header = BlockHeader::parse(&from_hex(BLOCK).unwrap()).unwrap();
+        assert_eq!(header.version, 0x20000002);
+        assert_eq!(header.prev_block.to_string(), "000000000000000000fd0c220a0a8c3bc5a7b487e8c8de0dfa2373b12894c38e");
+        let mut merkle_root = header.merkle_root;
+        merkle_root.reverse();
+        assert_eq!(to_hex(&merkle_root), "be258bfd38db61f957315c3f9e9c5e15216857398d50402d5089a8e0fc50075b");
+        assert_eq!(header.timestamp, 0x59a7771e);
+        assert_eq!(header.bits, 0x18013ce9);
+        assert_eq!(header.nonce, 0x1dd7ffa4);
+
+        let genesis = BlockHeader::parse(&from_hex(header_hex(GENESIS_BLOCK)).unwrap()).unwrap();
+        assert_eq!(genesis.version, 1);
+        assert_eq!(genesis.prev_block, BlockHash::default());
+        assert_eq!(genesis.timestamp, 1231006505);
+        assert_eq!(genesis.bits, 0x1d00ffff);
+        assert_eq!(genesis.nonce, 2083236893);
+    }
+
+    #[test]
+    fn test_serialize() {
+        for raw in [header_hex(GENESIS_BLOCK), header_hex(BLOCK_1), BLOCK] {
+            let bytes = from_hex(raw).unwrap();
+            let header = BlockHeader::parse(&bytes).unwrap();
+            assert_eq!(header.serialize().to_vec(), bytes);
+            assert_eq!(header.consensus_serialize(), bytes);
+        }
+
+        // a stream is read one header at a time, the bytes must hold exactly one
+        let bytes = from_hex(&format!("{}{}", header_hex(GENESIS_BLOCK), BLOCK)).unwrap();
+        let mut stream = Cursor::new(&bytes);
+        assert_eq!(BlockHeader::parse_stream(&mut stream).unwrap().nonce, 2083236893);
+        assert_eq!(BlockHeader::parse_stream(&mut stream).unwrap().nonce, 0x1dd7ffa4);
+        assert!(BlockHeader::parse_stream(&mut stream).is_err());
+        assert!(BlockHeader::parse(&bytes).is_err());
+        assert!(BlockHeader::parse(&bytes[..HEADER_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn test_hash() {
+        let header = BlockHeader::parse(&from_hex(BLOCK).unwrap()).unwrap();
+        let hash = header.hash();
+        assert_eq!(hash.to_string(), "0000000000000000007e9e4c586439b0cdbe13b1370bdd9435d76a644d047523");
+        assert_eq!(hash.as_bytes()[31], 0x00);
+        assert_eq!(hash.to_string().parse::<BlockHash>().unwrap(), hash);
+        assert_eq!(format!("{:?}", hash), format!("BlockHash({})", hash));
+        assert!("7e9e4c".parse::<BlockHash>().is_err());
+
+        // each header refers to the hash of the one before it
+        let genesis = BlockHeader::parse(&from_hex(header_hex(GENESIS_BLOCK)).unwrap()).unwrap();
+        assert_eq!(genesis.hash().to_string(), "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        let block_1 = BlockHeader::parse(&from_hex(header_hex(BLOCK_1)).unwrap()).unwrap();
+        assert_eq!(block_1.prev_block, genesis.hash());
+        assert_eq!(block_1.hash().to_string(), "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048");
+    }
+
+    #[test]
+    fn test_version_bits() {
+        let mut header = BlockHeader::parse(&from_hex(BLOCK).unwrap()).unwrap();
+        assert!(header.is_bip9());
+        assert!(header.signals_segwit());
+        assert!(!header.signals_taproot());
+        assert!(header.signals_bit(1));
+        assert!(!header.signals_bit(0));
+
+        header.version = 0x20000004;
+        assert!(header.signals_taproot());
+        assert!(!header.signals_segwit());
+        // bits 29 and above are the BIP9 prefix
+        assert!(!header.signals_bit(29));
+        assert!(!header.signals_bit(40));
+        header.version = 0x30000000;
+        assert!(header.signals_bit(28));
+        // the prefix must be 001
+        header.version = 0x60000004;
+        assert!(!header.is_bip9());
+        assert!(!header.signals_taproot());
+        header.version = -1;
+        assert!(!header.is_bip9());
+
+        let genesis = BlockHeader::parse(&from_hex(header_hex(GENESIS_BLOCK)).unwrap()).unwrap();
+        assert!(!genesis.is_bip9());
+        assert!(!genesis.signals_bit(0));
+        // a version 4 header sets bit 2 without signalling
+        let mut header = genesis;
+        header.version = 4;
+        assert!(!header.signals_taproot());
+    }
+}
@@ -0,0 +1,17 @@
+pub mod block;
+pub mod genesis;
+pub mod hash;
+pub mod header;
+pub mod merkle;
+pub mod merkle_block;
+pub mod pow;
+#[cfg(test)]
+mod fixtures;
+
+pub use block::{Block, BlockError};
+pub use genesis::Genesis;
+pub use hash::BlockHash;
+pub use header::BlockHeader;
+pub use merkle::{merkle_root, MerkleError, MerkleProof, MerkleTree};
+pub use merkle_block::MerkleBlock;
+pub use pow::{bits_to_target, calculate_new_bits, target_to_bits, PowError};
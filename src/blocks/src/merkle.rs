@@ -0,0 +1,383 @@
+use ec_cryptography::helper::hash256;
+use transactions::txid::Txid;
+
+/// Why a partial merkle tree is invalid
+#[derive(Debug, PartialEq, Eq)]
+pub enum MerkleError {
+    /// The tree has no leaves
+    NoTransactions,
+    /// The tree has this many leaves, more than a block can hold transactions
+    TooManyTransactions(u32),
+    /// The flag bits ran out before the tree was populated
+    NotEnoughFlagBits,
+    /// The hashes ran out before the tree was populated
+    NotEnoughHashes,
+    /// This many hashes were left over
+    UnusedHashes(usize),
+    /// A flag bit left over is set, or a whole byte of them is left over
+    UnusedFlagBits,
+    /// A node's children are the same hash, which would let a tree with its last leaves duplicated
+    /// have the same root (CVE-2012-2459)
+    DuplicateHash,
+    /// The root isn't the merkle root of the block
+    RootMismatch,
+}
+
+/// The parent of two nodes of a merkle tree: the hash256 of the two, one after the other
+pub fn merkle_parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let digest = hash256(&[left.as_slice(), right].concat());
+    digest.try_into().expect("hash256 digests are 32 bytes")
+}
+
+/// The level above `hashes`. A last node without a sibling is paired with itself.
+pub fn merkle_parent_level(hashes: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    hashes
+        .chunks(2)
+        .map(|pair| merkle_parent(&pair[0], pair.last().expect("chunks are never empty")))
+        .collect()
+}
+
+/// The root of the merkle tree of `hashes`, None if there are none
+pub fn merkle_root(hashes: &[[u8; 32]]) -> Option<[u8; 32]> {
+    let mut level = hashes.to_vec();
+    while level.len() > 1 {
+        level = merkle_parent_level(&level);
+    }
+    level.first().copied()
+}
+
+/// A merkle tree with all its levels, the root at depth 0 and the leaves at `max_depth`. Nodes are
+/// None until they're known, so a tree can hold just the branches a partial merkle tree proves.
+/// Hashes are in internal byte order, the reverse of how txids are displayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleTree {
+    total: usize,
+    max_depth: usize,
+    nodes: Vec<Vec<Option<[u8; 32]>>>,
+}
+
+impl MerkleTree {
+    /// An empty tree for `total` leaves
+    pub fn new(total: usize) -> MerkleTree {
+        let mut max_depth = 0;
+        while 1 << max_depth < total {
+            max_depth += 1;
+        }
+        let nodes = (0..=max_depth)
+            .map(|depth| vec![None; total.div_ceil(1 << (max_depth - depth))])
+            .collect();
+        MerkleTree { total, max_depth, nodes }
+    }
+
+    /// The tree of all the `leaves`, every level computed
+    pub fn from_leaves(leaves: &[[u8; 32]]) -> MerkleTree {
+        let mut tree = MerkleTree::new(leaves.len());
+        let mut level = leaves.to_vec();
+        for depth in (0..=tree.max_depth).rev() {
+            tree.nodes[depth] = level.iter().copied().map(Some).collect();
+            level = merkle_parent_level(&level);
+        }
+        tree
+    }
+
+    /// How many leaves the tree has
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The depth of the leaves
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// The nodes at `depth`, from the left
+    pub fn level(&self, depth: usize) -> &[Option<[u8; 32]>] {
+        self.nodes.get(depth).map_or(&[], Vec::as_slice)
+    }
+
+    /// The node at `index` of the level at `depth`, if it's in the tree and known
+    pub fn node(&self, depth: usize, index: usize) -> Option<[u8; 32]> {
+        self.level(depth).get(index).copied().flatten()
+    }
+
+    /// The root, once it's known
+    pub fn root(&self) -> Option<[u8; 32]> {
+        self.node(0, 0)
+    }
+
+    /// The leaves, once they're known
+    pub fn leaves(&self) -> &[Option<[u8; 32]>] {
+        self.level(self.max_depth)
+    }
+
+    /// Populates the tree from a partial merkle tree, the way BIP37 merkle blocks encode one: its nodes
+    /// are visited depth first, each taking a flag bit. A set bit on an inner node means a leaf under it
+    /// matched and its children follow, anything else takes the next hash. Returns the matched leaves.
+    pub fn populate_tree(&mut self, flag_bits: &[bool], hashes: &[[u8; 32]]) -> Result<Vec<[u8; 32]>, MerkleError> {
+        if self.total == 0 {
+            return Err(MerkleError::NoTransactions);
+        }
+        let mut flag_bits = flag_bits.iter().copied();
+        let mut hashes = hashes.iter().copied();
+        let mut matches = vec![];
+        self.populate_node(0, 0, &mut flag_bits, &mut hashes, &mut matches)?;
+
+        let unused_hashes = hashes.count();
+        if unused_hashes > 0 {
+            return Err(MerkleError::UnusedHashes(unused_hashes));
+        }
+        // only the bits padding the last byte can be left over
+        let unused_bits: Vec<bool> = flag_bits.collect();
+        if unused_bits.len() >= 8 || unused_bits.contains(&true) {
+            return Err(MerkleError::UnusedFlagBits);
+        }
+        Ok(matches)
+    }
+
+    /// The proof that `txid` is one of the leaves, for a tree with all its nodes known, like the tree of a
+    /// full block. None if it isn't a leaf, or a node the proof needs isn't known.
+    pub fn proof(&self, txid: &Txid) -> Option<MerkleProof> {
+        let index = self.leaves().iter().position(|leaf| *leaf == Some(*txid.as_bytes()))?;
+        let branch = (1..=self.max_depth)
+            .rev()
+            .map(|depth| {
+                let position = index >> (self.max_depth - depth);
+                // a last node without a sibling was paired with itself
+                let sibling = if position ^ 1 < self.level(depth).len() { position ^ 1 } else { position };
+                self.node(depth, sibling)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(MerkleProof { txid: *txid, index, branch })
+    }
+
+    fn populate_node(
+        &mut self,
+        depth: usize,
+        index: usize,
+        flag_bits: &mut impl Iterator<Item = bool>,
+        hashes: &mut impl Iterator<Item = [u8; 32]>,
+        matches: &mut Vec<[u8; 32]>,
+    ) -> Result<[u8; 32], MerkleError> {
+        let flag_bit = flag_bits.next().ok_or(MerkleError::NotEnoughFlagBits)?;
+        let hash = if depth == self.max_depth || !flag_bit {
+            let hash = hashes.next().ok_or(MerkleError::NotEnoughHashes)?;
+            if depth == self.max_depth && flag_bit {
+                matches.push(hash);
+            }
+            hash
+        } else {
+            let left = self.populate_node(depth + 1, index * 2, flag_bits, hashes, matches)?;
+            let right = if index * 2 + 1 < self.nodes[depth + 1].len() {
+                let right = self.populate_node(depth + 1, index * 2 + 1, flag_bits, hashes, matches)?;
+                if right == left {
+                    return Err(MerkleError::DuplicateHash);
+                }
+                right
+            } else {
+                left
+            };
+            merkle_parent(&left, &right)
+        };
+        self.nodes[depth][index] = Some(hash);
+        Ok(hash)
+    }
+}
+
+/// The proof that a transaction is in a block: the siblings of the nodes on the path from its txid to
+/// the merkle root. A light client checks it against the merkle root of a header it trusts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub txid: Txid,
+    /// The position of the transaction in the block, whose bits say on which side each sibling is
+    pub index: usize,
+    /// The sibling at each level, from the leaves up
+    pub branch: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    /// The root the branch leads to from the txid
+    pub fn root(&self) -> [u8; 32] {
+        self.branch.iter().enumerate().fold(*self.txid.as_bytes(), |hash, (depth, sibling)| {
+            match self.index >> depth & 1 {
+                0 => merkle_parent(&hash, sibling),
+                _ => merkle_parent(sibling, &hash),
+            }
+        })
+    }
+
+    /// Whether the proof leads to `root`, with an index that fits a tree of its depth
+    pub fn verify(&self, root: &[u8; 32]) -> bool {
+        self.index >> self.branch.len() == 0 && self.root() == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::{hex_to_32_bytes, to_hex};
+
+    use super::*;
+
+    fn hashes(hexes: &[&str]) -> Vec<[u8; 32]> {
+        hexes.iter().map(|hex| hex_to_32_bytes(hex).unwrap()).collect()
+    }
+
+    const LEAVES: [&str; 12] = [
+        "c117ea8ec828342f4dfb0ad6bd140e03a50720ece40169ee38bdc15d9eb64cf5",
+        "c131474164b412e3406696da1ee20ab0fc9bf41c8f05fa8ceea7a08d672d7cc5",
+        "f391da6ecfeed1814efae39e7fcb3838ae0b02c02ae7d0a5848a66947c0727b0",
+        "3d238a92a94532b946c90e19c49351c763696cff3db400485b813aecb8a13181",
+        "10092f2633be5f3ce349bf9ddbde36caa3dd10dfa0ec8106bce23acbff637dae",
+        "7d37b3d54fa6a64869084bfd2e831309118b9e833610e6228adacdbd1b4ba161",
+        "8118a77e542892fe15ae3fc771a4abfd2f5d5d5997544c3487ac36b5c85170fc",
+        "dff6879848c2c9b62fe652720b8df5272093acfaa45a43cdb3696fe2466a3877",
+        "b825c0745f46ac58f7d3759e6dc535a1fec7820377f24d4c2c6ad2cc55c0cb59",
+        "95513952a04bd8992721e9b7e2937f1c04ba31e0469fbe615a78197f68f52b7c",
+        "2e6d722e5e4dbdf2447ddecc9f7dabb8e299bae921c99ad5b0184cd9eb8e5908",
+        "b13a750047bc0bdceb2473e5fe488c2596d7a7124b4e716fdd29b046ef99bbf0",
+    ];
+
+    #[test]
+    fn test_merkle_root() {
+        let leaves = hashes(&LEAVES);
+        let parent = merkle_parent(&leaves[0], &leaves[1]);
+        assert_eq!(to_hex(&parent), "8b30c5ba100f6f2e5ad1e2a742e5020491240f8eb514fe97c713c31718ad7ecd");
+
+        // the last of an odd number of nodes is paired with itself
+        let level = merkle_parent_level(&leaves[..11]);
+        assert_eq!(level.len(), 6);
+        assert_eq!(level[5], merkle_parent(&leaves[10], &leaves[10]));
+
+        let root = merkle_root(&leaves).unwrap();
+        assert_eq!(to_hex(&root), "acbcab8bcc1af95d8d563b77d24c3d19b18f1486383d75a5085c4e86c86beed6");
+        assert_eq!(merkle_root(&leaves[..1]), Some(leaves[0]));
+        assert_eq!(merkle_root(&[]), None);
+    }
+
+    #[test]
+    fn test_tree_levels() {
+        let tree = MerkleTree::new(27);
+        assert_eq!(tree.max_depth(), 5);
+        let sizes: Vec<usize> = (0..=5).map(|depth| tree.level(depth).len()).collect();
+        assert_eq!(sizes, [1, 2, 4, 7, 14, 27]);
+        assert_eq!(tree.root(), None);
+        assert!(tree.level(6).is_empty());
+
+        let leaves = hashes(&LEAVES);
+        let tree = MerkleTree::from_leaves(&leaves);
+        assert_eq!(tree.total(), 12);
+        assert_eq!(tree.root(), merkle_root(&leaves));
+        assert_eq!(tree.node(3, 0), Some(merkle_parent(&leaves[0], &leaves[1])));
+        assert_eq!(tree.node(0, 0), tree.root());
+        assert_eq!(tree.node(3, 6), None);
+        assert!(tree.leaves().iter().zip(&leaves).all(|(node, leaf)| *node == Some(*leaf)));
+
+        assert_eq!(MerkleTree::new(1).max_depth(), 0);
+        assert_eq!(MerkleTree::from_leaves(&leaves[..1]).root(), Some(leaves[0]));
+    }
+
+    #[test]
+    fn test_populate_tree() {
+        let leaves = hashes(&[
+            "9745f7173ef14ee4155722d1cbf13304339fd00d900b759c6f9d58579b5765fb",
+            "5573c8ede34936c29cdfdfe743f7f5fdfbd4f54ba0705259e62f39917065cb9b",
+            "82a02ecbb6623b4274dfcab82b336dc017a27136e08521091e443e62582e8f05",
+            "507ccae5ed9b340363a0e6d765af148be9cb1c8766ccc922f83e4ae681658308",
+            "a7a4aec28e7162e1e9ef33dfa30f0bc0526e6cf4b11a576f6c5de58593898330",
+            "bb6267664bd833fd9fc82582853ab144fece26b7a8a5bf328f8a059445b59add",
+            "ea6d7ac1ee77fbacee58fc717b990c4fcccf1b19af43103c090f601677fd8836",
+            "457743861de496c429912558a106b810b0507975a49773228aa788df40730d41",
+            "7688029288efc9e9a0011c960a6ed9e5466581abf3e3a6c26ee317461add619a",
+            "b1ae7f15836cb2286cdd4e2c37bf9bb7da0a2846d06867a429f654b2e7f383c9",
+            "9b74f89fa3f93e71ff2c241f32945d877281a6a50a6bf94adac002980aafe5ab",
+            "b3a92b5b255019bdaf754875633c2de9fec2ab03e6b8ce669d07cb5b18804638",
+            "b5c0b915312b9bdaedd2b86aa2d0f8feffc73a2d37668fd9010179261e25e263",
+            "c9d52c5cb1e557b92c84c52e7c4bfbce859408bedffc8a5560fd6e35e10b8800",
+            "c555bc5fc3bc096df0a0c9532f07640bfb76bfe4fc1ace214b8b228a1297a4c2",
+            "f9dbfafc3af3400954975da24eb325e326960a25b87fffe23eef3e7ed2fb610e",
+        ]);
+        // every node flagged, all the leaves given
+        let mut tree = MerkleTree::new(16);
+        assert_eq!(tree.populate_tree(&[true; 31], &leaves), Ok(leaves.clone()));
+        assert_eq!(to_hex(&tree.root().unwrap()), "597c4bafe3832b17cbbabe56f878f4fc2ad0f6a402cee7fa851a9cb205f87ed1");
+        assert_eq!(tree, MerkleTree::from_leaves(&leaves));
+
+        let mut tree = MerkleTree::new(5);
+        assert_eq!(tree.populate_tree(&[true; 11], &leaves[..5]), Ok(leaves[..5].to_vec()));
+        assert_eq!(tree.root(), merkle_root(&leaves[..5]));
+
+        // only the third of 3 leaves matched: the first two are given as their parent
+        let full = MerkleTree::from_leaves(&leaves[..3]);
+        let mut tree = MerkleTree::new(3);
+        let matches = tree.populate_tree(&[true, false, true, true], &[full.node(1, 0).unwrap(), leaves[2]]);
+        assert_eq!(matches, Ok(vec![leaves[2]]));
+        assert_eq!(tree.root(), full.root());
+        assert_eq!(tree.node(2, 0), None);
+        assert_eq!(tree.node(2, 2), Some(leaves[2]));
+    }
+
+    #[test]
+    fn test_populate_tree_errors() {
+        let leaves = hashes(&LEAVES[..3]);
+        let populate = |flag_bits: &[bool], hashes: &[[u8; 32]]| MerkleTree::new(3).populate_tree(flag_bits, hashes);
+
+        assert_eq!(populate(&[true; 6], &leaves), Ok(leaves.clone()));
+        assert_eq!(populate(&[true; 5], &leaves), Err(MerkleError::NotEnoughFlagBits));
+        assert_eq!(populate(&[true; 6], &leaves[..2]), Err(MerkleError::NotEnoughHashes));
+        assert_eq!(populate(&[true; 6], &hashes(&LEAVES[..5])), Err(MerkleError::UnusedHashes(2)));
+        // bits padding the last byte must be unset
+        let mut padded = [true, true, true, true, true, true, false, false];
+        assert_eq!(populate(&padded, &leaves), Ok(leaves.clone()));
+        padded[7] = true;
+        assert_eq!(populate(&padded, &leaves), Err(MerkleError::UnusedFlagBits));
+        assert_eq!(populate(&[[true; 6].as_slice(), &[false; 10]].concat(), &leaves), Err(MerkleError::UnusedFlagBits));
+        // a root alone proves nothing matched
+        assert_eq!(populate(&[false], &leaves[..1]), Ok(vec![]));
+
+        // the 3 leaves with the last duplicated have the same root
+        let duplicated = [leaves[0], leaves[1], leaves[2], leaves[2]];
+        assert_eq!(merkle_root(&duplicated), merkle_root(&leaves));
+        assert_eq!(MerkleTree::new(4).populate_tree(&[true; 7], &duplicated), Err(MerkleError::DuplicateHash));
+        assert_eq!(MerkleTree::new(0).populate_tree(&[true], &leaves[..1]), Err(MerkleError::NoTransactions));
+    }
+
+    #[test]
+    fn test_proof() {
+        let leaves = hashes(&LEAVES[..11]);
+        let tree = MerkleTree::from_leaves(&leaves);
+        let root = tree.root().unwrap();
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = tree.proof(&Txid::from_bytes(*leaf)).unwrap();
+            assert_eq!(proof.index, index);
+            assert_eq!(proof.branch.len(), 4);
+            assert!(proof.verify(&root));
+        }
+
+        // the last leaf is its own sibling
+        let proof = tree.proof(&Txid::from_bytes(leaves[10])).unwrap();
+        assert_eq!(proof.branch[0], leaves[10]);
+        assert_eq!(proof.branch[1], merkle_parent(&leaves[8], &leaves[9]));
+
+        let mut wrong = proof.clone();
+        wrong.index = 9;
+        assert!(!wrong.verify(&root));
+        wrong.index = 10 + 16;
+        assert!(!wrong.verify(&root));
+        let mut wrong = proof.clone();
+        wrong.branch[2][0] ^= 1;
+        assert!(!wrong.verify(&root));
+        assert!(!proof.verify(&leaves[0]));
+
+        assert_eq!(tree.proof(&Txid::from_bytes(hashes(&LEAVES)[11])), None);
+        // a single transaction is the root
+        let proof = MerkleTree::from_leaves(&leaves[..1]).proof(&Txid::from_bytes(leaves[0])).unwrap();
+        assert!(proof.branch.is_empty());
+        assert!(proof.verify(&leaves[0]));
+
+        // a partial tree can only prove the leaves it matched
+        let mut partial = MerkleTree::new(3);
+        let full = MerkleTree::from_leaves(&leaves[..3]);
+        partial.populate_tree(&[true, false, true, true], &[full.node(1, 0).unwrap(), leaves[2]]).unwrap();
+        assert_eq!(partial.proof(&Txid::from_bytes(leaves[2])), full.proof(&Txid::from_bytes(leaves[2])));
+        assert_eq!(partial.proof(&Txid::from_bytes(leaves[0])), None);
+    }
+}
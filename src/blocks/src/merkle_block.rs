@@ -0,0 +1,165 @@
+use std::io::{self, Read, Write};
+
+use ec_cryptography::consensus::{ConsensusDecode, ConsensusEncode};
+use transactions::txid::Txid;
+
+use crate::{BlockHeader, MerkleError, MerkleTree};
+
+/// The most transactions a block can hold: its weight limit over the least a transaction can weigh
+pub const MAX_BLOCK_TRANSACTIONS: u32 = 4_000_000 / 240;
+
+/// A block header with the partial merkle tree proving which of its transactions match a bloom filter
+/// (BIP37), as the merkleblock message carries it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleBlock {
+    pub header: BlockHeader,
+    /// How many transactions the block has, the leaves of its merkle tree
+    pub total: u32,
+    /// The hashes of the partial merkle tree, depth first, in internal byte order
+    pub hashes: Vec<[u8; 32]>,
+    /// The flag bits of the partial merkle tree, packed 8 to a byte from the least significant bit
+    pub flags: Vec<u8>,
+}
+
+/// Unpacks flag bytes into bits, the least significant bit of each byte first
+pub fn bytes_to_bit_field(bytes: &[u8]) -> Vec<bool> {
+    bytes.iter().flat_map(|byte| (0..8).map(move |bit| byte >> bit & 1 == 1)).collect()
+}
+
+impl MerkleBlock {
+    /// Reads a merkle block from a stream, consuming exactly its bytes
+    pub fn parse_stream<R: Read>(reader: &mut R) -> io::Result<MerkleBlock> {
+        MerkleBlock::consensus_decode(reader)
+    }
+
+    /// Parses the payload of a merkleblock message. The bytes must hold exactly one.
+    pub fn parse(bytes: &[u8]) -> io::Result<MerkleBlock> {
+        MerkleBlock::consensus_deserialize(bytes)
+    }
+
+    /// The header, the transaction count, then the varint counted hashes and flag bytes
+    pub fn serialize(&self) -> Vec<u8> {
+        self.consensus_serialize()
+    }
+
+    /// The flag bits of the partial merkle tree
+    pub fn flag_bits(&self) -> Vec<bool> {
+        bytes_to_bit_field(&self.flags)
+    }
+
+    /// Rebuilds the partial merkle tree depth first, and checks that its root is the one the header
+    /// commits to. Returns the txids it proves are in the block, in the order of the block.
+    pub fn validate(&self) -> Result<Vec<Txid>, MerkleError> {
+        if self.total > MAX_BLOCK_TRANSACTIONS {
+            return Err(MerkleError::TooManyTransactions(self.total));
+        }
+        // there is at most one hash for each leaf, and a flag bit for each hash
+        if self.hashes.len() > self.total as usize {
+            return Err(MerkleError::UnusedHashes(self.hashes.len() - self.total as usize));
+        }
+        if self.flags.len() * 8 < self.hashes.len() {
+            return Err(MerkleError::NotEnoughFlagBits);
+        }
+
+        let mut tree = MerkleTree::new(self.total as usize);
+        let matches = tree.populate_tree(&self.flag_bits(), &self.hashes)?;
+        if tree.root() != Some(self.header.merkle_root) {
+            return Err(MerkleError::RootMismatch);
+        }
+        Ok(matches.into_iter().map(Txid::from_bytes).collect())
+    }
+}
+
+impl ConsensusEncode for MerkleBlock {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.header.consensus_encode(writer)?
+            + self.total.consensus_encode(writer)?
+            + self.hashes.consensus_encode(writer)?
+            + self.flags.consensus_encode(writer)?)
+    }
+}
+
+impl ConsensusDecode for MerkleBlock {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(MerkleBlock {
+            header: BlockHeader::consensus_decode(reader)?,
+            total: u32::consensus_decode(reader)?,
+            hashes: Vec::<[u8; 32]>::consensus_decode(reader)?,
+            flags: Vec::<u8>::consensus_decode(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::{from_hex, to_hex};
+
+    use super::*;
+    use crate::{fixtures::GENESIS_BLOCK, Block};
+
+    const MERKLE_BLOCK: &str = "00000020df3b053dc46f162a9b00c7f0d5124e2676d47bbe7c5d0793a500000000000000ef445fef2ed495c275892206ca533e7411907971013ab83e3b47bd0d692d14d4dc7c835b67d8001ac157e670bf0d00000aba412a0d1480e370173072c9562becffe87aa661c1e4a6dbc305d38ec5dc088a7cf92e6458aca7b32edae818f9c2c98c37e06bf72ae0ce80649a38655ee1e27d34d9421d940b16732f24b94023e9d572a7f9ab8023434a4feb532d2adfc8c2c2158785d1bd04eb99df2e86c54bc13e139862897217400def5d72c280222c4cbaee7261831e1550dbb8fa82853e9fe506fc5fda3f7b919d8fe74b6282f92763cef8e625f977af7c8619c32a369b832bc2d051ecd9c73c51e76370ceabd4f25097c256597fa898d404ed53425de608ac6bfe426f6e2bb457f1c554866eb69dcb8d6bf6f880e9a59b3cd053e6c7060eeacaacf4dac6697dac20e4bd3f38a2ea2543d1ab7953e3430790a9f81e1c67f5b58c825acf46bd02848384eebe9af917274cdfbb1a28a5d58a23a17977def0de10d644258d9c54f886d47d293a411cb6226103b55635";
+
+    #[test]
+    fn test_parse() {
+        let bytes = from_hex(MERKLE_BLOCK).unwrap();
+        let merkle_block = MerkleBlock::parse(&bytes).unwrap();
+        assert_eq!(merkle_block.header.version, 0x20000000);
+        assert_eq!(
+            merkle_block.header.merkle_root.to_vec(),
+            from_hex("ef445fef2ed495c275892206ca533e7411907971013ab83e3b47bd0d692d14d4").unwrap()
+        );
+        assert_eq!(merkle_block.total, 3519);
+        assert_eq!(merkle_block.hashes.len(), 10);
+        assert_eq!(to_hex(&merkle_block.hashes[0]), "ba412a0d1480e370173072c9562becffe87aa661c1e4a6dbc305d38ec5dc088a");
+        assert_eq!(merkle_block.flags, vec![0xb5, 0x56, 0x35]);
+        assert_eq!(merkle_block.serialize(), bytes);
+
+        let bits = bytes_to_bit_field(&[0x01, 0x40]);
+        assert_eq!(bits.len(), 16);
+        let set: Vec<usize> = (0..16).filter(|index| bits[*index]).collect();
+        assert_eq!(set, [0, 14]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let merkle_block = MerkleBlock::parse(&from_hex(MERKLE_BLOCK).unwrap()).unwrap();
+        let matches = merkle_block.validate().unwrap();
+        assert!(!matches.is_empty());
+
+        let mut wrong = merkle_block.clone();
+        wrong.hashes[3][0] ^= 1;
+        assert_eq!(wrong.validate(), Err(MerkleError::RootMismatch));
+        let mut wrong = merkle_block.clone();
+        wrong.total += 1;
+        assert!(wrong.validate().is_err());
+        let mut wrong = merkle_block.clone();
+        wrong.hashes.pop();
+        assert_eq!(wrong.validate(), Err(MerkleError::NotEnoughHashes));
+        let mut wrong = merkle_block.clone();
+        wrong.flags.push(0);
+        assert_eq!(wrong.validate(), Err(MerkleError::UnusedFlagBits));
+        let mut wrong = merkle_block.clone();
+        wrong.flags.truncate(1);
+        assert_eq!(wrong.validate(), Err(MerkleError::NotEnoughFlagBits));
+        let mut wrong = merkle_block.clone();
+        wrong.total = MAX_BLOCK_TRANSACTIONS + 1;
+        assert_eq!(wrong.validate(), Err(MerkleError::TooManyTransactions(MAX_BLOCK_TRANSACTIONS + 1)));
+        let mut wrong = merkle_block;
+        wrong.total = 0;
+        assert_eq!(wrong.validate(), Err(MerkleError::UnusedHashes(10)));
+    }
+
+    #[test]
+    fn test_validate_genesis() {
+        // a merkle block matching the only transaction of the genesis block
+        let block = Block::parse(&from_hex(GENESIS_BLOCK).unwrap()).unwrap();
+        let txid = block.transactions[0].txid();
+        let merkle_block =
+            MerkleBlock { header: block.header.clone(), total: 1, hashes: vec![*txid.as_bytes()], flags: vec![0x01] };
+        assert_eq!(merkle_block.validate(), Ok(vec![txid]));
+
+        // the same, with nothing matched
+        let merkle_block = MerkleBlock { flags: vec![0x00], ..merkle_block };
+        assert_eq!(merkle_block.validate(), Ok(vec![]));
+    }
+}
@@ -0,0 +1,206 @@
+use rug::{integer::Order, Integer};
+
+use crate::BlockHeader;
+
+/// The sign bit of the mantissa of a compact target
+const COMPACT_SIGN_BIT: u32 = 0x0080_0000;
+
+/// The bits of the genesis block, whose target has a difficulty of 1
+pub const DIFFICULTY_1_BITS: u32 = 0x1d00ffff;
+
+/// How many blocks the target stays the same for
+pub const RETARGET_INTERVAL: u32 = 2016;
+/// How long a retarget interval should take, in seconds
+pub const TWO_WEEKS: i64 = 60 * 60 * 24 * 14;
+
+/// Why a header's proof of work is invalid
+#[derive(Debug, PartialEq, Eq)]
+pub enum PowError {
+    /// The bits set the sign bit of a nonzero mantissa, a target below zero
+    Negative,
+    /// The bits encode a target of more than 256 bits
+    Overflow,
+    /// The bits encode a target of zero, which no hash can meet
+    Zero,
+    /// The hash of the header is above its target
+    HashAboveTarget,
+}
+
+/// Decodes the compact "bits" encoding of a target, the way Core's SetCompact does: the top byte
+/// is the size of the target in bytes, the lower three the most significant bytes of it. The 0x00800000
+/// bit is a sign, which no valid target has set.
+pub fn bits_to_target(bits: u32) -> Result<Integer, PowError> {
+    let size = bits >> 24;
+    // sizes below 3 shift bytes of the mantissa out, the sign only counts if some are left
+    let mantissa = match size {
+        0..=3 => (bits & 0x007f_ffff) >> (8 * (3 - size)),
+        _ => bits & 0x007f_ffff,
+    };
+    if mantissa == 0 {
+        return Err(PowError::Zero);
+    }
+    if bits & COMPACT_SIGN_BIT != 0 {
+        return Err(PowError::Negative);
+    }
+    // only the bytes of the mantissa that are set have to fit in 256 bits
+    if size > 34 || (mantissa > 0xff && size > 33) || (mantissa > 0xffff && size > 32) {
+        return Err(PowError::Overflow);
+    }
+
+    Ok(Integer::from(mantissa) << (8 * size.saturating_sub(3)))
+}
+
+/// Encodes a target in the compact "bits" form, the way Core's GetCompact does, dropping all but its
+/// 3 most significant bytes. A mantissa that would set the sign bit is shifted a byte further down.
+pub fn target_to_bits(target: &Integer) -> u32 {
+    let mut size = target.significant_bits().div_ceil(8);
+    let mut mantissa = if size <= 3 {
+        target.to_u32_wrapping() << (8 * (3 - size))
+    } else {
+        (target.clone() >> (8 * (size - 3))).to_u32_wrapping()
+    };
+    if mantissa & COMPACT_SIGN_BIT != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    size << 24 | mantissa
+}
+
+/// The bits of the next retarget interval, from those of the last and the seconds its blocks took,
+/// the timestamp of its last block minus that of its first. The target is scaled by how long the
+/// interval took against TWO_WEEKS, changing it by a factor of 4 at most, and is never easier than
+/// the genesis block's.
+pub fn calculate_new_bits(prev_bits: u32, time_differential: i64) -> Result<u32, PowError> {
+    let time_differential = time_differential.clamp(TWO_WEEKS / 4, TWO_WEEKS * 4);
+    let new_target = bits_to_target(prev_bits)? * time_differential / TWO_WEEKS;
+    let max_target = bits_to_target(DIFFICULTY_1_BITS)?;
+    Ok(target_to_bits(&new_target.min(max_target)))
+}
+
+impl BlockHeader {
+    /// The target the hash of the header must not exceed, decoded from its bits
+    pub fn target(&self) -> Result<Integer, PowError> {
+        bits_to_target(self.bits)
+    }
+
+    /// Checks the proof of work: the hash of the header, read as a little-endian number, must not
+    /// exceed the target its bits encode. The target isn't checked against the network's limit.
+    pub fn check_pow(&self) -> Result<(), PowError> {
+        let target = self.target()?;
+        let proof = Integer::from_digits(self.hash().as_bytes(), Order::Lsf);
+        if proof > target {
+            return Err(PowError::HashAboveTarget);
+        }
+        Ok(())
+    }
+
+    /// How many times harder than the genesis block's target the header's is to meet, the ratio of
+    /// the two as block explorers and Core's getdifficulty show it. Targets easier than the genesis one,
+    /// as on regtest, have a difficulty below 1.
+    pub fn difficulty(&self) -> Result<f64, PowError> {
+        let target = self.target()?;
+        let difficulty_1 = bits_to_target(DIFFICULTY_1_BITS)?;
+        // the whole part is exact, only the fraction is rounded
+        let (whole, remainder) = difficulty_1.div_rem(target.clone());
+        Ok(whole.to_f64() + remainder.to_f64() / target.to_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::from_hex;
+
+    use super::*;
+
+    #[test]
+    fn test_target() {
+        let raw = "020000208ec39428b17323fa0ddec8e887b4a7c53b8c0a0a220cfd0000000000000000005b0750fce0a889502d40508d39576821155e9c9e3f5c3157f961db38fd8b25be1e77a759e93c0118a4ffd71d";
+        let header = BlockHeader::parse(&from_hex(raw).unwrap()).unwrap();
+        let target = Integer::from_str_radix("13ce9000000000000000000000000000000000000000000", 16).unwrap();
+        assert_eq!(header.target(), Ok(target));
+
+        // the genesis target, 0xffff followed by 26 zero bytes
+        assert_eq!(bits_to_target(0x1d00ffff), Ok(Integer::from(0xffff) << 208));
+        assert_eq!(bits_to_target(0x01123456), Ok(Integer::from(0x12)));
+        assert_eq!(bits_to_target(0x02123456), Ok(Integer::from(0x1234)));
+        assert_eq!(bits_to_target(0x04123456), Ok(Integer::from(0x12345600)));
+        assert_eq!(bits_to_target(0x20123456), Ok(Integer::from(0x123456) << 232));
+        // a mantissa with leading zero bytes can take a larger size
+        assert_eq!(bits_to_target(0x22000001), Ok(Integer::from(1) << 248));
+    }
+
+    #[test]
+    fn test_target_edge_cases() {
+        assert_eq!(bits_to_target(0x00000000), Err(PowError::Zero));
+        assert_eq!(bits_to_target(0x1d000000), Err(PowError::Zero));
+        // the mantissa is shifted out
+        assert_eq!(bits_to_target(0x00123456), Err(PowError::Zero));
+        assert_eq!(bits_to_target(0x01003456), Err(PowError::Zero));
+        // the sign bit only counts with a nonzero mantissa
+        assert_eq!(bits_to_target(0x04923456), Err(PowError::Negative));
+        assert_eq!(bits_to_target(0x01fedcba), Err(PowError::Negative));
+        assert_eq!(bits_to_target(0x04800000), Err(PowError::Zero));
+        assert_eq!(bits_to_target(0x00923456), Err(PowError::Zero));
+
+        assert_eq!(bits_to_target(0xff123456), Err(PowError::Overflow));
+        assert_eq!(bits_to_target(0x21010000), Err(PowError::Overflow));
+        assert_eq!(bits_to_target(0x22000100), Err(PowError::Overflow));
+        assert_eq!(bits_to_target(0x23000001), Err(PowError::Overflow));
+    }
+
+    #[test]
+    fn test_check_pow() {
+        let raw = "04000000fbedbbf0cfdaf278c094f187f2eb987c86a199da22bbb20400000000000000007b7697b29129648fa08b4bcd13c9d5e60abb973a1efac9c8d573c71c807c56c3d6213557faa80518c3737ec1";
+        let mut header = BlockHeader::parse(&from_hex(raw).unwrap()).unwrap();
+        assert_eq!(header.check_pow(), Ok(()));
+
+        header.nonce -= 1;
+        assert_eq!(header.check_pow(), Err(PowError::HashAboveTarget));
+        header.bits = 0x1d800000 | 0xffff;
+        assert_eq!(header.check_pow(), Err(PowError::Negative));
+    }
+
+    #[test]
+    fn test_difficulty() {
+        let raw = "020000208ec39428b17323fa0ddec8e887b4a7c53b8c0a0a220cfd0000000000000000005b0750fce0a889502d40508d39576821155e9c9e3f5c3157f961db38fd8b25be1e77a759e93c0118a4ffd71d";
+        let mut header = BlockHeader::parse(&from_hex(raw).unwrap()).unwrap();
+        assert!((header.difficulty().unwrap() - 888171856257.3206).abs() < 1e-3);
+
+        header.bits = DIFFICULTY_1_BITS;
+        assert_eq!(header.difficulty(), Ok(1.0));
+        // the regtest target is easier than the genesis one
+        header.bits = 0x207fffff;
+        assert!((header.difficulty().unwrap() - 4.656542373906925e-10).abs() < 1e-24);
+        header.bits = 0x1d000000;
+        assert_eq!(header.difficulty(), Err(PowError::Zero));
+    }
+
+    #[test]
+    fn test_target_to_bits() {
+        for bits in [0x1d00ffff, 0x18013ce9, 0x1c05a3f4, 0x01120000, 0x02123400, 0x04123456, 0x207fffff] {
+            assert_eq!(target_to_bits(&bits_to_target(bits).unwrap()), bits);
+        }
+        assert_eq!(target_to_bits(&Integer::ZERO), 0);
+        // 0x80 would be read back as the sign, so the mantissa takes a byte more
+        assert_eq!(target_to_bits(&Integer::from(0x80)), 0x02008000);
+        assert_eq!(target_to_bits(&Integer::from(0x12345678)), 0x04123456);
+    }
+
+    #[test]
+    fn test_calculate_new_bits() {
+        // blocks that came 4 times too fast, 302,400 seconds being a quarter of two weeks
+        assert_eq!(calculate_new_bits(0x1801d854, 302400), Ok(0x17761500));
+
+        // the first retarget of mainnet, at block 32,256, from the times of blocks 30,240 and 32,255
+        assert_eq!(calculate_new_bits(0x1d00ffff, 1262152739 - 1261130161), Ok(0x1d00d86a));
+        // the first 2016 blocks took longer than two weeks, but the target can't get easier than the genesis one
+        assert_eq!(calculate_new_bits(0x1d00ffff, 1233061996 - 1231006505), Ok(0x1d00ffff));
+        // blocks 66,528 to 68,543 took under a quarter of two weeks, the target only shrinks by 4
+        assert_eq!(calculate_new_bits(0x1c05a3f4, 1279297671 - 1279008237), Ok(0x1c0168fd));
+        // and grows by 4 at most
+        assert_eq!(calculate_new_bits(0x1c387f6f, 1269211443 - 1263163443), Ok(0x1d00e1fd));
+        assert_eq!(calculate_new_bits(0x1c387f6f, -600), calculate_new_bits(0x1c387f6f, TWO_WEEKS / 4));
+
+        assert_eq!(calculate_new_bits(0x04923456, TWO_WEEKS), Err(PowError::Negative));
+    }
+}
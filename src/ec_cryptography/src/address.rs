@@ -0,0 +1,301 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{
+    bech32::{bech32_decode, decode_segwit_address, encode_segwit_address, Bech32Error},
+    network::Network,
+    serializer::{decode_base58check, encode_base58check, Base58Error},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AddressError {
+    Base58(Base58Error),
+    Bech32(Bech32Error),
+    /// The Base58Check version byte is not a known P2PKH or P2SH prefix
+    UnknownVersion(u8),
+    /// The human readable part is not a known segwit prefix
+    UnknownHrp(String),
+    /// The hash or witness program has the wrong length for the address type
+    InvalidLength(usize),
+    /// Only witness versions 0 (P2WPKH, P2WSH) and 1 (P2TR) are understood
+    UnsupportedWitnessVersion(u8),
+    /// The address is valid, but for a different network than expected
+    NetworkMismatch,
+}
+
+impl From<Base58Error> for AddressError {
+    fn from(error: Base58Error) -> Self {
+        AddressError::Base58(error)
+    }
+}
+
+impl From<Bech32Error> for AddressError {
+    fn from(error: Bech32Error) -> Self {
+        AddressError::Bech32(error)
+    }
+}
+
+/// A Bitcoin address, holding the hash or witness program it commits to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    /// Pay to public key hash: the hash160 of a public key
+    P2PKH { hash: Vec<u8>, network: Network },
+    /// Pay to script hash: the hash160 of a redeem script
+    P2SH { hash: Vec<u8>, network: Network },
+    /// Pay to witness public key hash: a 20-byte version 0 witness program
+    P2WPKH { program: Vec<u8>, network: Network },
+    /// Pay to witness script hash: a 32-byte version 0 witness program
+    P2WSH { program: Vec<u8>, network: Network },
+    /// Pay to taproot: a 32-byte version 1 witness program (the output key)
+    P2TR { program: Vec<u8>, network: Network },
+}
+
+impl Address {
+    /// Parses a Base58Check or bech32/bech32m address, validating its checksum and payload length.
+    /// Test networks share their Base58 prefixes (and testnet and signet their bech32 prefix),
+    /// so the network of the result is the first one using them. Use `parse_for_network` to pin it.
+    pub fn parse(address: &str) -> Result<Address, AddressError> {
+        if Self::is_segwit(address) {
+            return Self::parse_segwit(address);
+        }
+
+        let (version, hash) = decode_base58check(address)?;
+        if hash.len() != 20 {
+            return Err(AddressError::InvalidLength(hash.len()));
+        }
+
+        for network in [Network::Mainnet, Network::Testnet] {
+            if version == network.p2pkh_version() {
+                return Ok(Address::P2PKH { hash, network });
+            }
+            if version == network.p2sh_version() {
+                return Ok(Address::P2SH { hash, network });
+            }
+        }
+
+        Err(AddressError::UnknownVersion(version))
+    }
+
+    /// Parses an address and checks it uses the prefixes of the expected network.
+    pub fn parse_for_network(address: &str, network: Network) -> Result<Address, AddressError> {
+        let mut parsed = Self::parse(address)?;
+
+        let matches = match parsed {
+            Address::P2PKH { .. } | Address::P2SH { .. } => {
+                parsed.network().p2pkh_version() == network.p2pkh_version()
+            }
+            _ => parsed.network().bech32_hrp() == network.bech32_hrp(),
+        };
+        if !matches {
+            return Err(AddressError::NetworkMismatch);
+        }
+
+        parsed.set_network(network);
+        Ok(parsed)
+    }
+
+    /// Segwit addresses start with a known human readable part followed by the '1' separator
+    fn is_segwit(address: &str) -> bool {
+        let lowercase = address.to_lowercase();
+        match lowercase.rfind('1') {
+            Some(separator) => Network::from_bech32_hrp(&lowercase[..separator]).is_some(),
+            None => false,
+        }
+    }
+
+    fn parse_segwit(address: &str) -> Result<Address, AddressError> {
+        let (hrp, _, _) = bech32_decode(address)?;
+        let network = Network::from_bech32_hrp(&hrp).ok_or_else(|| AddressError::UnknownHrp(hrp.clone()))?;
+
+        let (version, program) = decode_segwit_address(&hrp, address)?;
+        match (version, program.len()) {
+            (0, 20) => Ok(Address::P2WPKH { program, network }),
+            (0, 32) => Ok(Address::P2WSH { program, network }),
+            (1, 32) => Ok(Address::P2TR { program, network }),
+            (0, length) | (1, length) => Err(AddressError::InvalidLength(length)),
+            (version, _) => Err(AddressError::UnsupportedWitnessVersion(version)),
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        match self {
+            Address::P2PKH { network, .. }
+            | Address::P2SH { network, .. }
+            | Address::P2WPKH { network, .. }
+            | Address::P2WSH { network, .. }
+            | Address::P2TR { network, .. } => *network,
+        }
+    }
+
+    fn set_network(&mut self, new_network: Network) {
+        match self {
+            Address::P2PKH { network, .. }
+            | Address::P2SH { network, .. }
+            | Address::P2WPKH { network, .. }
+            | Address::P2WSH { network, .. }
+            | Address::P2TR { network, .. } => *network = new_network,
+        }
+    }
+
+    /// The hash (for Base58 addresses) or witness program (for segwit addresses) the address commits to
+    pub fn payload(&self) -> &[u8] {
+        match self {
+            Address::P2PKH { hash, .. } | Address::P2SH { hash, .. } => hash,
+            Address::P2WPKH { program, .. }
+            | Address::P2WSH { program, .. }
+            | Address::P2TR { program, .. } => program,
+        }
+    }
+
+    /// The witness version for segwit addresses, `None` for legacy addresses
+    pub fn witness_version(&self) -> Option<u8> {
+        match self {
+            Address::P2PKH { .. } | Address::P2SH { .. } => None,
+            Address::P2WPKH { .. } | Address::P2WSH { .. } => Some(0),
+            Address::P2TR { .. } => Some(1),
+        }
+    }
+
+    /// The raw script_pubkey of the output the address pays to, which `scripts::Script::from_bytes` wraps.
+    /// Witness programs are their version opcode (OP_0 or OP_1) followed by the push of the program.
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        let payload = self.payload();
+        let mut script = match self {
+            // OP_DUP OP_HASH160 <hash> OP_EQUALVERIFY OP_CHECKSIG
+            Address::P2PKH { .. } => vec![0x76, 0xa9],
+            // OP_HASH160 <hash> OP_EQUAL
+            Address::P2SH { .. } => vec![0xa9],
+            _ => {
+                let version = self.witness_version().expect("segwit addresses have a witness version");
+                vec![if version == 0 { 0x00 } else { 0x50 + version }]
+            }
+        };
+        script.push(payload.len() as u8);
+        script.extend_from_slice(payload);
+        match self {
+            Address::P2PKH { .. } => script.extend_from_slice(&[0x88, 0xac]),
+            Address::P2SH { .. } => script.push(0x87),
+            _ => {}
+        }
+        script
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let encoded = match self {
+            Address::P2PKH { hash, network } => encode_base58check(network.p2pkh_version(), hash),
+            Address::P2SH { hash, network } => encode_base58check(network.p2sh_version(), hash),
+            _ => encode_segwit_address(
+                self.network().bech32_hrp(),
+                self.witness_version().unwrap(),
+                self.payload(),
+            )
+            .map_err(|_| std::fmt::Error)?,
+        };
+
+        write!(f, "{}", encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_legacy_addresses() {
+        let address = Address::parse("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH").unwrap();
+        assert_eq!(
+            address,
+            Address::P2PKH {
+                hash: hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap(),
+                network: Network::Mainnet
+            }
+        );
+        assert_eq!(address.to_string(), "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH");
+
+        let address = Address::parse("mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r").unwrap();
+        assert!(matches!(address, Address::P2PKH { network: Network::Testnet, .. }));
+        assert_eq!(address.to_string(), "mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r");
+
+        let address = Address::parse("3CMNFxN1oHBc4R1EpboAL5yzHGgE611Xou").unwrap();
+        assert!(matches!(address, Address::P2SH { network: Network::Mainnet, .. }));
+        assert_eq!(hex::encode(address.payload()), "74f209f6ea907e2ea48f74fae05782ae8a665257");
+        assert_eq!(address.to_string(), "3CMNFxN1oHBc4R1EpboAL5yzHGgE611Xou");
+    }
+
+    #[test]
+    fn test_parse_segwit_addresses() {
+        let address = Address::parse("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        assert!(matches!(address, Address::P2WPKH { network: Network::Mainnet, .. }));
+        assert_eq!(hex::encode(address.payload()), "751e76e8199196d454941c45d1b3a323f1433bd6");
+        assert_eq!(address.to_string(), "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+
+        let raw = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+        let address = Address::parse(raw).unwrap();
+        assert!(matches!(address, Address::P2WSH { network: Network::Testnet, .. }));
+        assert_eq!(address.to_string(), raw);
+
+        let raw = "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0";
+        let address = Address::parse(raw).unwrap();
+        assert!(matches!(address, Address::P2TR { network: Network::Mainnet, .. }));
+        assert_eq!(address.witness_version(), Some(1));
+        assert_eq!(address.to_string(), raw);
+    }
+
+    #[test]
+    fn test_script_pubkey() {
+        let cases = [
+            ("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH", "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac"),
+            ("3CMNFxN1oHBc4R1EpboAL5yzHGgE611Xou", "a91474f209f6ea907e2ea48f74fae05782ae8a66525787"),
+            ("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", "0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            ),
+        ];
+
+        for (address, script_pubkey) in cases {
+            assert_eq!(hex::encode(Address::parse(address).unwrap().script_pubkey()), script_pubkey);
+        }
+    }
+
+    #[test]
+    fn test_parse_for_network() {
+        // signet shares its prefixes with testnet
+        let address = Address::parse_for_network("mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r", Network::Signet).unwrap();
+        assert_eq!(address.network(), Network::Signet);
+        assert_eq!(address.to_string(), "mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r");
+
+        let program = hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        let address = Address::P2WPKH { program, network: Network::Regtest };
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("bcrt1q"));
+        assert_eq!(Address::parse_for_network(&encoded, Network::Regtest).unwrap(), address);
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        assert_eq!(
+            Address::parse("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMJ"),
+            Err(AddressError::Base58(Base58Error::InvalidChecksum))
+        );
+        assert_eq!(
+            Address::parse("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5"),
+            Err(AddressError::Bech32(Bech32Error::InvalidChecksum))
+        );
+        // witness version 16 is valid bech32m, but has no address type yet
+        assert_eq!(Address::parse("BC1SW50QGDZ25J"), Err(AddressError::UnsupportedWitnessVersion(16)));
+        assert_eq!(
+            Address::parse_for_network("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH", Network::Testnet),
+            Err(AddressError::NetworkMismatch)
+        );
+        assert_eq!(
+            Address::parse_for_network("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", Network::Regtest),
+            Err(AddressError::NetworkMismatch)
+        );
+    }
+}
@@ -0,0 +1,346 @@
+/// The 32 characters used by bech32, each one encoding 5 bits
+pub const CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// The generator of the BCH code used for the checksum
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+/// The constant the checksum is xored with for bech32m (BIP350)
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+/// The checksum variant. Witness version 0 addresses use bech32, version 1 and above use bech32m.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Bech32,
+    Bech32m,
+}
+
+impl Variant {
+    fn constant(&self) -> u32 {
+        match self {
+            Variant::Bech32 => 1,
+            Variant::Bech32m => BECH32M_CONST,
+        }
+    }
+
+    /// The variant a segwit address with the given witness version must use
+    pub fn for_witness_version(version: u8) -> Variant {
+        if version == 0 {
+            Variant::Bech32
+        } else {
+            Variant::Bech32m
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Bech32Error {
+    /// The string mixes upper and lower case characters
+    MixedCase,
+    /// The string has no '1' separating the human readable part from the data
+    MissingSeparator,
+    /// The human readable part is empty or contains characters outside the ASCII range 33-126
+    InvalidHrp,
+    /// The data part contains a character outside the bech32 charset
+    InvalidCharacter(char),
+    /// The string is longer than 90 characters, or the data part is too short to hold a checksum
+    InvalidLength,
+    /// The checksum does not match the human readable part and data
+    InvalidChecksum,
+    /// The checksum is valid, but uses the wrong variant for the witness version
+    InvalidVariant,
+    /// The 5-bit groups could not be converted back to bytes without leftover bits
+    InvalidPadding,
+    /// The decoded human readable part is not the one that was expected
+    HrpMismatch,
+    /// Witness versions range from 0 to 16
+    InvalidWitnessVersion(u8),
+    /// The witness program length is not allowed for its version
+    InvalidProgramLength(usize),
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    let mut checksum: u32 = 1;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = (checksum & 0x1ffffff) << 5 ^ (*value as u32);
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+/// Expands the human readable part so it can be fed into the checksum:
+/// the high bits of each character, a zero, then the low bits of each character.
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.bytes().map(|c| c & 31));
+    expanded
+}
+
+fn create_checksum(hrp: &str, data: &[u8], variant: Variant) -> Vec<u8> {
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(data);
+    values.extend_from_slice(&[0; 6]);
+
+    let checksum = polymod(&values) ^ variant.constant();
+    (0..6).map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8).collect()
+}
+
+/// Returns the variant whose checksum matches, if any
+fn verify_checksum(hrp: &str, data: &[u8]) -> Option<Variant> {
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(data);
+
+    match polymod(&values) {
+        1 => Some(Variant::Bech32),
+        BECH32M_CONST => Some(Variant::Bech32m),
+        _ => None,
+    }
+}
+
+/// Regroups a sequence of `from`-bit values into `to`-bit values.
+/// When `pad` is set, leftover bits are zero-padded into a final group, otherwise they must be zero
+/// and fewer than `from` bits.
+pub fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, Bech32Error> {
+    let mut accumulator: u32 = 0;
+    let mut bits: u32 = 0;
+    let max_value: u32 = (1 << to) - 1;
+    let mut converted = Vec::new();
+
+    for value in data {
+        accumulator = (accumulator << from) | (*value as u32);
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            converted.push(((accumulator >> bits) & max_value) as u8);
+        }
+    }
+
+    if pad {
+        if bits > 0 {
+            converted.push(((accumulator << (to - bits)) & max_value) as u8);
+        }
+    } else if bits >= from || ((accumulator << (to - bits)) & max_value) != 0 {
+        return Err(Bech32Error::InvalidPadding);
+    }
+
+    Ok(converted)
+}
+
+/// Encodes 5-bit data with the given human readable part, appending the checksum of the given variant.
+pub fn bech32_encode(hrp: &str, data: &[u8], variant: Variant) -> String {
+    let checksum = create_checksum(hrp, data, variant);
+    let charset = CHARSET.as_bytes();
+
+    let mut encoded = String::from(hrp);
+    encoded.push('1');
+    for value in data.iter().chain(checksum.iter()) {
+        encoded.push(charset[*value as usize] as char);
+    }
+
+    encoded
+}
+
+/// Decodes a bech32 or bech32m string into its human readable part and 5-bit data, with the checksum removed.
+/// The variant of the checksum is returned alongside.
+pub fn bech32_decode(encoded: &str) -> Result<(String, Vec<u8>, Variant), Bech32Error> {
+    if encoded.len() > 90 {
+        return Err(Bech32Error::InvalidLength);
+    }
+    if encoded.to_lowercase() != encoded && encoded.to_uppercase() != encoded {
+        return Err(Bech32Error::MixedCase);
+    }
+
+    let encoded = encoded.to_lowercase();
+    let separator = encoded.rfind('1').ok_or(Bech32Error::MissingSeparator)?;
+
+    let (hrp, data_part) = (&encoded[..separator], &encoded[separator + 1..]);
+    if hrp.is_empty() || hrp.bytes().any(|c| !(33..=126).contains(&c)) {
+        return Err(Bech32Error::InvalidHrp);
+    }
+    if data_part.len() < 6 {
+        return Err(Bech32Error::InvalidLength);
+    }
+
+    let mut data = Vec::new();
+    for c in data_part.chars() {
+        let value = CHARSET.find(c).ok_or(Bech32Error::InvalidCharacter(c))?;
+        data.push(value as u8);
+    }
+
+    let variant = verify_checksum(hrp, &data).ok_or(Bech32Error::InvalidChecksum)?;
+
+    data.truncate(data.len() - 6);
+    Ok((hrp.to_string(), data, variant))
+}
+
+/// Encodes a witness program as a segwit address, e.g. `bc1q...` for P2WPKH and P2WSH or `bc1p...` for P2TR.
+pub fn encode_segwit_address(hrp: &str, version: u8, program: &[u8]) -> Result<String, Bech32Error> {
+    validate_witness_program(version, program)?;
+
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true)?);
+
+    Ok(bech32_encode(hrp, &data, Variant::for_witness_version(version)))
+}
+
+/// Decodes a segwit address for the expected human readable part.
+/// Returns the witness version and the witness program.
+pub fn decode_segwit_address(hrp: &str, address: &str) -> Result<(u8, Vec<u8>), Bech32Error> {
+    let (decoded_hrp, data, variant) = bech32_decode(address)?;
+    if decoded_hrp != hrp {
+        return Err(Bech32Error::HrpMismatch);
+    }
+    if data.is_empty() {
+        return Err(Bech32Error::InvalidLength);
+    }
+
+    let version = data[0];
+    if version <= 16 && variant != Variant::for_witness_version(version) {
+        return Err(Bech32Error::InvalidVariant);
+    }
+    let program = convert_bits(&data[1..], 5, 8, false)?;
+    validate_witness_program(version, &program)?;
+
+    Ok((version, program))
+}
+
+fn validate_witness_program(version: u8, program: &[u8]) -> Result<(), Bech32Error> {
+    if version > 16 {
+        return Err(Bech32Error::InvalidWitnessVersion(version));
+    }
+    if program.len() < 2 || program.len() > 40 {
+        return Err(Bech32Error::InvalidProgramLength(program.len()));
+    }
+    // version 0 programs are either a 20-byte key hash or a 32-byte script hash
+    if version == 0 && program.len() != 20 && program.len() != 32 {
+        return Err(Bech32Error::InvalidProgramLength(program.len()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bech32_checksum() {
+        let valid = [
+            "A12UEL5L",
+            "an83characterlonghumanreadablepartthatcontainsthenumber1andtheexcludedcharactersbio1tt5tgs",
+            "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw",
+            "split1checkupstagehandshakeupstreamerranterredcaperred2y9e3w",
+        ];
+
+        for encoded in valid {
+            let (hrp, data, variant) = bech32_decode(encoded).unwrap();
+            assert_eq!(variant, Variant::Bech32);
+            assert_eq!(bech32_encode(&hrp, &data, variant), encoded.to_lowercase());
+        }
+    }
+
+    #[test]
+    fn test_bech32m_checksum() {
+        let valid = [
+            "A1LQFN3A",
+            "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+            "split1checkupstagehandshakeupstreamerranterredcaperredlc445v",
+        ];
+
+        for encoded in valid {
+            let (hrp, data, variant) = bech32_decode(encoded).unwrap();
+            assert_eq!(variant, Variant::Bech32m);
+            assert_eq!(bech32_encode(&hrp, &data, variant), encoded.to_lowercase());
+        }
+    }
+
+    #[test]
+    fn test_bech32m_segwit_address() {
+        let address = "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0";
+        let (version, program) = decode_segwit_address("bc", address).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(hex::encode(&program), "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        assert_eq!(encode_segwit_address("bc", version, &program).unwrap(), address);
+
+        let (version, program) = decode_segwit_address("bc", "BC1SW50QGDZ25J").unwrap();
+        assert_eq!(version, 16);
+        assert_eq!(hex::encode(&program), "751e");
+        assert_eq!(encode_segwit_address("bc", version, &program).unwrap(), "bc1sw50qgdz25j");
+    }
+
+    #[test]
+    fn test_bech32m_wrong_variant() {
+        // version 0 with a bech32m checksum
+        assert_eq!(
+            decode_segwit_address("bc", "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh"),
+            Err(Bech32Error::InvalidVariant)
+        );
+        assert_eq!(
+            decode_segwit_address("tb", "tb1q0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq24jc47"),
+            Err(Bech32Error::InvalidVariant)
+        );
+        // version 1 and 2 with a bech32 checksum
+        assert_eq!(
+            decode_segwit_address("bc", "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd"),
+            Err(Bech32Error::InvalidVariant)
+        );
+        assert_eq!(
+            decode_segwit_address("bc", "bc1zw508d6qejxtdg4y5r3zarvaryvg6kdaj"),
+            Err(Bech32Error::InvalidVariant)
+        );
+    }
+
+    #[test]
+    fn test_bech32_invalid() {
+        assert_eq!(bech32_decode("pzry9x0s0muk"), Err(Bech32Error::MissingSeparator));
+        assert_eq!(bech32_decode("1pzry9x0s0muk"), Err(Bech32Error::InvalidHrp));
+        assert_eq!(bech32_decode("x1b4n0q5v"), Err(Bech32Error::InvalidCharacter('b')));
+        assert_eq!(bech32_decode("li1dgmt3"), Err(Bech32Error::InvalidLength));
+        assert_eq!(bech32_decode("A1G7SGD8"), Err(Bech32Error::InvalidChecksum));
+        assert_eq!(bech32_decode("a12UEL5L"), Err(Bech32Error::MixedCase));
+    }
+
+    #[test]
+    fn test_segwit_address() {
+        let (version, program) = decode_segwit_address("bc", "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4").unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(hex::encode(&program), "751e76e8199196d454941c45d1b3a323f1433bd6");
+        assert_eq!(
+            encode_segwit_address("bc", version, &program).unwrap(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+
+        let address = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+        let (version, program) = decode_segwit_address("tb", address).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(hex::encode(&program), "1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262");
+        assert_eq!(encode_segwit_address("tb", version, &program).unwrap(), address);
+    }
+
+    #[test]
+    fn test_segwit_address_invalid() {
+        assert_eq!(
+            decode_segwit_address("bc", "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"),
+            Err(Bech32Error::HrpMismatch)
+        );
+        // version 0 with a 16 byte program
+        assert_eq!(
+            decode_segwit_address("bc", "BC1QR508D6QEJXTDG4Y5R3ZARVARYV98GJ9P"),
+            Err(Bech32Error::InvalidProgramLength(16))
+        );
+        // zero padding of more than 4 bits
+        assert_eq!(
+            decode_segwit_address("tb", "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3pjxtptv"),
+            Err(Bech32Error::InvalidPadding)
+        );
+        assert_eq!(
+            encode_segwit_address("bc", 17, &[0; 20]),
+            Err(Bech32Error::InvalidWitnessVersion(17))
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use std::io::{self, Cursor, Read, Write};
+
+use finite_fields::FieldElement;
+use rug::{integer::Order, ops::Pow, Integer};
+
+use crate::{traits::Serializer, EllipticCurve};
+
+/// Types with a Bitcoin consensus serialization, written to any stream.
+pub trait ConsensusEncode {
+    /// Writes the serialization to the writer, returning the number of bytes written
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize>;
+
+    /// The serialization as a byte vector
+    fn consensus_serialize(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.consensus_encode(&mut bytes).expect("writing to a Vec cannot fail");
+        bytes
+    }
+}
+
+/// Types that can be read back from their Bitcoin consensus serialization.
+pub trait ConsensusDecode: Sized {
+    /// Reads a value from the reader, consuming exactly its bytes
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self>;
+
+    /// Decodes a value that must take up the whole slice
+    fn consensus_deserialize(bytes: &[u8]) -> io::Result<Self> {
+        let mut cursor = Cursor::new(bytes);
+        let value = Self::consensus_decode(&mut cursor)?;
+
+        if cursor.position() as usize != bytes.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "data left over after decoding"));
+        }
+        Ok(value)
+    }
+}
+
+macro_rules! impl_int_encoding {
+    ($($int:ty),*) => {$(
+        impl ConsensusEncode for $int {
+            fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+                writer.write_all(&self.to_le_bytes())?;
+                Ok(std::mem::size_of::<$int>())
+            }
+        }
+
+        impl ConsensusDecode for $int {
+            fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+                let mut bytes = [0u8; std::mem::size_of::<$int>()];
+                reader.read_exact(&mut bytes)?;
+                Ok(<$int>::from_le_bytes(bytes))
+            }
+        }
+    )*};
+}
+
+// integers are serialized in little endian
+impl_int_encoding!(u8, u16, u32, u64, i32, i64);
+
+/// A variable length integer, used for counts and lengths.
+/// Values below 0xfd take a single byte, larger values are prefixed with 0xfd, 0xfe or 0xff
+/// followed by 2, 4 or 8 little-endian bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VarInt(pub u64);
+
+impl ConsensusEncode for VarInt {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        match self.0 {
+            0..=0xfc => (self.0 as u8).consensus_encode(writer),
+            0xfd..=0xffff => Ok(0xfdu8.consensus_encode(writer)? + (self.0 as u16).consensus_encode(writer)?),
+            0x10000..=0xffffffff => Ok(0xfeu8.consensus_encode(writer)? + (self.0 as u32).consensus_encode(writer)?),
+            _ => Ok(0xffu8.consensus_encode(writer)? + self.0.consensus_encode(writer)?),
+        }
+    }
+}
+
+impl ConsensusDecode for VarInt {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let value = match u8::consensus_decode(reader)? {
+            0xfd => u16::consensus_decode(reader)? as u64,
+            0xfe => u32::consensus_decode(reader)? as u64,
+            0xff => u64::consensus_decode(reader)?,
+            value => value as u64,
+        };
+        Ok(VarInt(value))
+    }
+}
+
+/// Fixed size byte arrays (hashes, chain codes, magic bytes) are written as they are
+impl<const N: usize> ConsensusEncode for [u8; N] {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(self)?;
+        Ok(N)
+    }
+}
+
+impl<const N: usize> ConsensusDecode for [u8; N] {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut bytes = [0u8; N];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Vectors are prefixed with their item count as a varint
+impl<T: ConsensusEncode> ConsensusEncode for Vec<T> {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut written = VarInt(self.len() as u64).consensus_encode(writer)?;
+        for item in self {
+            written += item.consensus_encode(writer)?;
+        }
+        Ok(written)
+    }
+}
+
+impl<T: ConsensusDecode> ConsensusDecode for Vec<T> {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let VarInt(count) = VarInt::consensus_decode(reader)?;
+
+        // the count is not trusted for preallocation, the items are read one by one
+        let mut items = vec![];
+        for _ in 0..count {
+            items.push(T::consensus_decode(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+/// Field elements are written as 32-byte big endian numbers, the way they appear in keys and signatures
+impl ConsensusEncode for FieldElement {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        let digits = self.num().to_digits::<u8>(Order::MsfBe);
+        if digits.len() > 32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "field element is larger than 32 bytes"));
+        }
+
+        let mut bytes = [0u8; 32];
+        bytes[32 - digits.len()..].copy_from_slice(&digits);
+        bytes.consensus_encode(writer)
+    }
+}
+
+/// Points are written in compressed SEC format
+impl ConsensusEncode for EllipticCurve {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        self.write_sec(writer, true)?;
+        Ok(33)
+    }
+}
+
+/// Reads a compressed or uncompressed SEC public key on secp256k1
+impl ConsensusDecode for EllipticCurve {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid SEC public key");
+        let prime = Integer::from(2).pow(256) - Integer::from(2).pow(32) - Integer::from(977);
+
+        let prefix = u8::consensus_decode(reader)?;
+        let x = Integer::from_digits(&<[u8; 32]>::consensus_decode(reader)?, Order::MsfBe);
+        if x >= prime {
+            return Err(invalid());
+        }
+
+        let y = match prefix {
+            0x04 => Integer::from_digits(&<[u8; 32]>::consensus_decode(reader)?, Order::MsfBe),
+            0x02 | 0x03 => {
+                // y^2 = x^3 + 7, and since p % 4 == 3 the square root is (x^3 + 7)^((p + 1) / 4)
+                let alpha: Integer = (x.clone().pow(3) + 7) % &prime;
+                let exponent = (prime.clone() + 1) / 4;
+                let beta = alpha.pow_mod(&exponent, &prime).map_err(|_| invalid())?;
+
+                if beta.is_odd() == (prefix == 0x03) {
+                    beta
+                } else {
+                    prime.clone() - beta
+                }
+            }
+            _ => return Err(invalid()),
+        };
+
+        // check the point is on the curve before building it, as the constructor panics otherwise
+        let lhs = y.clone().pow_mod(&Integer::from(2), &prime).map_err(|_| invalid())?;
+        let rhs = (x.clone().pow(3) + 7) % &prime;
+        if y >= prime || lhs != rhs {
+            return Err(invalid());
+        }
+
+        Ok(EllipticCurve::secp_point(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::s256_field::secp_generator_point;
+
+    #[test]
+    fn test_int_encoding() {
+        assert_eq!(1u32.consensus_serialize(), vec![0x01, 0x00, 0x00, 0x00]);
+        assert_eq!((-2i64).consensus_serialize(), vec![0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(u16::consensus_deserialize(&[0x34, 0x12]).unwrap(), 0x1234);
+        assert!(u32::consensus_deserialize(&[0x01, 0x00, 0x00]).is_err());
+        assert!(u16::consensus_deserialize(&[0x01, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_varint_encoding() {
+        assert_eq!(VarInt(0xfc).consensus_serialize(), vec![0xfc]);
+        assert_eq!(VarInt(0xfd).consensus_serialize(), vec![0xfd, 0xfd, 0x00]);
+        assert_eq!(VarInt(0x10000).consensus_serialize(), vec![0xfe, 0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(VarInt(u64::MAX).consensus_serialize().len(), 9);
+
+        for value in [0, 0xfc, 0xfd, 0xffff, 0x10000, 0xffffffff, 0x100000000, u64::MAX] {
+            let encoded = VarInt(value).consensus_serialize();
+            assert_eq!(VarInt::consensus_deserialize(&encoded).unwrap(), VarInt(value));
+        }
+    }
+
+    #[test]
+    fn test_vec_encoding() {
+        let items: Vec<u32> = vec![1, 2];
+        let encoded = items.consensus_serialize();
+        assert_eq!(encoded, vec![0x02, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00]);
+        assert_eq!(Vec::<u32>::consensus_deserialize(&encoded).unwrap(), items);
+
+        // a byte vector is a varint length followed by the bytes
+        let script: Vec<u8> = vec![0x76, 0xa9];
+        assert_eq!(script.consensus_serialize(), vec![0x02, 0x76, 0xa9]);
+
+        // a huge count with no data behind it fails instead of allocating
+        assert!(Vec::<u8>::consensus_deserialize(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn test_point_encoding() {
+        let point = secp_generator_point().scalar_mul(Integer::from(5001));
+        let encoded = point.consensus_serialize();
+        assert_eq!(
+            hex::encode(&encoded),
+            "0357a4f368868a8a6d572991e484e664810ff14c05c0fa023275251151fe0e53d1"
+        );
+        assert_eq!(EllipticCurve::consensus_deserialize(&encoded).unwrap(), point);
+
+        let point = secp_generator_point().scalar_mul(Integer::from(5000));
+        let uncompressed = hex::decode(point.sec(false)).unwrap();
+        assert_eq!(EllipticCurve::consensus_deserialize(&uncompressed).unwrap(), point);
+
+        // x = 7 is not on the curve
+        let mut invalid = [0u8; 33];
+        invalid[0] = 0x02;
+        invalid[32] = 0x07;
+        assert!(EllipticCurve::consensus_deserialize(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_field_element_encoding() {
+        let prime = Integer::from(223);
+        let element = FieldElement::new(Integer::from(0xab), prime);
+        let encoded = element.consensus_serialize();
+        assert_eq!(encoded.len(), 32);
+        assert_eq!(encoded[31], 0xab);
+        assert!(encoded[..31].iter().all(|byte| *byte == 0));
+    }
+}
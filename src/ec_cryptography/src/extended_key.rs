@@ -0,0 +1,428 @@
+use std::fmt::{Display, Formatter};
+
+use rug::{integer::Order, ops::Pow, Integer};
+
+use crate::{
+    helper::{from_hex, hash160, hmac_sha512},
+    network::Network,
+    s256_field::{secp_generator_point, to_32_bytes, S256Field},
+    serializer::{decode_base58_checksum, encode_base58_checksum, parse_sec, Base58Error},
+    traits::Serializer,
+    PrivateKey,
+};
+
+/// Length of a serialized extended key before the Base58Check encoding
+const EXTENDED_KEY_LENGTH: usize = 78;
+/// Child numbers from this one on are hardened: derived from the private key, so an extended public key
+/// can't derive them
+pub const HARDENED: u32 = 1 << 31;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExtendedKeyError {
+    Base58(Base58Error),
+    /// The decoded key is not 78 bytes long
+    InvalidLength(usize),
+    /// The version bytes are not a known xpub/xprv/tpub/tprv prefix
+    UnknownVersion([u8; 4]),
+    /// Private key data must be prefixed with a 0x00 byte
+    InvalidPrivateKeyPrefix,
+    /// The private key is not in the range 1..n-1
+    InvalidPrivateKey,
+    /// The public key is not a compressed SEC point on secp256k1
+    InvalidPublicKey,
+    /// A master key (depth 0) must have a zero parent fingerprint and child number
+    InvalidMasterKey,
+    /// The hardened child with this number was asked of a public key
+    HardenedFromPublic(u32),
+    /// The child with this number is not a valid key, which happens for fewer than 1 in 2^127 numbers;
+    /// the next number should be used instead
+    InvalidChild(u32),
+    /// The key is at depth 255, its children's depth doesn't fit in a byte
+    MaxDepth,
+}
+
+impl From<Base58Error> for ExtendedKeyError {
+    fn from(error: Base58Error) -> Self {
+        ExtendedKeyError::Base58(error)
+    }
+}
+
+/// The key held by an extended key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtendedKeyData {
+    /// A 32-byte big endian secret
+    Private([u8; 32]),
+    /// A 33-byte compressed SEC public key
+    Public([u8; 33]),
+}
+
+/// A BIP32 extended key: a key together with the chain code and position needed to derive its children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedKey {
+    pub network: Network,
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: u32,
+    pub chain_code: [u8; 32],
+    pub key: ExtendedKeyData,
+}
+
+impl ExtendedKey {
+    pub fn is_private(&self) -> bool {
+        matches!(self.key, ExtendedKeyData::Private(_))
+    }
+
+    /// The compressed SEC public key, computed from the secret of a private key
+    pub fn public_key(&self) -> [u8; 33] {
+        match &self.key {
+            ExtendedKeyData::Private(secret) => {
+                let key = PrivateKey::new(Integer::from_digits(secret, Order::MsfBe));
+                from_hex(&key.point().sec(true)).unwrap().try_into().unwrap()
+            }
+            ExtendedKeyData::Public(sec) => *sec,
+        }
+    }
+
+    /// The first four bytes of the hash160 of the public key, which the key's children hold as their
+    /// parent fingerprint
+    pub fn fingerprint(&self) -> [u8; 4] {
+        hash160(&self.public_key())[..4].try_into().unwrap()
+    }
+
+    /// The extended public key at the same position, which can derive the public keys of the
+    /// non-hardened children
+    pub fn to_public(&self) -> ExtendedKey {
+        ExtendedKey { key: ExtendedKeyData::Public(self.public_key()), ..self.clone() }
+    }
+
+    /// Derives the child with this number (BIP32's CKDpriv and CKDpub). A private key derives private children,
+    /// hardened ones included; a public key derives the public keys of the non-hardened ones.
+    pub fn derive_child(&self, child_number: u32) -> Result<ExtendedKey, ExtendedKeyError> {
+        let depth = self.depth.checked_add(1).ok_or(ExtendedKeyError::MaxDepth)?;
+        let mut data = match &self.key {
+            ExtendedKeyData::Private(secret) if child_number >= HARDENED => [&[0x00], &secret[..]].concat(),
+            ExtendedKeyData::Public(_) if child_number >= HARDENED => {
+                return Err(ExtendedKeyError::HardenedFromPublic(child_number))
+            }
+            _ => self.public_key().to_vec(),
+        };
+        data.extend_from_slice(&child_number.to_be_bytes());
+
+        let hash = hmac_sha512(&self.chain_code, &data);
+        let tweak = Integer::from_digits(&hash[..32], Order::MsfBe);
+        let order = S256Field::order();
+        if tweak >= order {
+            return Err(ExtendedKeyError::InvalidChild(child_number));
+        }
+
+        let key = match &self.key {
+            ExtendedKeyData::Private(secret) => {
+                let secret = (tweak + Integer::from_digits(secret, Order::MsfBe)) % &order;
+                if secret == 0 {
+                    return Err(ExtendedKeyError::InvalidChild(child_number));
+                }
+                ExtendedKeyData::Private(to_32_bytes(&secret))
+            }
+            ExtendedKeyData::Public(sec) => {
+                let parent = parse_sec(sec).ok_or(ExtendedKeyError::InvalidPublicKey)?;
+                let point = secp_generator_point().scalar_mul(tweak) + parent;
+                if point.x.is_none() {
+                    return Err(ExtendedKeyError::InvalidChild(child_number));
+                }
+                ExtendedKeyData::Public(from_hex(&point.sec(true)).unwrap().try_into().unwrap())
+            }
+        };
+
+        Ok(ExtendedKey {
+            network: self.network,
+            depth,
+            parent_fingerprint: self.fingerprint(),
+            child_number,
+            chain_code: hash[32..].try_into().unwrap(),
+            key,
+        })
+    }
+
+    /// Derives the descendant at the end of a path of child numbers, `m/0h/1` being `[HARDENED, 1]`
+    pub fn derive_path(&self, path: &[u32]) -> Result<ExtendedKey, ExtendedKeyError> {
+        path.iter().try_fold(self.clone(), |key, child_number| key.derive_child(*child_number))
+    }
+
+    /// The 78-byte serialization: version, depth, parent fingerprint, child number, chain code and key data
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(EXTENDED_KEY_LENGTH);
+
+        let version = match self.key {
+            ExtendedKeyData::Private(_) => self.network.xprv_version(),
+            ExtendedKeyData::Public(_) => self.network.xpub_version(),
+        };
+        result.extend_from_slice(&version);
+        result.push(self.depth);
+        result.extend_from_slice(&self.parent_fingerprint);
+        result.extend_from_slice(&self.child_number.to_be_bytes());
+        result.extend_from_slice(&self.chain_code);
+
+        match &self.key {
+            ExtendedKeyData::Private(secret) => {
+                result.push(0x00);
+                result.extend_from_slice(secret);
+            }
+            ExtendedKeyData::Public(sec) => result.extend_from_slice(sec),
+        }
+
+        result
+    }
+
+    /// Parses a Base58Check encoded extended key, validating its version, key data and master key fields.
+    /// Test networks share their version bytes, so those keys are returned as testnet keys.
+    pub fn parse(encoded: &str) -> Result<ExtendedKey, ExtendedKeyError> {
+        let data = decode_base58_checksum(encoded)?;
+        if data.len() != EXTENDED_KEY_LENGTH {
+            return Err(ExtendedKeyError::InvalidLength(data.len()));
+        }
+
+        let version: [u8; 4] = data[0..4].try_into().unwrap();
+        let (network, private) = [Network::Mainnet, Network::Testnet]
+            .into_iter()
+            .find_map(|network| {
+                if version == network.xprv_version() {
+                    Some((network, true))
+                } else if version == network.xpub_version() {
+                    Some((network, false))
+                } else {
+                    None
+                }
+            })
+            .ok_or(ExtendedKeyError::UnknownVersion(version))?;
+
+        let depth = data[4];
+        let parent_fingerprint: [u8; 4] = data[5..9].try_into().unwrap();
+        let child_number = u32::from_be_bytes(data[9..13].try_into().unwrap());
+        let chain_code: [u8; 32] = data[13..45].try_into().unwrap();
+
+        if depth == 0 && (parent_fingerprint != [0; 4] || child_number != 0) {
+            return Err(ExtendedKeyError::InvalidMasterKey);
+        }
+
+        let key = if private {
+            if data[45] != 0x00 {
+                return Err(ExtendedKeyError::InvalidPrivateKeyPrefix);
+            }
+            let secret: [u8; 32] = data[46..].try_into().unwrap();
+            if !is_valid_secret(&secret) {
+                return Err(ExtendedKeyError::InvalidPrivateKey);
+            }
+            ExtendedKeyData::Private(secret)
+        } else {
+            let sec: [u8; 33] = data[45..].try_into().unwrap();
+            if !is_valid_public_key(&sec) {
+                return Err(ExtendedKeyError::InvalidPublicKey);
+            }
+            ExtendedKeyData::Public(sec)
+        };
+
+        Ok(ExtendedKey { network, depth, parent_fingerprint, child_number, chain_code, key })
+    }
+}
+
+impl Display for ExtendedKey {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", encode_base58_checksum(&self.serialize()))
+    }
+}
+
+/// A secret is valid if it is in the range 1..n-1
+fn is_valid_secret(secret: &[u8; 32]) -> bool {
+    let secret = Integer::from_digits(secret, Order::MsfBe);
+    secret != 0 && secret < S256Field::order()
+}
+
+/// A compressed public key is valid if its x coordinate is below p and x^3 + 7 has a square root mod p
+fn is_valid_public_key(sec: &[u8; 33]) -> bool {
+    if sec[0] != 0x02 && sec[0] != 0x03 {
+        return false;
+    }
+
+    let prime = Integer::from(2).pow(256) - Integer::from(2).pow(32) - Integer::from(977);
+    let x = Integer::from_digits(&sec[1..], Order::MsfBe);
+    if x >= prime {
+        return false;
+    }
+
+    let alpha: Integer = (x.pow(3) + 7) % &prime;
+    alpha.legendre(&prime) != -1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER_XPRV: &str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+    const MASTER_XPUB: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+    #[test]
+    fn test_parse_master_keys() {
+        let chain_code = hex::decode("873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508").unwrap();
+
+        let xprv = ExtendedKey::parse(MASTER_XPRV).unwrap();
+        assert_eq!(xprv.network, Network::Mainnet);
+        assert_eq!(xprv.depth, 0);
+        assert_eq!(xprv.parent_fingerprint, [0; 4]);
+        assert_eq!(xprv.child_number, 0);
+        assert_eq!(xprv.chain_code.to_vec(), chain_code);
+        assert_eq!(
+            xprv.key,
+            ExtendedKeyData::Private(
+                hex::decode("e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35")
+                    .unwrap()
+                    .try_into()
+                    .unwrap()
+            )
+        );
+        assert_eq!(xprv.to_string(), MASTER_XPRV);
+
+        let xpub = ExtendedKey::parse(MASTER_XPUB).unwrap();
+        assert!(!xpub.is_private());
+        assert_eq!(xpub.chain_code.to_vec(), chain_code);
+        assert_eq!(
+            xpub.key,
+            ExtendedKeyData::Public(
+                hex::decode("0339a36013301597daef41fbe593a02cc513d0b55527ec2df1050e2e8ff49c85c2")
+                    .unwrap()
+                    .try_into()
+                    .unwrap()
+            )
+        );
+        assert_eq!(xpub.to_string(), MASTER_XPUB);
+    }
+
+    #[test]
+    fn test_serialize_child_keys() {
+        let xprv = ExtendedKey {
+            network: Network::Mainnet,
+            depth: 1,
+            parent_fingerprint: [0x34, 0x42, 0x19, 0x3e],
+            child_number: 0x80000000,
+            chain_code: hex::decode("47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141")
+                .unwrap()
+                .try_into()
+                .unwrap(),
+            key: ExtendedKeyData::Private(
+                hex::decode("edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea")
+                    .unwrap()
+                    .try_into()
+                    .unwrap(),
+            ),
+        };
+        assert_eq!(xprv.serialize().len(), 78);
+        assert_eq!(
+            xprv.to_string(),
+            "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7"
+        );
+
+        let xpub = ExtendedKey {
+            key: ExtendedKeyData::Public(
+                hex::decode("035a784662a4a20a65bf6aab9ae98a6c068a81c52e4b032c0fb5400c706cfccc56")
+                    .unwrap()
+                    .try_into()
+                    .unwrap(),
+            ),
+            ..xprv
+        };
+        let encoded = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+        assert_eq!(xpub.to_string(), encoded);
+        assert_eq!(ExtendedKey::parse(encoded).unwrap(), xpub);
+    }
+
+    #[test]
+    fn test_derive_child() {
+        // BIP32 test vector 1
+        let master = ExtendedKey::parse(MASTER_XPRV).unwrap();
+        assert_eq!(master.to_public().to_string(), MASTER_XPUB);
+        assert_eq!(master.fingerprint(), [0x34, 0x42, 0x19, 0x3e]);
+
+        let child = master.derive_child(HARDENED).unwrap();
+        assert_eq!(
+            child.to_string(),
+            "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7"
+        );
+        let xpub = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+        assert_eq!(child.derive_child(1).unwrap().to_public().to_string(), xpub);
+        // the public key derives the same public child
+        assert_eq!(child.to_public().derive_child(1).unwrap().to_string(), xpub);
+
+        let path = [HARDENED, 1, HARDENED + 2];
+        assert_eq!(
+            master.derive_path(&path).unwrap().to_string(),
+            "xprv9z4pot5VBttmtdRTWfWQmoH1taj2axGVzFqSb8C9xaxKymcFzXBDptWmT7FwuEzG3ryjH4ktypQSAewRiNMjANTtpgP4mLTj34bhnZX7UiM"
+        );
+        assert_eq!(master.derive_path(&[]), Ok(master.clone()));
+
+        let xpub = master.to_public();
+        assert_eq!(xpub.derive_child(HARDENED), Err(ExtendedKeyError::HardenedFromPublic(HARDENED)));
+        assert_eq!(ExtendedKey { depth: 255, ..xpub }.derive_child(0), Err(ExtendedKeyError::MaxDepth));
+    }
+
+    #[test]
+    fn test_testnet_keys() {
+        let tprv = "tprv8ZgxMBicQKsPeDgjzdC36fs6bMjGApWDNLR9erAXMs5skhMv36j9MV5ecvfavji5khqjWaWSFhN3YcCUUdiKH6isR4Pwy3U5y5egddBr16m";
+        let key = ExtendedKey::parse(tprv).unwrap();
+        assert_eq!(key.network, Network::Testnet);
+        assert!(key.is_private());
+        assert_eq!(key.to_string(), tprv);
+
+        let tpub = "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp";
+        let key = ExtendedKey::parse(tpub).unwrap();
+        assert_eq!(key.network, Network::Testnet);
+        assert_eq!(key.to_string(), tpub);
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        // xpub version with private key data
+        assert_eq!(
+            ExtendedKey::parse("xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gYweD1YUMnzkxQw1bm6XhhCCXF5rvDu3SQRW2A1Z5yqnVwyY4cNT"),
+            Err(ExtendedKeyError::InvalidPublicKey)
+        );
+        // xprv version with public key data
+        assert_eq!(
+            ExtendedKey::parse("xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChpzxM5bEu4ku6ynu4tP6GqJ5kziULDsCA7bVctSatEcmUDntDMZ"),
+            Err(ExtendedKeyError::InvalidPrivateKeyPrefix)
+        );
+        // public key with an uncompressed prefix
+        assert_eq!(
+            ExtendedKey::parse("xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ45ycVBsADt89FVXeDkYqbSeZmpjjnJETkyyiMwXokWPisrtUjm"),
+            Err(ExtendedKeyError::InvalidPublicKey)
+        );
+        // x = 7 is not on the curve
+        assert_eq!(
+            ExtendedKey::parse("xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gYym6yCVZtiQKSpLUqpuy2xafsZZR8vydJmD1kZ1yXu2Lp8uNH4N"),
+            Err(ExtendedKeyError::InvalidPublicKey)
+        );
+        // private key of zero, and equal to n
+        assert_eq!(
+            ExtendedKey::parse("xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChijLXZSun8bsGj49MuvWWsqL9fqS5fhiDUkRQvq8cj8L42RGwHP"),
+            Err(ExtendedKeyError::InvalidPrivateKey)
+        );
+        assert_eq!(
+            ExtendedKey::parse("xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkg5hntwdZH6QYdrGVYWUCS2Xv6FCMHoYQZYQDohv67LnGTwiNd"),
+            Err(ExtendedKeyError::InvalidPrivateKey)
+        );
+        // zero depth with a non-zero parent fingerprint
+        assert_eq!(
+            ExtendedKey::parse("xpub661no6RGEX3uL6dc6M8bUbc5Ph2363vCtHUiHCByZhkM3E5Pib2Jqz9jyaf3Y74swPBRK7Bq3jhAsFJYSTjZAtMH88erWK8tisBizhqqxYU"),
+            Err(ExtendedKeyError::InvalidMasterKey)
+        );
+        assert_eq!(
+            ExtendedKey::parse("DMwo58pR1QLEFihHjsV3UitNxKrNqYFsAafRXRqo94qF1BmWg6wrsRU8BcmsjxJqFamqEkwbCN2CVcjoXsYGDajtJrXiPyx6y8NNnWDgT3QAm5U"),
+            Err(ExtendedKeyError::UnknownVersion([0x01, 0x01, 0x01, 0x01]))
+        );
+        assert_eq!(
+            ExtendedKey::parse("Deb7pNXSbX7qSvc2eMjkNYTrggh4pBgYa2QMFjEjj6hUy1i6QK7Zm1qdZkHEwqHpT7WeE6V55dTU8PuuzPAiP8JDwAcsuN3v858r83c7mPeYLX"),
+            Err(ExtendedKeyError::InvalidLength(77))
+        );
+        let mut corrupted = MASTER_XPUB.to_string();
+        corrupted.replace_range(110..111, "9");
+        assert!(matches!(ExtendedKey::parse(&corrupted), Err(ExtendedKeyError::Base58(_))));
+    }
+}
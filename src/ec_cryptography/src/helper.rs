@@ -1,12 +1,576 @@
-use sha256::digest;
+use hex::FromHexError;
+use hmac::{Hmac, Mac};
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256, Sha512};
 
-pub fn double_hash(data: &str) -> Vec<u8> {
-    // first hash round
-    let mut hash = digest(data.as_bytes());
+#[derive(Debug, PartialEq, Eq)]
+pub enum HexError {
+    /// Hex strings encode each byte with two characters
+    OddLength,
+    /// The character at the given index is not a hex digit
+    InvalidCharacter { c: char, index: usize },
+    /// The decoded bytes do not have the expected fixed length
+    InvalidLength { expected: usize, found: usize },
+}
 
-    // second hash round
-    hash = digest(hash);
-    println!("hash: {:?}", hash);
+impl From<FromHexError> for HexError {
+    fn from(error: FromHexError) -> Self {
+        match error {
+            FromHexError::OddLength => HexError::OddLength,
+            FromHexError::InvalidHexCharacter { c, index } => HexError::InvalidCharacter { c, index },
+            // only returned when decoding into a slice, which we never do
+            FromHexError::InvalidStringLength => HexError::OddLength,
+        }
+    }
+}
 
-    hash.into_bytes()
+/// Lowercase hex representation of the bytes
+pub fn to_hex(bytes: &[u8]) -> String {
+    hex::encode(bytes)
+}
+
+/// Decodes a hex string, keeping any leading zero bytes
+pub fn from_hex(data: &str) -> Result<Vec<u8>, HexError> {
+    Ok(hex::decode(data)?)
+}
+
+/// Decodes a hex string that must hold exactly 32 bytes, such as a hash or a txid
+pub fn hex_to_32_bytes(data: &str) -> Result<[u8; 32], HexError> {
+    let bytes = from_hex(data)?;
+    let found = bytes.len();
+    bytes.try_into().map_err(|_| HexError::InvalidLength { expected: 32, found })
+}
+
+/// Declares a 32-byte hash newtype, such as a txid or a block hash.
+///
+/// The bytes are kept in the order the hash produces them, which is also how they appear
+/// inside transactions and blocks. Block explorers and RPCs display them reversed, which is
+/// what `Display` and `FromStr` use.
+#[macro_export]
+macro_rules! hash_newtype {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+        pub struct $name([u8; 32]);
+
+        impl $name {
+            /// Wraps a hash in internal byte order
+            pub fn from_bytes(bytes: [u8; 32]) -> $name {
+                $name(bytes)
+            }
+
+            /// The hash in internal byte order, as it is serialized
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+
+            /// The hash in display byte order
+            pub fn to_display_bytes(&self) -> [u8; 32] {
+                let mut bytes = self.0;
+                bytes.reverse();
+                bytes
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{}", $crate::helper::to_hex(&self.to_display_bytes()))
+            }
+        }
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = $crate::helper::HexError;
+
+            /// Parses a hash in display order, as shown by block explorers
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let mut bytes = $crate::helper::hex_to_32_bytes(s)?;
+                bytes.reverse();
+                Ok($name(bytes))
+            }
+        }
+    };
+}
+
+/// Double sha256 over anything that can be viewed as bytes, so both messages (`&str`)
+/// and binary serializations (`&[u8]`, `Vec<u8>`) can be hashed.
+pub fn double_hash<T: AsRef<[u8]>>(data: T) -> Vec<u8> {
+    hash256(data.as_ref())
+}
+
+/// A single round of sha256 over the raw bytes
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+/// A single round of ripemd160 over the raw bytes
+pub fn ripemd160(data: &[u8]) -> Vec<u8> {
+    Ripemd160::digest(data).to_vec()
+}
+
+/// Applies two rounds of sha256 to the raw bytes, as used for checksums and transaction ids
+pub fn hash256(data: &[u8]) -> Vec<u8> {
+    sha256(&sha256(data))
+}
+
+/// sha256 followed by ripemd160, used to shorten public keys and scripts for addresses
+pub fn hash160(data: &[u8]) -> Vec<u8> {
+    ripemd160(&sha256(data))
+}
+
+/// The first 4 bytes of the hash256 of the data.
+/// Used as the checksum of Base58Check strings and of P2P message payloads.
+pub fn checksum4(data: &[u8]) -> [u8; 4] {
+    let hash = hash256(data);
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PayloadError {
+    /// The data ends before the length, checksum or payload does
+    Incomplete,
+    /// The checksum does not match the payload
+    InvalidChecksum,
+}
+
+/// Frames a payload the way P2P messages carry it: its length as 4 little-endian bytes,
+/// its checksum, then the payload itself.
+pub fn frame_payload(payload: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(payload.len() + 8);
+    framed.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    framed.extend_from_slice(&checksum4(payload));
+    framed.extend_from_slice(payload);
+    framed
+}
+
+/// Reads a framed payload from the start of the data and verifies its checksum.
+/// Returns the payload and the number of bytes it took up, including the length and checksum.
+pub fn parse_framed_payload(data: &[u8]) -> Result<(Vec<u8>, usize), PayloadError> {
+    if data.len() < 8 {
+        return Err(PayloadError::Incomplete);
+    }
+
+    let length = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let payload = data.get(8..8 + length).ok_or(PayloadError::Incomplete)?;
+    if checksum4(payload) != data[4..8] {
+        return Err(PayloadError::InvalidChecksum);
+    }
+
+    Ok((payload.to_vec(), 8 + length))
+}
+
+/// The hash functions a `Hasher` can compute. All of them start with a round of sha256.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashFunction {
+    Sha256,
+    Hash256,
+    Hash160,
+}
+
+/// An incremental hasher with optional domain separation and output truncation.
+///
+/// A tag prefixes the data with sha256(tag) twice, as in BIP340 tagged hashes, so hashes
+/// computed for one purpose can never collide with hashes computed for another.
+#[derive(Clone)]
+pub struct Hasher {
+    function: HashFunction,
+    engine: Sha256,
+    length: Option<usize>,
+    is_empty: bool,
+}
+
+impl Hasher {
+    pub fn new(function: HashFunction) -> Hasher {
+        Hasher { function, engine: Sha256::new(), length: None, is_empty: true }
+    }
+
+    /// A sha256 hasher for the BIP340 tagged hash with the given tag
+    pub fn tagged(tag: &str) -> Hasher {
+        Hasher::new(HashFunction::Sha256).tag(tag)
+    }
+
+    /// Prefixes the data with the tag. Tags must be set before any data is added.
+    pub fn tag(mut self, tag: &str) -> Hasher {
+        assert!(self.is_empty, "The tag must be set before any data is hashed");
+
+        let tag_hash = sha256(tag.as_bytes());
+        self.engine = Sha256::new_with_prefix([tag_hash.as_slice(), &tag_hash].concat());
+        self
+    }
+
+    /// Keeps only the first `length` bytes of the digest
+    pub fn truncate(mut self, length: usize) -> Hasher {
+        self.length = Some(length);
+        self
+    }
+
+    /// Adds more data to the hash
+    pub fn update(&mut self, data: &[u8]) -> &mut Hasher {
+        self.engine.update(data);
+        self.is_empty = false;
+        self
+    }
+
+    /// Adds more data to the hash, for use while building the hasher
+    pub fn chain(mut self, data: &[u8]) -> Hasher {
+        self.update(data);
+        self
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        let first_round = self.engine.finalize().to_vec();
+
+        let mut digest = match self.function {
+            HashFunction::Sha256 => first_round,
+            HashFunction::Hash256 => sha256(&first_round),
+            HashFunction::Hash160 => ripemd160(&first_round),
+        };
+
+        if let Some(length) = self.length {
+            digest.truncate(length);
+        }
+        digest
+    }
+}
+
+/// The BIP340 tagged hash: sha256(sha256(tag) || sha256(tag) || data)
+pub fn tagged_hash(tag: &str, data: &[u8]) -> Vec<u8> {
+    Hasher::tagged(tag).chain(data).finalize()
+}
+
+/// HMAC with sha256 as the hash function (RFC 2104)
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// HMAC with sha512 as the hash function, used by BIP32 key derivation
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// A deterministic random bit generator built on HMAC-SHA256 (NIST SP 800-90A).
+/// Seeded with a private key and message hash, it produces the RFC 6979 signing nonces.
+pub struct HmacDrbg {
+    k: Vec<u8>,
+    v: Vec<u8>,
+}
+
+impl HmacDrbg {
+    pub fn new(seed: &[u8]) -> HmacDrbg {
+        let mut drbg = HmacDrbg { k: vec![0x00; 32], v: vec![0x01; 32] };
+        drbg.update(seed);
+        drbg
+    }
+
+    /// Mixes more data into the internal state
+    pub fn reseed(&mut self, seed: &[u8]) {
+        self.update(seed);
+    }
+
+    /// Produces the next `length` pseudo-random bytes
+    pub fn generate(&mut self, length: usize) -> Vec<u8> {
+        let mut output = Vec::with_capacity(length);
+        while output.len() < length {
+            self.v = hmac_sha256(&self.k, &self.v);
+            output.extend_from_slice(&self.v);
+        }
+        output.truncate(length);
+
+        // move the state forward so the same bytes are never returned twice
+        self.update(&[]);
+        output
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.k = hmac_sha256(&self.k, &[self.v.as_slice(), &[0x00], data].concat());
+        self.v = hmac_sha256(&self.k, &self.v);
+
+        if data.is_empty() {
+            return;
+        }
+
+        self.k = hmac_sha256(&self.k, &[self.v.as_slice(), &[0x01], data].concat());
+        self.v = hmac_sha256(&self.k, &self.v);
+    }
+}
+
+/// 32-bit MurmurHash3 (x86 variant) with a seed, as used by BIP37 bloom filters.
+/// This is not a cryptographic hash; it is only meant to spread items across the filter.
+pub fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+
+    let mut hash = seed;
+    let chunks = data.chunks_exact(4);
+    let tail = chunks.remainder();
+
+    for chunk in chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+        hash ^= k;
+        hash = hash.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+
+    // the remaining 1 to 3 bytes are mixed in without the rotation of the hash
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (i, byte) in tail.iter().enumerate() {
+            k |= (*byte as u32) << (8 * i);
+        }
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+    }
+
+    // finalization mix, forcing all bits of the hash to avalanche
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85ebca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2ae35);
+    hash ^= hash >> 16;
+
+    hash
+}
+
+/// SipHash-2-4 keyed with two 64-bit halves of a 128-bit key, as used by BIP158 compact filters.
+pub fn siphash24(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut v = [
+        k0 ^ 0x736f6d6570736575,
+        k1 ^ 0x646f72616e646f6d,
+        k0 ^ 0x6c7967656e657261,
+        k1 ^ 0x7465646279746573,
+    ];
+
+    let chunks = data.chunks_exact(8);
+    let tail = chunks.remainder();
+
+    for chunk in chunks {
+        let m = u64::from_le_bytes(chunk.try_into().unwrap());
+        v[3] ^= m;
+        sip_round(&mut v);
+        sip_round(&mut v);
+        v[0] ^= m;
+    }
+
+    // the last block holds the remaining bytes, with the message length in the top byte
+    let mut last = (data.len() as u64) << 56;
+    for (i, byte) in tail.iter().enumerate() {
+        last |= (*byte as u64) << (8 * i);
+    }
+    v[3] ^= last;
+    sip_round(&mut v);
+    sip_round(&mut v);
+    v[0] ^= last;
+
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        sip_round(&mut v);
+    }
+
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(13) ^ v[0];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(16) ^ v[2];
+    v[0] = v[0].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(21) ^ v[0];
+    v[2] = v[2].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(17) ^ v[2];
+    v[2] = v[2].rotate_left(32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(from_hex("00ff10").unwrap(), vec![0x00, 0xff, 0x10]);
+        assert_eq!(to_hex(&[0x00, 0xff, 0x10]), "00ff10");
+        assert_eq!(from_hex("").unwrap(), Vec::<u8>::new());
+
+        assert_eq!(from_hex("abc"), Err(HexError::OddLength));
+        assert_eq!(from_hex("0g"), Err(HexError::InvalidCharacter { c: 'g', index: 1 }));
+    }
+
+    #[test]
+    fn test_hex_to_32_bytes() {
+        let hash = "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81";
+        let bytes = hex_to_32_bytes(hash).unwrap();
+        assert_eq!(bytes[0], 0xd1);
+        assert_eq!(to_hex(&bytes), hash);
+
+        assert_eq!(hex_to_32_bytes("00ff"), Err(HexError::InvalidLength { expected: 32, found: 2 }));
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            hex::encode(sha256(b"hello world")),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(
+            hex::encode(hash256(b"hello world")),
+            "bc62d4b80d9e36da29c16c5d4d9f11731f36052c72401a76c23c0fb5a9b74423"
+        );
+    }
+
+    #[test]
+    fn test_double_hash() {
+        let expected = "bc62d4b80d9e36da29c16c5d4d9f11731f36052c72401a76c23c0fb5a9b74423";
+        assert_eq!(hex::encode(double_hash("hello world")), expected);
+        assert_eq!(hex::encode(double_hash(b"hello world")), expected);
+        assert_eq!(hex::encode(double_hash(b"hello world".to_vec())), expected);
+
+        // binary data that is not valid utf-8
+        let raw = hex::decode("0100000001ff00").unwrap();
+        assert_eq!(double_hash(&raw), hash256(&raw));
+    }
+
+    #[test]
+    fn test_hmac() {
+        // RFC 4231 test cases 1 and 2
+        let key = [0x0b; 20];
+        assert_eq!(
+            to_hex(&hmac_sha256(&key, b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            to_hex(&hmac_sha512(&key, b"Hi There")),
+            "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cdedaa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854"
+        );
+        assert_eq!(
+            to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_hmac_drbg() {
+        // RFC 6979 nonce for private key 1 signing sha256("Satoshi Nakamoto") on secp256k1
+        let mut seed = vec![0u8; 31];
+        seed.push(1);
+        seed.extend(sha256(b"Satoshi Nakamoto"));
+
+        let mut drbg = HmacDrbg::new(&seed);
+        assert_eq!(
+            to_hex(&drbg.generate(32)),
+            "8f8a276c19f4149656b280621e358cce24f5f52542772691ee69063b74f15d15"
+        );
+        assert_eq!(
+            to_hex(&drbg.generate(32)),
+            "f15fb763a6bcbbacbde0a6a9ae2a02482bd92f3e75a50b357bd551ddd771045e"
+        );
+    }
+
+    #[test]
+    fn test_murmur3() {
+        // (expected, seed, data) vectors from Bitcoin Core's hash tests
+        let vectors = [
+            (0x00000000, 0x00000000, ""),
+            (0x6a396f08, 0xfba4c795, ""),
+            (0x81f16f39, 0xffffffff, ""),
+            (0x514e28b7, 0x00000000, "00"),
+            (0xea3f0b17, 0xfba4c795, "00"),
+            (0xfd6cf10d, 0x00000000, "ff"),
+            (0x16c6b7ab, 0x00000000, "0011"),
+            (0x8eb51c3d, 0x00000000, "001122"),
+            (0xb4471bf8, 0x00000000, "00112233"),
+            (0xe2301fa8, 0x00000000, "0011223344"),
+            (0xfc2e4a15, 0x00000000, "001122334455"),
+            (0xb074502c, 0x00000000, "00112233445566"),
+            (0x8034d2a0, 0x00000000, "0011223344556677"),
+            (0xb4698def, 0x00000000, "001122334455667788"),
+        ];
+
+        for (expected, seed, data) in vectors {
+            assert_eq!(murmur3_32(&from_hex(data).unwrap(), seed), expected, "murmur3 of {}", data);
+        }
+    }
+
+    #[test]
+    fn test_siphash24() {
+        let k0 = 0x0706050403020100;
+        let k1 = 0x0f0e0d0c0b0a0908;
+
+        assert_eq!(siphash24(k0, k1, &[]), 0x726fdb47dd0e0e31);
+        assert_eq!(siphash24(k0, k1, &[0x00]), 0x74f839c593dc67fd);
+        assert_eq!(siphash24(k0, k1, &[0, 1, 2, 3, 4, 5, 6, 7]), 0x93f5f5799a932462);
+
+        // the example from the SipHash paper, a 15 byte message
+        let message: Vec<u8> = (0..15).collect();
+        assert_eq!(siphash24(k0, k1, &message), 0xa129ca6149be45e5);
+    }
+
+    #[test]
+    fn test_checksum4() {
+        // the checksum of an empty payload, carried by every verack message
+        assert_eq!(checksum4(&[]), [0x5d, 0xf6, 0xe0, 0xe2]);
+        assert_eq!(checksum4(b"hello").to_vec(), hash256(b"hello")[..4].to_vec());
+    }
+
+    #[test]
+    fn test_framed_payload() {
+        assert_eq!(frame_payload(&[]), vec![0x00, 0x00, 0x00, 0x00, 0x5d, 0xf6, 0xe0, 0xe2]);
+
+        let mut framed = frame_payload(b"hello");
+        assert_eq!(framed.len(), 13);
+        // anything after the payload belongs to the next message
+        framed.push(0xff);
+        assert_eq!(parse_framed_payload(&framed), Ok((b"hello".to_vec(), 13)));
+
+        assert_eq!(parse_framed_payload(&framed[..12]), Err(PayloadError::Incomplete));
+        assert_eq!(parse_framed_payload(&framed[..7]), Err(PayloadError::Incomplete));
+
+        framed[4] ^= 0x01;
+        assert_eq!(parse_framed_payload(&framed), Err(PayloadError::InvalidChecksum));
+    }
+
+    #[test]
+    fn test_hasher() {
+        assert_eq!(Hasher::new(HashFunction::Hash256).chain(b"hello").finalize(), hash256(b"hello"));
+        assert_eq!(Hasher::new(HashFunction::Hash160).chain(b"hello").finalize(), hash160(b"hello"));
+
+        // incremental updates give the same digest as hashing everything at once
+        let mut hasher = Hasher::new(HashFunction::Sha256);
+        hasher.update(b"hello ").update(b"world");
+        assert_eq!(hasher.finalize(), sha256(b"hello world"));
+
+        let truncated = Hasher::new(HashFunction::Hash256).truncate(4).chain(b"hello").finalize();
+        assert_eq!(truncated, hash256(b"hello")[..4].to_vec());
+    }
+
+    #[test]
+    fn test_tagged_hash() {
+        // the leaf hash of a tapscript made of OP_TRUE
+        assert_eq!(
+            to_hex(&tagged_hash("TapLeaf", &[0xc0, 0x01, 0x51])),
+            "a85b2107f791b26a84e7586c28cec7cb61202ed3d01944d832500f363782d675"
+        );
+        assert_eq!(
+            to_hex(&Hasher::tagged("BIP0340/challenge").finalize()),
+            "c216d352f5818b7b4beacd4ae0a26fe888080823d2a598856661bcd54f1b3713"
+        );
+        assert_ne!(tagged_hash("TapLeaf", b"data"), tagged_hash("TapBranch", b"data"));
+    }
+
+    #[test]
+    fn test_hash160() {
+        assert_eq!(hex::encode(ripemd160(b"hello world")), "98c615784ccb5fe5936fbc0cbe9dfdb408d92f0f");
+        assert_eq!(hex::encode(hash160(b"hello world")), "d7d5ee7824ff93f94c3055af9382c86c68b5ca92");
+    }
 }
\ No newline at end of file
@@ -4,9 +4,15 @@ use rug::{integer::Order, ops::{Pow, RemRounding}, Integer};
 mod s256_field;
 pub mod traits;
 
+pub mod address;
+pub mod bech32;
+pub mod consensus;
+pub mod extended_key;
 pub mod helper;
+pub mod network;
 pub mod serializer;
 
+pub use s256_field::{tap_tweak_public_key, verify_schnorr, PrivateKey, Signature};
 use traits::Serializer;
 use finite_fields::FieldElement;
 
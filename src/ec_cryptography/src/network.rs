@@ -0,0 +1,108 @@
+/// The Bitcoin network a key, address or message belongs to.
+/// Each network uses its own prefixes so that data meant for one cannot be mistaken for another.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    /// Base58Check version byte of P2PKH addresses
+    pub fn p2pkh_version(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            _ => 0x6f,
+        }
+    }
+
+    /// Base58Check version byte of P2SH addresses
+    pub fn p2sh_version(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x05,
+            _ => 0xc4,
+        }
+    }
+
+    /// Base58Check version byte of WIF encoded private keys
+    pub fn wif_prefix(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x80,
+            _ => 0xef,
+        }
+    }
+
+    /// Human readable part of segwit addresses
+    pub fn bech32_hrp(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "bc",
+            Network::Testnet | Network::Signet => "tb",
+            Network::Regtest => "bcrt",
+        }
+    }
+
+    /// Version bytes of extended public keys (xpub/tpub)
+    pub fn xpub_version(&self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => [0x04, 0x88, 0xb2, 0x1e],
+            _ => [0x04, 0x35, 0x87, 0xcf],
+        }
+    }
+
+    /// Version bytes of extended private keys (xprv/tprv)
+    pub fn xprv_version(&self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => [0x04, 0x88, 0xad, 0xe4],
+            _ => [0x04, 0x35, 0x83, 0x94],
+        }
+    }
+
+    /// The magic bytes that start every P2P network message
+    pub fn magic(&self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => [0xf9, 0xbe, 0xb4, 0xd9],
+            Network::Testnet => [0x0b, 0x11, 0x09, 0x07],
+            Network::Signet => [0x0a, 0x03, 0xcf, 0x40],
+            Network::Regtest => [0xfa, 0xbf, 0xb5, 0xda],
+        }
+    }
+
+    /// Finds the network using the given segwit human readable part.
+    /// Testnet and signet share "tb", in which case testnet is returned.
+    pub fn from_bech32_hrp(hrp: &str) -> Option<Network> {
+        match hrp {
+            "bc" => Some(Network::Mainnet),
+            "tb" => Some(Network::Testnet),
+            "bcrt" => Some(Network::Regtest),
+            _ => None,
+        }
+    }
+
+    /// Whether this is one of the test networks, which share their Base58 prefixes
+    pub fn is_testnet(&self) -> bool {
+        *self != Network::Mainnet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_prefixes() {
+        assert_eq!(Network::Mainnet.p2pkh_version(), 0x00);
+        assert_eq!(Network::Testnet.p2sh_version(), 0xc4);
+        assert_eq!(Network::Signet.wif_prefix(), 0xef);
+        assert_eq!(Network::Regtest.bech32_hrp(), "bcrt");
+        assert_eq!(Network::Signet.bech32_hrp(), "tb");
+        assert_eq!(Network::Mainnet.magic(), [0xf9, 0xbe, 0xb4, 0xd9]);
+        assert_eq!(Network::Testnet.magic(), [0x0b, 0x11, 0x09, 0x07]);
+
+        for network in [Network::Mainnet, Network::Testnet, Network::Regtest] {
+            assert_eq!(Network::from_bech32_hrp(network.bech32_hrp()), Some(network));
+        }
+        assert_eq!(Network::from_bech32_hrp("ltc"), None);
+    }
+}
@@ -4,7 +4,7 @@ use rug::{Integer, Complete};
 use rug::ops::Pow;
 use std::fmt::{Debug, Formatter};
 
-use crate::EllipticCurve;
+use crate::{helper::{tagged_hash, to_hex, HmacDrbg}, EllipticCurve};
 
 pub struct S256Field {
     x: Option<FieldElement>,
@@ -89,22 +89,25 @@ impl Signature {
         Signature { r, s }
     }
 
-    /// This is the Distingished Encoding Rule for encoding Signatures
+    /// This is the Distingished Encoding Rule for encoding Signatures:
+    /// 0x30, the length of the rest, then r and s each as 0x02, a length and the big endian number
     pub fn der(&self) -> String {
-        let prefix = "30";
-
-        // calculate the length of the signature
-        let signature_length = self.length();
-        println!("Signature length: {}", signature_length);
+        to_hex(&self.der_bytes())
+    }
 
+    pub fn der_bytes(&self) -> Vec<u8> {
         let (r_length, r) = self.der_integer_length(self.r.to_digits::<u8>(Order::MsfBe));
         let (s_length, s) = self.der_integer_length(self.s.to_digits::<u8>(Order::MsfBe));
-        println!("r length: {}", r_length);
-        println!("s length: {}", s_length);
 
-        prefix.to_string()
+        let mut der = vec![0x30, (self.length() - 2) as u8];
+        der.extend_from_slice(&[0x02, r_length as u8]);
+        der.extend_from_slice(&r);
+        der.extend_from_slice(&[0x02, s_length as u8]);
+        der.extend_from_slice(&s);
+        der
     }
 
+    /// The length of the DER encoding
     pub fn length(&self) -> usize {
         let r = self.r.to_digits::<u8>(Order::MsfBe);
         let s = self.s.to_digits::<u8>(Order::MsfBe);
@@ -112,26 +115,277 @@ impl Signature {
         let r_len = self.der_integer_length(r);
         let s_len = self.der_integer_length(s);
 
-        1 + 1 + r_len.0 + s_len.0
+        // each integer has a marker and a length byte
+        1 + 1 + (2 + r_len.0) + (2 + s_len.0)
     }
 
+    /// Parses a DER signature (without a hash type byte). Only the strict encoding of BIP66 is accepted:
+    /// exact lengths, and positive integers without padding they don't need.
+    pub fn parse_der(der: &[u8]) -> Option<Signature> {
+        let [0x30, length, 0x02, r_length, rest @ ..] = der else {
+            return None;
+        };
+        if *length as usize != der.len() - 2 || rest.len() < *r_length as usize {
+            return None;
+        }
+        let (r, rest) = rest.split_at(*r_length as usize);
+        let [0x02, s_length, s @ ..] = rest else {
+            return None;
+        };
+        if *s_length as usize != s.len() {
+            return None;
+        }
+
+        // positive, and padded with a zero only when the next byte has its high bit set
+        let is_strict_integer = |integer: &[u8]| match integer {
+            [] => false,
+            [first, ..] if first & 0x80 != 0 => false,
+            [0x00, next, ..] => next & 0x80 != 0,
+            _ => true,
+        };
+        if !is_strict_integer(r) || !is_strict_integer(s) {
+            return None;
+        }
+
+        Some(Signature::new(Integer::from_digits(r, Order::MsfBe), Integer::from_digits(s, Order::MsfBe)))
+    }
+
+    /// Whether this is the signature of the hash z by the public key: with u = z / s and v = r / s,
+    /// uG + vP must have r as its x coordinate
+    pub fn verify(&self, public_key: &EllipticCurve, z: Integer) -> bool {
+        let order = S256Field::order();
+        if self.r <= 0 || self.r >= order || self.s <= 0 || self.s >= order {
+            return false;
+        }
+
+        let s = FieldElement::new(self.s.clone(), order.clone());
+        let u = FieldElement::new(z % &order, order.clone()) / s.clone();
+        let v = FieldElement::new(self.r.clone(), order) / s;
+
+        let total = secp_generator_point().scalar_mul(u.num()) + public_key.scalar_mul(v.num());
+        total.x.is_some_and(|x| x.num() == self.r)
+    }
+
+    /// DER integers are signed, so a number with its high bit set gets a leading zero byte
     pub fn der_integer_length(&self, mut data: Vec<u8>) -> (usize, Vec<u8>) {
-        let mut length = data.len();
-        if data[0] & 0x80 >= 0x80 {
-            println!("Data: {:?}", data);
-            data.insert(0, 00);
-            length += 1;
+        if !matches!(data.first(), Some(byte) if byte & 0x80 == 0) {
+            data.insert(0, 0x00);
+        }
+
+        (data.len(), data)
+    }
+}
+
+/// A secp256k1 private key: a secret number e in 1..n, whose public key is the point eG
+pub struct PrivateKey {
+    secret: Integer,
+    point: EllipticCurve,
+}
+
+impl Debug for PrivateKey {
+    /// The secret is left out so it doesn't end up in logs
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "PrivateKey({:?})", self.point)
+    }
+}
+
+impl PrivateKey {
+    pub fn new(secret: Integer) -> PrivateKey {
+        assert!(secret > 0 && secret < S256Field::order(), "The secret must be between 1 and n - 1");
+
+        let point = secp_generator_point().scalar_mul(secret.clone());
+        PrivateKey { secret, point }
+    }
+
+    /// The public key
+    pub fn point(&self) -> &EllipticCurve {
+        &self.point
+    }
+
+    /// Signs the hash z: with R = kG and r = R.x, the signature is (r, (z + re) / k).
+    /// The nonce k comes from RFC 6979, and s is kept in the lower half of the order
+    /// as the standardness rules require.
+    pub fn sign(&self, z: Integer) -> Signature {
+        let order = Self::order();
+        let k = self.deterministic_k(z.clone());
+        let r = secp_generator_point().scalar_mul(k.clone()).x.unwrap().num();
+
+        let z = FieldElement::new(z, order.clone());
+        let r_element = FieldElement::new(r.clone(), order.clone());
+        let e = FieldElement::new(self.secret.clone(), order.clone());
+        let k = FieldElement::new(k, order.clone());
+
+        let mut s = ((z + r_element * e) / k).num();
+        if s > order.clone() / 2 {
+            s = order - s;
         }
 
-        (length, data)
+        Signature::new(r, s)
+    }
+
+    /// The x coordinate of the public key: BIP340 keys leave out the y coordinate and take the even one
+    pub fn x_only_public_key(&self) -> [u8; 32] {
+        to_32_bytes(&self.point.x.clone().unwrap().num())
+    }
+
+    /// Signs the 32-byte message with a BIP340 Schnorr signature, R.x followed by s = k + ed.
+    /// The secret and the nonce are negated when their points have an odd y, as only even ones
+    /// can be recovered from an x coordinate. `aux_rand` is mixed into the nonce: fresh randomness
+    /// protects against side channels, but all zeros still gives a valid, deterministic signature.
+    pub fn sign_schnorr(&self, message: &[u8; 32], aux_rand: &[u8; 32]) -> [u8; 64] {
+        let order = Self::order();
+        let secret = self.even_y_secret();
+        let public_key = self.x_only_public_key();
+
+        let masked: Vec<u8> = to_32_bytes(&secret)
+            .iter()
+            .zip(tagged_hash("BIP0340/aux", aux_rand))
+            .map(|(byte, mask)| byte ^ mask)
+            .collect();
+        let nonce_hash = tagged_hash("BIP0340/nonce", &[masked.as_slice(), &public_key, message].concat());
+        let k = Integer::from_digits(&nonce_hash, Order::MsfBe) % &order;
+        assert!(k != 0, "The nonce must not be zero");
+
+        let nonce_point = secp_generator_point().scalar_mul(k.clone());
+        let k = if has_even_y(&nonce_point) { k } else { order.clone() - k };
+        let r = to_32_bytes(&nonce_point.x.unwrap().num());
+
+        let challenge = tagged_hash("BIP0340/challenge", &[r.as_slice(), &public_key, message].concat());
+        let e = Integer::from_digits(&challenge, Order::MsfBe) % &order;
+        let s = (k + e * secret) % &order;
+
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&r);
+        signature[32..].copy_from_slice(&to_32_bytes(&s));
+        signature
+    }
+
+    /// The key of a taproot output whose internal key is this one (BIP341): the secret plus
+    /// the hash of the x-only public key and the root of the script tree. BIP86 wallets
+    /// commit to no scripts at all, with a `merkle_root` of None.
+    pub fn tap_tweak(&self, merkle_root: Option<&[u8; 32]>) -> PrivateKey {
+        let tweak = tap_tweak_hash(&self.x_only_public_key(), merkle_root);
+        assert!(tweak < Self::order(), "The tweak must be less than the order");
+        PrivateKey::new((self.even_y_secret() + tweak) % Self::order())
+    }
+
+    /// The secret whose point is the even y one sharing this key's x coordinate
+    fn even_y_secret(&self) -> Integer {
+        if has_even_y(&self.point) {
+            self.secret.clone()
+        } else {
+            Self::order() - self.secret.clone()
+        }
+    }
+
+    /// The RFC 6979 nonce: a HMAC-DRBG seeded with the secret and the hash picks k,
+    /// so signing the same hash twice never reuses a k with a different message
+    fn deterministic_k(&self, mut z: Integer) -> Integer {
+        let order = Self::order();
+        if z > order {
+            z -= &order;
+        }
+
+        let seed = [to_32_bytes(&self.secret), to_32_bytes(&z)].concat();
+        let mut drbg = HmacDrbg::new(&seed);
+        loop {
+            let candidate = Integer::from_digits(&drbg.generate(32), Order::MsfBe);
+            if candidate > 0 && candidate < order {
+                return candidate;
+            }
+        }
+    }
+
+    fn order() -> Integer {
+        S256Field::order()
     }
 }
 
+/// Checks a BIP340 Schnorr signature of the 32-byte message by the x-only public key:
+/// with e the challenge hash, sG - eP must be the point R with an even y whose x the signature starts with
+pub fn verify_schnorr(public_key: &[u8; 32], message: &[u8; 32], signature: &[u8; 64]) -> bool {
+    let order = S256Field::order();
+    let Some(point) = lift_x(&Integer::from_digits(public_key, Order::MsfBe)) else {
+        return false;
+    };
+    let r = Integer::from_digits(&signature[..32], Order::MsfBe);
+    let s = Integer::from_digits(&signature[32..], Order::MsfBe);
+    if r >= secp_prime() || s >= order {
+        return false;
+    }
+
+    let challenge = tagged_hash("BIP0340/challenge", &[&signature[..32], public_key, message].concat());
+    let e = Integer::from_digits(&challenge, Order::MsfBe) % &order;
+
+    let minus_e = (order.clone() - e) % &order;
+    let nonce_point = secp_generator_point().scalar_mul(s) + point.scalar_mul(minus_e);
+    nonce_point.x.is_some() && has_even_y(&nonce_point) && nonce_point.x.unwrap().num() == r
+}
+
+/// Tweaks an x-only internal key with the merkle root of its script tree (BIP341), giving the x-only
+/// output key and whether its y is odd, which script path spends reveal. None if the internal key isn't on the curve.
+pub fn tap_tweak_public_key(internal_key: &[u8; 32], merkle_root: Option<&[u8; 32]>) -> Option<([u8; 32], bool)> {
+    let point = lift_x(&Integer::from_digits(internal_key, Order::MsfBe))?;
+    let tweak = tap_tweak_hash(internal_key, merkle_root);
+    if tweak >= S256Field::order() {
+        return None;
+    }
+
+    let output_key = point + secp_generator_point().scalar_mul(tweak);
+    let x = output_key.x.clone()?.num();
+    Some((to_32_bytes(&x), !has_even_y(&output_key)))
+}
+
+/// The TapTweak hash of the internal key and the merkle root, if there is a script tree
+fn tap_tweak_hash(internal_key: &[u8; 32], merkle_root: Option<&[u8; 32]>) -> Integer {
+    let mut data = internal_key.to_vec();
+    if let Some(merkle_root) = merkle_root {
+        data.extend_from_slice(merkle_root);
+    }
+    Integer::from_digits(&tagged_hash("TapTweak", &data), Order::MsfBe)
+}
+
+/// The point with this x coordinate and an even y, if there is one on the curve
+pub(crate) fn lift_x(x: &Integer) -> Option<EllipticCurve> {
+    let prime = secp_prime();
+    if *x >= prime {
+        return None;
+    }
+
+    // p = 3 mod 4, so a square root of c is c^((p + 1) / 4)
+    let c: Integer = (x.clone().pow(3) + 7) % &prime;
+    let exponent = (prime.clone() + 1) / 4;
+    let y = c.clone().pow_mod(&exponent, &prime).ok()?;
+    if (y.clone() * &y) % &prime != c {
+        return None;
+    }
+
+    let y = if y.is_even() { y } else { prime - y };
+    Some(EllipticCurve::secp_point(x.clone(), y))
+}
+
+fn secp_prime() -> Integer {
+    Integer::from(2).pow(256) - Integer::from(2).pow(32) - Integer::from(977)
+}
+
+fn has_even_y(point: &EllipticCurve) -> bool {
+    point.y.clone().unwrap().num().is_even()
+}
+
+pub(crate) fn to_32_bytes(number: &Integer) -> [u8; 32] {
+    let digits = number.to_digits::<u8>(Order::MsfBe);
+    let mut bytes = [0u8; 32];
+    bytes[32 - digits.len()..].copy_from_slice(&digits);
+    bytes
+}
+
 #[cfg(test)]
 mod tests {
     use rug::{integer::Order, Integer};
 
-    use super::Signature;
+    use crate::{helper::{from_hex, hex_to_32_bytes, to_hex}, traits::Serializer};
+
+    use super::{has_even_y, tap_tweak_public_key, verify_schnorr, PrivateKey, Signature};
 
     #[test]
     fn test_der_encryption() {
@@ -143,5 +397,117 @@ mod tests {
         let der_r = signature.der_integer_length(signature.r.to_digits::<u8>(Order::MsfBe));
         println!("DER R length: {}", der_r.0);
         println!("DER R value: {:?}", der_r.1);
+
+        assert_eq!(
+            signature.der(),
+            "3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec"
+        );
+        assert_eq!(signature.length(), 71);
+    }
+
+    #[test]
+    fn test_sign() {
+        let private_key = PrivateKey::new(Integer::from(8675309));
+        assert_eq!(
+            private_key.point().sec(true),
+            "03935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b67"
+        );
+
+        let z = Integer::from_str_radix("e7d1c817898faed0a239969010e7945eae339200624b16e075945d32941d54f6", 16).unwrap();
+        let signature = private_key.sign(z);
+        assert_eq!(
+            signature.der(),
+            "30450221008ed46aa2cf12d6d81065bfabe903670165b538f65ee9a3385e6327d80c66d3b502203124f804410527497329ec4715e18558082d489b218677bd029e7fa306a72236"
+        );
+    }
+
+    #[test]
+    fn test_sign_schnorr() {
+        // the first two BIP340 test vectors
+        let private_key = PrivateKey::new(Integer::from(3));
+        assert_eq!(
+            to_hex(&private_key.x_only_public_key()),
+            "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9"
+        );
+        assert_eq!(
+            to_hex(&private_key.sign_schnorr(&[0; 32], &[0; 32])),
+            "e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0"
+        );
+
+        let secret = Integer::from_str_radix("b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef", 16).unwrap();
+        let private_key = PrivateKey::new(secret);
+        let message = hex_to_32_bytes("243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c89").unwrap();
+        let mut aux_rand = [0; 32];
+        aux_rand[31] = 1;
+        assert_eq!(
+            to_hex(&private_key.sign_schnorr(&message, &aux_rand)),
+            "6896bd60eeae296db48a229ff71dfe071bde413e6d43f917dc8dcf8c78de33418906d11ac976abccb20b091292bff4ea897efcb639ea871cfa95f6de339e4b0a"
+        );
+    }
+
+    #[test]
+    fn test_parse_der_and_verify() {
+        let private_key = PrivateKey::new(Integer::from(8675309));
+        let z = Integer::from_str_radix("e7d1c817898faed0a239969010e7945eae339200624b16e075945d32941d54f6", 16).unwrap();
+        let der = private_key.sign(z.clone()).der_bytes();
+
+        let signature = Signature::parse_der(&der).unwrap();
+        assert_eq!(signature.der_bytes(), der);
+        assert!(signature.verify(private_key.point(), z.clone()));
+        assert!(!signature.verify(private_key.point(), z + 1));
+        assert!(!signature.verify(PrivateKey::new(Integer::from(8675310)).point(), Integer::from(1)));
+
+        // a trailing byte, a wrong length, and r padded with a zero it doesn't need
+        assert!(Signature::parse_der(&[der.as_slice(), &[0x01]].concat()).is_none());
+        let mut wrong_length = der.clone();
+        wrong_length[1] -= 1;
+        assert!(Signature::parse_der(&wrong_length).is_none());
+        let padded = [&[0x30, der[1] + 1, 0x02, der[3] + 1, 0x00], &der[4..]].concat();
+        assert!(Signature::parse_der(&padded).is_none());
+    }
+
+    #[test]
+    fn test_verify_schnorr() {
+        let private_key = PrivateKey::new(Integer::from(3));
+        let message = [0x42; 32];
+        let signature = private_key.sign_schnorr(&message, &[0; 32]);
+        let public_key = private_key.x_only_public_key();
+        assert!(verify_schnorr(&public_key, &message, &signature));
+        assert!(!verify_schnorr(&public_key, &[0x43; 32], &signature));
+
+        // the second BIP340 test vector, whose key has an odd y
+        let public_key = hex_to_32_bytes("dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659").unwrap();
+        let message = hex_to_32_bytes("243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c89").unwrap();
+        let signature: [u8; 64] = from_hex("6896bd60eeae296db48a229ff71dfe071bde413e6d43f917dc8dcf8c78de33418906d11ac976abccb20b091292bff4ea897efcb639ea871cfa95f6de339e4b0a").unwrap().try_into().unwrap();
+        assert!(verify_schnorr(&public_key, &message, &signature));
+
+        let mut tweaked = signature;
+        tweaked[63] ^= 1;
+        assert!(!verify_schnorr(&public_key, &message, &tweaked));
+    }
+
+    #[test]
+    fn test_tap_tweak() {
+        // the first BIP86 key, m/86'/0'/0'/0/0 of the "abandon ... about" mnemonic
+        let secret = Integer::from_str_radix("41f41d69260df4cf277826a9b65a3717e4eeddbeedf637f212ca096576479361", 16).unwrap();
+        let internal_key = PrivateKey::new(secret);
+        assert_eq!(
+            to_hex(&internal_key.x_only_public_key()),
+            "cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115"
+        );
+        assert_eq!(
+            to_hex(&internal_key.tap_tweak(None).x_only_public_key()),
+            "a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"
+        );
+
+        // tweaking the public key gives the public key of the tweaked private key
+        let internal = internal_key.x_only_public_key();
+        for merkle_root in [None, Some([7u8; 32])] {
+            let tweaked = internal_key.tap_tweak(merkle_root.as_ref());
+            let (output_key, is_odd) = tap_tweak_public_key(&internal, merkle_root.as_ref()).unwrap();
+            assert_eq!(output_key, tweaked.x_only_public_key());
+            assert_eq!(is_odd, !has_even_y(tweaked.point()));
+        }
+        assert_eq!(tap_tweak_public_key(&[0xff; 32], None), None);
     }
 }
\ No newline at end of file
@@ -1,6 +1,25 @@
 use rug::{integer::Order, Integer};
 
-use crate::{s256_field::Signature, traits::Serializer, EllipticCurve};
+use crate::{
+    helper::checksum4,
+    network::Network,
+    s256_field::{lift_x, Signature},
+    traits::Serializer,
+    EllipticCurve,
+};
+
+/// The Base58 alphabet used by Bitcoin. It leaves out 0, O, I and l, which are easily confused.
+pub const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Base58Error {
+    /// The string contains a character outside the Base58 alphabet
+    InvalidCharacter(char),
+    /// The decoded data is too short to hold a version byte and a checksum
+    TooShort,
+    /// The last 4 bytes do not match the hash256 of the rest of the data
+    InvalidChecksum,
+}
 
 impl Serializer for EllipticCurve {
     fn sec(&self, is_compressed: bool) -> String {
@@ -22,6 +41,29 @@ impl Serializer for EllipticCurve {
     }
 }
 
+/// Parses a public key in SEC format: 0x04 and both coordinates, or 0x02 or 0x03 (for an even or odd y)
+/// and the x coordinate. Returns None if it's neither, or the point isn't on the curve.
+pub fn parse_sec(sec: &[u8]) -> Option<EllipticCurve> {
+    match sec {
+        [0x04, coordinates @ ..] if coordinates.len() == 64 => {
+            let x = Integer::from_digits(&coordinates[..32], Order::MsfBe);
+            let y = Integer::from_digits(&coordinates[32..], Order::MsfBe);
+            let point = EllipticCurve::secp_point(x, y);
+            point.is_valid().then_some(point)
+        }
+        [prefix @ (0x02 | 0x03), x @ ..] if x.len() == 32 => {
+            let x = Integer::from_digits(x, Order::MsfBe);
+            let even_point = lift_x(&x)?;
+
+            // the other point with this x has p - y, which is odd
+            let y = even_point.y.clone()?.num();
+            let y = if *prefix == 0x02 { y } else { even_point.prime() - y };
+            Some(EllipticCurve::secp_point(x, y))
+        }
+        _ => None,
+    }
+}
+
 fn pad_or_truncate_data(input: String) -> String {
     let mut data = input;
     if data.len() < 64 {
@@ -35,13 +77,188 @@ fn pad_or_truncate_data(input: String) -> String {
     }
 }
 
+/// The standard Base64 alphabet (RFC 4648), used to exchange PSBTs as text
+pub const BASE64_ALPHABET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Base64Error {
+    /// The string contains a character outside the Base64 alphabet
+    InvalidCharacter(char),
+    /// Padded Base64 strings are always a multiple of 4 characters long
+    InvalidLength,
+    /// '=' padding appears somewhere other than the end, or unused bits are not zero
+    InvalidPadding,
+}
+
+/// Encodes bytes in Base64 with '=' padding.
+pub fn encode_base64(data: &[u8]) -> String {
+    let alphabet = BASE64_ALPHABET.as_bytes();
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        // pack up to 3 bytes into 24 bits, then split those into 4 groups of 6 bits
+        let mut group = [0u8; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (bits >> (18 - 6 * i)) & 0x3f;
+                encoded.push(alphabet[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Decodes a padded Base64 string.
+pub fn decode_base64(data: &str) -> Result<Vec<u8>, Base64Error> {
+    if !data.len().is_multiple_of(4) {
+        return Err(Base64Error::InvalidLength);
+    }
+
+    let padding = data.bytes().rev().take_while(|c| *c == b'=').count();
+    if padding > 2 {
+        return Err(Base64Error::InvalidPadding);
+    }
+
+    let mut decoded = Vec::with_capacity(data.len() / 4 * 3);
+    let content = &data[..data.len() - padding];
+
+    for chunk in content.as_bytes().chunks(4) {
+        let mut bits: u32 = 0;
+        for (i, c) in chunk.iter().enumerate() {
+            let index = BASE64_ALPHABET
+                .find(*c as char)
+                .ok_or(if *c == b'=' {
+                    Base64Error::InvalidPadding
+                } else {
+                    Base64Error::InvalidCharacter(*c as char)
+                })?;
+            bits |= (index as u32) << (18 - 6 * i);
+        }
+
+        // a final chunk of 2 or 3 characters holds 1 or 2 bytes
+        let bytes = bits.to_be_bytes();
+        let byte_count = chunk.len() - 1;
+        if bytes[1 + byte_count..].iter().any(|byte| *byte != 0) {
+            return Err(Base64Error::InvalidPadding);
+        }
+        decoded.extend_from_slice(&bytes[1..1 + byte_count]);
+    }
+
+    Ok(decoded)
+}
+
+/// Encodes bytes in Base58.
+/// Every leading zero byte is encoded as a '1', since they would otherwise be lost in the number conversion.
+pub fn encode_base58(data: &[u8]) -> String {
+    let alphabet = BASE58_ALPHABET.as_bytes();
+    let leading_zeros = data.iter().take_while(|byte| **byte == 0).count();
+
+    let mut num = Integer::from_digits(data, Order::MsfBe);
+    let mut encoded = Vec::new();
+
+    while num > 0 {
+        let (quotient, remainder) = num.div_rem(Integer::from(58));
+        encoded.push(alphabet[remainder.to_usize().unwrap()]);
+        num = quotient;
+    }
+
+    encoded.extend(std::iter::repeat_n(b'1', leading_zeros));
+    encoded.reverse();
+
+    String::from_utf8(encoded).unwrap()
+}
+
+/// Decodes a Base58 string back to bytes, restoring a zero byte for every leading '1'.
+pub fn decode_base58(data: &str) -> Result<Vec<u8>, Base58Error> {
+    let leading_ones = data.chars().take_while(|c| *c == '1').count();
+
+    let mut num = Integer::ZERO;
+    for c in data.chars() {
+        let index = BASE58_ALPHABET
+            .find(c)
+            .ok_or(Base58Error::InvalidCharacter(c))?;
+        num = num * 58 + index as u32;
+    }
+
+    let mut decoded = vec![0u8; leading_ones];
+    decoded.extend(num.to_digits::<u8>(Order::MsfBe));
+
+    Ok(decoded)
+}
+
+/// Appends the first 4 bytes of the hash256 of the data as a checksum, then encodes it in Base58.
+pub fn encode_base58_checksum(data: &[u8]) -> String {
+    let mut data = data.to_vec();
+    data.extend_from_slice(&checksum4(&data));
+
+    encode_base58(&data)
+}
+
+/// Decodes a Base58 string and verifies the trailing 4-byte checksum, which is removed from the result.
+pub fn decode_base58_checksum(data: &str) -> Result<Vec<u8>, Base58Error> {
+    let decoded = decode_base58(data)?;
+    if decoded.len() < 5 {
+        return Err(Base58Error::TooShort);
+    }
+
+    let (body, checksum) = decoded.split_at(decoded.len() - 4);
+    if checksum4(body) != checksum {
+        return Err(Base58Error::InvalidChecksum);
+    }
+
+    Ok(body.to_vec())
+}
+
+/// Prefixes the payload with its version byte and appends the first 4 bytes of its hash256
+/// as a checksum before encoding it in Base58.
+pub fn encode_base58check(version: u8, payload: &[u8]) -> String {
+    let mut data = vec![version];
+    data.extend_from_slice(payload);
+
+    encode_base58_checksum(&data)
+}
+
+/// Decodes a Base58Check string, verifying its checksum.
+/// Returns the version byte and the payload.
+pub fn decode_base58check(data: &str) -> Result<(u8, Vec<u8>), Base58Error> {
+    let body = decode_base58_checksum(data)?;
+    Ok((body[0], body[1..].to_vec()))
+}
+
+/// Serializes a private key in Wallet Import Format: the network's WIF prefix, the 32-byte secret,
+/// and a 0x01 suffix if the matching public key is serialized compressed, all in Base58Check.
+pub fn encode_wif(secret: &[u8; 32], compressed: bool, network: Network) -> String {
+    let mut payload = secret.to_vec();
+    if compressed {
+        payload.push(0x01);
+    }
+
+    encode_base58check(network.wif_prefix(), &payload)
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
     use rug::{ops::Pow, Integer};
 
-    use crate::{s256_field::secp_generator_point, traits::Serializer};
+    use crate::{
+        s256_field::{secp_generator_point, to_32_bytes},
+        traits::Serializer,
+    };
+
+    use crate::network::Network;
+
+    use super::{
+        decode_base58, decode_base58check, decode_base64, encode_base58, encode_base58check,
+        encode_base64, encode_wif, parse_sec, Base58Error, Base64Error,
+    };
 
     #[test]
     fn test_uncompressed_sec() {
@@ -107,5 +324,140 @@ mod tests {
             "0296be5b1292f6c856b3c5654e886fc13511462059089cdf9c479623bfcbe77690",
             "Serialized point is incorrect"
         );
+
+        let mut stream = vec![0xff];
+        point.write_sec(&mut stream, true).unwrap();
+        assert_eq!(stream.len(), 34);
+        assert_eq!(hex::encode(&stream[1..]), serialized);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_sec() {
+        for secret in [5000, 5001, 2019_i32.pow(2)] {
+            let point = secp_generator_point().scalar_mul(Integer::from(secret));
+            for is_compressed in [true, false] {
+                let sec = hex::decode(point.sec(is_compressed)).unwrap();
+                assert_eq!(parse_sec(&sec), Some(point.clone()));
+            }
+        }
+
+        let mut sec = hex::decode("0357a4f368868a8a6d572991e484e664810ff14c05c0fa023275251151fe0e53d1").unwrap();
+        assert!(parse_sec(&sec[..32]).is_none());
+        sec[0] = 0x05;
+        assert!(parse_sec(&sec).is_none());
+        // x = 5 isn't on the curve: 5^3 + 7 has no square root
+        let mut not_on_curve = vec![0x02; 33];
+        not_on_curve[1..].copy_from_slice(&[0; 32]);
+        not_on_curve[32] = 5;
+        assert!(parse_sec(&not_on_curve).is_none());
+    }
+
+    #[test]
+    fn test_base58() {
+        let data = hex::decode("7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d").unwrap();
+        assert_eq!(encode_base58(&data), "9MA8fRQrT4u8Zj8ZRd6MAiiyaxb2Y1CMpvVkHQu5hVM6");
+
+        let data = hex::decode("eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c").unwrap();
+        assert_eq!(encode_base58(&data), "4fE3H2E6XMp4SsxtwinF7w9a34ooUrwWe4WsW1458Pd");
+
+        let data = hex::decode("c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab6").unwrap();
+        assert_eq!(encode_base58(&data), "EQJsjkd6JaGwxrjEhfeqPenqHwrBmPQZjJGNSCHBkcF7");
+
+        assert_eq!(decode_base58("EQJsjkd6JaGwxrjEhfeqPenqHwrBmPQZjJGNSCHBkcF7").unwrap(), data);
+    }
+
+    #[test]
+    fn test_base58_leading_zeros() {
+        let data = vec![0, 0, 0, 1, 2, 3];
+        let encoded = encode_base58(&data);
+        assert_eq!(encoded, "111Ldp");
+        assert_eq!(decode_base58(&encoded).unwrap(), data);
+
+        assert_eq!(encode_base58(&[]), "");
+        assert_eq!(decode_base58("").unwrap(), Vec::<u8>::new());
+        assert_eq!(decode_base58("11").unwrap(), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_base58_invalid_character() {
+        assert_eq!(decode_base58("9MA8fRQ0"), Err(Base58Error::InvalidCharacter('0')));
+        assert_eq!(decode_base58("Il"), Err(Base58Error::InvalidCharacter('I')));
+    }
+
+    #[test]
+    fn test_base58check() {
+        let hash = hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+
+        let mainnet = encode_base58check(0x00, &hash);
+        assert_eq!(mainnet, "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH");
+        assert_eq!(decode_base58check(&mainnet).unwrap(), (0x00, hash.clone()));
+
+        let testnet = encode_base58check(0x6f, &hash);
+        assert_eq!(testnet, "mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r");
+        assert_eq!(decode_base58check(&testnet).unwrap(), (0x6f, hash));
+    }
+
+    #[test]
+    fn test_base58check_errors() {
+        // the last character has been changed
+        assert_eq!(
+            decode_base58check("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMJ"),
+            Err(Base58Error::InvalidChecksum)
+        );
+        assert_eq!(
+            decode_base58check("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAM0"),
+            Err(Base58Error::InvalidCharacter('0'))
+        );
+        assert_eq!(decode_base58check("1111"), Err(Base58Error::TooShort));
+    }
+
+    #[test]
+    fn test_wif() {
+        assert_eq!(
+            encode_wif(&to_32_bytes(&Integer::from(5003)), true, Network::Testnet),
+            "cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN8rFTv2sfUK"
+        );
+        assert_eq!(
+            encode_wif(&to_32_bytes(&Integer::from(2021).pow(5)), false, Network::Testnet),
+            "91avARGdfge8E4tZfYLoxeJ5sGBdNJQH4kvjpWAxgzczjbCwxic"
+        );
+        assert_eq!(
+            encode_wif(&to_32_bytes(&Integer::from_str_radix("54321deadbeef", 16).unwrap()), true, Network::Mainnet),
+            "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgiuQJv1h8Ytr2S53a"
+        );
+    }
+
+    #[test]
+    fn test_base64() {
+        // RFC 4648 test vectors
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+
+        for (raw, encoded) in vectors {
+            assert_eq!(encode_base64(raw.as_bytes()), encoded);
+            assert_eq!(decode_base64(encoded).unwrap(), raw.as_bytes());
+        }
+
+        // the magic bytes of a PSBT
+        let psbt_magic = hex::decode("70736274ff").unwrap();
+        assert_eq!(encode_base64(&psbt_magic), "cHNidP8=");
+        assert_eq!(decode_base64("cHNidP8=").unwrap(), psbt_magic);
+    }
+
+    #[test]
+    fn test_base64_invalid() {
+        assert_eq!(decode_base64("Zm9"), Err(Base64Error::InvalidLength));
+        assert_eq!(decode_base64("Zm9*"), Err(Base64Error::InvalidCharacter('*')));
+        assert_eq!(decode_base64("Z==="), Err(Base64Error::InvalidPadding));
+        assert_eq!(decode_base64("Zm=v"), Err(Base64Error::InvalidPadding));
+        // "Zh==" has non-zero bits after the single encoded byte
+        assert_eq!(decode_base64("Zh=="), Err(Base64Error::InvalidPadding));
+    }
+}
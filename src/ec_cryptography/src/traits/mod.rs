@@ -1,3 +1,14 @@
+use std::io::{self, Write};
+
+use crate::helper::from_hex;
+
 pub trait Serializer {
     fn sec(&self, is_compressed: bool) -> String;
-}
\ No newline at end of file
+
+    /// Writes the SEC bytes straight to a writer, e.g. a transaction or network stream being built
+    fn write_sec<W: Write>(&self, writer: &mut W, is_compressed: bool) -> io::Result<()> {
+        let bytes = from_hex(&self.sec(is_compressed))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid SEC encoding"))?;
+        writer.write_all(&bytes)
+    }
+}
@@ -61,12 +61,36 @@ pub fn is_prime(num: Integer) -> bool {
     *is_prime.clone().lock().unwrap()
 }
 
+/// Returns the distinct prime factors of a number, in ascending order.
+/// Uses trial division, so it is only practical for small numbers (e.g. the order of a toy field).
+pub fn prime_factors(num: Integer) -> Vec<Integer> {
+    let mut factors = vec![];
+    let mut remainder = num;
+    let mut divisor = Integer::from(2);
+
+    while divisor.clone() * divisor.clone() <= remainder {
+        if remainder.is_divisible(&divisor) {
+            factors.push(divisor.clone());
+            while remainder.is_divisible(&divisor) {
+                remainder /= &divisor;
+            }
+        }
+        divisor += 1;
+    }
+
+    if remainder > 1 {
+        factors.push(remainder);
+    }
+
+    factors
+}
+
 #[cfg(test)]
 mod tests {
     use rug::Integer;
     use rug::ops::Pow;
 
-    use crate::helpers::is_prime;
+    use crate::helpers::{is_prime, prime_factors};
 
     #[test]
     #[ignore]
@@ -76,4 +100,11 @@ mod tests {
 
         assert!(is_prime(prime.clone()), "Not a prime number");
     }
+
+    #[test]
+    fn test_prime_factors() {
+        assert_eq!(prime_factors(Integer::from(30)), vec![Integer::from(2), Integer::from(3), Integer::from(5)]);
+        assert_eq!(prime_factors(Integer::from(222)), vec![Integer::from(2), Integer::from(3), Integer::from(37)]);
+        assert_eq!(prime_factors(Integer::from(2)), vec![Integer::from(2)]);
+    }
 }
\ No newline at end of file
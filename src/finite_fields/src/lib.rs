@@ -1,7 +1,7 @@
-use std::{fmt::Debug, ops::{Add, Div, Mul, Rem, Sub}};
+use std::{fmt::Debug, iter::{Product, Sum}, ops::{Add, Div, Mul, Rem, Sub}};
 use rug::{integer::IsPrime, ops::{Pow, RemRounding}};
 
-use helpers::is_prime;
+use helpers::{is_prime, prime_factors};
 use rug::{Integer, Assign};
 
 pub mod helpers;
@@ -45,6 +45,36 @@ impl FieldElement {
             }
     }
 
+    /// Finds a primitive root (generator) of the multiplicative group of the field of order `order`.
+    /// An element g generates the group if g^((p - 1) / q) != 1 for every prime factor q of p - 1.
+    /// Factoring p - 1 uses trial division, so this is intended for small fields.
+    pub fn find_generator(order: Integer) -> Option<FieldElement> {
+        let group_order = order.clone() - Integer::from(1);
+        let one = FieldElement::new(Integer::from(1), order.clone());
+
+        // The multiplicative group of F_2 is trivial, so 1 generates it
+        if group_order == 1 {
+            return Some(one);
+        }
+
+        let factors = prime_factors(group_order.clone());
+
+        let mut candidate = Integer::from(2);
+        while candidate < order {
+            let element = FieldElement::new(candidate.clone(), order.clone());
+            let is_generator = factors.iter().all(|factor| {
+                element.pow(group_order.clone() / factor.clone()) != Ok(one.clone())
+            });
+
+            if is_generator {
+                return Some(element);
+            }
+            candidate += 1;
+        }
+
+        None
+    }
+
     fn is_equal(&self, other: &FieldElement) -> bool {
         self == other
     }
@@ -118,6 +148,36 @@ impl Div for FieldElement {
     }
 }
 
+/// Sums an iterator of field elements, e.g. `elements.into_iter().sum::<FieldElement>()`.
+/// The prime of the result is taken from the elements, so the iterator must not be empty.
+impl Sum for FieldElement {
+    fn sum<I: Iterator<Item = FieldElement>>(iter: I) -> FieldElement {
+        iter.reduce(|acc, element| acc + element)
+            .expect("Cannot sum an empty iterator of field elements")
+    }
+}
+
+impl<'a> Sum<&'a FieldElement> for FieldElement {
+    fn sum<I: Iterator<Item = &'a FieldElement>>(iter: I) -> FieldElement {
+        iter.cloned().sum()
+    }
+}
+
+/// Multiplies an iterator of field elements together.
+/// As with `Sum`, the iterator must not be empty.
+impl Product for FieldElement {
+    fn product<I: Iterator<Item = FieldElement>>(iter: I) -> FieldElement {
+        iter.reduce(|acc, element| acc * element)
+            .expect("Cannot multiply an empty iterator of field elements")
+    }
+}
+
+impl<'a> Product<&'a FieldElement> for FieldElement {
+    fn product<I: Iterator<Item = &'a FieldElement>>(iter: I) -> FieldElement {
+        iter.cloned().product()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::panic;
@@ -233,4 +293,47 @@ mod tests {
         let b = FieldElement::new(Integer::from(11), Integer::from(31));
         assert_eq!(a.pow(Integer::from(-4)).unwrap() * b, FieldElement::new(Integer::from(13), Integer::from(31)));
     }
+
+    #[test]
+    fn test_find_generator() {
+        let generator = FieldElement::find_generator(Integer::from(31));
+        assert_eq!(generator, Some(FieldElement::new(Integer::from(3), Integer::from(31))));
+
+        let generator = FieldElement::find_generator(Integer::from(223)).unwrap();
+        assert_eq!(generator, FieldElement::new(Integer::from(3), Integer::from(223)));
+
+        // every non-zero element of the field is a power of the generator
+        let mut elements = vec![];
+        for exponent in 1..223 {
+            elements.push(generator.pow(Integer::from(exponent)).unwrap().num());
+        }
+        elements.sort();
+        elements.dedup();
+        assert_eq!(elements.len(), 222);
+    }
+
+    #[test]
+    fn test_sum_and_product() {
+        let elements = vec![
+            FieldElement::new(Integer::from(17), Integer::from(31)),
+            FieldElement::new(Integer::from(21), Integer::from(31)),
+            FieldElement::new(Integer::from(9), Integer::from(31)),
+        ];
+
+        let sum: FieldElement = elements.iter().sum();
+        assert_eq!(sum, FieldElement::new(Integer::from(16), Integer::from(31)));
+
+        let product: FieldElement = elements.iter().product();
+        assert_eq!(product, FieldElement::new(Integer::from(20), Integer::from(31)));
+
+        let owned_sum: FieldElement = elements.clone().into_iter().sum();
+        assert_eq!(owned_sum, sum);
+
+        let owned_product: FieldElement = elements.into_iter().product();
+        assert_eq!(owned_product, product);
+
+        let empty: Vec<FieldElement> = vec![];
+        let result = panic::catch_unwind(|| empty.into_iter().sum::<FieldElement>());
+        assert!(result.is_err(), "Empty sums have no prime");
+    }
 }
@@ -0,0 +1,138 @@
+use ec_cryptography::helper::{from_hex, to_hex};
+
+use crate::{
+    interpreter::{decode_num, encode_num},
+    Command, Opcode, Script,
+};
+
+/// The biggest number `asm` shows in decimal, the largest that fits in four bytes
+const MAX_ASM_NUMBER: i64 = 0x7fffffff;
+
+/// Why `Script::from_asm` couldn't read a script
+#[derive(Debug, PartialEq, Eq)]
+pub enum AsmError {
+    /// A word that is neither an opcode name, a number nor hex data
+    UnknownToken(String),
+    /// OP_PUSHDATA1, OP_PUSHDATA2 or OP_PUSHDATA4 on its own; data is written as hex and pushed
+    /// with the shortest opcode instead
+    PushDataOpcode(String),
+}
+
+impl Script {
+    /// The script in Bitcoin Core's asm format, as `decoderawtransaction` shows it: pushes of up
+    /// to four bytes and the number opcodes as decimal numbers, longer pushes as hex, other
+    /// opcodes by name and a push that runs past the end as `[error]`
+    pub fn asm(&self) -> String {
+        self.commands()
+            .iter()
+            .map(|command| match command {
+                Command::Push(data) if data.len() <= 4 => decode_num(data, 4).unwrap_or_default().to_string(),
+                Command::Push(data) => to_hex(data),
+                Command::Op(byte) => match Opcode::from_u8(*byte) {
+                    Some(opcode) => match opcode.small_number() {
+                        Some(number) => number.to_string(),
+                        None => opcode.name().to_string(),
+                    },
+                    None => "OP_UNKNOWN".to_string(),
+                },
+                Command::Invalid(_) => "[error]".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Reads a script written as `asm` writes it, separated by whitespace. Opcodes may be named
+    /// with or without the `OP_` prefix, numbers are pushed with OP_0 to OP_16, OP_1NEGATE or the
+    /// shortest encoding, and anything else must be hex data.
+    ///
+    /// The round trip gives back the same script when its pushes are minimal, except for a five
+    /// byte push whose hex is all decimal digits, which reads as a number.
+    pub fn from_asm(asm: &str) -> Result<Script, AsmError> {
+        let commands = asm.split_whitespace().map(asm_command).collect::<Result<_, _>>()?;
+        Ok(Script::from_commands(commands))
+    }
+}
+
+fn asm_command(token: &str) -> Result<Command, AsmError> {
+    if let Some(number) = asm_number(token) {
+        return Ok(number_command(number));
+    }
+
+    let name = if token.starts_with("OP_") { token.to_string() } else { format!("OP_{}", token) };
+    if let Some(opcode) = Opcode::from_name(&name) {
+        return match opcode {
+            Opcode::OpPushData1 | Opcode::OpPushData2 | Opcode::OpPushData4 => {
+                Err(AsmError::PushDataOpcode(token.to_string()))
+            }
+            _ => Ok(Command::Op(opcode.to_u8())),
+        };
+    }
+
+    from_hex(token)
+        .map(Command::Push)
+        .map_err(|_| AsmError::UnknownToken(token.to_string()))
+}
+
+/// A decimal number as `asm` writes them, without leading zeros and small enough for four bytes
+fn asm_number(token: &str) -> Option<i64> {
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    if (digits.len() > 1 && digits.starts_with('0')) || token == "-0" {
+        return None;
+    }
+
+    token.parse().ok().filter(|number: &i64| number.abs() <= MAX_ASM_NUMBER)
+}
+
+fn number_command(number: i64) -> Command {
+    match number {
+        0 => Command::Op(Opcode::Op0.to_u8()),
+        -1 => Command::Op(Opcode::Op1Negate.to_u8()),
+        1..=16 => Command::Op(Opcode::Op1.to_u8() + number as u8 - 1),
+        _ => Command::Push(encode_num(number)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asm() {
+        let p2pkh = Script::from_hex("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").unwrap();
+        assert_eq!(p2pkh.asm(), "OP_DUP OP_HASH160 bc3b654dca7e56b04dca18f2566cdaf02e8d9ada OP_EQUALVERIFY OP_CHECKSIG");
+
+        // small pushes and the number opcodes are numbers
+        assert_eq!(Script::from_hex("0051604f0201020380ff80").unwrap().asm(), "0 1 16 -1 513 -65408");
+
+        // undefined bytes, OP_INVALIDOPCODE and a push past the end
+        assert_eq!(Script::from_hex("bbff05aabb").unwrap().asm(), "OP_UNKNOWN OP_INVALIDOPCODE [error]");
+        assert_eq!(Script::default().asm(), "");
+    }
+
+    #[test]
+    fn test_from_asm() {
+        let hex = "76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac";
+        let p2pkh = Script::from_hex(hex).unwrap();
+        assert_eq!(Script::from_asm(&p2pkh.asm()).unwrap(), p2pkh);
+        assert_eq!(Script::from_asm("DUP HASH160 bc3b654dca7e56b04dca18f2566cdaf02e8d9ada EQUALVERIFY CHECKSIG"), Ok(p2pkh));
+
+        let numbers = Script::from_hex("0051604f0201020380ff80011104ffffff7f04ffffffff").unwrap();
+        assert_eq!(numbers.asm(), "0 1 16 -1 513 -65408 17 2147483647 -2147483647");
+        assert_eq!(Script::from_asm(&numbers.asm()), Ok(numbers));
+
+        // a non-minimal push comes back minimal
+        assert_eq!(Script::from_asm(&Script::from_hex("020100").unwrap().asm()).unwrap().to_hex(), "51");
+
+        assert_eq!(Script::from_asm("OP_TRUE OP_NOP2 OP_NOP3").unwrap().to_hex(), "51b1b2");
+        assert_eq!(Script::from_asm("  2\n 3 OP_ADD ").unwrap().to_hex(), "525393");
+
+        assert_eq!(Script::from_asm("OP_FOO"), Err(AsmError::UnknownToken("OP_FOO".to_string())));
+        assert_eq!(Script::from_asm("abc"), Err(AsmError::UnknownToken("abc".to_string())));
+        assert_eq!(Script::from_asm("007"), Err(AsmError::UnknownToken("007".to_string())));
+        assert_eq!(Script::from_asm("[error]"), Err(AsmError::UnknownToken("[error]".to_string())));
+        assert_eq!(Script::from_asm("OP_PUSHDATA1 01"), Err(AsmError::PushDataOpcode("OP_PUSHDATA1".to_string())));
+    }
+}
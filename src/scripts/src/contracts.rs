@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+
+use crate::{interpreter::encode_num, Command, MultisigError, Opcode, Script};
+
+impl Script {
+    /// A hash-timelock contract, for a P2WSH output: `recipient` can spend it with the preimage of
+    /// `payment_hash`, `sender` once the transaction's locktime reaches `lock_time` (a height, or a timestamp from
+    /// 500,000,000 on).
+    ///
+    /// OP_IF OP_SIZE 32 OP_EQUALVERIFY OP_SHA256 <payment_hash> OP_EQUALVERIFY <recipient>
+    /// OP_ELSE <lock_time> OP_CHECKLOCKTIMEVERIFY OP_DROP <sender> OP_ENDIF OP_CHECKSIG
+    ///
+    /// The size check keeps the preimage 32 bytes, so that the same hash locks a contract on a chain
+    /// with a different push limit.
+    pub fn htlc(payment_hash: &[u8; 32], recipient: &[u8], sender: &[u8], lock_time: u32) -> Script {
+        let op = |opcode: Opcode| Command::Op(opcode.to_u8());
+        Script::from_commands(vec![
+            op(Opcode::OpIf),
+            op(Opcode::OpSize),
+            Command::Push(encode_num(32)),
+            op(Opcode::OpEqualVerify),
+            op(Opcode::OpSha256),
+            Command::Push(payment_hash.to_vec()),
+            op(Opcode::OpEqualVerify),
+            Command::Push(recipient.to_vec()),
+            op(Opcode::OpElse),
+            Command::Push(encode_num(lock_time as i64)),
+            op(Opcode::OpCheckLockTimeVerify),
+            op(Opcode::OpDrop),
+            Command::Push(sender.to_vec()),
+            op(Opcode::OpEndIf),
+            op(Opcode::OpCheckSig),
+        ])
+    }
+
+    /// The witness with which the recipient of an `htlc` claims it: their signature, the preimage and a
+    /// 1 to take the OP_IF branch
+    pub fn htlc_claim_witness(signature: &[u8], preimage: &[u8], witness_script: &Script) -> Vec<Vec<u8>> {
+        Script::p2wsh_witness(vec![signature.to_vec(), preimage.to_vec(), vec![1]], witness_script)
+    }
+
+    /// The witness with which the sender of an `htlc` takes it back: their signature and an empty item to
+    /// take the OP_ELSE branch. The spending transaction must have a locktime of at least the contract's,
+    /// of the same kind, and an input sequence below 0xffffffff so that the locktime applies.
+    pub fn htlc_refund_witness(signature: &[u8], witness_script: &Script) -> Vec<Vec<u8>> {
+        Script::p2wsh_witness(vec![signature.to_vec(), vec![]], witness_script)
+    }
+
+    /// A 2-of-3 escrow, for a P2WSH output: the buyer and the seller can spend it together, or
+    /// either of them with the arbiter when they disagree
+    pub fn escrow(buyer: &[u8], seller: &[u8], arbiter: &[u8]) -> Result<Script, MultisigError> {
+        Script::multisig(2, &[buyer.to_vec(), seller.to_vec(), arbiter.to_vec()])
+    }
+
+    /// The witness spending an `escrow`, or any P2WSH multisig, with the `signatures` by SEC public key.
+    /// None if fewer of the keys than it requires have signed.
+    pub fn escrow_witness(signatures: &BTreeMap<Vec<u8>, Vec<u8>>, witness_script: &Script) -> Option<Vec<Vec<u8>>> {
+        let inputs = witness_script.multisig_inputs(signatures)?;
+        Some(Script::p2wsh_witness(inputs, witness_script))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::{
+        helper::{from_hex, sha256, to_hex},
+        traits::Serializer,
+        PrivateKey,
+    };
+    use rug::{integer::Order, Integer};
+
+    use super::*;
+    use crate::interpreter::{FixedSighash, ScriptError, SigVersion, SignatureChecker};
+
+    const Z: [u8; 32] = [0x42; 32];
+
+    /// Checks signatures against Z, for a transaction with this locktime
+    struct LockedUntil(i64);
+
+    impl SignatureChecker for LockedUntil {
+        fn check_signature(&self, signature: &[u8], public_key: &[u8], script_code: &Script, _: SigVersion) -> bool {
+            FixedSighash(Z).check_signature(signature, public_key, script_code, SigVersion::WitnessV0)
+        }
+
+        fn check_lock_time(&self, lock_time: i64) -> bool {
+            lock_time <= self.0
+        }
+    }
+
+    fn key(secret: u32) -> (PrivateKey, Vec<u8>) {
+        let key = PrivateKey::new(Integer::from(secret));
+        let sec = from_hex(&key.point().sec(true)).unwrap();
+        (key, sec)
+    }
+
+    fn sign(key: &PrivateKey) -> Vec<u8> {
+        let mut signature = key.sign(Integer::from_digits(&Z, Order::MsfBe)).der_bytes();
+        signature.push(0x01);
+        signature
+    }
+
+    fn spend(witness_script: &Script, witness: &[Vec<u8>], lock_time: i64) -> Result<(), ScriptError> {
+        Script::verify_spend(&Script::default(), &witness_script.to_p2wsh(), witness, &LockedUntil(lock_time))
+    }
+
+    #[test]
+    fn test_htlc() {
+        let ((recipient, recipient_sec), (sender, sender_sec)) = (key(11), key(12));
+        let preimage = [0x5a; 32];
+        let payment_hash: [u8; 32] = sha256(&preimage).try_into().unwrap();
+        let htlc = Script::htlc(&payment_hash, &recipient_sec, &sender_sec, 800_000);
+        assert_eq!(
+            htlc.asm(),
+            format!(
+                "OP_IF OP_SIZE 32 OP_EQUALVERIFY OP_SHA256 {} OP_EQUALVERIFY {} OP_ELSE 800000 \
+                 OP_CHECKLOCKTIMEVERIFY OP_DROP {} OP_ENDIF OP_CHECKSIG",
+                to_hex(&payment_hash),
+                to_hex(&recipient_sec),
+                to_hex(&sender_sec)
+            )
+        );
+
+        // the recipient claims with the preimage at any time
+        let (recipient_sig, sender_sig) = (sign(&recipient), sign(&sender));
+        let claim = Script::htlc_claim_witness(&recipient_sig, &preimage, &htlc);
+        assert_eq!(spend(&htlc, &claim, 0), Ok(()));
+        let wrong_preimage = Script::htlc_claim_witness(&recipient_sig, &[0x5b; 32], &htlc);
+        assert_eq!(spend(&htlc, &wrong_preimage, 0), Err(ScriptError::VerifyFailed(Opcode::OpEqualVerify)));
+        let sender_claim = Script::htlc_claim_witness(&sender_sig, &preimage, &htlc);
+        assert_eq!(spend(&htlc, &sender_claim, 0), Err(ScriptError::EvalFalse));
+
+        // the sender takes it back from the locktime on
+        let refund = Script::htlc_refund_witness(&sender_sig, &htlc);
+        assert_eq!(spend(&htlc, &refund, 799_999), Err(ScriptError::UnsatisfiedLockTime));
+        assert_eq!(spend(&htlc, &refund, 800_000), Ok(()));
+    }
+
+    #[test]
+    fn test_escrow() {
+        let ((buyer, buyer_sec), (_, seller_sec), (arbiter, arbiter_sec)) = (key(21), key(22), key(23));
+        let escrow = Script::escrow(&buyer_sec, &seller_sec, &arbiter_sec).unwrap();
+        assert_eq!(escrow.multisig_keys().map(|(required, keys)| (required, keys.len())), Some((2, 3)));
+        assert_eq!(Script::escrow(&buyer_sec, &seller_sec, &[0x02; 20]), Err(MultisigError::InvalidPublicKey(2)));
+
+        // the buyer and the arbiter overrule the seller
+        let mut signatures = BTreeMap::new();
+        signatures.insert(arbiter_sec, sign(&arbiter));
+        assert_eq!(Script::escrow_witness(&signatures, &escrow), None);
+        signatures.insert(buyer_sec, sign(&buyer));
+        let witness = Script::escrow_witness(&signatures, &escrow).unwrap();
+        assert_eq!(witness.len(), 4);
+        assert_eq!(spend(&escrow, &witness, 0), Ok(()));
+    }
+}
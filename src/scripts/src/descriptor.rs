@@ -0,0 +1,509 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use ec_cryptography::{
+    address::Address,
+    extended_key::{ExtendedKey, ExtendedKeyError, HARDENED},
+    helper::{from_hex, hash160, to_hex},
+    network::Network,
+    serializer::parse_sec,
+    tap_tweak_public_key,
+};
+
+use crate::{interpreter::MAX_PUBKEYS_PER_MULTISIG, MultisigError, Script};
+
+/// The characters a descriptor can have, in the order the checksum reads them (BIP380)
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+/// The characters of the checksum, those of bech32
+const CHECKSUM_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const CHECKSUM_GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+const CHECKSUM_LENGTH: usize = 8;
+
+/// An output descriptor (BIP380): the scripts of a wallet, described by their type and keys.
+/// `pkh`, `wpkh`, `sh(wpkh)`, `wsh(multi)`, `wsh(sortedmulti)` and key path only `tr` are supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Descriptor {
+    /// `pkh(KEY)`: P2PKH
+    Pkh(DescriptorKey),
+    /// `wpkh(KEY)`: P2WPKH, with a compressed key
+    Wpkh(DescriptorKey),
+    /// `sh(wpkh(KEY))`: P2WPKH nested in P2SH
+    ShWpkh(DescriptorKey),
+    /// `wsh(multi(k,KEY,...))`: a P2WSH multisig, its keys sorted (BIP67) for `sortedmulti`
+    WshMulti { required: usize, keys: Vec<DescriptorKey>, sorted: bool },
+    /// `tr(KEY)`: a P2TR output spent by the key path, its key tweaked without a script tree (BIP86)
+    Tr(DescriptorKey),
+}
+
+/// A key in a descriptor, with where it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorKey {
+    /// The fingerprint of the master key and the path from it to the key, written `[d34db33f/84h/0h/0h]`
+    pub origin: Option<([u8; 4], Vec<u32>)>,
+    pub key: KeyExpression,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyExpression {
+    /// A SEC public key, or in `tr` an x-only one
+    Single(Vec<u8>),
+    /// An extended key and the path to derive from it. With a wildcard, the index of the script is a last step,
+    /// hardened for `*h`.
+    Extended { key: ExtendedKey, path: Vec<u32>, wildcard: Option<Wildcard> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wildcard {
+    /// `/*`
+    Unhardened,
+    /// `/*h` or `/*'`, which needs an extended private key
+    Hardened,
+}
+
+/// Why a descriptor couldn't be parsed or derived
+#[derive(Debug, PartialEq, Eq)]
+pub enum DescriptorError {
+    /// Unbalanced parentheses, or a character descriptors can't have
+    Syntax(String),
+    /// A script function that isn't supported, or not where it's used
+    UnknownFunction(String),
+    /// Neither a public key in hex nor an extended key, or a key of a kind the function can't take
+    InvalidKey(String),
+    /// A derivation path step that isn't a number below 2^31, or a wildcard before its end
+    InvalidPath(String),
+    /// The checksum after `#` isn't that of the descriptor, which is this one
+    ChecksumMismatch(String),
+    /// A threshold or key count `multi` can't have
+    Multisig(MultisigError),
+    /// Deriving a key from an extended key failed
+    Derivation(ExtendedKeyError),
+}
+
+impl From<MultisigError> for DescriptorError {
+    fn from(error: MultisigError) -> Self {
+        DescriptorError::Multisig(error)
+    }
+}
+
+impl From<ExtendedKeyError> for DescriptorError {
+    fn from(error: ExtendedKeyError) -> Self {
+        DescriptorError::Derivation(error)
+    }
+}
+
+/// The kinds of keys a script function takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyContext {
+    /// `pkh`: compressed or uncompressed
+    Legacy,
+    /// segwit v0: only compressed
+    Segwit,
+    /// `tr`: compressed or x-only
+    Taproot,
+}
+
+impl DescriptorKey {
+    /// Whether the key is different for each index
+    pub fn is_ranged(&self) -> bool {
+        matches!(self.key, KeyExpression::Extended { wildcard: Some(_), .. })
+    }
+
+    /// The public key at `index`, as written for a single key and compressed for an extended one
+    pub fn derive(&self, index: u32) -> Result<Vec<u8>, DescriptorError> {
+        match &self.key {
+            KeyExpression::Single(key) => Ok(key.clone()),
+            KeyExpression::Extended { key, path, wildcard } => {
+                let mut path = path.clone();
+                match wildcard {
+                    Some(Wildcard::Unhardened) => path.push(index),
+                    Some(Wildcard::Hardened) => path.push(index | HARDENED),
+                    None => {}
+                }
+                Ok(key.derive_path(&path)?.public_key().to_vec())
+            }
+        }
+    }
+}
+
+impl Descriptor {
+    /// Whether the descriptor describes a script per index, rather than a single one
+    pub fn is_ranged(&self) -> bool {
+        match self {
+            Descriptor::Pkh(key) | Descriptor::Wpkh(key) | Descriptor::ShWpkh(key) | Descriptor::Tr(key) => {
+                key.is_ranged()
+            }
+            Descriptor::WshMulti { keys, .. } => keys.iter().any(DescriptorKey::is_ranged),
+        }
+    }
+
+    /// The script_pubkey at `index`, which only ranged descriptors look at
+    pub fn derive(&self, index: u32) -> Result<Script, DescriptorError> {
+        let key_hash = |key: &DescriptorKey| -> Result<[u8; 20], DescriptorError> {
+            Ok(hash160(&key.derive(index)?).try_into().unwrap())
+        };
+
+        let script = match self {
+            Descriptor::Pkh(key) => Script::p2pkh(&key_hash(key)?),
+            Descriptor::Wpkh(key) => Script::p2wpkh(&key_hash(key)?),
+            Descriptor::ShWpkh(key) => Script::p2wpkh(&key_hash(key)?).to_p2sh(),
+            Descriptor::WshMulti { required, keys, sorted } => {
+                let keys = keys.iter().map(|key| key.derive(index)).collect::<Result<Vec<_>, _>>()?;
+                let witness_script = if *sorted {
+                    Script::sorted_multisig(*required, &keys)?
+                } else {
+                    Script::multisig(*required, &keys)?
+                };
+                witness_script.to_p2wsh()
+            }
+            Descriptor::Tr(key) => {
+                let internal_key = key.derive(index)?;
+                // a compressed key leaves out its parity byte
+                let internal_key: [u8; 32] = internal_key[internal_key.len() - 32..].try_into().unwrap();
+                let (output_key, _) = tap_tweak_public_key(&internal_key, None)
+                    .ok_or_else(|| DescriptorError::InvalidKey(to_hex(&internal_key)))?;
+                Script::p2tr(&output_key)
+            }
+        };
+        Ok(script)
+    }
+
+    /// The address of the script_pubkey at `index`
+    pub fn address(&self, index: u32, network: Network) -> Result<Address, DescriptorError> {
+        let script_pubkey = self.derive(index)?;
+        Ok(script_pubkey.to_address(network).expect("descriptors only describe scripts with addresses"))
+    }
+}
+
+/// The eight characters that go after `#`, computed from the descriptor before it
+pub fn descriptor_checksum(descriptor: &str) -> Result<String, DescriptorError> {
+    let mut symbols = vec![];
+    let mut groups = vec![];
+    for char in descriptor.chars() {
+        let value = INPUT_CHARSET.find(char).ok_or_else(|| DescriptorError::Syntax(descriptor.to_string()))? as u64;
+        symbols.push(value & 31);
+        groups.push(value >> 5);
+        if let [first, second, third] = groups[..] {
+            symbols.push(first * 9 + second * 3 + third);
+            groups.clear();
+        }
+    }
+    match groups[..] {
+        [first] => symbols.push(first),
+        [first, second] => symbols.push(first * 3 + second),
+        _ => {}
+    }
+    symbols.extend([0; CHECKSUM_LENGTH]);
+
+    let checksum = checksum_polymod(&symbols) ^ 1;
+    let checksum = (0..CHECKSUM_LENGTH)
+        .map(|index| CHECKSUM_CHARSET[((checksum >> (5 * (CHECKSUM_LENGTH - 1 - index))) & 31) as usize] as char)
+        .collect();
+    Ok(checksum)
+}
+
+fn checksum_polymod(symbols: &[u64]) -> u64 {
+    symbols.iter().fold(1, |checksum, symbol| {
+        let top = checksum >> 35;
+        let checksum = ((checksum & 0x7ffffffff) << 5) ^ symbol;
+        CHECKSUM_GENERATOR
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| (top >> index) & 1 == 1)
+            .fold(checksum, |checksum, (_, generator)| checksum ^ generator)
+    })
+}
+
+impl FromStr for Descriptor {
+    type Err = DescriptorError;
+
+    /// Parses a descriptor, checking its checksum if it has one
+    fn from_str(descriptor: &str) -> Result<Self, Self::Err> {
+        let body = match descriptor.split_once('#') {
+            Some((body, checksum)) => {
+                let expected = descriptor_checksum(body)?;
+                if checksum != expected {
+                    return Err(DescriptorError::ChecksumMismatch(expected));
+                }
+                body
+            }
+            None => {
+                // only for the characters the checksum covers
+                descriptor_checksum(descriptor)?;
+                descriptor
+            }
+        };
+
+        let (function, args) = call(body)?;
+        let descriptor = match (function, args.as_slice()) {
+            ("pkh", [key]) => Descriptor::Pkh(parse_key(key, KeyContext::Legacy)?),
+            ("wpkh", [key]) => Descriptor::Wpkh(parse_key(key, KeyContext::Segwit)?),
+            ("sh", [inner]) => {
+                let (function, args) = call(inner)?;
+                match (function, args.as_slice()) {
+                    ("wpkh", [key]) => Descriptor::ShWpkh(parse_key(key, KeyContext::Segwit)?),
+                    _ => return Err(DescriptorError::UnknownFunction(inner.to_string())),
+                }
+            }
+            ("wsh", [inner]) => parse_wsh(inner)?,
+            ("tr", [key]) => Descriptor::Tr(parse_key(key, KeyContext::Taproot)?),
+            _ => return Err(DescriptorError::UnknownFunction(body.to_string())),
+        };
+        Ok(descriptor)
+    }
+}
+
+/// `multi` or `sortedmulti`, the functions `wsh` takes
+fn parse_wsh(inner: &str) -> Result<Descriptor, DescriptorError> {
+    let (function, args) = call(inner)?;
+    let sorted = match function {
+        "multi" => false,
+        "sortedmulti" => true,
+        _ => return Err(DescriptorError::UnknownFunction(inner.to_string())),
+    };
+    let [required, keys @ ..] = args.as_slice() else {
+        return Err(DescriptorError::Syntax(inner.to_string()));
+    };
+
+    if keys.is_empty() || keys.len() > MAX_PUBKEYS_PER_MULTISIG {
+        return Err(MultisigError::KeyCount(keys.len()).into());
+    }
+    let required = match required.parse() {
+        Ok(required) if required >= 1 && required <= keys.len() => required,
+        Ok(required) => return Err(MultisigError::RequiredCount(required).into()),
+        Err(_) => return Err(DescriptorError::Syntax(inner.to_string())),
+    };
+    let keys = keys.iter().map(|key| parse_key(key, KeyContext::Segwit)).collect::<Result<_, _>>()?;
+    Ok(Descriptor::WshMulti { required, keys, sorted })
+}
+
+/// The name of a script function and its arguments, split at the commas outside nested parentheses
+fn call(expression: &str) -> Result<(&str, Vec<&str>), DescriptorError> {
+    let syntax = || DescriptorError::Syntax(expression.to_string());
+    let (function, args) = expression.split_once('(').ok_or_else(syntax)?;
+    let args = args.strip_suffix(')').ok_or_else(syntax)?;
+
+    let mut split = vec![];
+    let (mut depth, mut start) = (0usize, 0);
+    for (index, char) in args.char_indices() {
+        match char {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1).ok_or_else(syntax)?,
+            ',' if depth == 0 => {
+                split.push(&args[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&args[start..]);
+    if depth != 0 {
+        return Err(syntax());
+    }
+    Ok((function, split))
+}
+
+fn parse_key(expression: &str, context: KeyContext) -> Result<DescriptorKey, DescriptorError> {
+    let invalid_key = || DescriptorError::InvalidKey(expression.to_string());
+
+    let (origin, key) = match expression.strip_prefix('[') {
+        Some(rest) => {
+            let (origin, key) = rest.split_once(']').ok_or_else(invalid_key)?;
+            let mut steps = origin.split('/');
+            let fingerprint = steps
+                .next()
+                .and_then(|fingerprint| from_hex(fingerprint).ok())
+                .and_then(|fingerprint| fingerprint.try_into().ok())
+                .ok_or_else(invalid_key)?;
+            (Some((fingerprint, steps.map(parse_step).collect::<Result<_, _>>()?)), key)
+        }
+        None => (None, expression),
+    };
+
+    let mut steps = key.split('/');
+    let encoded = steps.next().unwrap_or_default();
+    let key = if let Ok(key) = from_hex(encoded) {
+        let valid = match (key.len(), context) {
+            (33, _) | (65, KeyContext::Legacy) => parse_sec(&key).is_some(),
+            (32, KeyContext::Taproot) => parse_sec(&[&[0x02], &key[..]].concat()).is_some(),
+            _ => false,
+        };
+        if !valid || steps.next().is_some() {
+            return Err(invalid_key());
+        }
+        KeyExpression::Single(key)
+    } else {
+        let extended_key = ExtendedKey::parse(encoded).map_err(|_| invalid_key())?;
+        let mut steps = steps.collect::<Vec<_>>();
+        let wildcard = match steps.last() {
+            Some(&"*") => Some(Wildcard::Unhardened),
+            Some(&("*h" | "*'")) => Some(Wildcard::Hardened),
+            _ => None,
+        };
+        if wildcard.is_some() {
+            steps.pop();
+        }
+        let path = steps.into_iter().map(parse_step).collect::<Result<_, _>>()?;
+        KeyExpression::Extended { key: extended_key, path, wildcard }
+    };
+    Ok(DescriptorKey { origin, key })
+}
+
+/// A step of a derivation path, hardened when it ends in `h` or `'`
+fn parse_step(step: &str) -> Result<u32, DescriptorError> {
+    let (number, hardened) = match step.strip_suffix(['h', '\'']) {
+        Some(number) => (number, HARDENED),
+        None => (step, 0),
+    };
+    match number.parse::<u32>() {
+        Ok(number) if number < HARDENED => Ok(number | hardened),
+        _ => Err(DescriptorError::InvalidPath(step.to_string())),
+    }
+}
+
+fn format_path(path: &[u32]) -> String {
+    path.iter()
+        .map(|step| if step & HARDENED != 0 { format!("/{}h", step & !HARDENED) } else { format!("/{}", step) })
+        .collect()
+}
+
+impl Display for DescriptorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((fingerprint, path)) = &self.origin {
+            write!(f, "[{}{}]", to_hex(fingerprint), format_path(path))?;
+        }
+        match &self.key {
+            KeyExpression::Single(key) => write!(f, "{}", to_hex(key)),
+            KeyExpression::Extended { key, path, wildcard } => {
+                write!(f, "{}{}", key, format_path(path))?;
+                match wildcard {
+                    Some(Wildcard::Unhardened) => write!(f, "/*"),
+                    Some(Wildcard::Hardened) => write!(f, "/*h"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl Display for Descriptor {
+    /// The descriptor with its checksum, hardened steps written with `h`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = match self {
+            Descriptor::Pkh(key) => format!("pkh({})", key),
+            Descriptor::Wpkh(key) => format!("wpkh({})", key),
+            Descriptor::ShWpkh(key) => format!("sh(wpkh({}))", key),
+            Descriptor::WshMulti { required, keys, sorted } => {
+                let function = if *sorted { "sortedmulti" } else { "multi" };
+                let keys = keys.iter().map(ToString::to_string).collect::<Vec<_>>();
+                format!("wsh({}({},{}))", function, required, keys.join(","))
+            }
+            Descriptor::Tr(key) => format!("tr({})", key),
+        };
+        let checksum = descriptor_checksum(&body).map_err(|_| fmt::Error)?;
+        write!(f, "{}#{}", body, checksum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The BIP84 account key of the "abandon ... about" seed, m/84h/0h/0h
+    const BIP84_XPUB: &str = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+    /// The BIP86 account key of the same seed, m/86h/0h/0h
+    const BIP86_XPUB: &str = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+    const KEY: &str = "03935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b67";
+    const OTHER_KEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn address(descriptor: &Descriptor, index: u32) -> String {
+        descriptor.address(index, Network::Mainnet).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)"), Ok("89f8spxm".to_string()));
+        assert_eq!(descriptor_checksum("pkh(é)"), Err(DescriptorError::Syntax("pkh(é)".to_string())));
+
+        let descriptor = format!("wpkh({})", KEY);
+        let checksum = descriptor_checksum(&descriptor).unwrap();
+        let parsed = format!("{}#{}", descriptor, checksum).parse::<Descriptor>().unwrap();
+        assert_eq!(parsed.to_string(), format!("{}#{}", descriptor, checksum));
+        assert_eq!(descriptor.parse::<Descriptor>(), Ok(parsed));
+        let wrong_checksum = format!("{}#qqqqqqqq", descriptor).parse::<Descriptor>();
+        assert_eq!(wrong_checksum, Err(DescriptorError::ChecksumMismatch(checksum)));
+    }
+
+    #[test]
+    fn test_derive_ranged() {
+        // BIP84: the first receiving and change addresses
+        let receive = format!("wpkh([73c5da0a/84'/0'/0']{}/0/*)", BIP84_XPUB).parse::<Descriptor>().unwrap();
+        assert!(receive.is_ranged());
+        assert_eq!(address(&receive, 0), "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+        assert_eq!(address(&receive, 1), "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g");
+        let change = format!("wpkh({}/1/*)", BIP84_XPUB).parse::<Descriptor>().unwrap();
+        assert_eq!(address(&change, 0), "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el");
+        assert_eq!(
+            receive.to_string().split_once('#').unwrap().0,
+            format!("wpkh([73c5da0a/84h/0h/0h]{}/0/*)", BIP84_XPUB)
+        );
+        assert_eq!(receive.to_string().parse::<Descriptor>(), Ok(receive));
+
+        // BIP86: the first key path address
+        let taproot = format!("tr({}/0/*)", BIP86_XPUB).parse::<Descriptor>().unwrap();
+        assert_eq!(address(&taproot, 0), "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr");
+
+        // hardened steps need the private key
+        let hardened = format!("pkh({}/0/*h)", BIP84_XPUB).parse::<Descriptor>().unwrap();
+        let error = ExtendedKeyError::HardenedFromPublic(HARDENED);
+        assert_eq!(hardened.derive(0), Err(DescriptorError::Derivation(error)));
+    }
+
+    #[test]
+    fn test_derive_single_keys() {
+        let key = from_hex(KEY).unwrap();
+        let key_hash: [u8; 20] = hash160(&key).try_into().unwrap();
+
+        let pkh = format!("pkh({})", KEY).parse::<Descriptor>().unwrap();
+        assert!(!pkh.is_ranged());
+        assert_eq!(pkh.derive(7), Ok(Script::p2pkh(&key_hash)));
+        let nested = format!("sh(wpkh({}))", KEY).parse::<Descriptor>().unwrap();
+        assert_eq!(nested.derive(0), Ok(Script::p2wpkh(&key_hash).to_p2sh()));
+
+        let multi = format!("wsh(multi(1,{},{}))", KEY, OTHER_KEY).parse::<Descriptor>().unwrap();
+        let keys = vec![key, from_hex(OTHER_KEY).unwrap()];
+        assert_eq!(multi.derive(0), Ok(Script::multisig(1, &keys).unwrap().to_p2wsh()));
+        let sorted = format!("wsh(sortedmulti(1,{},{}))", KEY, OTHER_KEY).parse::<Descriptor>().unwrap();
+        assert_eq!(sorted.derive(0), Ok(Script::sorted_multisig(1, &keys).unwrap().to_p2wsh()));
+        assert_ne!(sorted.derive(0), multi.derive(0));
+
+        // an x-only key, the same as its compressed form
+        let x_only = format!("tr({})", &OTHER_KEY[2..]).parse::<Descriptor>().unwrap();
+        let compressed = format!("tr({})", OTHER_KEY).parse::<Descriptor>().unwrap();
+        assert_eq!(x_only.derive(0), compressed.derive(0));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |descriptor: String| descriptor.parse::<Descriptor>().unwrap_err();
+        let uncompressed = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+        assert!(format!("pkh({})", uncompressed).parse::<Descriptor>().is_ok());
+        assert_eq!(parse(format!("wpkh({})", uncompressed)), DescriptorError::InvalidKey(uncompressed.to_string()));
+        assert_eq!(parse(format!("wpkh({})", &KEY[2..])), DescriptorError::InvalidKey(KEY[2..].to_string()));
+        assert_eq!(parse("pkh(xpub123)".to_string()), DescriptorError::InvalidKey("xpub123".to_string()));
+        assert_eq!(parse(format!("pkh({}/0/x)", BIP84_XPUB)), DescriptorError::InvalidPath("x".to_string()));
+        assert_eq!(parse(format!("pkh({}/*/0)", BIP84_XPUB)), DescriptorError::InvalidPath("*".to_string()));
+        let out_of_range = parse(format!("pkh({}/2147483648)", BIP84_XPUB));
+        assert_eq!(out_of_range, DescriptorError::InvalidPath("2147483648".to_string()));
+
+        assert_eq!(parse(format!("sh(pkh({}))", KEY)), DescriptorError::UnknownFunction(format!("pkh({})", KEY)));
+        assert_eq!(parse(format!("wsh(wpkh({}))", KEY)), DescriptorError::UnknownFunction(format!("wpkh({})", KEY)));
+        // script trees aren't supported
+        let tree = format!("tr({},pk({}))", KEY, KEY);
+        assert_eq!(parse(tree.clone()), DescriptorError::UnknownFunction(tree));
+        assert_eq!(parse(format!("wsh(multi(2,{}))", KEY)), DescriptorError::Multisig(MultisigError::RequiredCount(2)));
+        assert_eq!(parse(format!("pkh({}", KEY)), DescriptorError::Syntax(format!("pkh({}", KEY)));
+    }
+}
@@ -0,0 +1,1715 @@
+use ec_cryptography::{
+    consensus::{ConsensusEncode, VarInt},
+    helper::{hash160, hash256, ripemd160, sha256, Hasher},
+    serializer::parse_sec,
+    verify_schnorr, Signature,
+};
+use rug::{integer::Order, Integer};
+use sha1::{Digest, Sha1};
+
+use crate::{encode_push, Command, Opcode, Script, OP_PUSHDATA1, OP_PUSHDATA2, OP_PUSHDATA4};
+
+/// The most bytes a push can put on the stack
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+/// The most opcodes, pushes aside, a script can hold
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+/// The most items the stack and the altstack can hold together
+pub const MAX_STACK_SIZE: usize = 1000;
+/// The longest script that can run
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+/// The most bytes the numbers OP_CHECKLOCKTIMEVERIFY and OP_CHECKSEQUENCEVERIFY take can have,
+/// so they reach past 2^31
+pub const LOCKTIME_NUM_SIZE: usize = 5;
+/// Set on OP_CHECKSEQUENCEVERIFY arguments that aren't relative locks, for which it is still a NOP
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: i64 = 1 << 31;
+/// The most public keys OP_CHECKMULTISIG takes
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+/// The most bytes the numbers arithmetic opcodes take can have
+pub const MAX_NUM_SIZE: usize = 4;
+/// The leaf version of BIP342 tapscripts
+pub const TAPSCRIPT_LEAF_VERSION: u8 = 0xc0;
+/// The first byte of a taproot annex, which sets it apart from the other witness items
+pub const TAPROOT_ANNEX_PREFIX: u8 = 0x50;
+/// The most hashes the merkle path of a control block can hold, for a script tree 128 levels deep
+pub const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+/// The first byte of a control block holds the leaf version in its top 7 bits, and the parity in the last
+pub const TAPROOT_LEAF_MASK: u8 = 0xfe;
+/// What each signature a tapscript checks costs out of its validation weight budget (BIP342)
+const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+/// The validation weight budget every tapscript gets on top of the size of its witness
+const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+
+/// Why a script failed
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScriptError {
+    /// An opcode needed more items than the stack holds, or OP_PICK/OP_ROLL an item below the bottom
+    StackUnderflow,
+    /// OP_FROMALTSTACK ran with an empty altstack
+    AltStackUnderflow,
+    /// A number operand is longer than the opcode allows
+    NumberOverflow,
+    /// OP_VERIFY, or an opcode that ends with a verify, found false
+    VerifyFailed(Opcode),
+    /// OP_RETURN ran
+    OpReturn,
+    /// The script holds one of the opcodes disabled in 2010, even in a branch that doesn't run
+    DisabledOpcode(Opcode),
+    /// An opcode that is reserved, undefined or not supported here ran
+    BadOpcode(u8),
+    /// OP_CHECKMULTISIG got a key count outside 0 to MAX_PUBKEYS_PER_MULTISIG
+    PubKeyCount(i64),
+    /// OP_CHECKMULTISIG got a signature count outside 0 to its key count
+    SigCount(i64),
+    /// OP_CHECKLOCKTIMEVERIFY or OP_CHECKSEQUENCEVERIFY got a negative number
+    NegativeLockTime,
+    /// The spending transaction isn't locked until the time OP_CHECKLOCKTIMEVERIFY asks for,
+    /// or the input not for as long as OP_CHECKSEQUENCEVERIFY asks for
+    UnsatisfiedLockTime,
+    /// The script_sig of a P2SH spend does something other than push data
+    SigPushOnly,
+    /// A witness program output was spent with a non-empty script_sig
+    WitnessMalleated,
+    /// The script_sig of a P2SH wrapped witness program holds more than the push of the redeem script
+    WitnessMalleatedP2sh,
+    /// An input with a witness spends something other than a witness program
+    WitnessUnexpected,
+    /// The witness doesn't have the items the witness program takes, or its witness script
+    /// doesn't hash to the program
+    WitnessProgramMismatch,
+    /// A P2WSH output was spent with an empty witness, which has no witness script
+    WitnessProgramWitnessEmpty,
+    /// A version 0 witness program is neither 20 nor 32 bytes long
+    WitnessProgramWrongLength,
+    /// A witness program of a version left for soft forks, under `discourage_upgradable_witness_program`
+    DiscourageUpgradableWitnessProgram,
+    /// A witness script left more than the one true item on the stack
+    CleanStack,
+    /// OP_ELSE or OP_ENDIF without an OP_IF, or an OP_IF without its OP_ENDIF
+    UnbalancedConditional,
+    /// A push claims more bytes than the script has left
+    InvalidPush,
+    /// A push of this many bytes, more than MAX_SCRIPT_ELEMENT_SIZE
+    PushSize(usize),
+    /// The script has more than MAX_OPS_PER_SCRIPT opcodes
+    OpCount,
+    /// The stack and altstack hold more than MAX_STACK_SIZE items
+    StackSize,
+    /// The script is this many bytes, more than MAX_SCRIPT_SIZE
+    ScriptSize(usize),
+    /// The scripts ran to the end without leaving true on top of the stack
+    EvalFalse,
+    /// A Schnorr signature that isn't empty doesn't check out, which fails a taproot spend right away
+    SchnorrSig,
+    /// A taproot control block is this many bytes, rather than 33 and up to 128 hashes of 32
+    TaprootWrongControlSize(usize),
+    /// A tapscript ran OP_CHECKSIG, OP_CHECKSIGVERIFY or OP_CHECKSIGADD with an empty public key
+    PubKeyType,
+    /// A tapscript ran OP_CHECKMULTISIG or OP_CHECKMULTISIGVERIFY, which OP_CHECKSIGADD replaces
+    TapscriptCheckMultiSig,
+    /// A tapscript OP_IF or OP_NOTIF took something other than an empty item or 0x01
+    TapscriptMinimalIf,
+    /// A tapscript checks more signatures than the size of its witness pays for
+    TapscriptValidationWeight,
+    /// A push that doesn't use the shortest opcode, or a number that isn't in its shortest encoding,
+    /// under `VerifyFlags::minimal_data`
+    MinimalData,
+}
+
+impl ScriptError {
+    /// The name Bitcoin Core gives the error, as its script test vectors write it (SCRIPT_ERR_ without the prefix).
+    /// Core can't tell numbers that are too long apart from other errors, and reads a push past the end of a
+    /// script as a bad opcode.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptError::StackUnderflow => "INVALID_STACK_OPERATION",
+            ScriptError::AltStackUnderflow => "INVALID_ALTSTACK_OPERATION",
+            ScriptError::NumberOverflow => "UNKNOWN_ERROR",
+            ScriptError::VerifyFailed(Opcode::OpEqualVerify) => "EQUALVERIFY",
+            ScriptError::VerifyFailed(Opcode::OpNumEqualVerify) => "NUMEQUALVERIFY",
+            ScriptError::VerifyFailed(Opcode::OpCheckSigVerify) => "CHECKSIGVERIFY",
+            ScriptError::VerifyFailed(Opcode::OpCheckMultiSigVerify) => "CHECKMULTISIGVERIFY",
+            ScriptError::VerifyFailed(_) => "VERIFY",
+            ScriptError::OpReturn => "OP_RETURN",
+            ScriptError::DisabledOpcode(_) => "DISABLED_OPCODE",
+            ScriptError::BadOpcode(_) | ScriptError::InvalidPush => "BAD_OPCODE",
+            ScriptError::PubKeyCount(_) => "PUBKEY_COUNT",
+            ScriptError::SigCount(_) => "SIG_COUNT",
+            ScriptError::NegativeLockTime => "NEGATIVE_LOCKTIME",
+            ScriptError::UnsatisfiedLockTime => "UNSATISFIED_LOCKTIME",
+            ScriptError::SigPushOnly => "SIG_PUSHONLY",
+            ScriptError::WitnessMalleated => "WITNESS_MALLEATED",
+            ScriptError::WitnessMalleatedP2sh => "WITNESS_MALLEATED_P2SH",
+            ScriptError::WitnessUnexpected => "WITNESS_UNEXPECTED",
+            ScriptError::WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+            ScriptError::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+            ScriptError::WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+            ScriptError::DiscourageUpgradableWitnessProgram => "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+            ScriptError::CleanStack => "CLEANSTACK",
+            ScriptError::UnbalancedConditional => "UNBALANCED_CONDITIONAL",
+            ScriptError::PushSize(_) => "PUSH_SIZE",
+            ScriptError::OpCount => "OP_COUNT",
+            ScriptError::StackSize => "STACK_SIZE",
+            ScriptError::ScriptSize(_) => "SCRIPT_SIZE",
+            ScriptError::EvalFalse => "EVAL_FALSE",
+            ScriptError::SchnorrSig => "SCHNORR_SIG",
+            ScriptError::TaprootWrongControlSize(_) => "TAPROOT_WRONG_CONTROL_SIZE",
+            ScriptError::PubKeyType => "PUBKEYTYPE",
+            ScriptError::TapscriptCheckMultiSig => "TAPSCRIPT_CHECKMULTISIG",
+            ScriptError::TapscriptMinimalIf => "TAPSCRIPT_MINIMALIF",
+            ScriptError::TapscriptValidationWeight => "TAPSCRIPT_VALIDATION_WEIGHT",
+            ScriptError::MinimalData => "MINIMALDATA",
+        }
+    }
+}
+
+/// Which rules a script runs under, which picks the signature hash its signatures commit to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigVersion {
+    /// Scripts outside witnesses, with the legacy signature hash
+    Base,
+    /// Scripts of segwit v0 witness programs, with the BIP143 signature hash
+    WitnessV0,
+    /// Taproot key path spends, with the BIP341 signature hash
+    Taproot,
+    /// Leaf scripts of taproot script path spends (BIP342), with the BIP341 signature hash of the leaf
+    Tapscript,
+}
+
+/// Rules beyond consensus that scripts can be run with. Nodes apply them to the transactions they relay,
+/// blocks only need consensus, which is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VerifyFlags {
+    /// Pushes must use the shortest opcode for their data, OP_1 to OP_16 and OP_1NEGATE included,
+    /// and numbers must be in their shortest encoding (MINIMALDATA)
+    pub minimal_data: bool,
+    /// Witness programs of versions 2 to 16, and of version 1 other than taproot, fail rather than being
+    /// anyone-can-spend, so that nothing is relayed that a soft fork giving them a meaning could invalidate
+    /// (DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM)
+    pub discourage_upgradable_witness_program: bool,
+}
+
+impl VerifyFlags {
+    /// The rules Bitcoin Core relays transactions under, of the ones checked here
+    pub const STANDARD: VerifyFlags = VerifyFlags { minimal_data: true, discourage_upgradable_witness_program: true };
+}
+
+/// The leaf a taproot input is spent through, for script path spends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapLeafSpend {
+    /// The tapleaf hash of the executed script
+    pub leaf_hash: [u8; 32],
+    /// The opcode position of the last executed OP_CODESEPARATOR, or 0xffffffff if none was
+    pub codesep_pos: u32,
+}
+
+impl TapLeafSpend {
+    /// A spend of the leaf without any OP_CODESEPARATOR
+    pub fn new(leaf_hash: [u8; 32]) -> TapLeafSpend {
+        TapLeafSpend { leaf_hash, codesep_pos: u32::MAX }
+    }
+}
+
+/// The BIP341 tapleaf hash of a script
+pub fn tap_leaf_hash(script: &Script, leaf_version: u8) -> [u8; 32] {
+    let hash = Hasher::tagged("TapLeaf")
+        .chain(&[leaf_version])
+        .chain(&script.consensus_serialize())
+        .finalize();
+    hash.try_into().expect("sha256 is 32 bytes")
+}
+
+/// Checks the signatures the signature opcodes find on the stack
+pub trait SignatureChecker {
+    /// Whether `signature`, DER with its hash type byte, is valid for the SEC `public_key`.
+    /// `script_code` is the script the signature commits to: the one running, from its last
+    /// OP_CODESEPARATOR on and, outside witnesses, without the signature.
+    fn check_signature(&self, signature: &[u8], public_key: &[u8], script_code: &Script, sig_version: SigVersion)
+        -> bool;
+
+    /// Whether the spending transaction is locked until at least `lock_time` (BIP65), a height or a
+    /// timestamp like its own locktime. Without a transaction to check there is nothing to compare to.
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        let _ = lock_time;
+        false
+    }
+
+    /// Whether the input being spent has a relative lock of at least `sequence` (BIP112), encoded like
+    /// the sequences of inputs (BIP68). The interpreter has already skipped sequences with the disable flag.
+    fn check_sequence(&self, sequence: i64) -> bool {
+        let _ = sequence;
+        false
+    }
+
+    /// Whether `signature`, BIP340 Schnorr of 64 bytes or 65 with a hash type byte, is valid for the
+    /// x-only `public_key` over the BIP341 signature hash. It commits to the `annex` of the witness, if
+    /// there is one, and for tapscript signatures to the `leaf` they are checked in.
+    fn check_schnorr_signature(
+        &self,
+        signature: &[u8],
+        public_key: &[u8; 32],
+        annex: Option<&[u8]>,
+        leaf: Option<TapLeafSpend>,
+    ) -> bool {
+        let _ = (signature, public_key, annex, leaf);
+        false
+    }
+
+    /// Whether `control_block` proves `leaf_script` is in the script tree the taproot `output_key`
+    /// commits to (BIP341). The interpreter has already checked its size.
+    fn check_taproot_commitment(&self, control_block: &[u8], leaf_script: &Script, output_key: &[u8; 32]) -> bool {
+        let _ = (control_block, leaf_script, output_key);
+        false
+    }
+}
+
+/// Checks every signature against the same hash z, whatever its hash type byte,
+/// as the chapters on script evaluation do. With no transaction, it can't satisfy timelocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedSighash(pub [u8; 32]);
+
+impl SignatureChecker for FixedSighash {
+    fn check_signature(&self, signature: &[u8], public_key: &[u8], _script_code: &Script, _: SigVersion) -> bool {
+        let Some((_hash_type, der)) = signature.split_last() else {
+            return false;
+        };
+        match (Signature::parse_der(der), parse_sec(public_key)) {
+            (Some(signature), Some(point)) => signature.verify(&point, Integer::from_digits(&self.0, Order::MsfBe)),
+            _ => false,
+        }
+    }
+
+    fn check_schnorr_signature(
+        &self,
+        signature: &[u8],
+        public_key: &[u8; 32],
+        _annex: Option<&[u8]>,
+        _leaf: Option<TapLeafSpend>,
+    ) -> bool {
+        let signature = match signature.len() {
+            64 | 65 => signature[..64].try_into().expect("checked the length"),
+            _ => return false,
+        };
+        verify_schnorr(public_key, &self.0, signature)
+    }
+}
+
+/// What the interpreter tracks while running a single script
+struct Frame<'s> {
+    script: &'s Script,
+    /// Whether each enclosing OP_IF branch runs
+    conditions: Vec<bool>,
+    op_count: usize,
+    /// Where the script_code signatures commit to starts
+    code_start: usize,
+    /// The opcode position of the last OP_CODESEPARATOR, which tapscript signatures commit to instead
+    codesep_pos: u32,
+}
+
+impl Frame<'_> {
+    fn count_ops(&mut self, count: usize) -> Result<(), ScriptError> {
+        self.op_count += count;
+        if self.op_count > MAX_OPS_PER_SCRIPT {
+            return Err(ScriptError::OpCount);
+        }
+        Ok(())
+    }
+}
+
+/// What the signatures of a tapscript commit to beyond the transaction, and what they may cost
+struct TapscriptContext {
+    leaf_hash: [u8; 32],
+    annex: Option<Vec<u8>>,
+    validation_weight_left: i64,
+}
+
+/// The stack machine scripts run on. The stack carries over from one script to the next,
+/// which is how the script_sig hands its pushes to the script_pubkey.
+pub struct Interpreter<'a, C: SignatureChecker> {
+    stack: Vec<Vec<u8>>,
+    alt_stack: Vec<Vec<u8>>,
+    checker: &'a C,
+    sig_version: SigVersion,
+    /// Set while running a tapscript
+    tapscript: Option<TapscriptContext>,
+    flags: VerifyFlags,
+}
+
+impl<'a, C: SignatureChecker> Interpreter<'a, C> {
+    pub fn new(checker: &'a C) -> Interpreter<'a, C> {
+        Interpreter::with_stack(vec![], checker)
+    }
+
+    /// An interpreter whose stack starts with these items, the last one on top
+    pub fn with_stack(stack: Vec<Vec<u8>>, checker: &'a C) -> Interpreter<'a, C> {
+        Interpreter {
+            stack,
+            alt_stack: vec![],
+            checker,
+            sig_version: SigVersion::Base,
+            tapscript: None,
+            flags: VerifyFlags::default(),
+        }
+    }
+
+    /// Runs scripts with the rules of `flags` on top of consensus
+    pub fn with_flags(mut self, flags: VerifyFlags) -> Interpreter<'a, C> {
+        self.flags = flags;
+        self
+    }
+
+    /// The stack, bottom first
+    pub fn stack(&self) -> &[Vec<u8>] {
+        &self.stack
+    }
+
+    /// Whether the item on top of the stack is true
+    pub fn is_true(&self) -> bool {
+        self.stack.last().is_some_and(|top| cast_to_bool(top))
+    }
+
+    /// Runs the script. The altstack doesn't outlive it, the stack does.
+    pub fn execute(&mut self, script: &Script) -> Result<(), ScriptError> {
+        // tapscripts are only limited by the size of the block and their validation weight
+        let is_tapscript = self.sig_version == SigVersion::Tapscript;
+        if script.len() > MAX_SCRIPT_SIZE && !is_tapscript {
+            return Err(ScriptError::ScriptSize(script.len()));
+        }
+        self.alt_stack.clear();
+
+        let mut frame = Frame { script, conditions: vec![], op_count: 0, code_start: 0, codesep_pos: u32::MAX };
+        let mut offset = 0;
+
+        for (position, command) in script.commands().iter().enumerate() {
+            let start = offset;
+            offset += command_size(script.as_bytes(), offset, command);
+            let executing = frame.conditions.iter().all(|&condition| condition);
+
+            match command {
+                Command::Invalid(_) => return Err(ScriptError::InvalidPush),
+                Command::Push(data) => {
+                    if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                        return Err(ScriptError::PushSize(data.len()));
+                    }
+                    if executing {
+                        // only pushes that run have to be minimal
+                        if self.flags.minimal_data && !is_minimal_push(script.as_bytes()[start], data) {
+                            return Err(ScriptError::MinimalData);
+                        }
+                        self.stack.push(data.clone());
+                    }
+                }
+                Command::Op(byte) => {
+                    // pushes of small numbers don't count
+                    if *byte > Opcode::Op16.to_u8() && !is_tapscript {
+                        frame.count_ops(1)?;
+                    }
+                    let opcode = Opcode::from_u8(*byte);
+                    if let Some(opcode) = opcode.filter(|opcode| opcode.is_disabled()) {
+                        return Err(ScriptError::DisabledOpcode(opcode));
+                    }
+
+                    // the conditionals run in skipped branches too, to track where they end
+                    let is_conditional = (Opcode::OpIf.to_u8()..=Opcode::OpEndIf.to_u8()).contains(byte);
+                    if executing || is_conditional {
+                        match opcode {
+                            Some(Opcode::OpCodeSeparator) => {
+                                frame.code_start = offset;
+                                frame.codesep_pos = position as u32;
+                            }
+                            Some(opcode) => self.step(opcode, executing, &mut frame)?,
+                            None => return Err(ScriptError::BadOpcode(*byte)),
+                        }
+                    }
+                }
+            }
+
+            if self.stack.len() + self.alt_stack.len() > MAX_STACK_SIZE {
+                return Err(ScriptError::StackSize);
+            }
+        }
+
+        if !frame.conditions.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+        Ok(())
+    }
+
+    /// Runs a single opcode. `executing` is false in branches that are skipped, where only
+    /// the conditionals get here.
+    fn step(&mut self, opcode: Opcode, executing: bool, frame: &mut Frame) -> Result<(), ScriptError> {
+        use Opcode::*;
+
+        if let Some(number) = opcode.small_number() {
+            self.stack.push(encode_num(number as i64));
+            return Ok(());
+        }
+
+        match opcode {
+            // flow control
+            OpNop | OpNop1 | OpNop4 | OpNop5 | OpNop6 | OpNop7
+            | OpNop8 | OpNop9 | OpNop10 => {}
+            OpIf | OpNotIf => {
+                let condition = if executing {
+                    let top = self.stack.pop().ok_or(ScriptError::UnbalancedConditional)?;
+                    if self.sig_version == SigVersion::Tapscript && !(top.is_empty() || top == [1]) {
+                        return Err(ScriptError::TapscriptMinimalIf);
+                    }
+                    cast_to_bool(&top) == (opcode == OpIf)
+                } else {
+                    false
+                };
+                frame.conditions.push(condition);
+            }
+            OpElse => {
+                let condition = frame.conditions.last_mut().ok_or(ScriptError::UnbalancedConditional)?;
+                *condition = !*condition;
+            }
+            OpEndIf => {
+                frame.conditions.pop().ok_or(ScriptError::UnbalancedConditional)?;
+            }
+            OpVerify => self.verify(OpVerify)?,
+            OpCheckLockTimeVerify => {
+                // the locktime stays on the stack, it was a NOP before BIP65
+                let lock_time = self.decode_num(self.top(1)?, LOCKTIME_NUM_SIZE)?;
+                if lock_time < 0 {
+                    return Err(ScriptError::NegativeLockTime);
+                }
+                if !self.checker.check_lock_time(lock_time) {
+                    return Err(ScriptError::UnsatisfiedLockTime);
+                }
+            }
+            OpCheckSequenceVerify => {
+                let sequence = self.decode_num(self.top(1)?, LOCKTIME_NUM_SIZE)?;
+                if sequence < 0 {
+                    return Err(ScriptError::NegativeLockTime);
+                }
+                // left for future soft forks when the disable flag is set
+                if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG == 0 && !self.checker.check_sequence(sequence) {
+                    return Err(ScriptError::UnsatisfiedLockTime);
+                }
+            }
+            OpReturn => return Err(ScriptError::OpReturn),
+
+            // stack
+            OpToAltStack => {
+                let top = self.pop()?;
+                self.alt_stack.push(top);
+            }
+            OpFromAltStack => {
+                let top = self.alt_stack.pop().ok_or(ScriptError::AltStackUnderflow)?;
+                self.stack.push(top);
+            }
+            Op2Drop => {
+                self.pop()?;
+                self.pop()?;
+            }
+            Op2Dup => self.copy(2, 2)?,
+            Op3Dup => self.copy(3, 3)?,
+            Op2Over => self.copy(4, 2)?,
+            Op2Rot => {
+                self.depth(6)?;
+                let moved: Vec<_> = self.stack.drain(self.stack.len() - 6..self.stack.len() - 4).collect();
+                self.stack.extend(moved);
+            }
+            Op2Swap => {
+                self.depth(4)?;
+                let moved: Vec<_> = self.stack.drain(self.stack.len() - 4..self.stack.len() - 2).collect();
+                self.stack.extend(moved);
+            }
+            OpIfDup => {
+                if cast_to_bool(self.top(1)?) {
+                    self.copy(1, 1)?;
+                }
+            }
+            OpDepth => self.stack.push(encode_num(self.stack.len() as i64)),
+            OpDrop => {
+                self.pop()?;
+            }
+            OpDup => self.copy(1, 1)?,
+            OpNip => {
+                self.depth(2)?;
+                self.stack.remove(self.stack.len() - 2);
+            }
+            OpOver => self.copy(2, 1)?,
+            OpPick | OpRoll => {
+                let n = self.pop_num()?;
+                if n < 0 || n as usize >= self.stack.len() {
+                    return Err(ScriptError::StackUnderflow);
+                }
+                let index = self.stack.len() - 1 - n as usize;
+                let item = if opcode == OpPick {
+                    self.stack[index].clone()
+                } else {
+                    self.stack.remove(index)
+                };
+                self.stack.push(item);
+            }
+            OpRot => {
+                self.depth(3)?;
+                let item = self.stack.remove(self.stack.len() - 3);
+                self.stack.push(item);
+            }
+            OpSwap => {
+                self.depth(2)?;
+                let length = self.stack.len();
+                self.stack.swap(length - 1, length - 2);
+            }
+            OpTuck => {
+                let top = self.top(1)?.clone();
+                self.depth(2)?;
+                self.stack.insert(self.stack.len() - 2, top);
+            }
+            OpSize => {
+                let size = self.top(1)?.len();
+                self.stack.push(encode_num(size as i64));
+            }
+
+            // bitwise logic
+            OpEqual | OpEqualVerify => {
+                let (b, a) = (self.pop()?, self.pop()?);
+                self.push_bool(a == b);
+                if opcode == OpEqualVerify {
+                    self.verify(opcode)?;
+                }
+            }
+
+            // arithmetic
+            Op1Add | Op1Sub | OpNegate | OpAbs | OpNot | Op0NotEqual => {
+                let a = self.pop_num()?;
+                let result = match opcode {
+                    Op1Add => a + 1,
+                    Op1Sub => a - 1,
+                    OpNegate => -a,
+                    OpAbs => a.abs(),
+                    OpNot => (a == 0) as i64,
+                    _ => (a != 0) as i64,
+                };
+                self.stack.push(encode_num(result));
+            }
+            OpAdd | OpSub | OpBoolAnd | OpBoolOr | OpNumEqual | OpNumEqualVerify | OpNumNotEqual | OpLessThan
+            | OpGreaterThan | OpLessThanOrEqual | OpGreaterThanOrEqual | OpMin | OpMax => {
+                let (b, a) = (self.pop_num()?, self.pop_num()?);
+                let result = match opcode {
+                    OpAdd => a + b,
+                    OpSub => a - b,
+                    OpBoolAnd => (a != 0 && b != 0) as i64,
+                    OpBoolOr => (a != 0 || b != 0) as i64,
+                    OpNumEqual | OpNumEqualVerify => (a == b) as i64,
+                    OpNumNotEqual => (a != b) as i64,
+                    OpLessThan => (a < b) as i64,
+                    OpGreaterThan => (a > b) as i64,
+                    OpLessThanOrEqual => (a <= b) as i64,
+                    OpGreaterThanOrEqual => (a >= b) as i64,
+                    OpMin => a.min(b),
+                    _ => a.max(b),
+                };
+                self.stack.push(encode_num(result));
+                if opcode == OpNumEqualVerify {
+                    self.verify(opcode)?;
+                }
+            }
+            OpWithin => {
+                let (max, min, x) = (self.pop_num()?, self.pop_num()?, self.pop_num()?);
+                self.push_bool(min <= x && x < max);
+            }
+
+            // crypto
+            OpRipemd160 | OpSha1 | OpSha256 | OpHash160 | OpHash256 => {
+                let data = self.pop()?;
+                let hash = match opcode {
+                    OpRipemd160 => ripemd160(&data),
+                    OpSha1 => Sha1::digest(&data).to_vec(),
+                    OpSha256 => sha256(&data),
+                    OpHash160 => hash160(&data),
+                    _ => hash256(&data),
+                };
+                self.stack.push(hash);
+            }
+            OpCheckSig | OpCheckSigVerify if self.sig_version == SigVersion::Tapscript => {
+                let (public_key, signature) = (self.pop()?, self.pop()?);
+                let is_valid = self.check_tapscript_signature(&signature, &public_key, frame)?;
+                self.push_bool(is_valid);
+                if opcode == OpCheckSigVerify {
+                    self.verify(opcode)?;
+                }
+            }
+            OpCheckSigAdd if self.sig_version == SigVersion::Tapscript => {
+                let (public_key, n, signature) = (self.pop()?, self.pop_num()?, self.pop()?);
+                let is_valid = self.check_tapscript_signature(&signature, &public_key, frame)?;
+                self.stack.push(encode_num(n + is_valid as i64));
+            }
+            OpCheckMultiSig | OpCheckMultiSigVerify if self.sig_version == SigVersion::Tapscript => {
+                return Err(ScriptError::TapscriptCheckMultiSig);
+            }
+            OpCheckSig | OpCheckSigVerify => {
+                let (public_key, signature) = (self.pop()?, self.pop()?);
+                let script_code = self.script_code(frame, &[&signature]);
+                let is_valid = !signature.is_empty()
+                    && self.checker.check_signature(&signature, &public_key, &script_code, self.sig_version);
+                self.push_bool(is_valid);
+                if opcode == OpCheckSigVerify {
+                    self.verify(opcode)?;
+                }
+            }
+            OpCheckMultiSig | OpCheckMultiSigVerify => {
+                let key_count = self.pop_num()?;
+                if !(0..=MAX_PUBKEYS_PER_MULTISIG as i64).contains(&key_count) {
+                    return Err(ScriptError::PubKeyCount(key_count));
+                }
+                // every key counts toward the opcode limit
+                frame.count_ops(key_count as usize)?;
+                let mut public_keys = (0..key_count).map(|_| self.pop()).collect::<Result<Vec<_>, _>>()?;
+                public_keys.reverse();
+
+                let signature_count = self.pop_num()?;
+                if !(0..=key_count).contains(&signature_count) {
+                    return Err(ScriptError::SigCount(signature_count));
+                }
+                let mut signatures = (0..signature_count).map(|_| self.pop()).collect::<Result<Vec<_>, _>>()?;
+                signatures.reverse();
+
+                // the original implementation pops one item too many, which every spend now has to provide
+                self.pop()?;
+
+                let deleted: Vec<&[u8]> = signatures.iter().map(Vec::as_slice).collect();
+                let script_code = self.script_code(frame, &deleted);
+
+                // signatures must come in the same order as their keys, so each key is tried once,
+                // giving up as soon as the keys left are fewer than the signatures left
+                let (mut signature_index, mut key_index) = (0, 0);
+                while signature_index < signatures.len()
+                    && signatures.len() - signature_index <= public_keys.len() - key_index
+                {
+                    let signature = &signatures[signature_index];
+                    if !signature.is_empty()
+                        && self.checker.check_signature(signature, &public_keys[key_index], &script_code, self.sig_version)
+                    {
+                        signature_index += 1;
+                    }
+                    key_index += 1;
+                }
+                self.push_bool(signature_index == signatures.len());
+                if opcode == OpCheckMultiSigVerify {
+                    self.verify(opcode)?;
+                }
+            }
+
+            _ => return Err(ScriptError::BadOpcode(opcode.to_u8())),
+        }
+        Ok(())
+    }
+
+    /// Checks a tapscript signature (BIP342). An empty signature is false, any other has to be valid,
+    /// and 32-byte keys are the only ones checked: the other lengths are left for new key types.
+    fn check_tapscript_signature(
+        &mut self,
+        signature: &[u8],
+        public_key: &[u8],
+        frame: &Frame,
+    ) -> Result<bool, ScriptError> {
+        let tapscript = self.tapscript.as_mut().expect("tapscripts run with their context");
+        if !signature.is_empty() {
+            tapscript.validation_weight_left -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+            if tapscript.validation_weight_left < 0 {
+                return Err(ScriptError::TapscriptValidationWeight);
+            }
+        }
+
+        match public_key.len() {
+            0 => return Err(ScriptError::PubKeyType),
+            32 if !signature.is_empty() => {
+                let public_key = public_key.try_into().expect("checked the length");
+                let leaf = TapLeafSpend { leaf_hash: tapscript.leaf_hash, codesep_pos: frame.codesep_pos };
+                let annex = tapscript.annex.as_deref();
+                if !self.checker.check_schnorr_signature(signature, public_key, annex, Some(leaf)) {
+                    return Err(ScriptError::SchnorrSig);
+                }
+            }
+            _ => {}
+        }
+        Ok(!signature.is_empty())
+    }
+
+    /// The script code signatures commit to. Witness scripts can't hold their own signatures,
+    /// so nothing is deleted from them.
+    fn script_code(&self, frame: &Frame, signatures: &[&[u8]]) -> Script {
+        script_code(frame.script, frame.code_start, self.sig_version, signatures)
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>, ScriptError> {
+        self.stack.pop().ok_or(ScriptError::StackUnderflow)
+    }
+
+    fn pop_num(&mut self) -> Result<i64, ScriptError> {
+        let item = self.pop()?;
+        self.decode_num(&item, MAX_NUM_SIZE)
+    }
+
+    /// `decode_num`, which under MINIMALDATA also takes only the shortest encoding
+    fn decode_num(&self, bytes: &[u8], max_size: usize) -> Result<i64, ScriptError> {
+        let number = decode_num(bytes, max_size)?;
+        if self.flags.minimal_data && *bytes != encode_num(number) {
+            return Err(ScriptError::MinimalData);
+        }
+        Ok(number)
+    }
+
+    /// The item `n` from the top, 1 being the top
+    fn top(&self, n: usize) -> Result<&Vec<u8>, ScriptError> {
+        self.depth(n)?;
+        Ok(&self.stack[self.stack.len() - n])
+    }
+
+    fn depth(&self, n: usize) -> Result<(), ScriptError> {
+        if self.stack.len() < n {
+            return Err(ScriptError::StackUnderflow);
+        }
+        Ok(())
+    }
+
+    /// Pushes copies of `count` items, starting with the one `depth` from the top
+    fn copy(&mut self, depth: usize, count: usize) -> Result<(), ScriptError> {
+        self.depth(depth)?;
+        for _ in 0..count {
+            // each copy pushed moves the next item to copy to the same depth
+            let item = self.stack[self.stack.len() - depth].clone();
+            self.stack.push(item);
+        }
+        Ok(())
+    }
+
+    fn push_bool(&mut self, value: bool) {
+        self.stack.push(if value { vec![1] } else { vec![] });
+    }
+
+    fn verify(&mut self, opcode: Opcode) -> Result<(), ScriptError> {
+        if cast_to_bool(&self.pop()?) {
+            Ok(())
+        } else {
+            Err(ScriptError::VerifyFailed(opcode))
+        }
+    }
+}
+
+impl Script {
+    /// Runs the script with every signature checked against the hash `z`.
+    /// It succeeds if it runs to the end and leaves true on top of the stack.
+    pub fn evaluate(&self, z: &[u8; 32]) -> Result<(), ScriptError> {
+        self.evaluate_with(&FixedSighash(*z))
+    }
+
+    /// Runs the script with `checker` checking the signatures
+    pub fn evaluate_with(&self, checker: &impl SignatureChecker) -> Result<(), ScriptError> {
+        let mut interpreter = Interpreter::new(checker);
+        interpreter.execute(self)?;
+        if interpreter.is_true() {
+            Ok(())
+        } else {
+            Err(ScriptError::EvalFalse)
+        }
+    }
+
+    /// Runs the script_sig, then the script_pubkey on the stack it leaves. The spend is valid
+    /// if the script_pubkey runs to the end and leaves true on top.
+    ///
+    /// P2SH script_pubkeys (BIP16) only check the hash of the last push of the script_sig,
+    /// the redeem script, so that script then runs too, on the other pushes, and must leave true as well.
+    /// Witness programs (BIP141), bare or as a redeem script, are spent by the `witness` instead,
+    /// which must be empty for other outputs. Bare version 1 programs of 32 bytes are taproot outputs (BIP341).
+    ///
+    /// This is the one entry point for checking a spend: `checker` is what ties it to a transaction,
+    /// checking signatures against its signature hashes and locks against its locktime and sequences.
+    pub fn verify_spend(
+        script_sig: &Script,
+        script_pubkey: &Script,
+        witness: &[Vec<u8>],
+        checker: &impl SignatureChecker,
+    ) -> Result<(), ScriptError> {
+        Script::verify_spend_with_flags(script_sig, script_pubkey, witness, checker, VerifyFlags::default())
+    }
+
+    /// `verify_spend`, with the rules of `flags` on top of consensus
+    pub fn verify_spend_with_flags(
+        script_sig: &Script,
+        script_pubkey: &Script,
+        witness: &[Vec<u8>],
+        checker: &impl SignatureChecker,
+        flags: VerifyFlags,
+    ) -> Result<(), ScriptError> {
+        let mut interpreter = Interpreter::new(checker).with_flags(flags);
+        interpreter.execute(script_sig)?;
+        let mut stack = interpreter.stack.clone();
+        interpreter.execute(script_pubkey)?;
+        if !interpreter.is_true() {
+            return Err(ScriptError::EvalFalse);
+        }
+
+        let mut has_witness = false;
+        if let Some((version, program)) = script_pubkey.witness_program() {
+            // everything is in the witness, anything in the script_sig could be changed by anyone
+            if !script_sig.is_empty() {
+                return Err(ScriptError::WitnessMalleated);
+            }
+            verify_witness_program(version, program, witness, checker, flags, false)?;
+            has_witness = true;
+        } else if script_pubkey.p2sh_hash().is_some() {
+            if !script_sig.is_push_only() {
+                return Err(ScriptError::SigPushOnly);
+            }
+            // the script_pubkey hashed the redeem script, so it's there
+            let redeem_script = Script::from_bytes(stack.pop().ok_or(ScriptError::StackUnderflow)?);
+            let mut interpreter = Interpreter::with_stack(stack, checker).with_flags(flags);
+            interpreter.execute(&redeem_script)?;
+            if !interpreter.is_true() {
+                return Err(ScriptError::EvalFalse);
+            }
+
+            if let Some((version, program)) = redeem_script.witness_program() {
+                let redeem_push = Script::from_commands(vec![Command::Push(redeem_script.as_bytes().to_vec())]);
+                if *script_sig != redeem_push {
+                    return Err(ScriptError::WitnessMalleatedP2sh);
+                }
+                verify_witness_program(version, program, witness, checker, flags, true)?;
+                has_witness = true;
+            }
+        }
+
+        if !has_witness && !witness.is_empty() {
+            return Err(ScriptError::WitnessUnexpected);
+        }
+        Ok(())
+    }
+}
+
+/// Spends a witness program with its witness, running the script the program stands for on the witness items.
+/// `is_p2sh` is set for programs nested in P2SH, which taproot doesn't apply to.
+fn verify_witness_program(
+    version: u8,
+    program: &[u8],
+    witness: &[Vec<u8>],
+    checker: &impl SignatureChecker,
+    flags: VerifyFlags,
+    is_p2sh: bool,
+) -> Result<(), ScriptError> {
+    let mut tapscript = None;
+    let (script, stack) = match (version, program.len()) {
+        // P2WPKH: a signature and a key, checked the way P2PKH does
+        (0, 20) => {
+            if witness.len() != 2 {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            (Script::p2pkh(program.try_into().expect("the program is 20 bytes")), witness)
+        }
+        // P2WSH: the witness script comes last, and runs on the items before it
+        (0, 32) => {
+            let (witness_script, stack) = witness.split_last().ok_or(ScriptError::WitnessProgramWitnessEmpty)?;
+            if sha256(witness_script) != program {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            (Script::from_bytes(witness_script.clone()), stack)
+        }
+        (0, _) => return Err(ScriptError::WitnessProgramWrongLength),
+        // P2TR: a signature for the output key, or a leaf script of the tree it commits to
+        (1, 32) if !is_p2sh => {
+            let output_key = program.try_into().expect("the program is 32 bytes");
+            // an annex is the last of at least two items, and only signatures read it
+            let (annex, items) = match witness.split_last() {
+                Some((last, rest)) if !rest.is_empty() && last.first() == Some(&TAPROOT_ANNEX_PREFIX) => {
+                    (Some(last.as_slice()), rest)
+                }
+                _ => (None, witness),
+            };
+
+            let (control_block, leaf_script, stack) = match items {
+                [] => return Err(ScriptError::WitnessProgramWitnessEmpty),
+                [signature] => {
+                    return if checker.check_schnorr_signature(signature, output_key, annex, None) {
+                        Ok(())
+                    } else {
+                        Err(ScriptError::SchnorrSig)
+                    };
+                }
+                [stack @ .., leaf_script, control_block] => (control_block, leaf_script, stack),
+            };
+
+            let path_length = control_block.len().wrapping_sub(33);
+            if control_block.len() < 33
+                || !path_length.is_multiple_of(32)
+                || path_length / 32 > TAPROOT_CONTROL_MAX_NODE_COUNT
+            {
+                return Err(ScriptError::TaprootWrongControlSize(control_block.len()));
+            }
+            let leaf_script = Script::from_bytes(leaf_script.clone());
+            if !checker.check_taproot_commitment(control_block, &leaf_script, output_key) {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+
+            // other leaf versions are left for future soft forks, anyone can spend them
+            let leaf_version = control_block[0] & TAPROOT_LEAF_MASK;
+            if leaf_version != TAPSCRIPT_LEAF_VERSION {
+                return Ok(());
+            }
+            // so are tapscripts holding an OP_SUCCESS, even in a branch that doesn't run
+            for command in leaf_script.commands() {
+                match command {
+                    Command::Invalid(_) => return Err(ScriptError::InvalidPush),
+                    Command::Op(byte) if is_op_success(*byte) => return Ok(()),
+                    _ => {}
+                }
+            }
+
+            // signatures cost 50 each out of a budget the size of the witness pays for
+            let witness_size = witness
+                .iter()
+                .fold(compact_size_len(witness.len()), |size, item| size + compact_size_len(item.len()) + item.len());
+            tapscript = Some(TapscriptContext {
+                leaf_hash: tap_leaf_hash(&leaf_script, leaf_version),
+                annex: annex.map(<[u8]>::to_vec),
+                validation_weight_left: VALIDATION_WEIGHT_OFFSET + witness_size as i64,
+            });
+            if stack.len() > MAX_STACK_SIZE {
+                return Err(ScriptError::StackSize);
+            }
+            (leaf_script, stack)
+        }
+        // later versions are left for future soft forks, anyone can spend them
+        _ if flags.discourage_upgradable_witness_program => return Err(ScriptError::DiscourageUpgradableWitnessProgram),
+        _ => return Ok(()),
+    };
+
+    if let Some(item) = stack.iter().find(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+        return Err(ScriptError::PushSize(item.len()));
+    }
+    let sig_version = if tapscript.is_some() { SigVersion::Tapscript } else { SigVersion::WitnessV0 };
+    let mut interpreter = Interpreter {
+        stack: stack.to_vec(),
+        alt_stack: vec![],
+        checker,
+        sig_version,
+        tapscript,
+        flags,
+    };
+    interpreter.execute(&script)?;
+
+    // a witness script must leave its result alone on the stack
+    if interpreter.stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    if interpreter.is_true() {
+        Ok(())
+    } else {
+        Err(ScriptError::EvalFalse)
+    }
+}
+
+/// Whether `data` is pushed with the shortest opcode for it, `opcode` being the one it was pushed with.
+/// Empty data takes OP_0, single bytes of 1 to 16 and 0x81 take OP_1 to OP_16 and OP_1NEGATE.
+fn is_minimal_push(opcode: u8, data: &[u8]) -> bool {
+    match data {
+        [] | [1..=16] | [0x81] => false,
+        _ if data.len() <= 75 => opcode as usize == data.len(),
+        _ if data.len() <= 0xff => opcode == OP_PUSHDATA1,
+        _ if data.len() <= 0xffff => opcode == OP_PUSHDATA2,
+        _ => true,
+    }
+}
+
+/// How many bytes the varint of a length takes up
+fn compact_size_len(length: usize) -> usize {
+    VarInt(length as u64).consensus_serialize().len()
+}
+
+/// Whether the byte is one of the OP_SUCCESS opcodes of tapscript (BIP342), which make any
+/// tapscript holding them valid so that soft forks can give them a meaning
+fn is_op_success(byte: u8) -> bool {
+    matches!(byte, 80 | 98 | 126..=129 | 131..=134 | 137 | 138 | 141 | 142 | 149..=153 | 187..=254)
+}
+
+/// Encodes a number the way scripts push them: little-endian, as few bytes as possible,
+/// with the sign in the top bit of the last byte. Zero is the empty string.
+pub fn encode_num(number: i64) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut magnitude = number.unsigned_abs();
+    while magnitude > 0 {
+        bytes.push((magnitude & 0xff) as u8);
+        magnitude >>= 8;
+    }
+
+    // a byte for the sign if the top bit is taken
+    match bytes.last_mut() {
+        Some(last) if *last & 0x80 != 0 => bytes.push(if number < 0 { 0x80 } else { 0x00 }),
+        Some(last) if number < 0 => *last |= 0x80,
+        _ => {}
+    }
+    bytes
+}
+
+/// Decodes a number of at most `max_size` bytes, the inverse of `encode_num`.
+/// Encodings that aren't the shortest are accepted.
+pub fn decode_num(bytes: &[u8], max_size: usize) -> Result<i64, ScriptError> {
+    if bytes.len() > max_size {
+        return Err(ScriptError::NumberOverflow);
+    }
+    let Some((&last, _)) = bytes.split_last() else {
+        return Ok(0);
+    };
+
+    let magnitude = bytes
+        .iter()
+        .enumerate()
+        .fold(0i64, |number, (index, &byte)| number | (byte as i64) << (8 * index));
+    if last & 0x80 != 0 {
+        Ok(-(magnitude & !(0x80 << (8 * (bytes.len() - 1)))))
+    } else {
+        Ok(magnitude)
+    }
+}
+
+/// Whether a stack item is true: anything but zero, negative zero included
+pub fn cast_to_bool(item: &[u8]) -> bool {
+    match item.split_last() {
+        Some((&last, rest)) => rest.iter().any(|&byte| byte != 0) || (last != 0 && last != 0x80),
+        None => false,
+    }
+}
+
+/// The number of bytes the command at `offset` takes up in the script
+fn command_size(bytes: &[u8], offset: usize, command: &Command) -> usize {
+    match command {
+        Command::Op(_) => 1,
+        Command::Invalid(rest) => rest.len(),
+        Command::Push(data) => {
+            let prefix = match bytes[offset] {
+                OP_PUSHDATA1 => 2,
+                OP_PUSHDATA2 => 3,
+                OP_PUSHDATA4 => 5,
+                _ => 1,
+            };
+            prefix + data.len()
+        }
+    }
+}
+
+/// The script signatures commit to: from the last OP_CODESEPARATOR on. Legacy signatures also leave out
+/// every OP_CODESEPARATOR and any push of the signatures themselves (FindAndDelete), since a signature
+/// can't sign itself.
+fn script_code(script: &Script, code_start: usize, sig_version: SigVersion, signatures: &[&[u8]]) -> Script {
+    let legacy = sig_version == SigVersion::Base;
+    // the pushes as Core serializes them, so a one-byte signature is never turned into OP_N
+    let signature_pushes: Vec<Vec<u8>> = signatures
+        .iter()
+        .map(|signature| {
+            let mut push = vec![];
+            encode_push(&mut push, signature);
+            push
+        })
+        .collect();
+
+    let mut bytes = vec![];
+    let mut offset = 0;
+    for command in script.commands() {
+        let size = command_size(script.as_bytes(), offset, command);
+        let raw = &script.as_bytes()[offset..offset + size];
+        let deleted = legacy
+            && (*raw == [Opcode::OpCodeSeparator.to_u8()] || signature_pushes.iter().any(|push| push == raw));
+        if offset >= code_start && !deleted {
+            bytes.extend_from_slice(raw);
+        }
+        offset += size;
+    }
+    Script::from_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::{
+        helper::{from_hex, hash160, to_hex},
+        PrivateKey,
+    };
+
+    use super::*;
+
+    /// Runs a script written as hex on an empty stack
+    fn run(hex: &str) -> Result<(), ScriptError> {
+        Script::from_hex(hex).unwrap().evaluate(&[0; 32])
+    }
+
+    #[test]
+    fn test_p2pk() {
+        let z = from_hex("7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d").unwrap();
+        let sec = from_hex("04887387e452b8eacc4acfde10d9aaf7f6d9a0f975aabb10d006e4da568744d06c61de6d95231cd89026e286df3b6ae4a894a3378e393e93a0f45b666329a0ae34").unwrap();
+        let sig = from_hex("3045022000eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c022100c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab601").unwrap();
+
+        let script_pubkey = Script::from_commands(vec![Command::Push(sec), Command::Op(Opcode::OpCheckSig.to_u8())]);
+        let script_sig = Script::from_commands(vec![Command::Push(sig.clone())]);
+        let checker = FixedSighash(z.clone().try_into().unwrap());
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Ok(()));
+
+        // any other z fails, and so does a script_sig with no signature
+        let result = Script::verify_spend(&script_sig, &script_pubkey, &[], &FixedSighash([1; 32]));
+        assert_eq!(result, Err(ScriptError::EvalFalse));
+        let result = Script::verify_spend(&Script::default(), &script_pubkey, &[], &checker);
+        assert_eq!(result, Err(ScriptError::StackUnderflow));
+
+        // the same through OP_CHECKSIGVERIFY, in one script
+        let mut bytes = Script::from_commands(vec![Command::Push(sig)]).as_bytes().to_vec();
+        bytes.extend_from_slice(&script_pubkey.as_bytes()[..script_pubkey.len() - 1]);
+        bytes.extend_from_slice(&[Opcode::OpCheckSigVerify.to_u8(), Opcode::Op1.to_u8()]);
+        assert_eq!(Script::from_bytes(bytes).evaluate(&z.try_into().unwrap()), Ok(()));
+    }
+
+    /// The book's 2-of-2 P2SH spend, the first input of
+    /// 46df1a9484d0a81d03ce0ee543ab6e1a23ed06175c104a178268fad381216c2b
+    const P2SH_SCRIPT_SIG: &str = "00483045022100dc92655fe37036f47756db8102e0d7d5e28b3beb83a8fef4f5dc0559bddfb94e02205a36d4e4e6c7fcd16658c50783e00c341609977aed3ad00937bf4ee942a8993701483045022100da6bee3c93766232079a01639d07fa869598749729ae323eab8eef53577d611b02207bef15429dcadce2121ea07f233115c6f09034c0be68db99980b9a6c5e75402201475221022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb702103b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb7152ae";
+    const P2SH_Z: &str = "e71bfa115715d6fd33796948126f40a8cdd39f187e4afb03896795189fe1423c";
+
+    #[test]
+    fn test_multisig() {
+        let checker = FixedSighash(from_hex(P2SH_Z).unwrap().try_into().unwrap());
+        let script_sig = Script::from_hex(P2SH_SCRIPT_SIG).unwrap();
+        let Command::Push(redeem_script) = &script_sig.commands()[3] else {
+            panic!("the script_sig ends with the redeem script");
+        };
+        let redeem_script = Script::from_bytes(redeem_script.clone());
+        let signatures = &script_sig.commands()[..3];
+
+        // the redeem script as a bare multisig script_pubkey
+        let bare_script_sig = Script::from_commands(signatures.to_vec());
+        assert_eq!(Script::verify_spend(&bare_script_sig, &redeem_script, &[], &checker), Ok(()));
+
+        // as P2SH: the script_pubkey checks the redeem script's hash, which then runs on what's left
+        let script_pubkey = Script::from_hex("a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687").unwrap();
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Ok(()));
+
+        // the signatures have to be in the order of their keys
+        let swapped = Script::from_commands(vec![signatures[0].clone(), signatures[2].clone(), signatures[1].clone()]);
+        assert_eq!(Script::verify_spend(&swapped, &redeem_script, &[], &checker), Err(ScriptError::EvalFalse));
+        // without the extra item, the pop comes up short
+        let no_dummy = Script::from_commands(signatures[1..].to_vec());
+        assert_eq!(Script::verify_spend(&no_dummy, &redeem_script, &[], &checker), Err(ScriptError::StackUnderflow));
+
+        // 1-of-2 with the second key's signature skips the first key
+        let mut one_of_two = redeem_script.as_bytes().to_vec();
+        one_of_two[0] = Opcode::Op1.to_u8();
+        let script_sig = Script::from_commands(vec![signatures[0].clone(), signatures[2].clone()]);
+        assert_eq!(Script::verify_spend(&script_sig, &Script::from_bytes(one_of_two.clone()), &[], &checker), Ok(()));
+        // and the first key's signature matches before the second key is tried
+        let script_sig = Script::from_commands(vec![signatures[0].clone(), signatures[1].clone()]);
+        assert_eq!(Script::verify_spend(&script_sig, &Script::from_bytes(one_of_two), &[], &checker), Ok(()));
+
+        // OP_CHECKMULTISIGVERIFY, then the counts
+        let mut verify = redeem_script.as_bytes().to_vec();
+        *verify.last_mut().unwrap() = Opcode::OpCheckMultiSigVerify.to_u8();
+        verify.push(Opcode::Op1.to_u8());
+        assert_eq!(Script::verify_spend(&bare_script_sig, &Script::from_bytes(verify), &[], &checker), Ok(()));
+        assert_eq!(run("000000ae"), Ok(()));
+        assert_eq!(run("00000115ae"), Err(ScriptError::PubKeyCount(21)));
+        assert_eq!(run("00520051ae"), Err(ScriptError::SigCount(2)));
+        // the keys count toward the opcode limit
+        let mut ops = vec![0x61; 190];
+        ops.extend_from_slice(&[0x00, 0x00, 0x01, 0x14, 0xae]);
+        assert_eq!(Script::from_bytes(ops).evaluate(&[0; 32]), Err(ScriptError::OpCount));
+    }
+
+    #[test]
+    fn test_p2sh() {
+        let checker = FixedSighash(from_hex(P2SH_Z).unwrap().try_into().unwrap());
+        let script_sig = Script::from_hex(P2SH_SCRIPT_SIG).unwrap();
+        let script_pubkey = Script::from_hex("a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687").unwrap();
+
+        // the redeem script runs: with a signature left empty it hashes right but fails
+        let mut commands = script_sig.commands().to_vec();
+        commands[1] = Command::Op(0x00);
+        let one_signature = Script::from_commands(commands);
+        assert_eq!(Script::verify_spend(&one_signature, &script_pubkey, &[], &checker), Err(ScriptError::EvalFalse));
+
+        // a redeem script of OP_0 leaves false
+        let script_pubkey = Script::p2sh(&hash160(&[0x00]).try_into().unwrap());
+        let script_sig = Script::from_commands(vec![Command::Push(vec![0x00])]);
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Err(ScriptError::EvalFalse));
+
+        // only pushes can come before the redeem script
+        let script_pubkey = Script::p2sh(&hash160(&[0x51]).try_into().unwrap());
+        let script_sig = Script::from_commands(vec![Command::Op(0x61), Command::Push(vec![0x51])]);
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Err(ScriptError::SigPushOnly));
+        let script_sig = Script::from_commands(vec![Command::Op(0x51), Command::Push(vec![0x51])]);
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Ok(()));
+    }
+
+    /// Accepts signatures only in witness scripts, and only for this script code
+    struct WitnessOnly(Script);
+
+    impl SignatureChecker for WitnessOnly {
+        fn check_signature(&self, _: &[u8], _: &[u8], script_code: &Script, sig_version: SigVersion) -> bool {
+            sig_version == SigVersion::WitnessV0 && *script_code == self.0
+        }
+    }
+
+    #[test]
+    fn test_p2wpkh() {
+        let sec = from_hex("0349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a").unwrap();
+        let key_hash: [u8; 20] = hash160(&sec).try_into().unwrap();
+        let script_pubkey = Script::p2wpkh(&key_hash);
+        let witness = vec![vec![0x30, 0x01], sec.clone()];
+
+        // the witness runs through the P2PKH script of the key hash, which is also the script code
+        let checker = WitnessOnly(Script::p2pkh(&key_hash));
+        assert_eq!(Script::verify_spend(&Script::default(), &script_pubkey, &witness, &checker), Ok(()));
+        let other_key = vec![vec![0x30, 0x01], from_hex("02aa").unwrap()];
+        assert_eq!(
+            Script::verify_spend(&Script::default(), &script_pubkey, &other_key, &checker),
+            Err(ScriptError::VerifyFailed(Opcode::OpEqualVerify))
+        );
+        let extra_item = vec![vec![], vec![0x30, 0x01], sec.clone()];
+        assert_eq!(
+            Script::verify_spend(&Script::default(), &script_pubkey, &extra_item, &checker),
+            Err(ScriptError::WitnessProgramMismatch)
+        );
+
+        // nested in P2SH, the script_sig only pushes the P2WPKH script
+        let p2sh = script_pubkey.to_p2sh();
+        let script_sig = Script::p2sh_script_sig(vec![], &script_pubkey);
+        assert_eq!(Script::verify_spend(&script_sig, &p2sh, &witness, &checker), Ok(()));
+        let script_sig = Script::p2sh_script_sig(vec![Command::Op(0x51)], &script_pubkey);
+        let result = Script::verify_spend(&script_sig, &p2sh, &witness, &checker);
+        assert_eq!(result, Err(ScriptError::WitnessMalleatedP2sh));
+    }
+
+    #[test]
+    fn test_p2wsh() {
+        // <sig> OP_DROP <key> OP_CHECKSIG: witness scripts keep the signatures they hold in the script code
+        let sig = vec![0x30, 0x01];
+        let witness_script = Script::from_commands(vec![
+            Command::Push(sig.clone()),
+            Command::Op(Opcode::OpDrop.to_u8()),
+            Command::Push(vec![0x02; 33]),
+            Command::Op(Opcode::OpCheckSig.to_u8()),
+        ]);
+        let script_pubkey = witness_script.to_p2wsh();
+        let witness = Script::p2wsh_witness(vec![sig.clone()], &witness_script);
+        let checker = WitnessOnly(witness_script.clone());
+        assert_eq!(Script::verify_spend(&Script::default(), &script_pubkey, &witness, &checker), Ok(()));
+
+        // nested in P2SH
+        let p2sh = script_pubkey.to_p2sh();
+        let script_sig = Script::p2sh_script_sig(vec![], &script_pubkey);
+        assert_eq!(Script::verify_spend(&script_sig, &p2sh, &witness, &checker), Ok(()));
+
+        let other_script = Script::p2wsh_witness(vec![sig], &Script::from_hex("51").unwrap());
+        assert_eq!(
+            Script::verify_spend(&Script::default(), &script_pubkey, &other_script, &checker),
+            Err(ScriptError::WitnessProgramMismatch)
+        );
+        assert_eq!(
+            Script::verify_spend(&Script::default(), &script_pubkey, &[], &checker),
+            Err(ScriptError::WitnessProgramWitnessEmpty)
+        );
+
+        // the witness script must leave a single item
+        let two_items = Script::from_hex("5151").unwrap();
+        let witness = Script::p2wsh_witness(vec![], &two_items);
+        assert_eq!(
+            Script::verify_spend(&Script::default(), &two_items.to_p2wsh(), &witness, &checker),
+            Err(ScriptError::CleanStack)
+        );
+    }
+
+    #[test]
+    fn test_error_names() {
+        let cases = [
+            ("75", "INVALID_STACK_OPERATION"),
+            ("6c", "INVALID_ALTSTACK_OPERATION"),
+            ("0500000000008b", "UNKNOWN_ERROR"),
+            ("0069", "VERIFY"),
+            ("515288", "EQUALVERIFY"),
+            ("51529d", "NUMEQUALVERIFY"),
+            ("6a", "OP_RETURN"),
+            ("7e", "DISABLED_OPCODE"),
+            ("50", "BAD_OPCODE"),
+            ("4c", "BAD_OPCODE"),
+            ("68", "UNBALANCED_CONDITIONAL"),
+            ("00", "EVAL_FALSE"),
+        ];
+        for (hex, name) in cases {
+            assert_eq!(run(hex).unwrap_err().name(), name, "{}", hex);
+        }
+        assert_eq!(ScriptError::VerifyFailed(Opcode::OpCheckSigVerify).name(), "CHECKSIGVERIFY");
+        assert_eq!(ScriptError::PushSize(521).name(), "PUSH_SIZE");
+    }
+
+    #[test]
+    fn test_witness_programs() {
+        let checker = FixedSighash([0; 32]);
+        // version 0 programs are 20 or 32 bytes
+        let script_pubkey = Script::from_commands(vec![Command::Op(0x00), Command::Push(vec![1; 21])]);
+        assert_eq!(
+            Script::verify_spend(&Script::default(), &script_pubkey, &[vec![1]], &checker),
+            Err(ScriptError::WitnessProgramWrongLength)
+        );
+        // later versions can be spent by anyone, until a soft fork gives them a meaning, but aren't relayed
+        for version in [0x52, 0x60] {
+            let script_pubkey = Script::from_commands(vec![Command::Op(version), Command::Push(vec![1; 32])]);
+            assert_eq!(Script::verify_spend(&Script::default(), &script_pubkey, &[], &checker), Ok(()));
+            let result = Script::verify_spend_with_flags(
+                &Script::default(),
+                &script_pubkey,
+                &[],
+                &checker,
+                VerifyFlags::STANDARD,
+            );
+            assert_eq!(result, Err(ScriptError::DiscourageUpgradableWitnessProgram));
+        }
+        // so are version 1 programs that aren't taproot: other lengths, and any nested in P2SH
+        let short_v1 = Script::from_commands(vec![Command::Op(0x51), Command::Push(vec![1; 20])]);
+        assert_eq!(Script::verify_spend(&Script::default(), &short_v1, &[], &checker), Ok(()));
+        let flags = VerifyFlags { discourage_upgradable_witness_program: true, ..VerifyFlags::default() };
+        let result = Script::verify_spend_with_flags(&Script::default(), &short_v1, &[], &checker, flags);
+        assert_eq!(result, Err(ScriptError::DiscourageUpgradableWitnessProgram));
+        let p2tr = Script::p2tr(&[1; 32]);
+        let script_sig = Script::from_commands(vec![Command::Push(p2tr.as_bytes().to_vec())]);
+        let result = Script::verify_spend_with_flags(&script_sig, &p2tr.to_p2sh(), &[], &checker, flags);
+        assert_eq!(result, Err(ScriptError::DiscourageUpgradableWitnessProgram));
+    }
+
+    #[test]
+    fn test_p2tr_key_path() {
+        let key = PrivateKey::new(Integer::from(7));
+        let z = [0x42; 32];
+        let sig = key.sign_schnorr(&z, &[0; 32]).to_vec();
+        let script_pubkey = Script::p2tr(&key.x_only_public_key());
+        let checker = FixedSighash(z);
+        let witness = vec![sig.clone()];
+        assert_eq!(Script::verify_spend(&Script::default(), &script_pubkey, &witness, &checker), Ok(()));
+
+        // with a hash type byte, and with an annex
+        let with_hash_type = [sig.clone(), vec![0x01]].concat();
+        assert_eq!(Script::verify_spend(&Script::default(), &script_pubkey, &[with_hash_type], &checker), Ok(()));
+        let with_annex = vec![sig, vec![TAPROOT_ANNEX_PREFIX, 0xaa]];
+        assert_eq!(Script::verify_spend(&Script::default(), &script_pubkey, &with_annex, &checker), Ok(()));
+
+        assert_eq!(
+            Script::verify_spend(&Script::default(), &script_pubkey, &witness, &FixedSighash([0x43; 32])),
+            Err(ScriptError::SchnorrSig)
+        );
+        assert_eq!(
+            Script::verify_spend(&Script::default(), &script_pubkey, &[], &checker),
+            Err(ScriptError::WitnessProgramWitnessEmpty)
+        );
+
+        // nested in P2SH it's an unknown witness program, which anyone can spend
+        let script_sig = Script::p2sh_script_sig(vec![], &script_pubkey);
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey.to_p2sh(), &[vec![]], &checker), Ok(()));
+    }
+
+    /// Takes every control block for a commitment, and checks Schnorr signatures against a fixed hash
+    struct AnyLeaf(FixedSighash);
+
+    impl SignatureChecker for AnyLeaf {
+        fn check_signature(&self, _: &[u8], _: &[u8], _: &Script, _: SigVersion) -> bool {
+            false
+        }
+
+        fn check_schnorr_signature(
+            &self,
+            signature: &[u8],
+            public_key: &[u8; 32],
+            annex: Option<&[u8]>,
+            leaf: Option<TapLeafSpend>,
+        ) -> bool {
+            leaf.is_some() && self.0.check_schnorr_signature(signature, public_key, annex, leaf)
+        }
+
+        fn check_taproot_commitment(&self, _: &[u8], _: &Script, _: &[u8; 32]) -> bool {
+            true
+        }
+    }
+
+    /// Spends a P2TR output of an arbitrary key through `leaf_script`, with the `inputs` it takes
+    fn spend_leaf(inputs: Vec<Vec<u8>>, leaf_script: &str, checker: &impl SignatureChecker) -> Result<(), ScriptError> {
+        let mut witness = inputs;
+        witness.push(from_hex(leaf_script).unwrap());
+        witness.push(vec![TAPSCRIPT_LEAF_VERSION; 33]);
+        Script::verify_spend(&Script::default(), &Script::p2tr(&[1; 32]), &witness, checker)
+    }
+
+    #[test]
+    fn test_tapscript() {
+        let key = PrivateKey::new(Integer::from(7));
+        let z = [0x42; 32];
+        let sig = key.sign_schnorr(&z, &[0; 32]).to_vec();
+        let checker = AnyLeaf(FixedSighash(z));
+        // <key> OP_CHECKSIG
+        let checksig = format!("20{}ac", to_hex(&key.x_only_public_key()));
+        assert_eq!(spend_leaf(vec![sig.clone()], &checksig, &checker), Ok(()));
+
+        // the checker decides whether the control block commits to the leaf
+        assert_eq!(spend_leaf(vec![sig.clone()], &checksig, &FixedSighash(z)), Err(ScriptError::WitnessProgramMismatch));
+        let mut witness = vec![sig.clone(), from_hex(&checksig).unwrap(), vec![TAPSCRIPT_LEAF_VERSION; 34]];
+        let result = Script::verify_spend(&Script::default(), &Script::p2tr(&[1; 32]), &witness, &checker);
+        assert_eq!(result, Err(ScriptError::TaprootWrongControlSize(34)));
+        // leaf versions other than tapscript, and OP_SUCCESS opcodes, are left for soft forks
+        witness[2] = vec![0xc2; 33];
+        assert_eq!(Script::verify_spend(&Script::default(), &Script::p2tr(&[1; 32]), &witness, &checker), Ok(()));
+        assert_eq!(spend_leaf(vec![], "006a7e68", &checker), Ok(()));
+        assert_eq!(spend_leaf(vec![], "bb", &checker), Ok(()));
+
+        // an empty signature is false, but any other one has to be valid
+        assert_eq!(spend_leaf(vec![vec![]], &checksig, &checker), Err(ScriptError::EvalFalse));
+        let mut bad_sig = sig.clone();
+        bad_sig[0] ^= 1;
+        assert_eq!(spend_leaf(vec![bad_sig], &checksig, &checker), Err(ScriptError::SchnorrSig));
+        // keys of other lengths are left for new key types, but there has to be a key
+        assert_eq!(spend_leaf(vec![vec![1]], "0102ac", &checker), Ok(()));
+        assert_eq!(spend_leaf(vec![vec![1]], "00ac", &checker), Err(ScriptError::PubKeyType));
+
+        // 2-of-2 with OP_CHECKSIGADD, with the same key twice: <key> OP_CHECKSIG <key> OP_CHECKSIGADD OP_2 OP_NUMEQUAL
+        let checksigadd = format!("{checksig}20{}ba529c", to_hex(&key.x_only_public_key()));
+        assert_eq!(spend_leaf(vec![sig.clone(), sig.clone()], &checksigadd, &checker), Ok(()));
+        assert_eq!(spend_leaf(vec![vec![], sig.clone()], &checksigadd, &checker), Err(ScriptError::EvalFalse));
+        assert_eq!(spend_leaf(vec![], "000000ae", &checker), Err(ScriptError::TapscriptCheckMultiSig));
+
+        // OP_IF only takes an empty item or 1
+        assert_eq!(spend_leaf(vec![vec![1]], "635168", &checker), Ok(()));
+        assert_eq!(spend_leaf(vec![vec![2]], "635168", &checker), Err(ScriptError::TapscriptMinimalIf));
+
+        // each signature costs 50 of a budget of 50 plus the witness size: 169 for 4 checks of a 64-byte
+        // signature by a 1-byte key, OP_DUP <key> OP_CHECKSIGVERIFY, but 165 for 3
+        let checks = |count| format!("{}7551", "760101ad".repeat(count));
+        assert_eq!(spend_leaf(vec![vec![1; 64]], &checks(3), &checker), Ok(()));
+        assert_eq!(
+            spend_leaf(vec![vec![1; 64]], &checks(4), &checker),
+            Err(ScriptError::TapscriptValidationWeight)
+        );
+
+        // the op count and script size limits are gone
+        let mut ops = "61".repeat(202);
+        ops.push_str("51");
+        assert_eq!(spend_leaf(vec![], &ops, &checker), Ok(()));
+    }
+
+    #[test]
+    fn test_arithmetic_and_stack() {
+        // 2 OP_5 OP_ADD OP_7 OP_EQUAL
+        assert_eq!(run("5255935787"), Ok(()));
+        // 3 OP_DUP OP_MUL is disabled
+        assert_eq!(run("53769587"), Err(ScriptError::DisabledOpcode(Opcode::OpMul)));
+        // -1 OP_ABS OP_1 OP_NUMEQUAL
+        assert_eq!(run("4f90519c"), Ok(()));
+        // 3 OP_2 OP_5 OP_WITHIN
+        assert_eq!(run("535255a5"), Ok(()));
+        // 1 2 3 OP_2 OP_ROLL, leaves 2 3 1
+        let mut interpreter = Interpreter::new(&FixedSighash([0; 32]));
+        interpreter.execute(&Script::from_hex("515253527a").unwrap()).unwrap();
+        assert_eq!(interpreter.stack(), [vec![2], vec![3], vec![1]]);
+        // OP_2DUP OP_2OVER on 1 2
+        let mut interpreter = Interpreter::new(&FixedSighash([0; 32]));
+        interpreter.execute(&Script::from_hex("51526e70").unwrap()).unwrap();
+        assert_eq!(interpreter.stack(), [vec![1], vec![2], vec![1], vec![2], vec![1], vec![2]]);
+        // through the altstack: 1 OP_TOALTSTACK OP_FROMALTSTACK
+        assert_eq!(run("516b6c"), Ok(()));
+        assert_eq!(run("6c"), Err(ScriptError::AltStackUnderflow));
+        // a 5 byte operand
+        assert_eq!(run("0500000000808b"), Err(ScriptError::NumberOverflow));
+        // OP_DROP on an empty stack, then a stack left false
+        assert_eq!(run("75"), Err(ScriptError::StackUnderflow));
+        assert_eq!(run("00"), Err(ScriptError::EvalFalse));
+    }
+
+    #[test]
+    fn test_hashes() {
+        // "abc" OP_SHA1
+        let mut interpreter = Interpreter::new(&FixedSighash([0; 32]));
+        interpreter.execute(&Script::from_hex("03616263a7").unwrap()).unwrap();
+        assert_eq!(interpreter.stack(), [from_hex("a9993e364706816aba3e25717850c26c9cd0d89d").unwrap()]);
+
+        // "hello world" OP_HASH160 <hash> OP_EQUAL
+        assert_eq!(run("0b68656c6c6f20776f726c64a914d7d5ee7824ff93f94c3055af9382c86c68b5ca9287"), Ok(()));
+        assert_eq!(
+            run("0b68656c6c6f20776f726c64a914d7d5ee7824ff93f94c3055af9382c86c68b5ca9388"),
+            Err(ScriptError::VerifyFailed(Opcode::OpEqualVerify))
+        );
+    }
+
+    /// A checker for a transaction locked until this height
+    struct LockedUntil(i64);
+
+    impl SignatureChecker for LockedUntil {
+        fn check_signature(&self, _: &[u8], _: &[u8], _: &Script, _: SigVersion) -> bool {
+            false
+        }
+
+        fn check_lock_time(&self, lock_time: i64) -> bool {
+            lock_time <= self.0
+        }
+    }
+
+    #[test]
+    fn test_check_lock_time() {
+        // <500> OP_CHECKLOCKTIMEVERIFY, which leaves the locktime as the result
+        let script = Script::from_hex("02f401b1").unwrap();
+        assert_eq!(script.evaluate_with(&LockedUntil(500)), Ok(()));
+        assert_eq!(script.evaluate_with(&LockedUntil(499)), Err(ScriptError::UnsatisfiedLockTime));
+        assert_eq!(script.evaluate(&[0; 32]), Err(ScriptError::UnsatisfiedLockTime));
+
+        // locktimes take 5 bytes, to reach past 2^31
+        let script = Script::from_hex("050000008000b1").unwrap();
+        assert_eq!(script.evaluate_with(&LockedUntil(1 << 31)), Ok(()));
+        assert_eq!(Script::from_hex("06000000000001b1").unwrap().evaluate_with(&LockedUntil(0)), Err(ScriptError::NumberOverflow));
+
+        assert_eq!(Script::from_hex("4fb1").unwrap().evaluate_with(&LockedUntil(0)), Err(ScriptError::NegativeLockTime));
+        assert_eq!(Script::from_hex("b1").unwrap().evaluate_with(&LockedUntil(0)), Err(ScriptError::StackUnderflow));
+    }
+
+    /// A checker for an input relatively locked for this many blocks
+    struct LockedFor(i64);
+
+    impl SignatureChecker for LockedFor {
+        fn check_signature(&self, _: &[u8], _: &[u8], _: &Script, _: SigVersion) -> bool {
+            false
+        }
+
+        fn check_sequence(&self, sequence: i64) -> bool {
+            sequence <= self.0
+        }
+    }
+
+    #[test]
+    fn test_check_sequence() {
+        // <10> OP_CHECKSEQUENCEVERIFY
+        let script = Script::from_hex("5ab2").unwrap();
+        assert_eq!(script.evaluate_with(&LockedFor(10)), Ok(()));
+        assert_eq!(script.evaluate_with(&LockedFor(9)), Err(ScriptError::UnsatisfiedLockTime));
+        assert_eq!(script.evaluate(&[0; 32]), Err(ScriptError::UnsatisfiedLockTime));
+
+        // with the disable flag set it's a NOP, whatever the checker says
+        let script = Script::from_hex("050a00008000b2").unwrap();
+        assert_eq!(script.evaluate(&[0; 32]), Ok(()));
+
+        assert_eq!(Script::from_hex("4fb2").unwrap().evaluate_with(&LockedFor(0)), Err(ScriptError::NegativeLockTime));
+        assert_eq!(Script::from_hex("b2").unwrap().evaluate_with(&LockedFor(0)), Err(ScriptError::StackUnderflow));
+    }
+
+    #[test]
+    fn test_conditionals() {
+        // 1 OP_IF 2 OP_ELSE 3 OP_ENDIF 2 OP_EQUAL
+        assert_eq!(run("5163526753685287"), Ok(()));
+        // 0 OP_IF 2 OP_ELSE 3 OP_ENDIF 3 OP_EQUAL
+        assert_eq!(run("0063526753685387"), Ok(()));
+        // OP_NOTIF, nested in a skipped branch
+        assert_eq!(run("0063006451686851"), Ok(()));
+        // a skipped OP_RETURN doesn't run, a skipped disabled opcode still fails
+        assert_eq!(run("00636a6851"), Ok(()));
+        assert_eq!(run("51636a6851"), Err(ScriptError::OpReturn));
+        assert_eq!(run("0063956851"), Err(ScriptError::DisabledOpcode(Opcode::OpMul)));
+        // reserved opcodes only fail when they run
+        assert_eq!(run("0063506851"), Ok(()));
+        assert_eq!(run("50"), Err(ScriptError::BadOpcode(0x50)));
+
+        assert_eq!(run("5163"), Err(ScriptError::UnbalancedConditional));
+        assert_eq!(run("5168"), Err(ScriptError::UnbalancedConditional));
+        assert_eq!(run("67"), Err(ScriptError::UnbalancedConditional));
+        assert_eq!(run("63"), Err(ScriptError::UnbalancedConditional));
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(run("4d0902"), Err(ScriptError::InvalidPush));
+        let big_push = Script::from_commands(vec![Command::Push(vec![0; 521])]);
+        assert_eq!(big_push.evaluate(&[0; 32]), Err(ScriptError::PushSize(521)));
+        assert_eq!(Script::from_commands(vec![Command::Push(vec![1; 520])]).evaluate(&[0; 32]), Ok(()));
+        // even in a branch that doesn't run
+        let skipped_push = vec![Command::Op(0x00), Command::Op(0x63), Command::Push(vec![0; 521]), Command::Op(0x68)];
+        assert_eq!(Script::from_commands(skipped_push).evaluate(&[0; 32]), Err(ScriptError::PushSize(521)));
+
+        // 10,000 bytes, mostly pushes skipped over, and then one more
+        let sized_script = |padding: usize| {
+            let mut commands = vec![Command::Op(0x00), Command::Op(0x63)];
+            commands.extend((0..19).map(|_| Command::Push(vec![0; 520])));
+            commands.extend([Command::Push(vec![0; padding]), Command::Op(0x68), Command::Op(0x51)]);
+            Script::from_commands(commands)
+        };
+        assert_eq!(sized_script(58).len(), MAX_SCRIPT_SIZE);
+        assert_eq!(sized_script(58).evaluate(&[0; 32]), Ok(()));
+        assert_eq!(sized_script(59).evaluate(&[0; 32]), Err(ScriptError::ScriptSize(10_001)));
+
+        let ops_script = |count: usize| {
+            let mut ops = vec![0x61; count];
+            ops.push(0x51);
+            Script::from_bytes(ops)
+        };
+        assert_eq!(ops_script(201).evaluate(&[0; 32]), Ok(()));
+        assert_eq!(ops_script(202).evaluate(&[0; 32]), Err(ScriptError::OpCount));
+        // small number pushes don't count
+        assert_eq!(Script::from_bytes(vec![0x51; 1000]).evaluate(&[0; 32]), Ok(()));
+        assert_eq!(Script::from_bytes(vec![0x51; 1001]).evaluate(&[0; 32]), Err(ScriptError::StackSize));
+        // and the altstack counts towards the limit
+        let mut alt_stack = vec![0x51; 999];
+        alt_stack.extend([0x6b, 0x51, 0x51]);
+        assert_eq!(Script::from_bytes(alt_stack).evaluate(&[0; 32]), Err(ScriptError::StackSize));
+
+        // witness items are held to the push limit before the witness script runs
+        let checker = FixedSighash([0; 32]);
+        let witness_script = Script::from_commands(vec![Command::Op(0x75), Command::Op(0x51)]);
+        for (size, result) in [(520, Ok(())), (521, Err(ScriptError::PushSize(521)))] {
+            let witness = Script::p2wsh_witness(vec![vec![1; size]], &witness_script);
+            let spend = Script::verify_spend(&Script::default(), &witness_script.to_p2wsh(), &witness, &checker);
+            assert_eq!(spend, result);
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        for (number, hex) in [(0, ""), (1, "01"), (-1, "81"), (127, "7f"), (128, "8000"), (-128, "8080"), (255, "ff00")] {
+            assert_eq!(encode_num(number), from_hex(hex).unwrap());
+            assert_eq!(decode_num(&encode_num(number), MAX_NUM_SIZE), Ok(number));
+        }
+        assert_eq!(decode_num(&[0x01, 0x00], MAX_NUM_SIZE), Ok(1));
+        assert_eq!(decode_num(&[0; 5], MAX_NUM_SIZE), Err(ScriptError::NumberOverflow));
+
+        assert!(!cast_to_bool(&[]));
+        assert!(!cast_to_bool(&[0, 0x80]));
+        assert!(cast_to_bool(&[0x80, 0]));
+    }
+
+    #[test]
+    fn test_minimal_data() {
+        let checker = FixedSighash([0; 32]);
+        let execute = |hex: &str, flags: VerifyFlags| {
+            Interpreter::new(&checker).with_flags(flags).execute(&Script::from_hex(hex).unwrap())
+        };
+
+        // 5 and -1 take OP_5 and OP_1NEGATE, and OP_PUSHDATA is only for pushes of more than 75 bytes
+        for hex in ["0105", "0181", "4c00", "4c0142", "4d0100aa", "0201008b"] {
+            assert_eq!(execute(hex, VerifyFlags::default()), Ok(()));
+            assert_eq!(execute(hex, VerifyFlags::STANDARD), Err(ScriptError::MinimalData));
+        }
+        for hex in ["55", "4f", "0142", "00", "01118b"] {
+            assert_eq!(execute(hex, VerifyFlags::STANDARD), Ok(()));
+        }
+        // pushes in a branch that doesn't run aren't checked
+        assert_eq!(execute("0063010568", VerifyFlags::STANDARD), Ok(()));
+
+        let script_sig = Script::from_hex("0105").unwrap();
+        let script_pubkey = Script::from_hex("5587").unwrap();
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Ok(()));
+        let result = Script::verify_spend_with_flags(&script_sig, &script_pubkey, &[], &checker, VerifyFlags::STANDARD);
+        assert_eq!(result, Err(ScriptError::MinimalData));
+    }
+
+    #[test]
+    fn test_script_code() {
+        // sig OP_CODESEPARATOR <sig> OP_DROP OP_1, from the separator on without the signature
+        let script = Script::from_hex("02aabbab02aabb7551").unwrap();
+        assert_eq!(script_code(&script, 4, SigVersion::Base, &[&[0xaa, 0xbb]]).to_hex(), "7551");
+        assert_eq!(script_code(&script, 0, SigVersion::Base, &[&[0xaa]]).to_hex(), "02aabb02aabb7551");
+
+        // every separator after the last executed one is left out of legacy script code, but kept in witness ones
+        let script = Script::from_hex("ab02aabbab75ab51").unwrap();
+        assert_eq!(script_code(&script, 1, SigVersion::Base, &[]).to_hex(), "02aabb7551");
+        assert_eq!(script_code(&script, 1, SigVersion::WitnessV0, &[&[0xaa, 0xbb]]).to_hex(), "02aabbab75ab51");
+
+        // a one-byte signature is deleted as the push 01 05, not as OP_5
+        let script = Script::from_hex("01055575").unwrap();
+        assert_eq!(script_code(&script, 0, SigVersion::Base, &[&[0x05]]).to_hex(), "5575");
+    }
+}
@@ -1,155 +1,262 @@
+mod asm;
 mod codes;
+mod contracts;
+pub mod descriptor;
 pub mod helpers;
+pub mod interpreter;
+pub mod miniscript;
+mod opcodes;
+mod sigops;
+mod templates;
 mod traits;
-mod utils;
 
-use std::fmt::format;
+use std::{fmt::{format, Debug}, io::{self, Read, Write}};
 
+use ec_cryptography::{
+    consensus::{ConsensusDecode, ConsensusEncode, VarInt},
+    helper::{from_hex, to_hex, HexError},
+};
 use helpers::Stack;
 use ripemd::{Digest as RipemdDigest, Ripemd160};
-use rug::Integer;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use sha2::{Digest, Sha256};
-use utils::parse_varints;
+
+pub use asm::AsmError;
+pub use opcodes::Opcode;
+pub use templates::{MultisigError, ScriptType};
+
+const OP_PUSHDATA1: u8 = 0x4c;
+const OP_PUSHDATA2: u8 = 0x4d;
+const OP_PUSHDATA4: u8 = 0x4e;
+
+/// Appends a push of `data` prefixed by its length, which takes OP_PUSHDATA1, 2 or 4 once it no
+/// longer fits in the opcode. Unlike `Script::from_commands`, single small numbers stay data pushes.
+pub(crate) fn encode_push(bytes: &mut Vec<u8>, data: &[u8]) {
+    let length = data.len();
+    match length {
+        0..=75 => bytes.push(length as u8),
+        76..=0xff => bytes.extend_from_slice(&[OP_PUSHDATA1, length as u8]),
+        0x100..=0xffff => {
+            bytes.push(OP_PUSHDATA2);
+            bytes.extend_from_slice(&(length as u16).to_le_bytes());
+        }
+        _ => {
+            bytes.push(OP_PUSHDATA4);
+            bytes.extend_from_slice(&(length as u32).to_le_bytes());
+        }
+    }
+    bytes.extend_from_slice(data);
+}
 
 pub enum ScriptOpError {
     StackEmpty,
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
-pub struct Script(Vec<String>);
+/// A single element of a parsed script
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Command {
+    /// An opcode, including OP_0 and the small number opcodes
+    Op(u8),
+    /// Data pushed onto the stack, by a direct push or one of the OP_PUSHDATA opcodes
+    Push(Vec<u8>),
+    /// A push that claims more bytes than the script has left. It holds the rest of the script,
+    /// from the push opcode on, since such scripts can still appear on chain (they just can't be spent)
+    Invalid(Vec<u8>),
+}
+
+impl Command {
+    /// The opcode, for `Command::Op` commands of a defined opcode
+    pub fn opcode(&self) -> Option<Opcode> {
+        match self {
+            Command::Op(byte) => Opcode::from_u8(*byte),
+            _ => None,
+        }
+    }
+}
+
+/// A script, kept both as the raw bytes it was read from and as the list of commands they hold.
+///
+/// The raw bytes are what gets serialized and hashed, so scripts with non-minimal pushes
+/// round-trip exactly. Hex only appears when converting to and from text.
+#[derive(Clone, Default, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct Script {
+    bytes: Vec<u8>,
+    commands: Vec<Command>,
+}
+
+impl Debug for Script {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Script({})", self.to_hex())
+    }
+}
 
 impl Script {
-    fn new(commands: Vec<String>) -> Self {
-        Script(commands)
+    /// Wraps raw script bytes (without a length prefix). Malformed scripts are accepted,
+    /// their bad push shows up as a `Command::Invalid`.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        let commands = parse_commands(&bytes);
+        Script { bytes, commands }
     }
 
-    
-    /**
-     *  Parses a script command (usually a scriptSig or a pubkeyScript) 
-     *  using the instruction set defined.
-     */
-    pub fn parse(command: &str) -> Option<Self> {
-        // Convert the str, mostly a str representation of a hex string
-        let command_bytes = &Integer::from_str_radix(command, 16)
-            .unwrap()
-            .to_digits::<u8>(rug::integer::Order::MsfBe);
-
-        // keep track of bytes of the cammand parsed
-        let mut count = 0;
-        let mut commands = Vec::new();
-
-        while count < command_bytes.len() {
-            let current = command_bytes[count as usize]; // get the current byte
-            count += 1;
-
-            match current {
-                1..=75 => {
-                    // Push the next `current` bytes of data to the commands array
-                    let bytes_to_push: String =
-                        command_bytes[count as usize..count as usize + current as usize]
-                            .iter()
-                            .map(|byte: &u8| format!("{:02x}", byte))
-                            .collect::<Vec<String>>()
-                            .join("");
-                    let length = bytes_to_push.len() / 2;
-                    commands.push(bytes_to_push);
-
-                    // increment the count by the length of the bytes pushed
-                    count += length;
-                }
-                76 => {
-                    count += 1;
-                    // the next byte  is the length of the data to push
-                    let length = &command_bytes[count as usize];
-                    let bytes_to_push = command_bytes
-                        [(count as usize + 1)..(count as usize + 1 + *length as usize)]
-                        .iter()
-                        .map(|byte: &u8| format!("{:02x}", byte))
-                        .collect::<Vec<String>>()
-                        .join("");
-
-                    // increment the count by the length of the bytes pushed
-                    count += (bytes_to_push.len() / 2) + 1;
-                    
-                    commands.push(bytes_to_push);
-                }
-                77 => {
-                    count += 1;
-                    // the next 2 bytes contain the number of bytes to be oushed onto-stack
-                    let length = &command_bytes[count as usize..(count as usize + 2)]
-                        .iter()
-                        .fold(0, |acc, &x| acc + x as u16);
-
-                    let bytes_to_push = command_bytes
-                        [(count as usize + 2)..(count as usize + 2 + *length as usize)]
-                        .iter()
-                        .map(|byte: &u8| format!("{:02x}", byte))
-                        .collect::<Vec<String>>()
-                        .join("");
-
-                    // increment the count by the length of the bytes pushed
-                    count += (bytes_to_push.len() / 2) + 2;
-
-                    commands.push(bytes_to_push);
-                }
-                78 => {
-                    count += 1;
-                    // the next 4 bytes contain the number of bytes to be oushed onto-stack
-                    let length = &command_bytes[count as usize..(count as usize + 4)]
-                        .iter()
-                        .fold(0, |acc, &x| acc + x as u32);
-
-                    let bytes_to_push = command_bytes
-                        [(count as usize + 4)..(count as usize + 4 + *length as usize)]
-                        .iter()
-                        .map(|byte: &u8| format!("{:02x}", byte))
-                        .collect::<Vec<String>>()
-                        .join("");
-
-                    // increment the count by the length of the bytes pushed
-                    count += (bytes_to_push.len() / 2) + 4;
-
-                    commands.push(bytes_to_push);
-                }
-                _ => {
-                    // push the current byte to the commands array
-                    commands.push(format!("{:02x}", current));
+    /// Builds a script from its commands, encoding each push with the shortest push opcode, the way
+    /// MINIMALDATA asks for. Single bytes of 1 to 16 and 0x81 are pushed by OP_1 to OP_16 and OP_1NEGATE,
+    /// so they come back as `Command::Op`.
+    pub fn from_commands(commands: Vec<Command>) -> Self {
+        let mut bytes = vec![];
+        for command in &commands {
+            match command {
+                Command::Op(op) => bytes.push(*op),
+                Command::Invalid(rest) => bytes.extend_from_slice(rest),
+                Command::Push(data) if data.len() == 1 && (1..=16).contains(&data[0]) => {
+                    bytes.push(Opcode::Op1.to_u8() + data[0] - 1);
                 }
+                Command::Push(data) if *data == [0x81] => bytes.push(Opcode::Op1Negate.to_u8()),
+                Command::Push(data) => encode_push(&mut bytes, data),
             }
         }
 
-        if count != command_bytes.len() {
+        Script::from_bytes(bytes)
+    }
+
+    /// Reads a hex encoded script, without a length prefix
+    pub fn from_hex(hex: &str) -> Result<Self, HexError> {
+        Ok(Script::from_bytes(from_hex(hex)?))
+    }
+
+    /// Parses a hex encoded script that starts with its varint length, as it appears in transactions.
+    /// Returns None if the length is wrong or a push runs past the end.
+    pub fn parse(command: &str) -> Option<Self> {
+        let script = Script::consensus_deserialize(&from_hex(command).ok()?).ok()?;
+
+        if script.commands.iter().any(|command| matches!(command, Command::Invalid(_))) {
             None
         } else {
-            Some(Self::new(commands))
+            Some(script)
         }
     }
 
+    /// The hex encoded script with its varint length, the inverse of `parse`
     pub fn serialize(&self) -> String {
-        let mut result = String::new();
-
-        for cmd in &self.0 {
-            let length = cmd.len() / 2;
-            match length {
-                2..=75 => {
-                    result.push_str(&format!("{:02x}", length));
-                },
-                76..0x100 => {
-                    result.push_str("4c");
-                    result.push_str(&format!("{:02x}", length));
-                },
-                0x100..=520 => {
-                    result.push_str("4d");
-                    result.push_str(&format!("{:04x}", length));
-                },
-                _ => {
-                    println!("Error: Length of command is invalid");
-                }
+        to_hex(&self.consensus_serialize())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// The length in bytes, without the length prefix
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The size in a transaction, with the varint length
+    pub fn serialized_size(&self) -> usize {
+        VarInt(self.bytes.len() as u64).consensus_serialize().len() + self.bytes.len()
+    }
+
+    /// Whether the script only pushes data, counting OP_0 to OP_16 (and OP_RESERVED) as pushes
+    pub fn is_push_only(&self) -> bool {
+        self.commands.iter().all(|command| match command {
+            Command::Push(_) => true,
+            Command::Op(op) => *op <= Opcode::Op16.to_u8(),
+            Command::Invalid(_) => false,
+        })
+    }
+
+    /// The raw script as hex, without the length prefix
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.bytes)
+    }
+}
+
+fn parse_commands(bytes: &[u8]) -> Vec<Command> {
+    let mut commands = Vec::new();
+    let mut count = 0;
+
+    while count < bytes.len() {
+        let start = count;
+        let current = bytes[count];
+        count += 1;
+
+        // the number of bytes holding the push length, for the OP_PUSHDATA opcodes
+        let length_size = match current {
+            OP_PUSHDATA1 => 1,
+            OP_PUSHDATA2 => 2,
+            OP_PUSHDATA4 => 4,
+            1..=75 => 0,
+            _ => {
+                commands.push(Command::Op(current));
+                continue;
             }
-            result.push_str(cmd);
+        };
+
+        let length = if length_size == 0 {
+            current as usize
+        } else {
+            let Some(length_bytes) = bytes.get(count..count + length_size) else {
+                commands.push(Command::Invalid(bytes[start..].to_vec()));
+                break;
+            };
+            count += length_size;
+            length_bytes.iter().rev().fold(0, |acc, &byte| (acc << 8) | byte as usize)
+        };
+
+        match bytes.get(count..).filter(|rest| rest.len() >= length) {
+            Some(rest) => commands.push(Command::Push(rest[..length].to_vec())),
+            None => {
+                commands.push(Command::Invalid(bytes[start..].to_vec()));
+                break;
+            }
+        }
+        count += length;
+    }
+
+    commands
+}
+
+/// Scripts are written with their varint length
+impl ConsensusEncode for Script {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        let written = VarInt(self.bytes.len() as u64).consensus_encode(writer)?;
+        writer.write_all(&self.bytes)?;
+        Ok(written + self.bytes.len())
+    }
+}
+
+impl ConsensusDecode for Script {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let VarInt(length) = VarInt::consensus_decode(reader)?;
+
+        // read through `take` so a bogus length cannot make us allocate more than the stream holds
+        let mut bytes = vec![];
+        let read = reader.take(length).read_to_end(&mut bytes)?;
+        if read as u64 != length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "script is shorter than its length prefix"));
         }
 
-        result
+        Ok(Script::from_bytes(bytes))
+    }
+}
+
+/// In text formats (JSON), scripts are hex strings without the length prefix
+impl Serialize for Script {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for Script {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        Script::from_hex(&hex).map_err(|e| de::Error::custom(format!("invalid script hex: {:?}", e)))
     }
 }
 
@@ -167,11 +274,7 @@ mod tests {
 
         assert_eq!(stack.length(), 1, "Stack should have one item");
         let item = stack.pop().unwrap();
-        let item_byte = item
-            .iter()
-            .map(|byte: &u8| format!("{:02x}", byte))
-            .collect::<Vec<String>>()
-            .join("");
+        let item_byte = to_hex(&item);
         assert_eq!(item_byte, "d7d5ee7824ff93f94c3055af9382c86c68b5ca92");
     }
 
@@ -180,19 +283,58 @@ mod tests {
         let command = "6a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937";
         let script = Script::parse(command).unwrap();
 
-        assert_eq!(script.0[0], "6a");
+        // 0x6a is the length of the script
+        assert_eq!(script.len(), 0x6a);
+        assert_eq!(script.commands().len(), 2);
         assert_eq!(
-            script.0[1], 
-            "304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a71601".
-            to_string()
+            script.commands()[0],
+            Command::Push(from_hex("304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a71601").unwrap())
         );
         assert_eq!(
-            script.0[2], 
-            "035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937"
-                .to_string()
+            script.commands()[1],
+            Command::Push(from_hex("035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937").unwrap())
         );
 
         let serialized = script.serialize();
         assert_eq!(serialized, command);
+        assert_eq!(script.serialized_size(), 0x6b);
+    }
+
+    #[test]
+    fn test_commands_round_trip() {
+        let p2pkh = Script::from_hex("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").unwrap();
+        assert_eq!(p2pkh.commands()[0], Command::Op(0x76));
+        assert_eq!(p2pkh.commands()[2], Command::Push(from_hex("bc3b654dca7e56b04dca18f2566cdaf02e8d9ada").unwrap()));
+        assert_eq!(Script::from_commands(p2pkh.commands().to_vec()), p2pkh);
+
+        // OP_PUSHDATA1 and OP_PUSHDATA2
+        let script = Script::from_commands(vec![Command::Push(vec![0xab; 80]), Command::Push(vec![0xcd; 300])]);
+        assert_eq!(script.as_bytes()[..2], [0x4c, 80]);
+        assert_eq!(script.as_bytes()[82..85], [0x4d, 0x2c, 0x01]);
+        assert_eq!(Script::from_bytes(script.as_bytes().to_vec()).commands(), script.commands());
+
+        // OP_PUSHDATA4
+        let script = Script::from_commands(vec![Command::Push(vec![0xef; 0x10000])]);
+        assert_eq!(script.as_bytes()[..5], [0x4e, 0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(script.commands(), [Command::Push(vec![0xef; 0x10000])]);
+
+        // the numbers OP_1NEGATE and OP_1 to OP_16 push don't need a push
+        let numbers = vec![Command::Push(vec![0x81]), Command::Push(vec![16]), Command::Push(vec![17])];
+        let script = Script::from_commands(numbers);
+        assert_eq!(script.to_hex(), "4f600111");
+    }
+
+    #[test]
+    fn test_malformed_script() {
+        // a push of 5 bytes with only 2 left
+        let script = Script::from_hex("5105aabb").unwrap();
+        assert_eq!(script.commands(), [Command::Op(0x51), Command::Invalid(vec![0x05, 0xaa, 0xbb])]);
+        assert_eq!(script.to_hex(), "5105aabb");
+        assert_eq!(Script::from_commands(script.commands().to_vec()), script);
+        assert!(Script::parse("045105aabb").is_none());
+
+        // the length prefix must match
+        assert!(Script::parse("0551").is_none());
+        assert!(Script::parse("0151ff").is_none());
     }
 }
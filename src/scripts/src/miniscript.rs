@@ -0,0 +1,1063 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use ec_cryptography::{
+    helper::{from_hex, hash160, hash256, ripemd160, sha256, to_hex},
+    serializer::parse_sec,
+};
+
+use crate::{
+    interpreter::{encode_num, MAX_PUBKEYS_PER_MULTISIG},
+    Command, Opcode, Script,
+};
+
+/// Lock times below this are block heights, from it on UNIX times (BIP65)
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+/// Set in a sequence, the input has no relative lock (BIP68)
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+/// Set in a sequence, its relative lock is in units of 512 seconds rather than blocks
+const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+const SEQUENCE_LOCKTIME_MASK: u32 = 0xffff;
+
+/// A miniscript expression (https://bitcoin.sipa.be/miniscript/) for P2WSH witness scripts: a script
+/// written as a tree of fragments, so that what it takes to spend it can be worked out and satisfied.
+/// Keys are compressed SEC public keys.
+///
+/// `pk`, `pkh`, `and_n` and the `t:`, `l:` and `u:` wrappers are shorthands, parsed into the fragments they stand for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Miniscript {
+    /// `0`: never satisfied
+    False,
+    /// `1`: always satisfied
+    True,
+    /// `pk_k(key)`: leaves the key for an OP_CHECKSIG, written `pk(key)` with one
+    PkK(Vec<u8>),
+    /// `pk_h(key)`: takes the key from the witness and checks its HASH160, written `pkh(key)` with an OP_CHECKSIG
+    PkH(Vec<u8>),
+    /// `older(n)`: the input's sequence is a relative lock of at least `n`
+    Older(u32),
+    /// `after(n)`: the transaction's lock time is at least `n`
+    After(u32),
+    /// `sha256(h)`: a 32-byte preimage of `h`
+    Sha256([u8; 32]),
+    /// `hash256(h)`: a 32-byte preimage of `h`, hashed twice with SHA256
+    Hash256([u8; 32]),
+    /// `ripemd160(h)`: a 32-byte preimage of `h`
+    Ripemd160([u8; 20]),
+    /// `hash160(h)`: a 32-byte preimage of `h`, hashed with SHA256 then RIPEMD160
+    Hash160([u8; 20]),
+    /// `andor(X,Y,Z)`: X and Y, or Z. `and_n(X,Y)` is `andor(X,Y,0)`
+    AndOr(Box<Miniscript>, Box<Miniscript>, Box<Miniscript>),
+    /// `and_v(X,Y)`: X then Y, X verifying
+    AndV(Box<Miniscript>, Box<Miniscript>),
+    /// `and_b(X,Y)`: both, with OP_BOOLAND
+    AndB(Box<Miniscript>, Box<Miniscript>),
+    /// `or_b(X,Z)`: either, with OP_BOOLOR
+    OrB(Box<Miniscript>, Box<Miniscript>),
+    /// `or_c(X,Z)`: X, or else Z verifying
+    OrC(Box<Miniscript>, Box<Miniscript>),
+    /// `or_d(X,Z)`: X, or else Z
+    OrD(Box<Miniscript>, Box<Miniscript>),
+    /// `or_i(X,Z)`: X or Z, picked by the witness with OP_IF
+    OrI(Box<Miniscript>, Box<Miniscript>),
+    /// `thresh(k,X1,...,Xn)`: k of the sub-expressions
+    Thresh(usize, Vec<Miniscript>),
+    /// `multi(k,key1,...,keyn)`: k of the keys sign, with OP_CHECKMULTISIG
+    Multi(usize, Vec<Vec<u8>>),
+    /// `a:X`: X run on the alt stack
+    Alt(Box<Miniscript>),
+    /// `s:X`: X run under the top item
+    Swap(Box<Miniscript>),
+    /// `c:X`: X followed by OP_CHECKSIG
+    Check(Box<Miniscript>),
+    /// `d:X`: X only when the witness has a 1 on top
+    DupIf(Box<Miniscript>),
+    /// `v:X`: X verifying, failing the script rather than leaving zero
+    Verify(Box<Miniscript>),
+    /// `j:X`: X only when the top item isn't empty
+    NonZero(Box<Miniscript>),
+    /// `n:X`: X with its result turned into 0 or 1
+    ZeroNotEqual(Box<Miniscript>),
+}
+
+/// What a fragment takes from the stack and leaves on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseType {
+    /// Takes its inputs from the top of the stack and leaves a nonzero item when satisfied, zero when dissatisfied
+    B,
+    /// Takes its inputs from the top of the stack and leaves nothing; it can't be dissatisfied
+    V,
+    /// Like B, but leaves a key to check a signature with rather than the result
+    K,
+    /// Like B, but takes its inputs from under the top item, which it leaves on top
+    W,
+}
+
+/// The type of a fragment: its base type and the properties miniscript names by a letter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Type {
+    pub base: BaseType,
+    /// `z`: takes no items from the witness
+    pub zero_arg: bool,
+    /// `o`: takes exactly one item from the witness
+    pub one_arg: bool,
+    /// `n`: the top item it takes is never empty when satisfying
+    pub nonzero: bool,
+    /// `d`: can be dissatisfied without a signature, a preimage or a timelock
+    pub dissatisfiable: bool,
+    /// `u`: leaves exactly 1 when satisfied
+    pub unit: bool,
+}
+
+/// Why a miniscript couldn't be parsed
+#[derive(Debug, PartialEq, Eq)]
+pub enum MiniscriptError {
+    /// Unbalanced parentheses, an empty argument or nothing before a colon
+    Syntax(String),
+    /// A fragment that doesn't exist, or given the wrong number of arguments
+    UnknownFragment(String),
+    /// A wrapper letter that doesn't exist
+    UnknownWrapper(char),
+    /// Not a compressed SEC public key in hex
+    InvalidKey(String),
+    /// Not a hash of the size the fragment takes in hex
+    InvalidHash(String),
+    /// A timelock that isn't a number from 1 to 2^31 - 1, or a threshold that isn't a number
+    InvalidNumber(String),
+    /// A threshold k of n that isn't from 1 to n, with n at most 20 for `multi`, as (k, n)
+    Threshold(usize, usize),
+    /// The fragment with this name got a sub-expression of a type it can't take
+    TypeMismatch(&'static str),
+    /// The whole expression must be of base type B to be a script, this is the one it has
+    TopLevelType(BaseType),
+}
+
+/// What `Miniscript::satisfy` can use: signatures by key, preimages and the transaction's locks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Satisfier {
+    /// (key, signature) pairs, the signatures with their hash type byte
+    pub signatures: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Known preimages, matched against a hash fragment by hashing them
+    pub preimages: Vec<Vec<u8>>,
+    /// The sequence of the input being spent, for `older`
+    pub sequence: u32,
+    /// The lock time of the transaction, for `after`
+    pub lock_time: u32,
+}
+
+/// One way of spending a miniscript: the keys that sign, the hashes whose preimages are revealed and the locks
+/// the spending transaction needs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpendPath {
+    pub keys: Vec<Vec<u8>>,
+    pub hashes: Vec<Vec<u8>>,
+    /// The relative lock of the input, the highest `older` on the path
+    pub older: Option<u32>,
+    /// The lock time of the transaction, the highest `after` on the path
+    pub after: Option<u32>,
+}
+
+/// A satisfaction and a dissatisfaction, as witness items from the bottom of the stack
+#[derive(Debug, Clone)]
+struct Satisfactions {
+    sat: Option<Vec<Vec<u8>>>,
+    dissat: Option<Vec<Vec<u8>>>,
+}
+
+impl Satisfier {
+    fn signature(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.signatures.iter().find(|(signer, _)| signer == key).map(|(_, signature)| signature.clone())
+    }
+
+    fn preimage(&self, hash: &[u8], hasher: fn(&[u8]) -> Vec<u8>) -> Option<Vec<u8>> {
+        self.preimages.iter().find(|preimage| preimage.len() == 32 && hasher(preimage) == hash).cloned()
+    }
+
+    /// Whether the sequence satisfies OP_CHECKSEQUENCEVERIFY for `n`: enabled, of the same unit and as high
+    fn older(&self, n: u32) -> bool {
+        let same_unit = self.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG == n & SEQUENCE_LOCKTIME_TYPE_FLAG;
+        self.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG == 0
+            && same_unit
+            && self.sequence & SEQUENCE_LOCKTIME_MASK >= n & SEQUENCE_LOCKTIME_MASK
+    }
+
+    /// Whether the lock time satisfies OP_CHECKLOCKTIMEVERIFY for `n`: both heights or both times, and as high.
+    /// The input's sequence must also not be final, which isn't checked.
+    fn after(&self, n: u32) -> bool {
+        (self.lock_time < LOCKTIME_THRESHOLD) == (n < LOCKTIME_THRESHOLD) && self.lock_time >= n
+    }
+}
+
+impl Miniscript {
+    /// The fragment's name, as written before its arguments
+    fn name(&self) -> &'static str {
+        match self {
+            Miniscript::False => "0",
+            Miniscript::True => "1",
+            Miniscript::PkK(_) => "pk_k",
+            Miniscript::PkH(_) => "pk_h",
+            Miniscript::Older(_) => "older",
+            Miniscript::After(_) => "after",
+            Miniscript::Sha256(_) => "sha256",
+            Miniscript::Hash256(_) => "hash256",
+            Miniscript::Ripemd160(_) => "ripemd160",
+            Miniscript::Hash160(_) => "hash160",
+            Miniscript::AndOr(..) => "andor",
+            Miniscript::AndV(..) => "and_v",
+            Miniscript::AndB(..) => "and_b",
+            Miniscript::OrB(..) => "or_b",
+            Miniscript::OrC(..) => "or_c",
+            Miniscript::OrD(..) => "or_d",
+            Miniscript::OrI(..) => "or_i",
+            Miniscript::Thresh(..) => "thresh",
+            Miniscript::Multi(..) => "multi",
+            Miniscript::Alt(_) => "a",
+            Miniscript::Swap(_) => "s",
+            Miniscript::Check(_) => "c",
+            Miniscript::DupIf(_) => "d",
+            Miniscript::Verify(_) => "v",
+            Miniscript::NonZero(_) => "j",
+            Miniscript::ZeroNotEqual(_) => "n",
+        }
+    }
+
+    /// The type of the expression, by the rules of the miniscript spec for P2WSH. Fails on the first fragment
+    /// whose sub-expressions aren't of a type it takes.
+    pub fn type_check(&self) -> Result<Type, MiniscriptError> {
+        use BaseType::*;
+
+        let ty = |base, zero_arg, one_arg, nonzero, dissatisfiable, unit| Type {
+            base,
+            zero_arg,
+            one_arg,
+            nonzero,
+            dissatisfiable,
+            unit,
+        };
+        let mismatch = || MiniscriptError::TypeMismatch(self.name());
+
+        let ty = match self {
+            Miniscript::False => ty(B, true, false, false, true, true),
+            Miniscript::True => ty(B, true, false, false, false, true),
+            Miniscript::PkK(_) => ty(K, false, true, true, true, true),
+            Miniscript::PkH(_) => ty(K, false, false, true, true, true),
+            Miniscript::Older(_) | Miniscript::After(_) => ty(B, true, false, false, false, false),
+            Miniscript::Sha256(_) | Miniscript::Hash256(_) | Miniscript::Ripemd160(_) | Miniscript::Hash160(_) => {
+                ty(B, false, true, true, true, true)
+            }
+            Miniscript::AndOr(x, y, z) => {
+                let (x, y, z) = (x.type_check()?, y.type_check()?, z.type_check()?);
+                if x.base != B || !x.dissatisfiable || !x.unit || y.base != z.base || y.base == W {
+                    return Err(mismatch());
+                }
+                let one_arg = (x.zero_arg && y.one_arg && z.one_arg) || (x.one_arg && y.zero_arg && z.zero_arg);
+                let zero_arg = x.zero_arg && y.zero_arg && z.zero_arg;
+                ty(y.base, zero_arg, one_arg, false, x.dissatisfiable && z.dissatisfiable, y.unit && z.unit)
+            }
+            Miniscript::AndV(x, y) => {
+                let (x, y) = (x.type_check()?, y.type_check()?);
+                if x.base != V || y.base == W {
+                    return Err(mismatch());
+                }
+                let one_arg = (x.zero_arg && y.one_arg) || (x.one_arg && y.zero_arg);
+                let nonzero = x.nonzero || (x.zero_arg && y.nonzero);
+                ty(y.base, x.zero_arg && y.zero_arg, one_arg, nonzero, false, y.unit)
+            }
+            Miniscript::AndB(x, y) => {
+                let (x, y) = (x.type_check()?, y.type_check()?);
+                if x.base != B || y.base != W {
+                    return Err(mismatch());
+                }
+                let one_arg = (x.zero_arg && y.one_arg) || (x.one_arg && y.zero_arg);
+                let nonzero = x.nonzero || (x.zero_arg && y.nonzero);
+                let dissatisfiable = x.dissatisfiable && y.dissatisfiable;
+                ty(B, x.zero_arg && y.zero_arg, one_arg, nonzero, dissatisfiable, true)
+            }
+            Miniscript::OrB(x, z) => {
+                let (x, z) = (x.type_check()?, z.type_check()?);
+                if x.base != B || !x.dissatisfiable || z.base != W || !z.dissatisfiable {
+                    return Err(mismatch());
+                }
+                let one_arg = (x.zero_arg && z.one_arg) || (x.one_arg && z.zero_arg);
+                ty(B, x.zero_arg && z.zero_arg, one_arg, false, true, true)
+            }
+            Miniscript::OrC(x, z) => {
+                let (x, z) = (x.type_check()?, z.type_check()?);
+                if x.base != B || !x.dissatisfiable || !x.unit || z.base != V {
+                    return Err(mismatch());
+                }
+                ty(V, x.zero_arg && z.zero_arg, x.one_arg && z.zero_arg, false, false, false)
+            }
+            Miniscript::OrD(x, z) => {
+                let (x, z) = (x.type_check()?, z.type_check()?);
+                if x.base != B || !x.dissatisfiable || !x.unit || z.base != B {
+                    return Err(mismatch());
+                }
+                ty(B, x.zero_arg && z.zero_arg, x.one_arg && z.zero_arg, false, z.dissatisfiable, z.unit)
+            }
+            Miniscript::OrI(x, z) => {
+                let (x, z) = (x.type_check()?, z.type_check()?);
+                if x.base != z.base || x.base == W {
+                    return Err(mismatch());
+                }
+                let dissatisfiable = x.dissatisfiable || z.dissatisfiable;
+                ty(x.base, false, x.zero_arg && z.zero_arg, false, dissatisfiable, x.unit && z.unit)
+            }
+            Miniscript::Thresh(_, subs) => {
+                let types = subs.iter().map(Miniscript::type_check).collect::<Result<Vec<_>, _>>()?;
+                let well_typed = types.iter().enumerate().all(|(index, sub)| {
+                    let base = if index == 0 { B } else { W };
+                    sub.base == base && sub.dissatisfiable && sub.unit
+                });
+                if !well_typed {
+                    return Err(mismatch());
+                }
+                let zero_args = types.iter().filter(|sub| sub.zero_arg).count();
+                let one_arg = zero_args + 1 == types.len() && types.iter().any(|sub| sub.one_arg);
+                ty(B, zero_args == types.len(), one_arg, false, true, true)
+            }
+            Miniscript::Multi(..) => ty(B, false, false, true, true, true),
+            Miniscript::Alt(x) => {
+                let x = x.type_check()?;
+                if x.base != B {
+                    return Err(mismatch());
+                }
+                ty(W, false, false, false, x.dissatisfiable, x.unit)
+            }
+            Miniscript::Swap(x) => {
+                let x = x.type_check()?;
+                if x.base != B || !x.one_arg {
+                    return Err(mismatch());
+                }
+                ty(W, false, false, false, x.dissatisfiable, x.unit)
+            }
+            Miniscript::Check(x) => {
+                let x = x.type_check()?;
+                if x.base != K {
+                    return Err(mismatch());
+                }
+                ty(B, false, x.one_arg, x.nonzero, x.dissatisfiable, true)
+            }
+            Miniscript::DupIf(x) => {
+                let x = x.type_check()?;
+                if x.base != V || !x.zero_arg {
+                    return Err(mismatch());
+                }
+                ty(B, false, true, true, true, false)
+            }
+            Miniscript::Verify(x) => {
+                let x = x.type_check()?;
+                if x.base != B {
+                    return Err(mismatch());
+                }
+                ty(V, x.zero_arg, x.one_arg, x.nonzero, false, false)
+            }
+            Miniscript::NonZero(x) => {
+                let x = x.type_check()?;
+                if x.base != B || !x.nonzero {
+                    return Err(mismatch());
+                }
+                ty(B, false, x.one_arg, true, true, x.unit)
+            }
+            Miniscript::ZeroNotEqual(x) => {
+                let x = x.type_check()?;
+                if x.base != B {
+                    return Err(mismatch());
+                }
+                ty(B, x.zero_arg, x.one_arg, x.nonzero, x.dissatisfiable, true)
+            }
+        };
+        Ok(ty)
+    }
+
+    /// The witness script the expression compiles to
+    pub fn to_script(&self) -> Script {
+        let mut commands = vec![];
+        self.compile(&mut commands);
+        Script::from_commands(commands)
+    }
+
+    fn compile(&self, commands: &mut Vec<Command>) {
+        let op = |opcode: Opcode| Command::Op(opcode.to_u8());
+        let number = |n: i64| Command::Push(encode_num(n));
+
+        match self {
+            Miniscript::False => commands.push(op(Opcode::Op0)),
+            Miniscript::True => commands.push(op(Opcode::Op1)),
+            Miniscript::PkK(key) => commands.push(Command::Push(key.clone())),
+            Miniscript::PkH(key) => commands.extend([
+                op(Opcode::OpDup),
+                op(Opcode::OpHash160),
+                Command::Push(hash160(key)),
+                op(Opcode::OpEqualVerify),
+            ]),
+            Miniscript::Older(n) => commands.extend([number(*n as i64), op(Opcode::OpCheckSequenceVerify)]),
+            Miniscript::After(n) => commands.extend([number(*n as i64), op(Opcode::OpCheckLockTimeVerify)]),
+            Miniscript::Sha256(hash) | Miniscript::Hash256(hash) => {
+                let hasher = if let Miniscript::Sha256(_) = self { Opcode::OpSha256 } else { Opcode::OpHash256 };
+                commands.extend([op(Opcode::OpSize), number(32), op(Opcode::OpEqualVerify), op(hasher)]);
+                commands.extend([Command::Push(hash.to_vec()), op(Opcode::OpEqual)]);
+            }
+            Miniscript::Ripemd160(hash) | Miniscript::Hash160(hash) => {
+                let hasher =
+                    if let Miniscript::Ripemd160(_) = self { Opcode::OpRipemd160 } else { Opcode::OpHash160 };
+                commands.extend([op(Opcode::OpSize), number(32), op(Opcode::OpEqualVerify), op(hasher)]);
+                commands.extend([Command::Push(hash.to_vec()), op(Opcode::OpEqual)]);
+            }
+            Miniscript::AndOr(x, y, z) => {
+                x.compile(commands);
+                commands.push(op(Opcode::OpNotIf));
+                z.compile(commands);
+                commands.push(op(Opcode::OpElse));
+                y.compile(commands);
+                commands.push(op(Opcode::OpEndIf));
+            }
+            Miniscript::AndV(x, y) => {
+                x.compile(commands);
+                y.compile(commands);
+            }
+            Miniscript::AndB(x, y) | Miniscript::OrB(x, y) => {
+                x.compile(commands);
+                y.compile(commands);
+                commands.push(op(if let Miniscript::AndB(..) = self { Opcode::OpBoolAnd } else { Opcode::OpBoolOr }));
+            }
+            Miniscript::OrC(x, z) | Miniscript::OrD(x, z) => {
+                x.compile(commands);
+                if let Miniscript::OrD(..) = self {
+                    commands.push(op(Opcode::OpIfDup));
+                }
+                commands.push(op(Opcode::OpNotIf));
+                z.compile(commands);
+                commands.push(op(Opcode::OpEndIf));
+            }
+            Miniscript::OrI(x, z) => {
+                commands.push(op(Opcode::OpIf));
+                x.compile(commands);
+                commands.push(op(Opcode::OpElse));
+                z.compile(commands);
+                commands.push(op(Opcode::OpEndIf));
+            }
+            Miniscript::Thresh(k, subs) => {
+                for (index, sub) in subs.iter().enumerate() {
+                    sub.compile(commands);
+                    if index > 0 {
+                        commands.push(op(Opcode::OpAdd));
+                    }
+                }
+                commands.extend([number(*k as i64), op(Opcode::OpEqual)]);
+            }
+            Miniscript::Multi(k, keys) => {
+                commands.push(number(*k as i64));
+                commands.extend(keys.iter().cloned().map(Command::Push));
+                commands.extend([number(keys.len() as i64), op(Opcode::OpCheckMultiSig)]);
+            }
+            Miniscript::Alt(x) => {
+                commands.push(op(Opcode::OpToAltStack));
+                x.compile(commands);
+                commands.push(op(Opcode::OpFromAltStack));
+            }
+            Miniscript::Swap(x) => {
+                commands.push(op(Opcode::OpSwap));
+                x.compile(commands);
+            }
+            Miniscript::Check(x) => {
+                x.compile(commands);
+                commands.push(op(Opcode::OpCheckSig));
+            }
+            Miniscript::DupIf(x) => {
+                commands.extend([op(Opcode::OpDup), op(Opcode::OpIf)]);
+                x.compile(commands);
+                commands.push(op(Opcode::OpEndIf));
+            }
+            Miniscript::Verify(x) => {
+                x.compile(commands);
+                // the opcodes with a VERIFY version take it rather than an OP_VERIFY after them
+                let verify = match commands.last().and_then(Command::opcode) {
+                    Some(Opcode::OpEqual) => Some(Opcode::OpEqualVerify),
+                    Some(Opcode::OpCheckSig) => Some(Opcode::OpCheckSigVerify),
+                    Some(Opcode::OpCheckMultiSig) => Some(Opcode::OpCheckMultiSigVerify),
+                    Some(Opcode::OpNumEqual) => Some(Opcode::OpNumEqualVerify),
+                    _ => None,
+                };
+                match verify {
+                    Some(verify) => *commands.last_mut().unwrap() = op(verify),
+                    None => commands.push(op(Opcode::OpVerify)),
+                }
+            }
+            Miniscript::NonZero(x) => {
+                commands.extend([op(Opcode::OpSize), op(Opcode::Op0NotEqual), op(Opcode::OpIf)]);
+                x.compile(commands);
+                commands.push(op(Opcode::OpEndIf));
+            }
+            Miniscript::ZeroNotEqual(x) => {
+                x.compile(commands);
+                commands.push(op(Opcode::Op0NotEqual));
+            }
+        }
+    }
+
+    /// The ways the expression can be spent, one per choice of its branches
+    pub fn spend_paths(&self) -> Vec<SpendPath> {
+        let only = |path: SpendPath| vec![path];
+        match self {
+            Miniscript::False => vec![],
+            Miniscript::True => only(SpendPath::default()),
+            Miniscript::PkK(key) | Miniscript::PkH(key) => {
+                only(SpendPath { keys: vec![key.clone()], ..Default::default() })
+            }
+            Miniscript::Older(n) => only(SpendPath { older: Some(*n), ..Default::default() }),
+            Miniscript::After(n) => only(SpendPath { after: Some(*n), ..Default::default() }),
+            Miniscript::Sha256(hash) | Miniscript::Hash256(hash) => {
+                only(SpendPath { hashes: vec![hash.to_vec()], ..Default::default() })
+            }
+            Miniscript::Ripemd160(hash) | Miniscript::Hash160(hash) => {
+                only(SpendPath { hashes: vec![hash.to_vec()], ..Default::default() })
+            }
+            Miniscript::AndOr(x, y, z) => {
+                let mut paths = combine(&x.spend_paths(), &y.spend_paths());
+                paths.extend(z.spend_paths());
+                paths
+            }
+            Miniscript::AndV(x, y) | Miniscript::AndB(x, y) => combine(&x.spend_paths(), &y.spend_paths()),
+            Miniscript::OrB(x, z) | Miniscript::OrC(x, z) | Miniscript::OrD(x, z) | Miniscript::OrI(x, z) => {
+                [x.spend_paths(), z.spend_paths()].concat()
+            }
+            Miniscript::Thresh(k, subs) => {
+                let sub_paths = subs.iter().map(Miniscript::spend_paths).collect::<Vec<_>>();
+                combinations(subs.len(), *k)
+                    .into_iter()
+                    .flat_map(|chosen| {
+                        let paths = only(SpendPath::default());
+                        chosen.iter().fold(paths, |paths, index| combine(&paths, &sub_paths[*index]))
+                    })
+                    .collect()
+            }
+            Miniscript::Multi(k, keys) => combinations(keys.len(), *k)
+                .into_iter()
+                .map(|chosen| SpendPath {
+                    keys: chosen.into_iter().map(|index| keys[index].clone()).collect(),
+                    ..Default::default()
+                })
+                .collect(),
+            Miniscript::Alt(x)
+            | Miniscript::Swap(x)
+            | Miniscript::Check(x)
+            | Miniscript::DupIf(x)
+            | Miniscript::Verify(x)
+            | Miniscript::NonZero(x)
+            | Miniscript::ZeroNotEqual(x) => x.spend_paths(),
+        }
+    }
+
+    /// The witness items, bottom first, that satisfy the expression's script with what `satisfier` has,
+    /// without the witness script itself. Where there's a choice, the smallest is picked.
+    ///
+    /// The choice isn't checked for malleability: a third party may be able to change the witness of a
+    /// transaction spending some expressions without invalidating it.
+    pub fn satisfy(&self, satisfier: &Satisfier) -> Option<Vec<Vec<u8>>> {
+        self.satisfactions(satisfier).sat
+    }
+
+    fn satisfactions(&self, satisfier: &Satisfier) -> Satisfactions {
+        let satisfactions = |sat, dissat| Satisfactions { sat, dissat };
+        let empty = || Some(vec![]);
+        let item = |item: Vec<u8>| Some(vec![item]);
+
+        match self {
+            Miniscript::False => satisfactions(None, empty()),
+            Miniscript::True => satisfactions(empty(), None),
+            Miniscript::PkK(key) => satisfactions(satisfier.signature(key).map(|sig| vec![sig]), item(vec![])),
+            Miniscript::PkH(key) => {
+                let sat = satisfier.signature(key).map(|sig| vec![sig, key.clone()]);
+                satisfactions(sat, Some(vec![vec![], key.clone()]))
+            }
+            Miniscript::Older(n) => satisfactions(satisfier.older(*n).then(Vec::new), None),
+            Miniscript::After(n) => satisfactions(satisfier.after(*n).then(Vec::new), None),
+            Miniscript::Sha256(hash) => hash_satisfactions(satisfier.preimage(hash, sha256)),
+            Miniscript::Hash256(hash) => hash_satisfactions(satisfier.preimage(hash, hash256)),
+            Miniscript::Ripemd160(hash) => hash_satisfactions(satisfier.preimage(hash, ripemd160)),
+            Miniscript::Hash160(hash) => hash_satisfactions(satisfier.preimage(hash, hash160)),
+            Miniscript::AndOr(x, y, z) => {
+                let (x, y, z) = (x.satisfactions(satisfier), y.satisfactions(satisfier), z.satisfactions(satisfier));
+                let sat = smallest([concat(&y.sat, &x.sat), concat(&z.sat, &x.dissat)]);
+                satisfactions(sat, concat(&z.dissat, &x.dissat))
+            }
+            Miniscript::AndV(x, y) => {
+                let (x, y) = (x.satisfactions(satisfier), y.satisfactions(satisfier));
+                satisfactions(concat(&y.sat, &x.sat), None)
+            }
+            Miniscript::AndB(x, y) => {
+                let (x, y) = (x.satisfactions(satisfier), y.satisfactions(satisfier));
+                satisfactions(concat(&y.sat, &x.sat), concat(&y.dissat, &x.dissat))
+            }
+            Miniscript::OrB(x, z) => {
+                let (x, z) = (x.satisfactions(satisfier), z.satisfactions(satisfier));
+                let sat = smallest([concat(&z.dissat, &x.sat), concat(&z.sat, &x.dissat)]);
+                satisfactions(sat, concat(&z.dissat, &x.dissat))
+            }
+            Miniscript::OrC(x, z) => {
+                let (x, z) = (x.satisfactions(satisfier), z.satisfactions(satisfier));
+                satisfactions(smallest([x.sat, concat(&z.sat, &x.dissat)]), None)
+            }
+            Miniscript::OrD(x, z) => {
+                let (x, z) = (x.satisfactions(satisfier), z.satisfactions(satisfier));
+                let sat = smallest([x.sat.clone(), concat(&z.sat, &x.dissat)]);
+                satisfactions(sat, concat(&z.dissat, &x.dissat))
+            }
+            Miniscript::OrI(x, z) => {
+                let (x, z) = (x.satisfactions(satisfier), z.satisfactions(satisfier));
+                let (left, right) = (item(vec![1]), item(vec![]));
+                let sat = smallest([concat(&x.sat, &left), concat(&z.sat, &right)]);
+                let dissat = smallest([concat(&x.dissat, &left), concat(&z.dissat, &right)]);
+                satisfactions(sat, dissat)
+            }
+            Miniscript::Thresh(k, subs) => {
+                // by_count[i] is the smallest witness for the subs so far with i of them satisfied. The first sub's
+                // items go on top, so each one after goes under those before.
+                let mut by_count: Vec<Option<Vec<Vec<u8>>>> = vec![None; k + 1];
+                by_count[0] = empty();
+                for sub in subs {
+                    let sub = sub.satisfactions(satisfier);
+                    by_count = (0..=*k)
+                        .map(|count| {
+                            let satisfied = if count > 0 { concat(&sub.sat, &by_count[count - 1]) } else { None };
+                            smallest([concat(&sub.dissat, &by_count[count]), satisfied])
+                        })
+                        .collect();
+                }
+                // dissatisfied, all of them are
+                let dissat = by_count[0].clone();
+                satisfactions(by_count.pop().flatten(), dissat)
+            }
+            Miniscript::Multi(k, keys) => {
+                let signatures = keys.iter().filter_map(|key| satisfier.signature(key)).take(*k).collect::<Vec<_>>();
+                // OP_CHECKMULTISIG pops an extra item
+                let sat = (signatures.len() == *k).then(|| [vec![vec![]], signatures].concat());
+                satisfactions(sat, Some(vec![vec![]; k + 1]))
+            }
+            Miniscript::Alt(x) | Miniscript::Swap(x) | Miniscript::Check(x) | Miniscript::ZeroNotEqual(x) => {
+                x.satisfactions(satisfier)
+            }
+            Miniscript::DupIf(x) => {
+                let sat = concat(&x.satisfactions(satisfier).sat, &item(vec![1]));
+                satisfactions(sat, item(vec![]))
+            }
+            Miniscript::Verify(x) => satisfactions(x.satisfactions(satisfier).sat, None),
+            Miniscript::NonZero(x) => satisfactions(x.satisfactions(satisfier).sat, item(vec![])),
+        }
+    }
+}
+
+/// A preimage satisfies a hash fragment, any other 32 bytes dissatisfy it
+fn hash_satisfactions(preimage: Option<Vec<u8>>) -> Satisfactions {
+    Satisfactions { sat: preimage.map(|preimage| vec![preimage]), dissat: Some(vec![vec![0; 32]]) }
+}
+
+/// `bottom`'s items under `top`'s, when there are both
+fn concat(bottom: &Option<Vec<Vec<u8>>>, top: &Option<Vec<Vec<u8>>>) -> Option<Vec<Vec<u8>>> {
+    Some([bottom.clone()?, top.clone()?].concat())
+}
+
+/// The witness that takes the fewest bytes, each item with its length
+fn smallest<const N: usize>(witnesses: [Option<Vec<Vec<u8>>>; N]) -> Option<Vec<Vec<u8>>> {
+    witnesses
+        .into_iter()
+        .flatten()
+        .min_by_key(|witness| witness.iter().map(|item| 1 + item.len()).sum::<usize>())
+}
+
+/// Every path of `first` with every path of `second`
+fn combine(first: &[SpendPath], second: &[SpendPath]) -> Vec<SpendPath> {
+    first
+        .iter()
+        .flat_map(|a| {
+            second.iter().map(move |b| SpendPath {
+                keys: [a.keys.clone(), b.keys.clone()].concat(),
+                hashes: [a.hashes.clone(), b.hashes.clone()].concat(),
+                older: a.older.max(b.older),
+                after: a.after.max(b.after),
+            })
+        })
+        .collect()
+}
+
+/// Every way of choosing k of the indices 0 to n - 1, in order
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![vec![]];
+    }
+    (k - 1..n)
+        .flat_map(|last| {
+            combinations(last, k - 1).into_iter().map(move |mut chosen| {
+                chosen.push(last);
+                chosen
+            })
+        })
+        .collect()
+}
+
+impl FromStr for Miniscript {
+    type Err = MiniscriptError;
+
+    /// Parses and type-checks an expression, which must be of base type B
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let miniscript = parse(expression)?;
+        let ty = miniscript.type_check()?;
+        if ty.base != BaseType::B {
+            return Err(MiniscriptError::TopLevelType(ty.base));
+        }
+        Ok(miniscript)
+    }
+}
+
+fn parse(expression: &str) -> Result<Miniscript, MiniscriptError> {
+    // the wrapper letters come before a colon, which a fragment's name never has
+    let (wrappers, fragment) = match expression.split_once(':') {
+        Some((wrappers, fragment)) if !wrappers.contains('(') => (wrappers, fragment),
+        _ => ("", expression),
+    };
+    if fragment.len() < expression.len() && wrappers.is_empty() {
+        return Err(MiniscriptError::Syntax(expression.to_string()));
+    }
+
+    let (name, args) = match fragment.split_once('(') {
+        Some((name, rest)) => {
+            let args = rest.strip_suffix(')').ok_or_else(|| MiniscriptError::Syntax(fragment.to_string()))?;
+            (name, split_args(args).ok_or_else(|| MiniscriptError::Syntax(fragment.to_string()))?)
+        }
+        None => (fragment, vec![]),
+    };
+    let sub = |arg: &str| parse(arg).map(Box::new);
+
+    let mut miniscript = match (name, args.as_slice()) {
+        ("0", []) => Miniscript::False,
+        ("1", []) => Miniscript::True,
+        ("pk_k", [key]) => Miniscript::PkK(parse_key(key)?),
+        ("pk_h", [key]) => Miniscript::PkH(parse_key(key)?),
+        ("pk", [key]) => Miniscript::Check(Box::new(Miniscript::PkK(parse_key(key)?))),
+        ("pkh", [key]) => Miniscript::Check(Box::new(Miniscript::PkH(parse_key(key)?))),
+        ("older", [n]) => Miniscript::Older(parse_lock(n)?),
+        ("after", [n]) => Miniscript::After(parse_lock(n)?),
+        ("sha256", [hash]) => Miniscript::Sha256(parse_hash(hash)?),
+        ("hash256", [hash]) => Miniscript::Hash256(parse_hash(hash)?),
+        ("ripemd160", [hash]) => Miniscript::Ripemd160(parse_hash(hash)?),
+        ("hash160", [hash]) => Miniscript::Hash160(parse_hash(hash)?),
+        ("andor", [x, y, z]) => Miniscript::AndOr(sub(x)?, sub(y)?, sub(z)?),
+        ("and_n", [x, y]) => Miniscript::AndOr(sub(x)?, sub(y)?, Box::new(Miniscript::False)),
+        ("and_v", [x, y]) => Miniscript::AndV(sub(x)?, sub(y)?),
+        ("and_b", [x, y]) => Miniscript::AndB(sub(x)?, sub(y)?),
+        ("or_b", [x, z]) => Miniscript::OrB(sub(x)?, sub(z)?),
+        ("or_c", [x, z]) => Miniscript::OrC(sub(x)?, sub(z)?),
+        ("or_d", [x, z]) => Miniscript::OrD(sub(x)?, sub(z)?),
+        ("or_i", [x, z]) => Miniscript::OrI(sub(x)?, sub(z)?),
+        ("thresh", [k, subs @ ..]) if !subs.is_empty() => {
+            let k = parse_threshold(k, subs.len(), subs.len())?;
+            Miniscript::Thresh(k, subs.iter().map(|sub| parse(sub)).collect::<Result<_, _>>()?)
+        }
+        ("multi", [k, keys @ ..]) if !keys.is_empty() => {
+            let k = parse_threshold(k, keys.len(), MAX_PUBKEYS_PER_MULTISIG)?;
+            Miniscript::Multi(k, keys.iter().map(|key| parse_key(key)).collect::<Result<_, _>>()?)
+        }
+        _ => return Err(MiniscriptError::UnknownFragment(fragment.to_string())),
+    };
+
+    for wrapper in wrappers.chars().rev() {
+        let inner = Box::new(miniscript);
+        miniscript = match wrapper {
+            'a' => Miniscript::Alt(inner),
+            's' => Miniscript::Swap(inner),
+            'c' => Miniscript::Check(inner),
+            'd' => Miniscript::DupIf(inner),
+            'v' => Miniscript::Verify(inner),
+            'j' => Miniscript::NonZero(inner),
+            'n' => Miniscript::ZeroNotEqual(inner),
+            't' => Miniscript::AndV(inner, Box::new(Miniscript::True)),
+            'l' => Miniscript::OrI(Box::new(Miniscript::False), inner),
+            'u' => Miniscript::OrI(inner, Box::new(Miniscript::False)),
+            _ => return Err(MiniscriptError::UnknownWrapper(wrapper)),
+        };
+    }
+    Ok(miniscript)
+}
+
+/// The arguments between a fragment's parentheses, split at the commas outside nested ones
+fn split_args(args: &str) -> Option<Vec<&str>> {
+    let mut split = vec![];
+    let (mut depth, mut start) = (0usize, 0);
+    for (index, char) in args.char_indices() {
+        match char {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                split.push(&args[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&args[start..]);
+    (depth == 0 && split.iter().all(|arg| !arg.is_empty())).then_some(split)
+}
+
+fn parse_key(key: &str) -> Result<Vec<u8>, MiniscriptError> {
+    match from_hex(key) {
+        Ok(bytes) if bytes.len() == 33 && parse_sec(&bytes).is_some() => Ok(bytes),
+        _ => Err(MiniscriptError::InvalidKey(key.to_string())),
+    }
+}
+
+fn parse_hash<const N: usize>(hash: &str) -> Result<[u8; N], MiniscriptError> {
+    from_hex(hash)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| MiniscriptError::InvalidHash(hash.to_string()))
+}
+
+fn parse_lock(n: &str) -> Result<u32, MiniscriptError> {
+    match n.parse() {
+        Ok(n @ 1..=0x7fffffff) => Ok(n),
+        _ => Err(MiniscriptError::InvalidNumber(n.to_string())),
+    }
+}
+
+fn parse_threshold(k: &str, n: usize, max: usize) -> Result<usize, MiniscriptError> {
+    let k = k.parse().map_err(|_| MiniscriptError::InvalidNumber(k.to_string()))?;
+    if k == 0 || k > n || n > max {
+        return Err(MiniscriptError::Threshold(k, n));
+    }
+    Ok(k)
+}
+
+impl Display for Miniscript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the wrappers are written as letters in front of the fragment they wrap
+        let mut wrappers = String::new();
+        let mut fragment = self;
+        while let Miniscript::Alt(inner)
+        | Miniscript::Swap(inner)
+        | Miniscript::Check(inner)
+        | Miniscript::DupIf(inner)
+        | Miniscript::Verify(inner)
+        | Miniscript::NonZero(inner)
+        | Miniscript::ZeroNotEqual(inner) = fragment
+        {
+            wrappers.push_str(fragment.name());
+            fragment = inner;
+        }
+
+        // c:pk_k and c:pk_h are written pk and pkh
+        let checked_key = wrappers.ends_with('c') && matches!(fragment, Miniscript::PkK(_) | Miniscript::PkH(_));
+        if checked_key {
+            wrappers.pop();
+        }
+        if !wrappers.is_empty() {
+            write!(f, "{}:", wrappers)?;
+        }
+
+        match fragment {
+            Miniscript::False | Miniscript::True => write!(f, "{}", fragment.name()),
+            Miniscript::PkK(key) if checked_key => write!(f, "pk({})", to_hex(key)),
+            Miniscript::PkH(key) if checked_key => write!(f, "pkh({})", to_hex(key)),
+            Miniscript::PkK(key) | Miniscript::PkH(key) => write!(f, "{}({})", fragment.name(), to_hex(key)),
+            Miniscript::Older(n) | Miniscript::After(n) => write!(f, "{}({})", fragment.name(), n),
+            Miniscript::Sha256(hash) | Miniscript::Hash256(hash) => write!(f, "{}({})", fragment.name(), to_hex(hash)),
+            Miniscript::Ripemd160(hash) | Miniscript::Hash160(hash) => {
+                write!(f, "{}({})", fragment.name(), to_hex(hash))
+            }
+            Miniscript::AndOr(x, y, z) if **z == Miniscript::False => write!(f, "and_n({},{})", x, y),
+            Miniscript::AndOr(x, y, z) => write!(f, "andor({},{},{})", x, y, z),
+            Miniscript::AndV(x, y)
+            | Miniscript::AndB(x, y)
+            | Miniscript::OrB(x, y)
+            | Miniscript::OrC(x, y)
+            | Miniscript::OrD(x, y)
+            | Miniscript::OrI(x, y) => write!(f, "{}({},{})", fragment.name(), x, y),
+            Miniscript::Thresh(k, subs) => {
+                let subs = subs.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(f, "thresh({},{})", k, subs.join(","))
+            }
+            Miniscript::Multi(k, keys) => {
+                let keys = keys.iter().map(|key| to_hex(key)).collect::<Vec<_>>();
+                write!(f, "multi({},{})", k, keys.join(","))
+            }
+            _ => unreachable!("wrappers are written as letters"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::{traits::Serializer, PrivateKey};
+    use rug::{integer::Order, Integer};
+
+    use super::*;
+    use crate::interpreter::FixedSighash;
+
+    fn key(secret: u32) -> (PrivateKey, String) {
+        let key = PrivateKey::new(Integer::from(secret));
+        let sec = key.point().sec(true);
+        (key, sec)
+    }
+
+    fn sign(key: &PrivateKey, z: &[u8; 32]) -> Vec<u8> {
+        let mut signature = key.sign(Integer::from_digits(z, Order::MsfBe)).der_bytes();
+        signature.push(0x01);
+        signature
+    }
+
+    #[test]
+    fn test_parse_and_compile() {
+        let ((_, a), (_, b), (_, c)) = (key(1), key(2), key(3));
+
+        let expression = format!("or_d(pk({}),and_v(v:pkh({}),older(144)))", a, b);
+        let miniscript = expression.parse::<Miniscript>().unwrap();
+        assert_eq!(miniscript.to_string(), expression);
+        let b_hash = to_hex(&hash160(&from_hex(&b).unwrap()));
+        assert_eq!(
+            miniscript.to_script().asm(),
+            format!(
+                "{} OP_CHECKSIG OP_IFDUP OP_NOTIF OP_DUP OP_HASH160 {} OP_EQUALVERIFY OP_CHECKSIGVERIFY 144 \
+                 OP_CHECKSEQUENCEVERIFY OP_ENDIF",
+                a, b_hash
+            )
+        );
+
+        let thresh = format!("thresh(2,pk({}),s:pk({}),s:pk({}))", a, b, c).parse::<Miniscript>().unwrap();
+        assert_eq!(
+            thresh.to_script().asm(),
+            format!("{} OP_CHECKSIG OP_SWAP {} OP_CHECKSIG OP_ADD OP_SWAP {} OP_CHECKSIG OP_ADD 2 OP_EQUAL", a, b, c)
+        );
+        let multi = format!("multi(2,{},{})", a, b).parse::<Miniscript>().unwrap();
+        assert_eq!(multi.to_script().asm(), format!("2 {} {} 2 OP_CHECKMULTISIG", a, b));
+
+        // v: merges into the VERIFY opcodes, t:, l: and u: are shorthands
+        let hash = "ff".repeat(32);
+        let hashlock = format!("and_v(v:sha256({}),pk({}))", hash, a).parse::<Miniscript>().unwrap();
+        assert_eq!(
+            hashlock.to_script().asm(),
+            format!("OP_SIZE 32 OP_EQUALVERIFY OP_SHA256 {} OP_EQUALVERIFY {} OP_CHECKSIG", hash, a)
+        );
+        let verified = format!("tv:pk({})", a).parse::<Miniscript>().unwrap();
+        assert_eq!(verified.to_string(), format!("and_v(v:pk({}),1)", a));
+        assert_eq!(verified.to_script().asm(), format!("{} OP_CHECKSIGVERIFY 1", a));
+        let either = format!("or_i(l:pk({}),u:after(100))", a).parse::<Miniscript>().unwrap();
+        assert_eq!(either.to_string(), format!("or_i(or_i(0,pk({})),or_i(after(100),0))", a));
+        let either = format!("andor(pk({}),older(10),l:pk({}))", a, b).parse::<Miniscript>().unwrap();
+        assert_eq!(either.to_string(), format!("andor(pk({}),older(10),or_i(0,pk({})))", a, b));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let (_, a) = key(1);
+        let parse = |expression: String| expression.parse::<Miniscript>().unwrap_err();
+
+        assert_eq!(parse(format!("pk({}", a)), MiniscriptError::Syntax(format!("pk({}", a)));
+        assert_eq!(parse(format!("or_d(pk({}),)", a)), MiniscriptError::Syntax(format!("or_d(pk({}),)", a)));
+        assert_eq!(parse(format!(":pk({})", a)), MiniscriptError::Syntax(format!(":pk({})", a)));
+        assert_eq!(parse(format!("foo({})", a)), MiniscriptError::UnknownFragment(format!("foo({})", a)));
+        assert_eq!(parse("older(1,2)".to_string()), MiniscriptError::UnknownFragment("older(1,2)".to_string()));
+        assert_eq!(parse(format!("x:pk({})", a)), MiniscriptError::UnknownWrapper('x'));
+        assert_eq!(parse("pk(02aa)".to_string()), MiniscriptError::InvalidKey("02aa".to_string()));
+        assert_eq!(parse("sha256(aa)".to_string()), MiniscriptError::InvalidHash("aa".to_string()));
+        assert_eq!(parse("older(0)".to_string()), MiniscriptError::InvalidNumber("0".to_string()));
+        assert_eq!(parse("after(2147483648)".to_string()), MiniscriptError::InvalidNumber("2147483648".to_string()));
+        assert_eq!(parse(format!("thresh(2,pk({}))", a)), MiniscriptError::Threshold(2, 1));
+        assert_eq!(parse(format!("multi(0,{})", a)), MiniscriptError::Threshold(0, 1));
+
+        // and_v takes a V first, or_b a W second
+        assert_eq!(parse(format!("and_v(pk({}),pk({}))", a, a)), MiniscriptError::TypeMismatch("and_v"));
+        assert_eq!(parse(format!("or_b(pk({}),pk({}))", a, a)), MiniscriptError::TypeMismatch("or_b"));
+        assert_eq!(parse(format!("v:pk({})", a)), MiniscriptError::TopLevelType(BaseType::V));
+        assert_eq!(parse(format!("pk_k({})", a)), MiniscriptError::TopLevelType(BaseType::K));
+    }
+
+    #[test]
+    fn test_spend_paths() {
+        let ((_, a), (_, b), (_, c)) = (key(1), key(2), key(3));
+        let [a_key, b_key, c_key] = [&a, &b, &c].map(|sec| from_hex(sec).unwrap());
+
+        let miniscript = format!("or_d(pk({}),and_v(v:pkh({}),older(144)))", a, b).parse::<Miniscript>().unwrap();
+        assert_eq!(
+            miniscript.spend_paths(),
+            vec![
+                SpendPath { keys: vec![a_key.clone()], ..Default::default() },
+                SpendPath { keys: vec![b_key.clone()], older: Some(144), ..Default::default() },
+            ]
+        );
+
+        let thresh = format!("thresh(2,pk({}),s:pk({}),s:pk({}))", a, b, c).parse::<Miniscript>().unwrap();
+        let keys = thresh.spend_paths().into_iter().map(|path| path.keys).collect::<Vec<_>>();
+        assert_eq!(keys, vec![vec![a_key.clone(), b_key.clone()], vec![a_key, c_key.clone()], vec![b_key, c_key]]);
+
+        let hash = "ab".repeat(32);
+        let timelocked = format!("and_b(after(100),a:and_v(v:sha256({}),after(200)))", hash);
+        let paths = timelocked.parse::<Miniscript>().unwrap().spend_paths();
+        let hashes = vec![from_hex(&hash).unwrap()];
+        assert_eq!(paths, vec![SpendPath { hashes, after: Some(200), ..Default::default() }]);
+    }
+
+    #[test]
+    fn test_satisfy() {
+        let ((a, a_sec), (b, b_sec), (c, c_sec)) = (key(1), key(2), key(3));
+        let z = [0x42; 32];
+        let (a_sig, b_sig, c_sig) = (sign(&a, &z), sign(&b, &z), sign(&c, &z));
+        let [a_key, b_key, c_key] = [&a_sec, &b_sec, &c_sec].map(|sec| from_hex(sec).unwrap());
+        let spend = |miniscript: &Miniscript, witness: Vec<Vec<u8>>| {
+            let witness_script = miniscript.to_script();
+            let witness = Script::p2wsh_witness(witness, &witness_script);
+            Script::verify_spend(&Script::default(), &witness_script.to_p2wsh(), &witness, &FixedSighash(z))
+        };
+
+        let preimage = vec![0x07; 32];
+        let expression = format!("or_d(pk({}),and_v(v:pkh({}),sha256({})))", a_sec, b_sec, to_hex(&sha256(&preimage)));
+        let miniscript = expression.parse::<Miniscript>().unwrap();
+        assert_eq!(miniscript.satisfy(&Satisfier::default()), None);
+
+        let satisfier = Satisfier { signatures: vec![(a_key.clone(), a_sig.clone())], ..Default::default() };
+        let witness = miniscript.satisfy(&satisfier).unwrap();
+        assert_eq!(witness, vec![a_sig.clone()]);
+        assert_eq!(spend(&miniscript, witness), Ok(()));
+
+        // B and the preimage, under an empty signature that dissatisfies A
+        let satisfier = Satisfier {
+            signatures: vec![(b_key.clone(), b_sig.clone())],
+            preimages: vec![vec![0x08; 32], preimage.clone()],
+            ..Default::default()
+        };
+        let witness = miniscript.satisfy(&satisfier).unwrap();
+        assert_eq!(witness, vec![preimage, b_sig.clone(), b_key.clone(), vec![]]);
+        assert_eq!(spend(&miniscript, witness), Ok(()));
+
+        // any two, the first sub's items on top
+        let thresh = format!("thresh(2,pk({}),s:pk({}),s:pk({}))", a_sec, b_sec, c_sec).parse::<Miniscript>().unwrap();
+        let signatures = vec![(a_key.clone(), a_sig.clone()), (c_key.clone(), c_sig.clone())];
+        let satisfier = Satisfier { signatures: signatures.clone(), ..Default::default() };
+        let witness = thresh.satisfy(&satisfier).unwrap();
+        assert_eq!(witness, vec![c_sig.clone(), vec![], a_sig.clone()]);
+        assert_eq!(spend(&thresh, witness), Ok(()));
+        let one = Satisfier { signatures: signatures[1..].to_vec(), ..Default::default() };
+        assert_eq!(thresh.satisfy(&one), None);
+
+        let multi = format!("multi(2,{},{},{})", a_sec, b_sec, c_sec).parse::<Miniscript>().unwrap();
+        let witness = multi.satisfy(&satisfier).unwrap();
+        assert_eq!(witness, vec![vec![], a_sig, c_sig]);
+        assert_eq!(spend(&multi, witness), Ok(()));
+    }
+
+    #[test]
+    fn test_satisfy_timelocks() {
+        let older = "or_i(older(144),older(4194305))".parse::<Miniscript>().unwrap();
+        let at = |sequence| older.satisfy(&Satisfier { sequence, ..Default::default() });
+        assert_eq!(at(144), Some(vec![vec![1]]));
+        assert_eq!(at(143), None);
+        // a relative lock of 512 seconds takes the other branch, and disabling the lock satisfies neither
+        assert_eq!(at(SEQUENCE_LOCKTIME_TYPE_FLAG | 1), Some(vec![vec![]]));
+        assert_eq!(at(SEQUENCE_LOCKTIME_DISABLE_FLAG | 144), None);
+
+        let after = "after(500000000)".parse::<Miniscript>().unwrap();
+        assert_eq!(after.satisfy(&Satisfier { lock_time: 600_000_000, ..Default::default() }), Some(vec![]));
+        assert_eq!(after.satisfy(&Satisfier { lock_time: 800_000, ..Default::default() }), None);
+    }
+}
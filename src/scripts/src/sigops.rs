@@ -0,0 +1,133 @@
+use crate::{interpreter::MAX_PUBKEYS_PER_MULTISIG, Command, Opcode, Script};
+
+impl Script {
+    /// The signature operations in the script, which blocks are limited in (Core's GetSigOpCount).
+    /// OP_CHECKSIG and OP_CHECKSIGVERIFY count one. OP_CHECKMULTISIG and OP_CHECKMULTISIGVERIFY count
+    /// MAX_PUBKEYS_PER_MULTISIG, or, when `accurate`, the key count OP_1 to OP_16 pushes right before them.
+    ///
+    /// Legacy script_sigs and script_pubkeys are counted without `accurate`, redeem and witness scripts with it.
+    /// Counting stops at a push that runs past the end.
+    pub fn sigop_count(&self, accurate: bool) -> usize {
+        let mut count = 0;
+        let mut last = None;
+        for command in self.commands() {
+            if let Command::Invalid(_) = command {
+                break;
+            }
+            let opcode = command.opcode();
+            match opcode {
+                Some(Opcode::OpCheckSig | Opcode::OpCheckSigVerify) => count += 1,
+                Some(Opcode::OpCheckMultiSig | Opcode::OpCheckMultiSigVerify) => {
+                    count += match last.and_then(Opcode::small_number) {
+                        Some(keys @ 1..=16) if accurate => keys as usize,
+                        _ => MAX_PUBKEYS_PER_MULTISIG,
+                    }
+                }
+                _ => {}
+            }
+            last = opcode;
+        }
+        count
+    }
+
+    /// The signature operations of the redeem script this P2SH script_pubkey is spent with, the last push
+    /// of `script_sig`, counted accurately. Other script_pubkeys, and script_sigs that do more than push,
+    /// count none.
+    pub fn p2sh_sigop_count(&self, script_sig: &Script) -> usize {
+        if self.p2sh_hash().is_none() || !script_sig.is_push_only() {
+            return 0;
+        }
+        match script_sig.commands().last() {
+            Some(Command::Push(redeem_script)) => Script::from_bytes(redeem_script.clone()).sigop_count(true),
+            _ => 0,
+        }
+    }
+
+    /// The signature operations of the witness this script_pubkey is spent with, bare or nested in P2SH
+    /// (Core's CountWitnessSigOps). A P2WPKH spend counts one and a P2WSH spend the sigops of its witness script,
+    /// counted accurately. Taproot and later versions count none, tapscripts are limited by their
+    /// validation weight instead.
+    pub fn witness_sigop_count(&self, script_sig: &Script, witness: &[Vec<u8>]) -> usize {
+        if let Some((version, program)) = self.witness_program() {
+            return witness_program_sigops(version, program, witness);
+        }
+        if self.p2sh_hash().is_none() || !script_sig.is_push_only() {
+            return 0;
+        }
+        match script_sig.commands().last() {
+            Some(Command::Push(redeem_script)) => match Script::from_bytes(redeem_script.clone()).witness_program() {
+                Some((version, program)) => witness_program_sigops(version, program, witness),
+                None => 0,
+            },
+            _ => 0,
+        }
+    }
+}
+
+fn witness_program_sigops(version: u8, program: &[u8], witness: &[Vec<u8>]) -> usize {
+    match (version, program.len(), witness.last()) {
+        (0, 20, _) => 1,
+        (0, 32, Some(witness_script)) => Script::from_bytes(witness_script.clone()).sigop_count(true),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "2103935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b67";
+
+    #[test]
+    fn test_sigop_count() {
+        let p2pkh = Script::from_hex("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").unwrap();
+        assert_eq!(p2pkh.sigop_count(false), 1);
+
+        // 2-of-3: 20 unless counted accurately
+        let multisig = Script::from_hex(&format!("52{}53ae", KEY.repeat(3))).unwrap();
+        assert_eq!(multisig.sigop_count(false), 20);
+        assert_eq!(multisig.sigop_count(true), 3);
+        // OP_0 keys isn't a count OP_1 to OP_16 pushes
+        assert_eq!(Script::from_hex("00ae").unwrap().sigop_count(true), 20);
+
+        // OP_CHECKSIGVERIFY, OP_CHECKMULTISIGVERIFY, then a push past the end hides the OP_CHECKSIG
+        assert_eq!(Script::from_hex("ad51af05acac").unwrap().sigop_count(true), 2);
+        // pushed data isn't counted
+        assert_eq!(Script::from_hex("02acac").unwrap().sigop_count(false), 0);
+    }
+
+    #[test]
+    fn test_p2sh_sigop_count() {
+        let redeem_script = Script::from_hex(&format!("52{}53ae", KEY.repeat(3))).unwrap();
+        let p2sh = redeem_script.to_p2sh();
+        let signatures = vec![Command::Op(0x00), Command::Push(vec![0x30; 71]), Command::Push(vec![0x30; 71])];
+        let script_sig = Script::p2sh_script_sig(signatures, &redeem_script);
+        assert_eq!(p2sh.sigop_count(false), 0);
+        assert_eq!(p2sh.p2sh_sigop_count(&script_sig), 3);
+
+        // only P2SH spends have a redeem script
+        assert_eq!(redeem_script.p2sh_sigop_count(&script_sig), 0);
+        let not_push_only = Script::from_hex("7601ac").unwrap();
+        assert_eq!(p2sh.p2sh_sigop_count(&not_push_only), 0);
+    }
+
+    #[test]
+    fn test_witness_sigop_count() {
+        let p2wpkh = Script::from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        assert_eq!(p2wpkh.witness_sigop_count(&Script::default(), &[vec![0x30; 71], vec![0x02; 33]]), 1);
+
+        let witness_script = Script::from_hex(&format!("52{}53ae", KEY.repeat(3))).unwrap();
+        let witness = vec![vec![], vec![0x30; 71], vec![0x30; 71], witness_script.as_bytes().to_vec()];
+        let p2wsh = witness_script.to_p2wsh();
+        assert_eq!(p2wsh.witness_sigop_count(&Script::default(), &witness), 3);
+        assert_eq!(p2wsh.witness_sigop_count(&Script::default(), &[]), 0);
+
+        // nested in P2SH
+        let script_sig = Script::p2sh_script_sig(vec![], &p2wsh);
+        assert_eq!(p2wsh.to_p2sh().witness_sigop_count(&script_sig, &witness), 3);
+        assert_eq!(p2wsh.to_p2sh().p2sh_sigop_count(&script_sig), 0);
+
+        assert_eq!(Script::p2tr(&[1; 32]).witness_sigop_count(&Script::default(), &[vec![0; 64]]), 0);
+        assert_eq!(witness_script.witness_sigop_count(&Script::default(), &witness), 0);
+    }
+}
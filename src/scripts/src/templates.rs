@@ -0,0 +1,498 @@
+use std::collections::BTreeMap;
+
+use ec_cryptography::{
+    address::Address,
+    helper::{hash160, sha256},
+    network::Network,
+};
+
+use crate::{
+    interpreter::{decode_num, encode_num, MAX_NUM_SIZE, MAX_PUBKEYS_PER_MULTISIG},
+    Command, Opcode, Script,
+};
+
+/// Why a multisig script can't be built
+#[derive(Debug, PartialEq, Eq)]
+pub enum MultisigError {
+    /// There must be 1 to MAX_PUBKEYS_PER_MULTISIG keys, not this many
+    KeyCount(usize),
+    /// The signatures required must be 1 to the number of keys, not this many
+    RequiredCount(usize),
+    /// The key with this index isn't 33 or 65 bytes, the length of a SEC public key
+    InvalidPublicKey(usize),
+}
+
+/// The standard types of script_pubkey, the ones wallets recognise and nodes relay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptType {
+    /// Pay to public key: <key> OP_CHECKSIG
+    P2PK,
+    P2PKH,
+    P2SH,
+    P2WPKH,
+    P2WSH,
+    P2TR,
+    /// A witness program of a later version, left for future soft forks
+    WitnessUnknown,
+    /// Bare multisig: OP_m <keys> OP_n OP_CHECKMULTISIG, needing `required` signatures of `keys`
+    Multisig { required: u8, keys: u8 },
+    /// OP_RETURN followed by pushes: an output that can't be spent, carrying data
+    NullData,
+    NonStandard,
+}
+
+impl ScriptType {
+    /// The name Core gives the type, as in decoded transactions
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptType::P2PK => "pubkey",
+            ScriptType::P2PKH => "pubkeyhash",
+            ScriptType::P2SH => "scripthash",
+            ScriptType::P2WPKH => "witness_v0_keyhash",
+            ScriptType::P2WSH => "witness_v0_scripthash",
+            ScriptType::P2TR => "witness_v1_taproot",
+            ScriptType::WitnessUnknown => "witness_unknown",
+            ScriptType::Multisig { .. } => "multisig",
+            ScriptType::NullData => "nulldata",
+            ScriptType::NonStandard => "nonstandard",
+        }
+    }
+}
+
+impl Script {
+    /// The type of the script as a script_pubkey. Keys are SEC encoded, 33 or 65 bytes, and
+    /// version 0 witness programs of a length other than 20 or 32 bytes are non-standard.
+    pub fn classify(&self) -> ScriptType {
+        if self.p2pkh_hash().is_some() {
+            return ScriptType::P2PKH;
+        }
+        if self.p2sh_hash().is_some() {
+            return ScriptType::P2SH;
+        }
+        if let Some((version, program)) = self.witness_program() {
+            return match (version, program.len()) {
+                (0, 20) => ScriptType::P2WPKH,
+                (0, 32) => ScriptType::P2WSH,
+                (1, 32) => ScriptType::P2TR,
+                (0, _) => ScriptType::NonStandard,
+                _ => ScriptType::WitnessUnknown,
+            };
+        }
+
+        let is_key = |command: &Command| matches!(command, Command::Push(key) if key.len() == 33 || key.len() == 65);
+        match self.commands() {
+            [Command::Op(0x6a), data @ ..] if data.iter().all(|command| matches!(command, Command::Push(_))) => {
+                ScriptType::NullData
+            }
+            [key, Command::Op(0xac)] if is_key(key) => ScriptType::P2PK,
+            [Command::Op(m @ 0x51..=0x60), keys @ .., Command::Op(n @ 0x51..=0x60), Command::Op(0xae)]
+                if m <= n && (n - 0x50) as usize == keys.len() && keys.iter().all(is_key) =>
+            {
+                ScriptType::Multisig { required: m - 0x50, keys: n - 0x50 }
+            }
+            _ => ScriptType::NonStandard,
+        }
+    }
+
+    /// The P2PKH script_pubkey paying to the hash160 of a SEC public key:
+    /// OP_DUP OP_HASH160 <hash> OP_EQUALVERIFY OP_CHECKSIG.
+    /// It is also the script code segwit v0 signatures of P2WPKH inputs commit to.
+    pub fn p2pkh(hash160: &[u8; 20]) -> Script {
+        Script::from_commands(vec![
+            Command::Op(Opcode::OpDup.to_u8()),
+            Command::Op(Opcode::OpHash160.to_u8()),
+            Command::Push(hash160.to_vec()),
+            Command::Op(Opcode::OpEqualVerify.to_u8()),
+            Command::Op(Opcode::OpCheckSig.to_u8()),
+        ])
+    }
+
+    /// The script_sig spending a P2PKH output: the DER signature with its hash type byte,
+    /// then the SEC public key whose hash the output pays to
+    pub fn p2pkh_script_sig(signature: &[u8], public_key: &[u8]) -> Script {
+        Script::from_commands(vec![Command::Push(signature.to_vec()), Command::Push(public_key.to_vec())])
+    }
+
+    /// The hash160 a P2PKH script_pubkey pays to, if this is one
+    pub fn p2pkh_hash(&self) -> Option<&[u8; 20]> {
+        match self.as_bytes() {
+            [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] => hash.try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// The P2WPKH script_pubkey paying to the hash160 of a compressed SEC public key: OP_0 <hash>
+    pub fn p2wpkh(key_hash: &[u8; 20]) -> Script {
+        Script::from_commands(vec![Command::Op(Opcode::Op0.to_u8()), Command::Push(key_hash.to_vec())])
+    }
+
+    /// The P2WSH script_pubkey paying to the sha256 of a witness script: OP_0 <hash>
+    pub fn p2wsh(script_hash: &[u8; 32]) -> Script {
+        Script::from_commands(vec![Command::Op(Opcode::Op0.to_u8()), Command::Push(script_hash.to_vec())])
+    }
+
+    /// The P2WSH script_pubkey with this script as its witness script
+    pub fn to_p2wsh(&self) -> Script {
+        Script::p2wsh(&sha256(self.as_bytes()).try_into().expect("sha256 is 32 bytes"))
+    }
+
+    /// The P2WSH address with this script as its witness script
+    pub fn p2wsh_address(&self, network: Network) -> Address {
+        Address::P2WSH { program: sha256(self.as_bytes()), network }
+    }
+
+    /// The witness spending a P2WSH output: the items `inputs` the witness script takes, then the witness script
+    pub fn p2wsh_witness(inputs: Vec<Vec<u8>>, witness_script: &Script) -> Vec<Vec<u8>> {
+        let mut witness = inputs;
+        witness.push(witness_script.as_bytes().to_vec());
+        witness
+    }
+
+    /// The P2TR script_pubkey (BIP341) paying to an x-only taproot output key: OP_1 <key>.
+    /// The key is the internal key tweaked with the root of the script tree, if there is one.
+    pub fn p2tr(output_key: &[u8; 32]) -> Script {
+        Script::from_commands(vec![Command::Op(Opcode::Op1.to_u8()), Command::Push(output_key.to_vec())])
+    }
+
+    /// The version and program of a witness program (BIP141): a version opcode, OP_0 or OP_1 to OP_16,
+    /// followed by a direct push of 2 to 40 bytes and nothing else
+    pub fn witness_program(&self) -> Option<(u8, &[u8])> {
+        match self.as_bytes() {
+            [version @ (0x00 | 0x51..=0x60), length, program @ ..]
+                if (2..=40).contains(length) && *length as usize == program.len() =>
+            {
+                let version = if *version == 0 { 0 } else { version - 0x50 };
+                Some((version, program))
+            }
+            _ => None,
+        }
+    }
+
+    /// The address of a script_pubkey, for the types that have one: P2PKH, P2SH, P2WPKH, P2WSH and P2TR.
+    /// `Address::script_pubkey` goes the other way.
+    pub fn to_address(&self, network: Network) -> Option<Address> {
+        if let Some(hash) = self.p2pkh_hash() {
+            return Some(Address::P2PKH { hash: hash.to_vec(), network });
+        }
+        if let Some(hash) = self.p2sh_hash() {
+            return Some(Address::P2SH { hash: hash.to_vec(), network });
+        }
+
+        let (version, program) = self.witness_program()?;
+        let program = program.to_vec();
+        match (version, program.len()) {
+            (0, 20) => Some(Address::P2WPKH { program, network }),
+            (0, 32) => Some(Address::P2WSH { program, network }),
+            (1, 32) => Some(Address::P2TR { program, network }),
+            _ => None,
+        }
+    }
+
+    /// The multisig script needing `required` signatures of the SEC `public_keys`, in the order given:
+    /// OP_m <keys> OP_n OP_CHECKMULTISIG. Counts above 16 are pushed as numbers.
+    /// Only up to 3 keys are standard as a bare script_pubkey, so it usually goes in P2SH or P2WSH.
+    pub fn multisig(required: usize, public_keys: &[Vec<u8>]) -> Result<Script, MultisigError> {
+        if public_keys.is_empty() || public_keys.len() > MAX_PUBKEYS_PER_MULTISIG {
+            return Err(MultisigError::KeyCount(public_keys.len()));
+        }
+        if required == 0 || required > public_keys.len() {
+            return Err(MultisigError::RequiredCount(required));
+        }
+        if let Some(index) = public_keys.iter().position(|key| key.len() != 33 && key.len() != 65) {
+            return Err(MultisigError::InvalidPublicKey(index));
+        }
+
+        let mut commands = vec![number_command(required)];
+        commands.extend(public_keys.iter().cloned().map(Command::Push));
+        commands.push(number_command(public_keys.len()));
+        commands.push(Command::Op(Opcode::OpCheckMultiSig.to_u8()));
+        Ok(Script::from_commands(commands))
+    }
+
+    /// The multisig script with its keys sorted (BIP67), so that the same keys give the same script
+    /// whatever order the cosigners list them in. BIP67 only uses compressed keys.
+    pub fn sorted_multisig(required: usize, public_keys: &[Vec<u8>]) -> Result<Script, MultisigError> {
+        let mut public_keys = public_keys.to_vec();
+        public_keys.sort();
+        Script::multisig(required, &public_keys)
+    }
+
+    /// The signatures required and the public keys of a multisig script, if this is one
+    pub fn multisig_keys(&self) -> Option<(usize, Vec<&[u8]>)> {
+        let [required, keys @ .., key_count, Command::Op(0xae)] = self.commands() else {
+            return None;
+        };
+        let (required, key_count) = (command_number(required)?, command_number(key_count)?);
+        let keys = keys
+            .iter()
+            .map(|key| match key {
+                Command::Push(key) => Some(key.as_slice()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        (key_count == keys.len() as i64 && (1..=key_count).contains(&required)).then_some((required as usize, keys))
+    }
+
+    /// The items spending a multisig script once enough signatures are collected: the empty item
+    /// OP_CHECKMULTISIG pops too many, then signatures for the keys in `signatures` in the order of the keys.
+    /// They are the pushes of the script_sig of a bare or P2SH output, and the witness items of a P2WSH one.
+    ///
+    /// None if this isn't a multisig script, or while there are fewer signatures than it requires.
+    pub fn multisig_inputs(&self, signatures: &BTreeMap<Vec<u8>, Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+        let (required, keys) = self.multisig_keys()?;
+        let chosen: Vec<Vec<u8>> = keys.iter().filter_map(|key| signatures.get(*key).cloned()).take(required).collect();
+        if chosen.len() < required {
+            return None;
+        }
+        Some([vec![vec![]], chosen].concat())
+    }
+
+    /// The P2SH script_pubkey (BIP16) paying to the hash160 of a redeem script: OP_HASH160 <hash> OP_EQUAL
+    pub fn p2sh(script_hash: &[u8; 20]) -> Script {
+        Script::from_commands(vec![
+            Command::Op(Opcode::OpHash160.to_u8()),
+            Command::Push(script_hash.to_vec()),
+            Command::Op(Opcode::OpEqual.to_u8()),
+        ])
+    }
+
+    /// The hash160 a P2SH script_pubkey pays to, if this is one. Only this exact form is P2SH,
+    /// other scripts checking a hash the same way aren't.
+    pub fn p2sh_hash(&self) -> Option<&[u8; 20]> {
+        match self.as_bytes() {
+            [0xa9, 0x14, hash @ .., 0x87] => hash.try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// The P2SH script_pubkey with this script as its redeem script
+    pub fn to_p2sh(&self) -> Script {
+        Script::p2sh(&self.script_hash())
+    }
+
+    /// The P2SH address with this script as its redeem script
+    pub fn p2sh_address(&self, network: Network) -> Address {
+        Address::P2SH { hash: self.script_hash().to_vec(), network }
+    }
+
+    /// The script_sig spending a P2SH output: the pushes `inputs` the redeem script takes, then the redeem script
+    pub fn p2sh_script_sig(inputs: Vec<Command>, redeem_script: &Script) -> Script {
+        let mut commands = inputs;
+        commands.push(Command::Push(redeem_script.as_bytes().to_vec()));
+        Script::from_commands(commands)
+    }
+
+    fn script_hash(&self) -> [u8; 20] {
+        hash160(self.as_bytes()).try_into().expect("hash160 is 20 bytes")
+    }
+}
+
+/// The command pushing a count: OP_1 to OP_16, or a number push above 16
+fn number_command(number: usize) -> Command {
+    match number {
+        1..=16 => Command::Op(Opcode::Op1.to_u8() + number as u8 - 1),
+        _ => Command::Push(encode_num(number as i64)),
+    }
+}
+
+/// The number a small number opcode or a number push puts on the stack
+fn command_number(command: &Command) -> Option<i64> {
+    match command {
+        Command::Op(_) => command.opcode()?.small_number().map(i64::from),
+        Command::Push(data) => decode_num(data, MAX_NUM_SIZE).ok(),
+        Command::Invalid(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::from_hex;
+
+    use super::*;
+    use crate::interpreter::FixedSighash;
+
+    #[test]
+    fn test_p2pkh() {
+        let hash: [u8; 20] = from_hex("bc3b654dca7e56b04dca18f2566cdaf02e8d9ada").unwrap().try_into().unwrap();
+        let script_pubkey = Script::p2pkh(&hash);
+        assert_eq!(script_pubkey.to_hex(), "76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac");
+        assert_eq!(script_pubkey.p2pkh_hash(), Some(&hash));
+        assert_eq!(Script::from_hex("a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada87").unwrap().p2pkh_hash(), None);
+    }
+
+    #[test]
+    fn test_p2sh() {
+        // the book's 2-of-2 multisig
+        let redeem_script = Script::from_hex("5221022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb702103b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb7152ae").unwrap();
+        let script_pubkey = redeem_script.to_p2sh();
+        assert_eq!(script_pubkey.to_hex(), "a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687");
+        assert_eq!(script_pubkey.p2sh_hash().map(|hash| hash.to_vec()), Some(hash160(redeem_script.as_bytes())));
+        assert_eq!(redeem_script.p2sh_address(Network::Mainnet).to_string(), "3CLoMMyuoDQTPRD3XYZtCvgvkadrAdvdXh");
+
+        let address = Address::parse("3CLoMMyuoDQTPRD3XYZtCvgvkadrAdvdXh").unwrap();
+        assert_eq!(Script::p2sh(address.payload().try_into().unwrap()), script_pubkey);
+
+        let script_sig = Script::p2sh_script_sig(vec![Command::Op(0x00)], &redeem_script);
+        assert_eq!(script_sig.commands()[1], Command::Push(redeem_script.as_bytes().to_vec()));
+        assert!(script_sig.is_push_only());
+        assert_eq!(script_pubkey.p2pkh_hash(), None);
+    }
+
+    #[test]
+    fn test_p2wsh() {
+        // <G> OP_CHECKSIG, from BIP173
+        let witness_script =
+            Script::from_hex("210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac").unwrap();
+        let script_pubkey = witness_script.to_p2wsh();
+        assert_eq!(script_pubkey.to_hex(), "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262");
+        assert_eq!(script_pubkey.witness_program().map(|(version, _)| version), Some(0));
+        assert_eq!(
+            witness_script.p2wsh_address(Network::Mainnet).to_string(),
+            "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3"
+        );
+
+        let witness = Script::p2wsh_witness(vec![vec![0x30]], &witness_script);
+        assert_eq!(witness, [vec![0x30], witness_script.as_bytes().to_vec()]);
+    }
+
+    #[test]
+    fn test_multisig() {
+        // the book's 2-of-2
+        let keys = [
+            from_hex("022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb70").unwrap(),
+            from_hex("03b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb71").unwrap(),
+        ];
+        let script = Script::multisig(2, &keys).unwrap();
+        assert_eq!(script.to_hex(), "5221022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb702103b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb7152ae");
+        assert_eq!(script.classify(), ScriptType::Multisig { required: 2, keys: 2 });
+        assert_eq!(script.multisig_keys(), Some((2, vec![keys[0].as_slice(), keys[1].as_slice()])));
+
+        // sorted, the order they come in doesn't matter
+        let reversed = [keys[1].clone(), keys[0].clone()];
+        assert_ne!(Script::multisig(2, &reversed).unwrap(), script);
+        assert_eq!(Script::sorted_multisig(2, &reversed).unwrap(), script);
+
+        assert_eq!(Script::multisig(1, &[]), Err(MultisigError::KeyCount(0)));
+        assert_eq!(Script::multisig(1, &vec![keys[0].clone(); 21]), Err(MultisigError::KeyCount(21)));
+        assert_eq!(Script::multisig(0, &keys), Err(MultisigError::RequiredCount(0)));
+        assert_eq!(Script::multisig(3, &keys), Err(MultisigError::RequiredCount(3)));
+        assert_eq!(Script::multisig(1, &[keys[0].clone(), vec![0x02; 32]]), Err(MultisigError::InvalidPublicKey(1)));
+
+        // above 16 keys, the counts are pushes
+        let twenty = Script::multisig(17, &vec![keys[0].clone(); 20]).unwrap();
+        assert_eq!(twenty.as_bytes()[..2], [0x01, 17]);
+        assert_eq!(twenty.multisig_keys().map(|(required, keys)| (required, keys.len())), Some((17, 20)));
+        assert_eq!(twenty.classify(), ScriptType::NonStandard);
+        assert_eq!(Script::p2pkh(&[0; 20]).multisig_keys(), None);
+    }
+
+    #[test]
+    fn test_multisig_inputs() {
+        let keys: Vec<Vec<u8>> = (1..=3).map(|byte| vec![byte; 33]).collect();
+        let script = Script::multisig(2, &keys).unwrap();
+
+        // the signatures come in the order of their keys, however they were collected
+        let mut signatures = BTreeMap::from([(keys[2].clone(), vec![0x30, 3])]);
+        assert_eq!(script.multisig_inputs(&signatures), None);
+        signatures.insert(keys[0].clone(), vec![0x30, 1]);
+        assert_eq!(script.multisig_inputs(&signatures), Some(vec![vec![], vec![0x30, 1], vec![0x30, 3]]));
+        // only as many as it requires
+        signatures.insert(keys[1].clone(), vec![0x30, 2]);
+        assert_eq!(script.multisig_inputs(&signatures), Some(vec![vec![], vec![0x30, 1], vec![0x30, 2]]));
+        assert_eq!(Script::p2pkh(&[0; 20]).multisig_inputs(&signatures), None);
+
+        // in a P2SH script_sig, or a P2WSH witness
+        let inputs = script.multisig_inputs(&signatures).unwrap();
+        let script_sig = Script::p2sh_script_sig(inputs.iter().cloned().map(Command::Push).collect(), &script);
+        assert_eq!(script_sig.commands()[0], Command::Op(0x00));
+        assert!(script_sig.is_push_only());
+        assert_eq!(Script::p2wsh_witness(inputs, &script).len(), 4);
+    }
+
+    #[test]
+    fn test_classify() {
+        let cases = [
+            ("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac", ScriptType::P2PKH),
+            ("a914748284390f9e263a4b766a75d0633c50426eb87587", ScriptType::P2SH),
+            ("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1", ScriptType::P2WPKH),
+            ("00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262", ScriptType::P2WSH),
+            ("5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c", ScriptType::P2TR),
+            ("52020102", ScriptType::WitnessUnknown),
+            ("6a0b68656c6c6f20776f726c64", ScriptType::NullData),
+            ("6a", ScriptType::NullData),
+            ("2103935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b67ac", ScriptType::P2PK),
+            (
+                "512103935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b6751ae",
+                ScriptType::Multisig { required: 1, keys: 1 },
+            ),
+            ("0003010203", ScriptType::NonStandard),
+            ("51", ScriptType::NonStandard),
+            // more signatures than keys
+            ("522103935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b6751ae", ScriptType::NonStandard),
+            ("6a51", ScriptType::NonStandard),
+        ];
+        for (hex, expected) in cases {
+            assert_eq!(Script::from_hex(hex).unwrap().classify(), expected, "{}", hex);
+        }
+        assert_eq!(ScriptType::Multisig { required: 2, keys: 3 }.name(), "multisig");
+        assert_eq!(ScriptType::P2TR.name(), "witness_v1_taproot");
+    }
+
+    #[test]
+    fn test_address() {
+        let addresses = [
+            "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH",
+            "3CMNFxN1oHBc4R1EpboAL5yzHGgE611Xou",
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+        ];
+        for address in addresses {
+            let address = Address::parse(address).unwrap();
+            let script_pubkey = Script::from_bytes(address.script_pubkey());
+            assert_eq!(script_pubkey.to_address(address.network()), Some(address));
+        }
+
+        // P2PK, OP_RETURN and witness versions without an address type have none
+        let p2pk = Script::from_hex("210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac").unwrap();
+        assert_eq!(p2pk.to_address(Network::Mainnet), None);
+        assert_eq!(Script::from_hex("6a0401020304").unwrap().to_address(Network::Mainnet), None);
+        assert_eq!(Script::from_hex("5202abcd").unwrap().to_address(Network::Mainnet), None);
+    }
+
+    #[test]
+    fn test_witness_program() {
+        let p2wpkh = Script::p2wpkh(&[0xab; 20]);
+        assert_eq!(p2wpkh.to_hex(), format!("0014{}", "ab".repeat(20)));
+        assert_eq!(p2wpkh.witness_program(), Some((0, [0xab; 20].as_slice())));
+
+        let p2tr = Script::p2tr(&[0xcd; 32]);
+        assert_eq!(p2tr.to_hex(), format!("5120{}", "cd".repeat(32)));
+        assert_eq!(p2tr.witness_program(), Some((1, [0xcd; 32].as_slice())));
+        // the program is 2 to 40 bytes, pushed directly
+        assert_eq!(Script::from_hex("6002abcd").unwrap().witness_program(), Some((16, [0xab, 0xcd].as_slice())));
+        assert_eq!(Script::from_hex("0001ab").unwrap().witness_program(), None);
+        assert_eq!(Script::from_hex(&format!("0029{}", "00".repeat(41))).unwrap().witness_program(), None);
+        assert_eq!(Script::from_hex("004c02abcd").unwrap().witness_program(), None);
+        assert_eq!(Script::from_hex("4f02abcd").unwrap().witness_program(), None);
+    }
+
+    #[test]
+    fn test_p2pkh_spend() {
+        // the book's P2PK signature, for the same key's P2PKH output
+        let z = from_hex("7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d").unwrap();
+        let sec = from_hex("04887387e452b8eacc4acfde10d9aaf7f6d9a0f975aabb10d006e4da568744d06c61de6d95231cd89026e286df3b6ae4a894a3378e393e93a0f45b666329a0ae34").unwrap();
+        let sig = from_hex("3045022000eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c022100c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab601").unwrap();
+
+        let script_pubkey = Script::p2pkh(&hash160(&sec).try_into().unwrap());
+        let script_sig = Script::p2pkh_script_sig(&sig, &sec);
+        assert_eq!(script_sig.commands(), [Command::Push(sig.clone()), Command::Push(sec)]);
+        let checker = FixedSighash(z.try_into().unwrap());
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Ok(()));
+
+        // another key doesn't hash to what the output pays to
+        let other = from_hex("0349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a").unwrap();
+        assert!(Script::verify_spend(&Script::p2pkh_script_sig(&sig, &other), &script_pubkey, &[], &checker).is_err());
+    }
+}
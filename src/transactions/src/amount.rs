@@ -0,0 +1,217 @@
+use std::{
+    fmt::{Display, Formatter},
+    io::{self, Read, Write},
+    iter::Sum,
+    ops::{Add, AddAssign, Sub, SubAssign},
+    str::FromStr,
+};
+
+use ec_cryptography::consensus::{ConsensusDecode, ConsensusEncode};
+
+/// The decimals of a bitcoin: one satoshi is 0.00000001 BTC
+const BTC_DECIMALS: usize = 8;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AmountError {
+    /// The string isn't a decimal number
+    InvalidFormat,
+    /// The amount has more than 8 decimals, a fraction of a satoshi
+    TooPrecise,
+    /// The amount doesn't fit in 64 bits of satoshis
+    TooBig,
+    /// The unit after the number is neither BTC nor sat
+    UnknownDenomination(String),
+}
+
+/// An amount of bitcoin, counted in satoshis.
+/// Arithmetic with the operators panics on overflow like integers do in debug builds,
+/// the `checked_` methods return None instead.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const ONE_SAT: Amount = Amount(1);
+    pub const ONE_BTC: Amount = Amount(100_000_000);
+    /// All the bitcoin there will ever be: 21 million
+    pub const MAX_MONEY: Amount = Amount(21_000_000 * 100_000_000);
+
+    pub const fn from_sat(sats: u64) -> Amount {
+        Amount(sats)
+    }
+
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    /// Parses an amount in bitcoin with up to 8 decimals, e.g. "0.32454049" or "21"
+    pub fn from_btc(btc: &str) -> Result<Amount, AmountError> {
+        let (whole, fraction) = btc.split_once('.').unwrap_or((btc, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(AmountError::InvalidFormat);
+        }
+        if !whole.bytes().chain(fraction.bytes()).all(|digit| digit.is_ascii_digit()) {
+            return Err(AmountError::InvalidFormat);
+        }
+        if fraction.len() > BTC_DECIMALS {
+            return Err(AmountError::TooPrecise);
+        }
+
+        // the decimals padded to 8 digits are the satoshis
+        let sats = format!("{}{:0<width$}", whole, fraction, width = BTC_DECIMALS);
+        let sats = sats.trim_start_matches('0');
+        if sats.is_empty() {
+            return Ok(Amount::ZERO);
+        }
+        sats.parse().map(Amount).map_err(|_| AmountError::TooBig)
+    }
+
+    /// The amount in bitcoin with all 8 decimals, e.g. "0.32454049" or "1.00000000"
+    pub fn to_btc(self) -> String {
+        let whole = self.0 / Amount::ONE_BTC.0;
+        let fraction = self.0 % Amount::ONE_BTC.0;
+        format!("{}.{:0width$}", whole, fraction, width = BTC_DECIMALS)
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    /// Adds, stopping at the largest amount instead of overflowing
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Amount> {
+        self.0.checked_mul(factor).map(Amount)
+    }
+
+    /// Adds up the amounts, or returns None if the total doesn't fit in 64 bits
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+        amounts.into_iter().try_fold(Amount::ZERO, Amount::checked_add)
+    }
+}
+
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, other: Amount) -> Amount {
+        self.checked_add(other).expect("amount overflow")
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, other: Amount) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, other: Amount) -> Amount {
+        self.checked_sub(other).expect("amount underflow")
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, other: Amount) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+/// Shows the amount in bitcoin, e.g. "0.32454049 BTC"
+impl Display for Amount {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{} BTC", self.to_btc())
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    /// Parses a number followed by its unit, "BTC" or "sat", e.g. "0.001 BTC" or "100000 sat"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, denomination) = s.trim().split_once(' ').ok_or(AmountError::InvalidFormat)?;
+        match denomination.trim() {
+            "BTC" | "btc" => Amount::from_btc(number),
+            "sat" | "sats" => {
+                if number.is_empty() || !number.bytes().all(|digit| digit.is_ascii_digit()) {
+                    return Err(AmountError::InvalidFormat);
+                }
+                number.parse().map(Amount).map_err(|_| AmountError::TooBig)
+            }
+            other => Err(AmountError::UnknownDenomination(other.to_string())),
+        }
+    }
+}
+
+impl ConsensusEncode for Amount {
+    /// Amounts are 8-byte little-endian satoshis
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        self.0.consensus_encode(writer)
+    }
+}
+
+impl ConsensusDecode for Amount {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        u64::consensus_decode(reader).map(Amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_btc_strings() {
+        assert_eq!(Amount::from_btc("0.32454049"), Ok(Amount::from_sat(32454049)));
+        assert_eq!(Amount::from_btc("21"), Ok(Amount::from_sat(2_100_000_000)));
+        assert_eq!(Amount::from_btc("0.1"), Ok(Amount::from_sat(10_000_000)));
+        assert_eq!(Amount::from_btc(".00000001"), Ok(Amount::ONE_SAT));
+        assert_eq!(Amount::from_btc("0.00000000"), Ok(Amount::ZERO));
+        assert_eq!(Amount::from_btc("184467440737.09551615"), Ok(Amount::from_sat(u64::MAX)));
+
+        assert_eq!(Amount::from_btc("184467440737.09551616"), Err(AmountError::TooBig));
+        assert_eq!(Amount::from_btc("0.000000001"), Err(AmountError::TooPrecise));
+        assert_eq!(Amount::from_btc("-1"), Err(AmountError::InvalidFormat));
+        assert_eq!(Amount::from_btc("1.2.3"), Err(AmountError::InvalidFormat));
+        assert_eq!(Amount::from_btc("."), Err(AmountError::InvalidFormat));
+
+        assert_eq!(Amount::from_sat(32454049).to_btc(), "0.32454049");
+        assert_eq!(Amount::ONE_BTC.to_btc(), "1.00000000");
+        assert_eq!(Amount::MAX_MONEY.to_string(), "21000000.00000000 BTC");
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("0.001 BTC".parse(), Ok(Amount::from_sat(100_000)));
+        assert_eq!("546 sat".parse(), Ok(Amount::from_sat(546)));
+        assert_eq!(Amount::MAX_MONEY.to_string().parse(), Ok(Amount::MAX_MONEY));
+        assert_eq!("1 mBTC".parse::<Amount>(), Err(AmountError::UnknownDenomination("mBTC".to_string())));
+        assert_eq!("1".parse::<Amount>(), Err(AmountError::InvalidFormat));
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let max = Amount::from_sat(u64::MAX);
+        assert_eq!(max.checked_add(Amount::ONE_SAT), None);
+        assert_eq!(Amount::ZERO.checked_sub(Amount::ONE_SAT), None);
+        assert_eq!(max.checked_mul(2), None);
+        assert_eq!(Amount::ONE_BTC - Amount::ONE_SAT, Amount::from_sat(99_999_999));
+
+        assert_eq!(Amount::checked_sum([Amount::ONE_BTC, Amount::ONE_SAT]), Some(Amount::from_sat(100_000_001)));
+        assert_eq!(Amount::checked_sum([max, Amount::ONE_SAT]), None);
+        assert_eq!(Amount::checked_sum([]), Some(Amount::ZERO));
+    }
+}
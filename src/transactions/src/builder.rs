@@ -0,0 +1,398 @@
+use ec_cryptography::address::Address;
+use scripts::Script;
+
+use crate::{
+    amount::Amount,
+    input::{OutPoint, Sequence, TxIn},
+    locktime::LockTime,
+    output::TxOut,
+    utxo::{Utxo, UNSIGNED_INPUT_WEIGHT},
+    version::Version,
+    Transaction,
+};
+
+/// The segwit marker and flag, which count as witness data
+const SEGWIT_HEADER_WEIGHT: u64 = 2;
+
+/// Bitcoin Core's dust threshold for P2PKH outputs at the default dust relay feerate
+pub const DEFAULT_DUST_LIMIT: Amount = Amount::from_sat(546);
+
+/// How the inputs and outputs of a built transaction are ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TxOrdering {
+    /// In the order they were added, with the change output last
+    #[default]
+    AsAdded,
+    /// BIP69: inputs by previous txid (in display order) then index, outputs by amount then script_pubkey,
+    /// so the order gives nothing away about which output is the change
+    Bip69,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BuildError {
+    NoInputs,
+    NoOutputs,
+    /// A feerate was set, but the value of the input with this index is unknown (it was added with `add_input`)
+    MissingInputValue(usize),
+    /// The input with this index spends an output type whose signed size can't be estimated
+    UnknownInputType(usize),
+    /// The inputs hold `available`, but the outputs and the fee need `needed`
+    InsufficientFunds { needed: Amount, available: Amount },
+    /// The sum of the amounts doesn't fit in 64 bits
+    ValueOverflow,
+}
+
+/// An input to be: the outpoint it spends and, when it's known, the output there
+#[derive(Debug, Clone)]
+struct BuilderInput {
+    outpoint: OutPoint,
+    prevout: Option<Utxo>,
+    sequence: Option<Sequence>,
+}
+
+/// Assembles unsigned transactions, e.g. `TxBuilder::new().add_utxo(utxo).add_output(&address, amount).fee_rate(5).build()`.
+///
+/// Transactions are version 2 with a locktime of 0, and inputs get sequence 0xfffffffd
+/// (replaceable, no relative lock) unless told otherwise. With a feerate, every input must be added
+/// with `add_utxo` so the fee can be checked against the value they hold.
+///
+/// With a change script, what the inputs hold beyond the outputs and the fee goes back to it,
+/// unless that is below the dust limit: then it's left to the fee.
+#[derive(Debug, Clone)]
+pub struct TxBuilder {
+    version: Version,
+    inputs: Vec<BuilderInput>,
+    outputs: Vec<TxOut>,
+    fee_rate: Option<u64>,
+    locktime: LockTime,
+    sequence: Sequence,
+    change_script: Option<Script>,
+    dust_limit: Amount,
+    ordering: TxOrdering,
+}
+
+impl Default for TxBuilder {
+    fn default() -> Self {
+        TxBuilder::new()
+    }
+}
+
+impl TxBuilder {
+    pub fn new() -> TxBuilder {
+        TxBuilder {
+            version: Version::new(2),
+            inputs: vec![],
+            outputs: vec![],
+            fee_rate: None,
+            locktime: LockTime::default(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            change_script: None,
+            dust_limit: DEFAULT_DUST_LIMIT,
+            ordering: TxOrdering::default(),
+        }
+    }
+
+    pub fn version(mut self, version: u32) -> TxBuilder {
+        self.version = Version::new(version);
+        self
+    }
+
+    pub fn locktime(mut self, locktime: LockTime) -> TxBuilder {
+        self.locktime = locktime;
+        self
+    }
+
+    /// The sequence of the inputs that weren't given their own
+    pub fn sequence(mut self, sequence: Sequence) -> TxBuilder {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Spends an output whose value and script aren't known
+    pub fn add_input(mut self, outpoint: OutPoint) -> TxBuilder {
+        self.inputs.push(BuilderInput { outpoint, prevout: None, sequence: None });
+        self
+    }
+
+    /// Spends a known output, so it counts towards the funds and the size estimate
+    pub fn add_utxo(mut self, utxo: Utxo) -> TxBuilder {
+        self.inputs.push(BuilderInput { outpoint: utxo.outpoint, prevout: Some(utxo), sequence: None });
+        self
+    }
+
+    /// Sets the sequence of the last input added
+    pub fn with_sequence(mut self, sequence: Sequence) -> TxBuilder {
+        if let Some(input) = self.inputs.last_mut() {
+            input.sequence = Some(sequence);
+        }
+        self
+    }
+
+    /// Pays `amount` to the address
+    pub fn add_output(self, address: &Address, amount: Amount) -> TxBuilder {
+        self.add_output_script(Script::from_bytes(address.script_pubkey()), amount)
+    }
+
+    pub fn add_output_script(mut self, script_pubkey: Script, amount: Amount) -> TxBuilder {
+        self.outputs.push(TxOut::new(amount, script_pubkey));
+        self
+    }
+
+    /// The feerate to pay, in sat/vB
+    pub fn fee_rate(mut self, sat_vb: u64) -> TxBuilder {
+        self.fee_rate = Some(sat_vb);
+        self
+    }
+
+    /// Sends the change to this script_pubkey
+    pub fn change_script(mut self, script_pubkey: Script) -> TxBuilder {
+        self.change_script = Some(script_pubkey);
+        self
+    }
+
+    pub fn change_address(self, address: &Address) -> TxBuilder {
+        self.change_script(Script::from_bytes(address.script_pubkey()))
+    }
+
+    /// Change below this is left to the fee instead
+    pub fn dust_limit(mut self, dust_limit: Amount) -> TxBuilder {
+        self.dust_limit = dust_limit;
+        self
+    }
+
+    pub fn ordering(mut self, ordering: TxOrdering) -> TxBuilder {
+        self.ordering = ordering;
+        self
+    }
+
+    /// The transaction as it is, without checking the funds or the fee
+    fn assemble(&self) -> Transaction {
+        let mut inputs = self.inputs.clone();
+        let mut outputs = self.outputs.clone();
+        if self.ordering == TxOrdering::Bip69 {
+            inputs.sort_by_key(|input| (input.outpoint.txid.to_display_bytes(), input.outpoint.vout));
+            outputs.sort_by(|a, b| {
+                (a.value, a.script_pubkey.as_bytes()).cmp(&(b.value, b.script_pubkey.as_bytes()))
+            });
+        }
+
+        let inputs = inputs
+            .iter()
+            .map(|input| TxIn::from_outpoint(input.outpoint, input.sequence.unwrap_or(self.sequence)))
+            .collect();
+
+        Transaction {
+            version: self.version.clone(),
+            inputs,
+            outputs,
+            locktime: self.locktime,
+            testnet: false,
+        }
+    }
+
+    /// The vsize the transaction will have once every input is signed
+    pub fn estimated_vsize(&self) -> Result<u64, BuildError> {
+        let mut prevouts = vec![];
+        for (index, input) in self.inputs.iter().enumerate() {
+            prevouts.push(input.prevout.as_ref().ok_or(BuildError::MissingInputValue(index))?);
+        }
+
+        let mut weight = self.assemble().weight();
+        for (index, prevout) in prevouts.iter().enumerate() {
+            weight += prevout.input_weight().ok_or(BuildError::UnknownInputType(index))? - UNSIGNED_INPUT_WEIGHT;
+        }
+
+        // the other inputs get an empty witness once one of them has a witness
+        if prevouts.iter().any(|prevout| prevout.is_witness()) {
+            weight += SEGWIT_HEADER_WEIGHT + prevouts.iter().filter(|prevout| !prevout.is_witness()).count() as u64;
+        }
+
+        Ok(weight.div_ceil(4))
+    }
+
+    /// The fee the feerate asks for (0 without a feerate)
+    pub fn fee(&self) -> Result<Amount, BuildError> {
+        match self.fee_rate {
+            Some(fee_rate) => Amount::from_sat(fee_rate)
+                .checked_mul(self.estimated_vsize()?)
+                .ok_or(BuildError::ValueOverflow),
+            None => Ok(Amount::ZERO),
+        }
+    }
+
+    /// What the inputs hold beyond the outputs and the fee: what a change output can take.
+    /// Every input must have been added with `add_utxo`.
+    pub fn excess(&self) -> Result<Amount, BuildError> {
+        let mut available = Amount::ZERO;
+        for (index, input) in self.inputs.iter().enumerate() {
+            let prevout = input.prevout.as_ref().ok_or(BuildError::MissingInputValue(index))?;
+            available = available.checked_add(prevout.value()).ok_or(BuildError::ValueOverflow)?;
+        }
+
+        let needed = self
+            .outputs
+            .iter()
+            .try_fold(self.fee()?, |total, output| total.checked_add(output.value))
+            .ok_or(BuildError::ValueOverflow)?;
+
+        available
+            .checked_sub(needed)
+            .ok_or(BuildError::InsufficientFunds { needed, available })
+    }
+
+    /// The unsigned transaction. With a feerate or a change script, the inputs must cover the outputs and the fee.
+    /// The rest goes to the change output, which pays for its own size, or without one to the miner.
+    pub fn build(&self) -> Result<Transaction, BuildError> {
+        if self.inputs.is_empty() {
+            return Err(BuildError::NoInputs);
+        }
+        if self.outputs.is_empty() {
+            return Err(BuildError::NoOutputs);
+        }
+
+        if let Some(change_script) = &self.change_script {
+            let mut with_change = self.clone().add_output_script(change_script.clone(), Amount::ZERO);
+            // when there isn't enough to pay for the change output there is no change either
+            if let Ok(change) = with_change.excess() {
+                if change >= self.dust_limit {
+                    with_change.outputs.last_mut().expect("the change output was just added").value = change;
+                    return Ok(with_change.assemble());
+                }
+            }
+        }
+        if self.fee_rate.is_some() || self.change_script.is_some() {
+            self.excess()?;
+        }
+
+        Ok(self.assemble())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::to_hex;
+
+    use super::*;
+    use crate::{txid::Txid, witness::Witness};
+
+    fn p2wpkh_utxo(value: u64) -> Utxo {
+        let outpoint = OutPoint::new(Txid::from_bytes([7; 32]), 1);
+        let script_pubkey = Script::from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        Utxo::new(outpoint, TxOut::new(Amount::from_sat(value), script_pubkey))
+    }
+
+    #[test]
+    fn test_build() {
+        let address = Address::parse("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH").unwrap();
+        let utxo = p2wpkh_utxo(100_000);
+
+        let tx = TxBuilder::new()
+            .add_utxo(utxo.clone())
+            .add_input(OutPoint::new(Txid::from_bytes([8; 32]), 0))
+            .with_sequence(Sequence::MAX)
+            .add_output(&address, Amount::from_sat(60_000))
+            .locktime(LockTime::Blocks(800_000))
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.version.to_u32(), 2);
+        assert_eq!(tx.locktime, LockTime::Blocks(800_000));
+        assert_eq!(tx.inputs[0].outpoint(), utxo.outpoint);
+        assert_eq!(tx.inputs[0].sequence, Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert_eq!(tx.inputs[1].sequence, Sequence::MAX);
+        assert!(tx.inputs.iter().all(|input| input.script_sig.is_empty()));
+        assert_eq!(tx.outputs[0].value, Amount::from_sat(60_000));
+
+        // it round-trips through the parser
+        let parsed = Transaction::parse(&tx.serialize(), false).unwrap();
+        assert_eq!(to_hex(&parsed.serialize()), to_hex(&tx.serialize()));
+
+        assert_eq!(TxBuilder::new().add_output(&address, Amount::ONE_SAT).build().unwrap_err(), BuildError::NoInputs);
+        assert_eq!(TxBuilder::new().add_utxo(utxo).build().unwrap_err(), BuildError::NoOutputs);
+    }
+
+    #[test]
+    fn test_fee_rate() {
+        let address = Address::parse("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        let builder = TxBuilder::new().add_utxo(p2wpkh_utxo(100_000)).add_output(&address, Amount::from_sat(90_000)).fee_rate(10);
+
+        let mut tx = builder.build().unwrap();
+        let vsize = builder.estimated_vsize().unwrap();
+        assert_eq!(builder.fee().unwrap(), Amount::from_sat(10 * vsize));
+        assert_eq!(builder.excess().unwrap(), Amount::from_sat(10_000 - 10 * vsize));
+
+        // the estimate matches the signed transaction with the largest signature
+        tx.inputs[0].witness = Witness::p2wpkh(vec![0x30; 72], vec![0x02; 33]);
+        assert_eq!(tx.vsize(), vsize);
+
+        let too_much = builder.clone().add_output(&address, Amount::from_sat(9_000));
+        assert_eq!(
+            too_much.build().unwrap_err(),
+            BuildError::InsufficientFunds {
+                needed: Amount::from_sat(99_000 + 10 * too_much.estimated_vsize().unwrap()),
+                available: Amount::from_sat(100_000),
+            }
+        );
+
+        let unknown_value = builder.add_input(OutPoint::new(Txid::from_bytes([8; 32]), 0));
+        assert_eq!(unknown_value.build().unwrap_err(), BuildError::MissingInputValue(1));
+    }
+
+    #[test]
+    fn test_change() {
+        let address = Address::parse("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        let change_script = Script::from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        let builder = TxBuilder::new()
+            .add_utxo(p2wpkh_utxo(100_000))
+            .add_output(&address, Amount::from_sat(60_000))
+            .fee_rate(10)
+            .change_script(change_script.clone());
+
+        let tx = builder.build().unwrap();
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].script_pubkey, change_script);
+
+        // the change output pays for its own size
+        let with_change = TxBuilder::new()
+            .add_utxo(p2wpkh_utxo(100_000))
+            .add_output(&address, Amount::from_sat(60_000))
+            .add_output_script(change_script.clone(), Amount::ZERO)
+            .fee_rate(10);
+        assert_eq!(tx.outputs[1].value, Amount::from_sat(40_000) - with_change.fee().unwrap());
+
+        // change below the dust limit is left to the fee
+        let no_change = builder.clone().dust_limit(tx.outputs[1].value + Amount::ONE_SAT).build().unwrap();
+        assert_eq!(no_change.outputs.len(), 1);
+
+        let not_enough = TxBuilder::new().add_utxo(p2wpkh_utxo(1_000)).add_output(&address, Amount::from_sat(60_000)).change_script(change_script);
+        assert!(matches!(not_enough.build(), Err(BuildError::InsufficientFunds { .. })));
+    }
+
+    #[test]
+    fn test_bip69_ordering() {
+        let utxo = |txid: &str, vout| {
+            let mut utxo = p2wpkh_utxo(50_000);
+            utxo.outpoint = OutPoint::new(txid.parse().unwrap(), vout);
+            utxo
+        };
+        let low_txid = format!("00{}", "ff".repeat(31));
+        let high_txid = format!("ff{}", "00".repeat(31));
+
+        let tx = TxBuilder::new()
+            .add_utxo(utxo(&high_txid, 0))
+            .add_utxo(utxo(&low_txid, 1))
+            .add_utxo(utxo(&low_txid, 0))
+            .add_output_script(Script::from_hex("52").unwrap(), Amount::from_sat(2_000))
+            .add_output_script(Script::from_hex("53").unwrap(), Amount::from_sat(1_000))
+            .add_output_script(Script::from_hex("51").unwrap(), Amount::from_sat(2_000))
+            .ordering(TxOrdering::Bip69)
+            .build()
+            .unwrap();
+
+        let inputs: Vec<_> = tx.inputs.iter().map(|input| (input.prev_txid.to_string(), input.prev_index)).collect();
+        assert_eq!(inputs, vec![(low_txid.clone(), 0), (low_txid, 1), (high_txid, 0)]);
+
+        let outputs: Vec<_> = tx.outputs.iter().map(|output| (output.value.to_sat(), output.script_pubkey.to_hex())).collect();
+        assert_eq!(outputs, vec![(1_000, "53".into()), (2_000, "51".into()), (2_000, "52".into())]);
+    }
+}
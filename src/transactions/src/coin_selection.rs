@@ -0,0 +1,252 @@
+use std::cmp::Reverse;
+
+use crate::{amount::Amount, input::OutPoint, utxo::Utxo};
+
+/// How many branches branch and bound explores before giving up
+const BNB_TOTAL_TRIES: u32 = 100_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SelectionError {
+    /// The candidates hold `available` once their own fees are paid, short of what is `needed`
+    InsufficientFunds { needed: Amount, available: Amount },
+    /// The signed size of an input spending this output can't be estimated
+    UnknownInputType(OutPoint),
+    /// Branch and bound found no set of inputs close enough to the target to do without change
+    NoChangelessSolution,
+}
+
+/// The inputs picked, the fee they cost and what is left for a change output.
+///
+/// The selectors pay for the inputs they pick: their `target` is the value of the outputs plus the fee for
+/// the rest of the transaction, and each input costs its own weight at the feerate.
+/// Inputs worth less than that are never picked.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub inputs: Vec<Utxo>,
+    /// The fee for the selected inputs, on top of the one in the target
+    pub fee: Amount,
+    pub change: Amount,
+}
+
+/// A candidate with the fee for spending it at the feerate, and the value left once that is paid
+struct Candidate<'a> {
+    utxo: &'a Utxo,
+    fee: Amount,
+    effective_value: Amount,
+}
+
+fn candidates(utxos: &[Utxo], fee_rate: u64) -> Result<Vec<Candidate<'_>>, SelectionError> {
+    let mut candidates = vec![];
+    for utxo in utxos {
+        let weight = utxo.input_weight().ok_or(SelectionError::UnknownInputType(utxo.outpoint))?;
+        let fee = Amount::from_sat((fee_rate * weight).div_ceil(4));
+
+        if let Some(effective_value) = utxo.value().checked_sub(fee).filter(|value| *value > Amount::ZERO) {
+            candidates.push(Candidate { utxo, fee, effective_value });
+        }
+    }
+    Ok(candidates)
+}
+
+/// Takes candidates in order until they cover the target
+fn accumulate(candidates: Vec<Candidate>, target: Amount) -> Result<Selection, SelectionError> {
+    let mut selection = Selection { inputs: vec![], fee: Amount::ZERO, change: Amount::ZERO };
+    let mut value = Amount::ZERO;
+
+    for candidate in &candidates {
+        selection.inputs.push(candidate.utxo.clone());
+        selection.fee += candidate.fee;
+        value += candidate.effective_value;
+
+        if value >= target {
+            selection.change = value - target;
+            return Ok(selection);
+        }
+    }
+
+    Err(SelectionError::InsufficientFunds { needed: target, available: value })
+}
+
+/// Spends the largest UTXOs first, which keeps the number of inputs (and the fee) low
+pub fn largest_first(utxos: &[Utxo], target: Amount, fee_rate: u64) -> Result<Selection, SelectionError> {
+    let mut candidates = candidates(utxos, fee_rate)?;
+    candidates.sort_by_key(|candidate| Reverse(candidate.effective_value));
+    accumulate(candidates, target)
+}
+
+/// Spends the UTXOs in the order given, oldest first if they are sorted by confirmation
+pub fn fifo(utxos: &[Utxo], target: Amount, fee_rate: u64) -> Result<Selection, SelectionError> {
+    accumulate(candidates(utxos, fee_rate)?, target)
+}
+
+/// Looks for inputs that cover the target without needing change: they may exceed it by at most
+/// `cost_of_change` (what creating and later spending a change output would cost), which goes to the fee.
+/// This is the branch and bound search of Bitcoin Core, minimizing the excess.
+pub fn branch_and_bound(
+    utxos: &[Utxo],
+    target: Amount,
+    fee_rate: u64,
+    cost_of_change: Amount,
+) -> Result<Selection, SelectionError> {
+    let mut candidates = candidates(utxos, fee_rate)?;
+    candidates.sort_by_key(|candidate| Reverse(candidate.effective_value));
+
+    let available = candidates.iter().map(|candidate| candidate.effective_value).sum();
+    if available < target {
+        return Err(SelectionError::InsufficientFunds { needed: target, available });
+    }
+
+    let mut search = BranchAndBound {
+        candidates: &candidates,
+        target,
+        upper_bound: target + cost_of_change,
+        selected: vec![],
+        best: None,
+        tries: BNB_TOTAL_TRIES,
+    };
+    search.explore(0, Amount::ZERO, available);
+
+    let (selected, excess) = search.best.ok_or(SelectionError::NoChangelessSolution)?;
+    Ok(Selection {
+        inputs: selected.iter().map(|index| candidates[*index].utxo.clone()).collect(),
+        fee: selected.iter().map(|index| candidates[*index].fee).sum::<Amount>() + excess,
+        change: Amount::ZERO,
+    })
+}
+
+struct BranchAndBound<'a> {
+    candidates: &'a [Candidate<'a>],
+    target: Amount,
+    upper_bound: Amount,
+    /// The indexes of the candidates on the current branch
+    selected: Vec<usize>,
+    /// The selection with the smallest excess so far
+    best: Option<(Vec<usize>, Amount)>,
+    tries: u32,
+}
+
+impl BranchAndBound<'_> {
+    /// Tries including and then excluding the candidate at `index`. `remaining` is the value of it and the ones after it.
+    fn explore(&mut self, index: usize, value: Amount, remaining: Amount) {
+        if self.tries == 0 || value > self.upper_bound {
+            return;
+        }
+        self.tries -= 1;
+
+        if value >= self.target {
+            let excess = value - self.target;
+            if self.best.as_ref().is_none_or(|(_, best_excess)| excess < *best_excess) {
+                self.best = Some((self.selected.clone(), excess));
+            }
+            return;
+        }
+
+        // even taking everything left wouldn't reach the target
+        if index == self.candidates.len() || value + remaining < self.target {
+            return;
+        }
+
+        let effective_value = self.candidates[index].effective_value;
+        self.selected.push(index);
+        self.explore(index + 1, value + effective_value, remaining - effective_value);
+        self.selected.pop();
+
+        self.explore(index + 1, value, remaining - effective_value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scripts::Script;
+
+    use super::*;
+    use crate::{output::TxOut, txid::Txid};
+
+    /// P2WPKH outputs, which cost 68 vbytes to spend
+    fn utxos(values: &[u64]) -> Vec<Utxo> {
+        let script_pubkey = Script::from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let outpoint = OutPoint::new(Txid::from_bytes([1; 32]), index as u32);
+                Utxo::new(outpoint, TxOut::new(Amount::from_sat(*value), script_pubkey.clone()))
+            })
+            .collect()
+    }
+
+    fn sats(value: u64) -> Amount {
+        Amount::from_sat(value)
+    }
+
+    fn values(selection: &Selection) -> Vec<u64> {
+        selection.inputs.iter().map(|utxo| utxo.value().to_sat()).collect()
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let utxos = utxos(&[10_000, 50_000, 30_000]);
+
+        let selection = largest_first(&utxos, sats(60_000), 10).unwrap();
+        assert_eq!(values(&selection), vec![50_000, 30_000]);
+        assert_eq!(selection.fee, sats(2 * 680));
+        assert_eq!(selection.change, sats(80_000 - 60_000 - 2 * 680));
+
+        assert_eq!(
+            largest_first(&utxos, sats(90_000), 10).unwrap_err(),
+            SelectionError::InsufficientFunds { needed: sats(90_000), available: sats(90_000 - 3 * 680) }
+        );
+    }
+
+    #[test]
+    fn test_fifo() {
+        let utxos = utxos(&[10_000, 50_000, 30_000]);
+
+        let selection = fifo(&utxos, sats(55_000), 10).unwrap();
+        assert_eq!(values(&selection), vec![10_000, 50_000]);
+        assert_eq!(selection.change, sats(60_000 - 55_000 - 2 * 680));
+    }
+
+    #[test]
+    fn test_uneconomical_outputs_are_skipped() {
+        // at 10 sat/vB the 600 sat output costs more than it's worth
+        let utxos = utxos(&[600, 20_000]);
+        let selection = fifo(&utxos, sats(1_000), 10).unwrap();
+        assert_eq!(values(&selection), vec![20_000]);
+    }
+
+    #[test]
+    fn test_unknown_input_type() {
+        let mut utxos = utxos(&[20_000]);
+        utxos[0].output.script_pubkey = Script::from_hex("a914748284390f9e263a4b766a75d0633c50426eb87587").unwrap();
+        assert_eq!(
+            largest_first(&utxos, sats(1_000), 1).unwrap_err(),
+            SelectionError::UnknownInputType(utxos[0].outpoint)
+        );
+    }
+
+    #[test]
+    fn test_branch_and_bound() {
+        // with a feerate of 1 each input costs 68 sats
+        let utxos = utxos(&[1_068, 2_068, 3_068, 4_068, 5_068]);
+
+        // the first exact match, there are others with the same excess
+        let selection = branch_and_bound(&utxos, sats(6_000), 1, sats(0)).unwrap();
+        let mut selected = values(&selection);
+        selected.sort();
+        assert_eq!(selected, vec![1_068, 5_068]);
+        assert_eq!(selection.change, Amount::ZERO);
+        assert_eq!(selection.fee, sats(2 * 68));
+
+        // the excess allowed by the cost of change goes to the fee
+        let selection = branch_and_bound(&utxos, sats(10_500), 1, sats(600)).unwrap();
+        assert_eq!(selection.fee, sats(3 * 68 + 500));
+        assert_eq!(values(&selection).iter().sum::<u64>(), 10_500 + selection.fee.to_sat());
+
+        assert_eq!(branch_and_bound(&utxos, sats(500), 1, sats(100)).unwrap_err(), SelectionError::NoChangelessSolution);
+        assert!(matches!(
+            branch_and_bound(&utxos, sats(20_000), 1, sats(100)),
+            Err(SelectionError::InsufficientFunds { available, .. }) if available == sats(15_000)
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+use scripts::Command;
+
+use crate::{txid::Txid, Transaction};
+
+/// The previous index of a coinbase input, which spends the all-zero txid
+pub const COINBASE_PREV_INDEX: u32 = 0xffffffff;
+
+const OP_1: u8 = 0x51;
+const OP_16: u8 = 0x60;
+
+impl Transaction {
+    /// Whether this is the first transaction of a block, which creates new coins:
+    /// it has a single input spending index 0xffffffff of the all-zero txid
+    pub fn is_coinbase(&self) -> bool {
+        match self.inputs.as_slice() {
+            [input] => {
+                input.prev_index == COINBASE_PREV_INDEX && input.prev_txid == Txid::default()
+            }
+            _ => false,
+        }
+    }
+
+    /// The block height a coinbase commits to (BIP34): the number pushed by the first command
+    /// of its script_sig. Returns None for other transactions, or if the script_sig doesn't start with a height.
+    pub fn coinbase_height(&self) -> Option<u32> {
+        if !self.is_coinbase() {
+            return None;
+        }
+
+        match self.inputs[0].script_sig.commands().first()? {
+            Command::Op(0) => Some(0),
+            Command::Op(op @ OP_1..=OP_16) => Some((op - OP_1 + 1) as u32),
+            Command::Push(number) => decode_height(number),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a little-endian script number, rejecting negative numbers and heights above u32::MAX
+fn decode_height(number: &[u8]) -> Option<u32> {
+    // the top bit of the last byte is the sign
+    if number.len() > 5 || number.last().is_some_and(|last| last & 0x80 != 0) {
+        return None;
+    }
+
+    let height = number.iter().rev().fold(0u64, |height, byte| (height << 8) | *byte as u64);
+    u32::try_from(height).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::to_hex;
+    use scripts::Script;
+
+    use super::*;
+    use crate::input::{Sequence, TxIn};
+
+    const COINBASE_TX: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff5e03d71b07254d696e656420627920416e74506f6f6c20626a31312f4542312f4144362f43205914293101fabe6d6d678e2c8c34afc36896e7d9402824ed38e856676ee94bfdb0c6c4bcd8b2e5666a0400000000000000c7270000a5e00e00ffffffff01faf20b58000000001976a914338c84849423992471bffb1a54a8d9b1d69dc28a88ac00000000";
+
+    fn coinbase_with_script_sig(hex: &str) -> Transaction {
+        let mut tx = Transaction::parse_hex(COINBASE_TX, false).unwrap();
+        tx.inputs[0].script_sig = Script::from_hex(hex).unwrap();
+        tx
+    }
+
+    #[test]
+    fn test_coinbase() {
+        let tx = Transaction::parse_hex(COINBASE_TX, false).unwrap();
+        assert!(tx.is_coinbase());
+        assert_eq!(tx.coinbase_height(), Some(465879));
+        // the null prevout and arbitrary script_sig round-trip
+        assert_eq!(to_hex(&tx.serialize()), COINBASE_TX);
+
+        let mut spend = Transaction::parse_hex(COINBASE_TX, false).unwrap();
+        spend.inputs[0].prev_index = 0;
+        assert!(!spend.is_coinbase());
+        assert_eq!(spend.coinbase_height(), None);
+
+        // a coinbase has exactly one input
+        let null_input = TxIn::new(Txid::default(), COINBASE_PREV_INDEX, Script::default(), Sequence::default());
+        spend.inputs = vec![tx.inputs[0].clone(), null_input];
+        assert!(!spend.is_coinbase());
+    }
+
+    #[test]
+    fn test_coinbase_height_encodings() {
+        assert_eq!(coinbase_with_script_sig("00").coinbase_height(), Some(0));
+        assert_eq!(coinbase_with_script_sig("51").coinbase_height(), Some(1));
+        assert_eq!(coinbase_with_script_sig("60").coinbase_height(), Some(16));
+        assert_eq!(coinbase_with_script_sig("0111").coinbase_height(), Some(17));
+        // 128 needs a zero byte so it isn't read as negative
+        assert_eq!(coinbase_with_script_sig("028000").coinbase_height(), Some(128));
+        assert_eq!(coinbase_with_script_sig("0180").coinbase_height(), None);
+        assert_eq!(coinbase_with_script_sig("").coinbase_height(), None);
+        assert_eq!(coinbase_with_script_sig("6a").coinbase_height(), None);
+    }
+}
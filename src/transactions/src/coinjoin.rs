@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{Transaction, TransactionError};
+
+impl Transaction {
+    /// Combines two partial transactions built independently, as the participants of a coinjoin do:
+    /// the result has the inputs and outputs of both, this transaction's first.
+    ///
+    /// Both must have the same version and locktime, and no output can be spent by both.
+    /// Signatures that commit to all inputs or outputs no longer hold, so partial transactions are
+    /// merged unsigned, or signed with ANYONECANPAY.
+    pub fn merge(&self, other: &Transaction) -> Result<Transaction, TransactionError> {
+        if self.version != other.version || self.locktime != other.locktime {
+            return Err(TransactionError::IncompatibleMerge);
+        }
+
+        let mut outpoints: HashSet<_> = self.inputs.iter().map(|input| input.outpoint()).collect();
+        if let Some(input) = other.inputs.iter().find(|input| !outpoints.insert(input.outpoint())) {
+            return Err(TransactionError::DuplicateInput(input.outpoint()));
+        }
+
+        let mut merged = self.clone();
+        merged.inputs.extend(other.inputs.iter().cloned());
+        merged.outputs.extend(other.outputs.iter().cloned());
+        Ok(merged)
+    }
+
+    /// Puts the inputs and the outputs in a random order, so their position doesn't tell
+    /// which participant of a coinjoin they belong to
+    pub fn shuffle(&mut self, rng: &mut impl Rng) {
+        self.inputs.shuffle(rng);
+        self.outputs.shuffle(rng);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use scripts::Script;
+
+    use super::*;
+    use crate::{
+        amount::Amount,
+        input::{OutPoint, Sequence, TxIn},
+        locktime::LockTime,
+        output::TxOut,
+        txid::Txid,
+    };
+
+    /// A participant's part: spending output 0 of a transaction, and paying `value` to an output
+    fn partial_tx(seed: u8, value: u64) -> Transaction {
+        let outpoint = OutPoint::new(Txid::from_bytes([seed; 32]), 0);
+        Transaction {
+            inputs: vec![TxIn::from_outpoint(outpoint, Sequence::MAX)],
+            outputs: vec![TxOut::new(Amount::from_sat(value), Script::from_bytes(vec![0x51]))],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let merged = partial_tx(1, 1000).merge(&partial_tx(2, 2000)).unwrap();
+        assert_eq!(merged.inputs.len(), 2);
+        assert_eq!(merged.inputs[1].prev_txid, Txid::from_bytes([2; 32]));
+        assert_eq!(merged.outputs[1].value, Amount::from_sat(2000));
+
+        let merged = merged.merge(&partial_tx(3, 3000)).unwrap();
+        assert_eq!(merged.inputs.len(), 3);
+
+        // both spend the same output
+        let outpoint = OutPoint::new(Txid::from_bytes([2; 32]), 0);
+        assert!(matches!(
+            merged.merge(&partial_tx(2, 500)),
+            Err(TransactionError::DuplicateInput(duplicate)) if duplicate == outpoint
+        ));
+
+        let mut later = partial_tx(4, 4000);
+        later.locktime = LockTime::Blocks(800_000);
+        assert!(matches!(merged.merge(&later), Err(TransactionError::IncompatibleMerge)));
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let mut tx = (2..=8).fold(partial_tx(1, 1000), |tx, seed| {
+            tx.merge(&partial_tx(seed, seed as u64 * 1000)).unwrap()
+        });
+        let original = tx.clone();
+
+        tx.shuffle(&mut StdRng::seed_from_u64(7));
+        assert_ne!(tx.txid(), original.txid());
+
+        // the same inputs and outputs, in another order
+        let mut seeds: Vec<u8> = tx.inputs.iter().map(|input| input.prev_txid.as_bytes()[0]).collect();
+        seeds.sort();
+        assert_eq!(seeds, (1..=8).collect::<Vec<u8>>());
+        let mut values: Vec<u64> = tx.outputs.iter().map(|output| output.value.to_sat()).collect();
+        values.sort();
+        assert_eq!(values, (1..=8).map(|seed| seed * 1000).collect::<Vec<u64>>());
+    }
+}
@@ -1,35 +1,32 @@
-use std::fmt::Debug;
+use std::{fmt::{Debug, Display}, io::{self, Read, Write}};
 
-use rug::Integer;
+use ec_cryptography::{
+    consensus::{ConsensusDecode, ConsensusEncode},
+    helper::to_hex,
+};
+use scripts::Script;
 use serde::{Deserialize, Serialize};
 
-use crate::utils::TxFetcher;
+use crate::{
+    amount::Amount,
+    decode_prefix,
+    txid::Txid,
+    utils::{FetchError, TxFetcher},
+    witness::Witness,
+    ParseError,
+};
 
-#[derive(Debug, Default, Deserialize)]
-pub struct PrevOutput {
-    pub txid: String,
-    pub index: u64,
-}
-
-impl PrevOutput {
-    pub fn new(txid: String, index: u64) -> PrevOutput {
-        PrevOutput {
-            txid,
-            index,
-        }
-    }
-
-    pub fn parse_index(byte: &[u8]) -> u64 {
-        let mut padded = [0u8; 8];
-        padded[..byte.len()].copy_from_slice(byte);
-        u64::from_le_bytes(padded)
-    }
-}
-
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Sequence(pub u32);
 
 impl Sequence {
+    /// A final input: it disables the transaction's locktime (unless another input isn't final)
+    pub const MAX: Sequence = Sequence(0xffffffff);
+    /// The highest sequence that still enables the locktime, without signaling replaceability
+    pub const ENABLE_LOCKTIME_NO_RBF: Sequence = Sequence(0xfffffffe);
+    /// The highest sequence that signals replace-by-fee, without a relative lock
+    pub const ENABLE_RBF_NO_LOCKTIME: Sequence = Sequence(0xfffffffd);
+
     pub fn new(sequence: u32) -> Sequence {
         Sequence(sequence)
     }
@@ -39,89 +36,277 @@ impl Sequence {
         padded[..byte.len()].copy_from_slice(byte);
         Sequence(u32::from_le_bytes(padded))
     }
+
+    /// A relative lock of `blocks` confirmations (BIP68)
+    pub fn from_height(blocks: u16) -> Sequence {
+        Sequence(blocks as u32)
+    }
+
+    /// A relative lock of `intervals` times 512 seconds (BIP68)
+    pub fn from_512_second_intervals(intervals: u16) -> Sequence {
+        Sequence(SEQUENCE_LOCKTIME_TYPE_FLAG | intervals as u32)
+    }
+
+    /// A relative time lock of at least `seconds`, rounded up to the next 512 second interval.
+    /// Returns None if that doesn't fit in the 16 bits BIP68 has for it.
+    pub fn from_seconds_ceil(seconds: u32) -> Option<Sequence> {
+        let intervals = seconds.div_ceil(1 << SEQUENCE_LOCKTIME_GRANULARITY);
+        u16::try_from(intervals).ok().map(Sequence::from_512_second_intervals)
+    }
+
+    /// The relative lock this sequence encodes, if the disable flag isn't set.
+    /// Only transactions of version 2 or more are subject to it.
+    pub fn relative_locktime(self) -> Option<RelativeLockTime> {
+        if self.0 & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return None;
+        }
+
+        let value = (self.0 & SEQUENCE_LOCKTIME_MASK) as u16;
+        if self.0 & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+            Some(RelativeLockTime::Time(value))
+        } else {
+            Some(RelativeLockTime::Blocks(value))
+        }
+    }
+}
+
+/// Set on sequences that don't have a relative lock
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+/// Set when the relative lock is a time rather than a number of blocks
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+/// The bits holding the value of the relative lock
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+/// Relative time locks count in units of 2^9 = 512 seconds
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9;
+
+/// How long after the output it spends was confirmed an input can be mined (BIP68)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativeLockTime {
+    /// A number of blocks
+    Blocks(u16),
+    /// A number of 512 second intervals
+    Time(u16),
+}
+
+impl RelativeLockTime {
+    /// Whether the lock has expired for an output confirmed `blocks` blocks before the block the
+    /// spending transaction goes in, and whose confirming block's median time past was `seconds`
+    /// before the one of that block
+    pub fn is_satisfied_by(self, blocks: u32, seconds: u32) -> bool {
+        match self {
+            RelativeLockTime::Blocks(value) => blocks >= value as u32,
+            RelativeLockTime::Time(value) => seconds as u64 >= (value as u64) << SEQUENCE_LOCKTIME_GRANULARITY,
+        }
+    }
+
+    /// Whether an input with this relative lock passes OP_CHECKSEQUENCEVERIFY `required` (BIP112):
+    /// it must be of the same unit and at least as long
+    pub fn satisfies_csv(self, required: RelativeLockTime) -> bool {
+        match (self, required) {
+            (RelativeLockTime::Blocks(value), RelativeLockTime::Blocks(required))
+            | (RelativeLockTime::Time(value), RelativeLockTime::Time(required)) => required <= value,
+            _ => false,
+        }
+    }
+}
+
+/// A reference to a transaction output: the id of its transaction and its index there
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutPoint {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+impl OutPoint {
+    pub fn new(txid: Txid, vout: u32) -> OutPoint {
+        OutPoint { txid, vout }
+    }
 }
 
-#[derive(Debug, Default, Deserialize)]
+impl Display for OutPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+/// A transaction input: it spends output `prev_index` of the transaction `prev_txid`
+#[derive(Debug, Default, Clone)]
 pub struct TxIn {
-    pub previous_output: PrevOutput,
-    pub script_sig: Option<String>,
+    pub prev_txid: Txid,
+    pub prev_index: u32,
+    pub script_sig: Script,
     pub sequence: Sequence,
+    /// The witness stack (BIP141), empty for inputs that don't spend a segwit output.
+    /// It isn't part of the input's own encoding: segwit transactions write all witnesses after the outputs.
+    pub witness: Witness,
 }
 
 impl TxIn {
-    pub fn new(prev_output: PrevOutput, sig: Option<String>, sequence: Sequence) -> TxIn {
+    pub fn new(prev_txid: Txid, prev_index: u32, script_sig: Script, sequence: Sequence) -> TxIn {
         TxIn {
-            previous_output: prev_output,
-            script_sig: sig,
+            prev_txid,
+            prev_index,
+            script_sig,
             sequence,
+            witness: Witness::new(),
         }
     }
 
-    pub fn value(&self, testnet: bool) -> u64 {
-        let mut tx_fetcher = TxFetcher::new(testnet);
-        let tx = tx_fetcher.fetch(self.previous_output.txid.clone(), false);
-        tx.outputs[self.previous_output.index as usize].value
+    /// An input spending the outpoint, with an empty script_sig to be filled in when signing
+    pub fn from_outpoint(outpoint: OutPoint, sequence: Sequence) -> TxIn {
+        TxIn::new(outpoint.txid, outpoint.vout, Script::default(), sequence)
     }
 
-    pub fn parse_from_bytes(bytes: &[u8]) -> Vec<TxIn> {
-        let mut txs = vec![];
-        let prev_output = PrevOutput {
-            txid: hex::encode(&bytes[0..32]),
-            index: PrevOutput::parse_index(&bytes[32..36]),
-        };
+    /// The output this input spends
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint::new(self.prev_txid, self.prev_index)
+    }
 
-        let script_sig = match bytes[36] {
-            0x00 => None,
-            _ => Some(hex::encode(&bytes[36..(bytes.len() - 4)])),
-        };
+    /// The weight the input adds to a transaction (Core's GetTransactionInputWeight): its own bytes count
+    /// four times, its witness once. The witness includes its item count, which segwit transactions
+    /// write for every input.
+    pub fn weight(&self) -> u64 {
+        let base_size = 32 + 4 + self.script_sig.serialized_size() + 4;
+        (base_size * 4 + self.witness.consensus_serialize().len()) as u64
+    }
 
-        let sequence = Sequence::from_bytes(&bytes[(bytes.len() - 4)..]);
+    /// The value of the output this input spends, looked up with a block explorer
+    pub fn value(&self, tx_fetcher: &mut TxFetcher) -> Result<Amount, FetchError> {
+        let tx = tx_fetcher.fetch(&self.prev_txid, false)?;
 
-        txs.push(TxIn {
-            previous_output: prev_output,
-            script_sig,
-            sequence,
-        });
+        tx.outputs
+            .get(self.prev_index as usize)
+            .map(|output| output.value)
+            .ok_or(FetchError::MissingOutput(self.prev_index))
+    }
 
-        txs
+    /// Parses the input at the start of the bytes.
+    /// Returns the input and the number of bytes it took up, so the caller can move on to the next one.
+    pub fn parse(bytes: &[u8]) -> Result<(TxIn, usize), ParseError> {
+        decode_prefix(bytes, TxIn::consensus_decode)
     }
 
     pub fn serialize(&self) -> String {
-        let mut serialized = String::from("");
-
-        // serialize the prev_tx_index
-        let mut prev_id = Integer::from_str_radix(&self.previous_output.txid, 16).unwrap().to_digits::<u8>(rug::integer::Order::MsfLe);
-        prev_id.reverse();
-        serialized.push_str(
-            &prev_id.iter()
-            .map(|byte| format!("{:02x}", byte))
-            .collect::<String>()
-        );
+        to_hex(&self.consensus_serialize())
+    }
 
-        // serialize the prev_tx_index
-        let index = self.previous_output.index as u32;
-        serialized.push_str(
-            &index.to_le_bytes()
-            .iter()
-            .map(|byte| format!("{:02x}", (byte << 2) | 0))
-            .collect::<String>()
-        );
+    /// Reads an input from a stream: the previous txid and index, the script_sig and the sequence
+    pub fn parse_stream<R: Read>(reader: &mut R) -> io::Result<TxIn> {
+        TxIn::consensus_decode(reader)
+    }
 
-        // serialize the scriptsig
-        let scriptsig = self.script_sig.as_ref().unwrap();
-        serialized.push_str(
-            scriptsig
-        );
+    /// Writes the input to a stream in the same format `parse_stream` reads
+    pub fn serialize_stream<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.consensus_encode(writer).map(|_| ())
+    }
+
+    /// Writes the output this input spends: the previous txid and index
+    pub(crate) fn encode_outpoint<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.prev_txid.as_bytes().consensus_encode(writer)? + self.prev_index.consensus_encode(writer)?)
+    }
+}
+
+impl ConsensusEncode for TxIn {
+    /// The previous txid and index, the script_sig and the sequence
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.encode_outpoint(writer)?
+            + self.script_sig.consensus_encode(writer)?
+            + self.sequence.0.consensus_encode(writer)?)
+    }
+}
+
+impl ConsensusDecode for TxIn {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let prev_txid = Txid::from_bytes(<[u8; 32]>::consensus_decode(reader)?);
+        let prev_index = u32::consensus_decode(reader)?;
+        let script_sig = Script::consensus_decode(reader)?;
+        let sequence = u32::consensus_decode(reader)?;
 
-        // serialize the sequence
-        let sequence = self.sequence.0;
-        serialized.push_str(
-            &sequence.to_le_bytes()
-            .iter()
-            .map(|byte| format!("{:02x}", byte))
-            .collect::<String>()
+        Ok(TxIn::new(prev_txid, prev_index, script_sig, Sequence::new(sequence)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::from_hex;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_and_serialize() {
+        let raw = "813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff";
+        let mut bytes = from_hex(raw).unwrap();
+        // the bytes of the next input are not consumed
+        bytes.extend_from_slice(&[0x01, 0x02]);
+
+        let (input, consumed) = TxIn::parse(&bytes).unwrap();
+        assert_eq!(consumed, bytes.len() - 2);
+        assert_eq!(input.prev_txid.to_string(), "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81");
+        assert_eq!(input.prev_index, 0);
+        assert_eq!(input.script_sig.len(), 0x6b);
+        assert_eq!(input.script_sig.commands().len(), 2);
+        assert_eq!(input.sequence.0, 0xfffffffe);
+
+        assert_eq!(input.serialize(), raw);
+        // 148 bytes, and the item count of the empty witness
+        assert_eq!(input.weight(), 148 * 4 + 1);
+    }
+
+    #[test]
+    fn test_outpoint() {
+        let txid: Txid = "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81".parse().unwrap();
+        let outpoint = OutPoint::new(txid, 1);
+        assert_eq!(outpoint.to_string(), "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81:1");
+
+        let input = TxIn::from_outpoint(outpoint, Sequence::MAX);
+        assert_eq!(input.outpoint(), outpoint);
+        assert!(input.script_sig.is_empty());
+    }
+
+    #[test]
+    fn test_empty_script_sig() {
+        let input = TxIn::new(
+            "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81".parse().unwrap(),
+            1,
+            Script::default(),
+            Sequence::new(0xffffffff),
         );
 
-        serialized
+        let serialized = input.consensus_serialize();
+        assert_eq!(serialized.len(), 41);
+        assert_eq!(serialized[36], 0x00);
+
+        let (parsed, consumed) = TxIn::parse(&serialized).unwrap();
+        assert_eq!(consumed, 41);
+        assert_eq!(parsed.prev_index, 1);
+        assert!(parsed.script_sig.is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_relative_locktime() {
+        assert_eq!(Sequence::MAX.relative_locktime(), None);
+        assert_eq!(Sequence::from_height(144).relative_locktime(), Some(RelativeLockTime::Blocks(144)));
+        assert_eq!(Sequence(0x00400010).relative_locktime(), Some(RelativeLockTime::Time(16)));
+        // bits outside the flags and the value are ignored
+        assert_eq!(Sequence(0x7fbf0005).relative_locktime(), Some(RelativeLockTime::Blocks(5)));
+
+        assert_eq!(Sequence::from_seconds_ceil(512), Some(Sequence(0x00400001)));
+        assert_eq!(Sequence::from_seconds_ceil(513), Some(Sequence(0x00400002)));
+        assert_eq!(Sequence::from_seconds_ceil(u16::MAX as u32 * 512 + 1), None);
+
+        let lock = RelativeLockTime::Time(2);
+        assert!(!lock.is_satisfied_by(u32::MAX, 1023));
+        assert!(lock.is_satisfied_by(0, 1024));
+        assert!(RelativeLockTime::Blocks(6).is_satisfied_by(6, 0));
+        assert!(!RelativeLockTime::Blocks(6).is_satisfied_by(5, u32::MAX));
+
+        assert!(RelativeLockTime::Blocks(6).satisfies_csv(RelativeLockTime::Blocks(6)));
+        assert!(!RelativeLockTime::Blocks(6).satisfies_csv(RelativeLockTime::Blocks(7)));
+        assert!(!RelativeLockTime::Blocks(6).satisfies_csv(RelativeLockTime::Time(1)));
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        assert!(TxIn::parse(&[0u8; 40]).is_err());
+    }
+}
@@ -0,0 +1,415 @@
+use ec_cryptography::{
+    helper::{from_hex, to_hex},
+    network::Network,
+};
+use scripts::Script;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    amount::Amount,
+    coinbase::COINBASE_PREV_INDEX,
+    input::{Sequence, TxIn},
+    locktime::LockTime,
+    output::TxOut,
+    txid::Txid,
+    version::Version,
+    witness::Witness,
+    Transaction,
+};
+
+/// Byte strings are hex in JSON
+struct Hex(Vec<u8>);
+
+impl<'de> Deserialize<'de> for Hex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        from_hex(&hex)
+            .map(Hex)
+            .map_err(|e| de::Error::custom(format!("invalid hex: {:?}", e)))
+    }
+}
+
+/// Core writes amounts in bitcoin with 8 decimals, Esplora in satoshis
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonAmount {
+    Sats(u64),
+    Btc(f64),
+}
+
+impl JsonAmount {
+    /// Floats print in full without an exponent, so bitcoin amounts parse back exactly
+    fn to_amount<E: de::Error>(&self) -> Result<Amount, E> {
+        match *self {
+            JsonAmount::Sats(sats) => Ok(Amount::from_sat(sats)),
+            JsonAmount::Btc(btc) => {
+                Amount::from_btc(&btc.to_string()).map_err(|e| E::custom(format!("invalid amount {}: {:?}", btc, e)))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ScriptJson {
+    hex: Script,
+}
+
+/// An output as written by Core (`value` in bitcoin and a `scriptPubKey` object)
+/// or by Esplora (`value` in satoshis and a `scriptpubkey` hex string)
+#[derive(Deserialize)]
+struct TxOutJson {
+    value: JsonAmount,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey_object: Option<ScriptJson>,
+    scriptpubkey: Option<Script>,
+}
+
+/// An input as written by Core (a `scriptSig` object and `txinwitness`, or `coinbase` for
+/// coinbase inputs) or by Esplora (`scriptsig` and `witness`)
+#[derive(Deserialize)]
+struct TxInJson {
+    txid: Option<String>,
+    vout: Option<u32>,
+    #[serde(rename = "scriptSig")]
+    script_sig_object: Option<ScriptJson>,
+    scriptsig: Option<Script>,
+    coinbase: Option<Script>,
+    #[serde(alias = "witness")]
+    txinwitness: Option<Vec<Hex>>,
+    sequence: u32,
+}
+
+#[derive(Deserialize)]
+struct TransactionJson {
+    version: u32,
+    locktime: u32,
+    vin: Vec<TxIn>,
+    vout: Vec<TxOut>,
+}
+
+/// The output of Core's `decoderawtransaction` for a script_pubkey
+#[derive(Serialize)]
+struct ScriptPubKeyView {
+    asm: String,
+    hex: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    #[serde(rename = "type")]
+    script_type: &'static str,
+}
+
+#[derive(Serialize)]
+struct TxOutView {
+    value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<usize>,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: ScriptPubKeyView,
+}
+
+#[derive(Serialize)]
+struct ScriptSigView {
+    asm: String,
+    hex: String,
+}
+
+#[derive(Serialize)]
+struct TxInView {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coinbase: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    txid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vout: Option<u32>,
+    #[serde(rename = "scriptSig", skip_serializing_if = "Option::is_none")]
+    script_sig: Option<ScriptSigView>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    txinwitness: Vec<String>,
+    sequence: u32,
+}
+
+#[derive(Serialize)]
+struct TransactionView {
+    txid: String,
+    hash: String,
+    version: u32,
+    size: usize,
+    vsize: u64,
+    weight: u64,
+    locktime: u32,
+    vin: Vec<TxInView>,
+    vout: Vec<TxOutView>,
+}
+
+impl TxOut {
+    fn view(&self, n: Option<usize>, network: Network) -> TxOutView {
+        TxOutView {
+            value: self.value.to_sat() as f64 / Amount::ONE_BTC.to_sat() as f64,
+            n,
+            script_pubkey: ScriptPubKeyView {
+                asm: self.script_pubkey.asm(),
+                hex: self.script_pubkey.to_hex(),
+                address: self.script_pubkey.to_address(network).map(|address| address.to_string()),
+                script_type: self.script_pubkey.classify().name(),
+            },
+        }
+    }
+}
+
+impl TxIn {
+    fn is_coinbase(&self) -> bool {
+        self.prev_index == COINBASE_PREV_INDEX && self.prev_txid == Txid::default()
+    }
+
+    fn view(&self) -> TxInView {
+        let txinwitness = self.witness.iter().map(to_hex).collect();
+
+        if self.is_coinbase() {
+            return TxInView {
+                coinbase: Some(self.script_sig.to_hex()),
+                txid: None,
+                vout: None,
+                script_sig: None,
+                txinwitness,
+                sequence: self.sequence.0,
+            };
+        }
+
+        TxInView {
+            coinbase: None,
+            txid: Some(self.prev_txid.to_string()),
+            vout: Some(self.prev_index),
+            script_sig: Some(ScriptSigView { asm: self.script_sig.asm(), hex: self.script_sig.to_hex() }),
+            txinwitness,
+            sequence: self.sequence.0,
+        }
+    }
+}
+
+/// In JSON, outputs take the shape of Core's `decoderawtransaction` (without `n`),
+/// with mainnet addresses
+impl Serialize for TxOut {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.view(None, Network::Mainnet).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TxOut {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = TxOutJson::deserialize(deserializer)?;
+
+        let script_pubkey = match (json.script_pubkey_object, json.scriptpubkey) {
+            (Some(ScriptJson { hex }), _) | (None, Some(hex)) => hex,
+            (None, None) => return Err(de::Error::missing_field("scriptPubKey")),
+        };
+        Ok(TxOut::new(json.value.to_amount()?, script_pubkey))
+    }
+}
+
+impl Serialize for TxIn {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.view().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TxIn {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = TxInJson::deserialize(deserializer)?;
+
+        let (prev_txid, prev_index, script_sig) = match json.coinbase {
+            Some(script_sig) => (Txid::default(), COINBASE_PREV_INDEX, script_sig),
+            None => {
+                let script_sig = match (json.script_sig_object, json.scriptsig) {
+                    (Some(ScriptJson { hex }), _) | (None, Some(hex)) => hex,
+                    (None, None) => Script::default(),
+                };
+                let prev_txid = json.txid.ok_or_else(|| de::Error::missing_field("txid"))?;
+                (
+                    prev_txid.parse().map_err(|e| de::Error::custom(format!("invalid txid: {:?}", e)))?,
+                    json.vout.ok_or_else(|| de::Error::missing_field("vout"))?,
+                    script_sig,
+                )
+            }
+        };
+
+        let mut input = TxIn::new(prev_txid, prev_index, script_sig, Sequence::new(json.sequence));
+        if let Some(items) = json.txinwitness {
+            input.witness = Witness::from(items.into_iter().map(|Hex(item)| item).collect::<Vec<_>>());
+        }
+        Ok(input)
+    }
+}
+
+/// Transactions read and write the JSON of Core's `decoderawtransaction` (and `getrawtransaction` in verbose mode).
+/// Esplora's JSON can be read as well.
+impl Serialize for Transaction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let network = if self.testnet { Network::Testnet } else { Network::Mainnet };
+
+        TransactionView {
+            txid: self.txid().to_string(),
+            hash: self.wtxid().to_string(),
+            version: self.version.to_u32(),
+            size: self.serialize().len(),
+            vsize: self.vsize(),
+            weight: self.weight(),
+            locktime: self.locktime.to_consensus_u32(),
+            vin: self.inputs.iter().map(TxIn::view).collect(),
+            vout: self
+                .outputs
+                .iter()
+                .enumerate()
+                .map(|(n, output)| output.view(Some(n), network))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Transaction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = TransactionJson::deserialize(deserializer)?;
+
+        Ok(Transaction {
+            version: Version::new(json.version),
+            inputs: json.vin,
+            outputs: json.vout,
+            locktime: LockTime::from_consensus(json.locktime),
+            testnet: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::fixtures::RAW_TX;
+
+    const SCRIPT_SIG: &str = "483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a";
+
+    /// What `decoderawtransaction` prints for the transaction
+    fn core_json() -> Value {
+        json!({
+            "txid": "452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03",
+            "hash": "452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03",
+            "version": 1,
+            "size": 226,
+            "vsize": 226,
+            "weight": 904,
+            "locktime": 410393,
+            "vin": [{
+                "txid": "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81",
+                "vout": 0,
+                "scriptSig": {
+                    "asm": "3045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed[ALL] 0349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a",
+                    "hex": SCRIPT_SIG
+                },
+                "sequence": 4294967294u32
+            }],
+            "vout": [{
+                "value": 0.32454049,
+                "n": 0,
+                "scriptPubKey": {
+                    "asm": "OP_DUP OP_HASH160 bc3b654dca7e56b04dca18f2566cdaf02e8d9ada OP_EQUALVERIFY OP_CHECKSIG",
+                    "desc": "addr(1JAHBxA51vwp5C2zpSB15VbxSZK3hVJs2H)#5cxgqfv7",
+                    "hex": "76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac",
+                    "address": "1JAHBxA51vwp5C2zpSB15VbxSZK3hVJs2H",
+                    "type": "pubkeyhash"
+                }
+            }, {
+                "value": 0.10011545,
+                "n": 1,
+                "scriptPubKey": {
+                    "asm": "OP_DUP OP_HASH160 1c4bc762dd5423e332166702cb75f40df79fea12 OP_EQUALVERIFY OP_CHECKSIG",
+                    "hex": "76a9141c4bc762dd5423e332166702cb75f40df79fea1288ac",
+                    "type": "pubkeyhash"
+                }
+            }]
+        })
+    }
+
+    #[test]
+    fn test_deserialize_core_json() {
+        let tx: Transaction = serde_json::from_value(core_json()).unwrap();
+        assert_eq!(to_hex(&tx.serialize()), RAW_TX);
+        assert_eq!(tx.outputs[0].value, Amount::from_sat(32454049));
+    }
+
+    #[test]
+    fn test_deserialize_esplora_json() {
+        let esplora = json!({
+            "txid": "452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03",
+            "version": 1,
+            "locktime": 410393,
+            "vin": [{
+                "txid": "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81",
+                "vout": 0,
+                "prevout": { "scriptpubkey": "76a914", "value": 42505594 },
+                "scriptsig": SCRIPT_SIG,
+                "is_coinbase": false,
+                "sequence": 4294967294u32
+            }],
+            "vout": [
+                { "scriptpubkey": "76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac", "scriptpubkey_type": "p2pkh", "value": 32454049 },
+                { "scriptpubkey": "76a9141c4bc762dd5423e332166702cb75f40df79fea1288ac", "scriptpubkey_type": "p2pkh", "value": 10011545 }
+            ],
+            "status": { "confirmed": true }
+        });
+
+        let tx: Transaction = serde_json::from_value(esplora).unwrap();
+        assert_eq!(to_hex(&tx.serialize()), RAW_TX);
+    }
+
+    #[test]
+    fn test_serialize_like_core() {
+        let tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        let json = serde_json::to_value(&tx).unwrap();
+        let expected = core_json();
+
+        for field in ["txid", "hash", "version", "size", "vsize", "weight", "locktime"] {
+            assert_eq!(json[field], expected[field], "{}", field);
+        }
+        assert_eq!(json["vin"][0]["txid"], expected["vin"][0]["txid"]);
+        assert_eq!(json["vin"][0]["scriptSig"]["hex"], SCRIPT_SIG);
+        assert!(json["vin"][0].get("txinwitness").is_none());
+
+        // the output of decoderawtransaction, without the descriptor
+        let mut output = expected["vout"][0].clone();
+        output["scriptPubKey"].as_object_mut().unwrap().remove("desc");
+        assert_eq!(json["vout"][0], output);
+
+        let testnet = Transaction::parse_hex(RAW_TX, true).unwrap();
+        assert_eq!(serde_json::to_value(&testnet).unwrap()["vout"][0]["scriptPubKey"]["address"], "mxgEV1F3pxP4rJWcY19NuQpHJYukanKMBM");
+
+        // a round trip through JSON
+        let parsed: Transaction = serde_json::from_value(json).unwrap();
+        assert_eq!(to_hex(&parsed.serialize()), RAW_TX);
+    }
+
+    #[test]
+    fn test_coinbase_and_witness() {
+        let input: TxIn = serde_json::from_value(json!({
+            "coinbase": "03a0bb0d",
+            "txinwitness": ["0000000000000000000000000000000000000000000000000000000000000000"],
+            "sequence": 4294967295u32
+        }))
+        .unwrap();
+        assert!(input.is_coinbase());
+        assert_eq!(input.script_sig.to_hex(), "03a0bb0d");
+        assert_eq!(input.witness.len(), 1);
+
+        let json = serde_json::to_value(&input).unwrap();
+        assert_eq!(json["coinbase"], "03a0bb0d");
+        assert!(json.get("txid").is_none());
+        assert_eq!(json["txinwitness"][0].as_str().unwrap().len(), 64);
+    }
+
+    #[test]
+    fn test_address_and_asm() {
+        let p2wpkh = Script::from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        assert_eq!(p2wpkh.to_address(Network::Mainnet).unwrap().to_string(), "bc1qr583w2swedy2acd7rung055k8t3n7udp7vyzyg");
+        assert_eq!(p2wpkh.asm(), "0 1d0f172a0ecb48aee1be1f2687d2963ae33f71a1");
+    }
+}
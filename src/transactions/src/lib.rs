@@ -1,30 +1,135 @@
-use std::{fmt::{Debug, Display}, rc::Rc};
-use input::{PrevOutput, Sequence, TxIn};
+use std::{fmt::{Debug, Display}, io::{self, Cursor, Read, Write}};
+use input::{OutPoint, TxIn};
 use output::TxOut;
-use rug::Integer;
-use serde::{Deserialize, Serialize};
+use ec_cryptography::{
+    consensus::{ConsensusDecode, ConsensusEncode},
+    helper::{from_hex, hash256},
+};
 
 mod version;
+pub mod amount;
+pub mod builder;
+pub mod coin_selection;
+pub mod coinbase;
+pub mod coinjoin;
 pub mod input;
-mod output;
+mod json;
+pub mod locktime;
+pub mod malleability;
+pub mod output;
+pub mod package;
+pub mod policy;
+pub mod preimage;
+pub mod psbt;
+pub mod rbf;
+pub mod sighash;
+pub mod sign;
+pub mod sigops;
+pub mod stream;
+pub mod taproot;
+pub mod txid;
 pub mod utils;
+pub mod utxo;
+pub mod verify;
+pub mod witness;
+#[cfg(test)]
+mod fixtures;
 
-use sha2::{Sha256, Digest};
-use utils::TxFetcher;
+use amount::Amount;
+use locktime::LockTime;
+use txid::{Txid, Wtxid};
+use utxo::UtxoProvider;
 use version::Version;
+use witness::Witness;
+
+const SEGWIT_MARKER: u8 = 0x00;
+const SEGWIT_FLAG: u8 = 0x01;
 
 #[derive(Debug)]
 pub enum TransactionError {
     FailedToDecodeTX,
+    /// The stream could not be read, or ended before the transaction did
+    Io(io::Error),
+    /// Bytes were left over after the end of the transaction
+    TrailingBytes(usize),
+    /// The transaction has no input with the given index
+    InputIndexOutOfRange(usize),
+    /// The number of spent outputs given doesn't match the number of inputs
+    PrevoutsLengthMismatch(usize),
+    /// SIGHASH_SINGLE was used for an input without an output of the same index
+    SingleWithoutOutput(usize),
+    /// The annex doesn't start with 0x50
+    InvalidAnnex,
+    /// The script_pubkey being spent doesn't pay to the signing key
+    KeyMismatch,
+    /// The output spent by the input with this index could not be found
+    MissingPrevout(usize),
+    /// The outputs spend more than the inputs hold, by this much
+    NegativeFee(Amount),
+    /// The sum of the input or output values doesn't fit in 64 bits
+    ValueOverflow,
+    /// None of the inputs signal replace-by-fee
+    NotReplaceable,
+    /// The transaction has no output with the given index
+    OutputIndexOutOfRange(usize),
+    /// The change output is this many satoshis short of paying the higher fee
+    InsufficientChange(Amount),
+    /// The data of an OP_RETURN output is this many bytes, more than MAX_OP_RETURN_DATA
+    OpReturnTooLarge(usize),
+    /// The child of a package spends none of the parent's outputs
+    NotAChild,
+    /// The bytes don't hold a valid transaction
+    Parse(ParseError),
+    /// The transactions to merge have different versions or locktimes
+    IncompatibleMerge,
+    /// The transactions to merge both spend this output
+    DuplicateInput(OutPoint),
+    /// The signature doesn't check out against the key and the hash it should sign
+    InvalidSignature,
+    /// A control block of this many bytes isn't 33 bytes followed by up to 128 hashes
+    InvalidControlBlock(usize),
+}
+
+impl From<io::Error> for TransactionError {
+    fn from(error: io::Error) -> Self {
+        TransactionError::Io(error)
+    }
+}
+
+impl From<ParseError> for TransactionError {
+    fn from(error: ParseError) -> Self {
+        TransactionError::Parse(error)
+    }
+}
+
+/// Bytes that could not be parsed, and how far into them parsing got
+#[derive(Debug)]
+pub struct ParseError {
+    /// The number of bytes read before the error. When the data ends too early, it's where it ends.
+    pub offset: usize,
+    pub error: io::Error,
+}
+
+/// Decodes a value from the start of the bytes, returning it with the number of bytes it took up.
+/// Reading stops at the end of the slice: data that ends too early is an error, not a panic.
+pub(crate) fn decode_prefix<'a, T>(
+    bytes: &'a [u8],
+    decode: impl FnOnce(&mut Cursor<&'a [u8]>) -> io::Result<T>,
+) -> Result<(T, usize), ParseError> {
+    let mut cursor = Cursor::new(bytes);
+    match decode(&mut cursor) {
+        Ok(value) => Ok((value, cursor.position() as usize)),
+        Err(error) => Err(ParseError { offset: cursor.position() as usize, error }),
+    }
 }
 
 /// We construct a Transaction
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Clone)]
 pub struct Transaction {
     version: Version,
     pub inputs: Vec<TxIn>,
     outputs: Vec<TxOut>,
-    locktime: u32,
+    locktime: LockTime,
     pub testnet: bool,
 }
 
@@ -41,7 +146,7 @@ impl Display for Transaction {
         }
         
         write!(f, 
-            "Transaction {{ version: {:?}, inputs: {}, outputs: {}, locktime: {} }}", 
+            "Transaction {{ version: {:?}, inputs: {}, outputs: {}, locktime: {:?} }}", 
             self.version, inputs, outputs, self.locktime
         )
     }
@@ -50,255 +155,491 @@ impl Display for Transaction {
 impl Transaction {
     // Create a human readable hex of the transaction hash
     pub fn id(&self) -> String {
-        self.hash()
-            .iter()
-            .map(|byte| format!("{:02x}", byte))
-            .collect::<String>()
+        self.txid().to_string()
     }
 
-    // create a hash of the transaction
+    // create a hash of the transaction, in the byte order it has inside inputs
     pub fn hash(&self) -> Vec<u8> {
-        let serialized = self.serialize();
-        
-        // setup the hasher
-        let mut hasher = Sha256::new();
+        hash256(&self.serialize_legacy())
+    }
 
-        // hash the serialized transaction
-        hasher.update(serialized);
+    /// The transaction id: the hash256 of the serialization without witnesses
+    pub fn txid(&self) -> Txid {
+        let hash = self.hash();
+        Txid::from_bytes(hash.try_into().expect("hash256 is 32 bytes"))
+    }
 
-        // return the hash
-        let hash = hasher.finalize();
-        hash.to_vec()
+    /// The witness transaction id: the hash256 of the full serialization, witnesses included.
+    /// (Block witness commitments use zero in place of the coinbase's wtxid.)
+    pub fn wtxid(&self) -> Wtxid {
+        let hash = hash256(&self.serialize());
+        Wtxid::from_bytes(hash.try_into().expect("hash256 is 32 bytes"))
     }
 
     pub fn version(&self) -> Version {
         self.version.clone()
     }
 
-    pub fn fee(&self) -> u64 {
-        let mut input_value = 0;    // will hold the accumulation of all inputs
-        let mut output_value = 0;   // will hold the accumulation of all outputs
+    /// The fee paid: the value of the outputs being spent, looked up in `utxos`,
+    /// minus the value of the new outputs
+    pub fn fee(&self, utxos: &impl UtxoProvider) -> Result<Amount, TransactionError> {
+        let mut input_value = Amount::ZERO;
+        for (index, input) in self.inputs.iter().enumerate() {
+            let prev_output = utxos
+                .get_output(&input.prev_txid, input.prev_index)
+                .ok_or(TransactionError::MissingPrevout(index))?;
 
-        for input in &self.inputs {
-            input_value += input.value(self.testnet);
-        }
-        
-        for output in &self.outputs {
-            output_value += output.value;
+            input_value = input_value.checked_add(prev_output.value).ok_or(TransactionError::ValueOverflow)?;
         }
 
-        input_value - output_value
+        let output_value = Amount::checked_sum(self.outputs.iter().map(|output| output.value))
+            .ok_or(TransactionError::ValueOverflow)?;
+
+        input_value
+            .checked_sub(output_value)
+            .ok_or_else(|| TransactionError::NegativeFee(output_value - input_value))
     }
 
-    pub fn serialize(&self) -> String {
-        let mut serialized_tx = String::from("");
+    /// The weight of the transaction (BIP141): witness bytes count once, every other byte four times
+    pub fn weight(&self) -> u64 {
+        let base_size = self.serialize_legacy().len() as u64;
+        let total_size = self.serialize().len() as u64;
+        base_size * 3 + total_size
+    }
 
-        // serialize the version
-        let version = self.version.parse();
-        serialized_tx.push_str(&version);
+    /// The virtual size in vbytes: the weight divided by 4, rounded up. Feerates are given per vbyte.
+    pub fn vsize(&self) -> u64 {
+        self.weight().div_ceil(4)
+    }
 
-        // serialize the input length
-        let input_count = utils::encode_varints(self.inputs.len() as u64);
-        serialized_tx.push_str(&input_count.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
-        // serialize the tx_inputs
-        for input in &self.inputs {
-            let serialized_inputs = input.serialize();
-            serialized_tx.push_str(&serialized_inputs);
-        }
+    /// The feerate in sat/vB this transaction pays with the given fee
+    pub fn fee_rate(&self, fee: Amount) -> f64 {
+        fee.to_sat() as f64 / self.vsize() as f64
+    }
 
-        // serialize the output length
-        let output_count = utils::encode_varints(self.outputs.len() as u64);
-        serialized_tx.push_str(&output_count.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
-        
-        // serialize the transaction outputs
-        for output in &self.outputs {
-            let serialized_outputs = output.serialize();
-            serialized_tx.push_str(&serialized_outputs);
+    /// Whether any input has a witness, so the transaction is serialized in the segwit format
+    pub fn has_witness(&self) -> bool {
+        self.inputs.iter().any(|input| !input.witness.is_empty())
+    }
+
+    /// The raw transaction: the little-endian version, the varint counted inputs and outputs
+    /// (each script with its varint length) and the little-endian locktime.
+    /// Transactions with witnesses also have the segwit marker and flag, and the witnesses before the locktime.
+    pub fn serialize(&self) -> Vec<u8> {
+        self.consensus_serialize()
+    }
+
+    /// The serialization without witness data, which the txid commits to
+    pub fn serialize_legacy(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.encode_legacy(&mut bytes).expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    fn encode_legacy<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.version.consensus_encode(writer)?
+            + self.inputs.consensus_encode(writer)?
+            + self.outputs.consensus_encode(writer)?
+            + self.locktime.consensus_encode(writer)?)
+    }
+
+    /// Reads a transaction from a stream, consuming exactly its bytes.
+    /// Any data that follows (e.g. the next transaction of a block) is left in the reader.
+    pub fn parse_stream<R: Read>(reader: &mut R, testnet: bool) -> Result<Transaction, TransactionError> {
+        let mut transaction = Transaction::consensus_decode(reader)?;
+        transaction.testnet = testnet;
+        Ok(transaction)
+    }
+
+    /// Writes the transaction to a stream in the same format `parse_stream` reads
+    pub fn serialize_stream<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.consensus_encode(writer).map(|_| ())
+    }
+
+    /// Parses a raw transaction: the version, the varint counted inputs and outputs, and the locktime,
+    /// in either the legacy or the segwit format. The bytes must hold exactly one transaction.
+    pub fn parse(bytes: &[u8], testnet: bool) -> Result<Transaction, TransactionError> {
+        let (mut transaction, length) = decode_prefix(bytes, Transaction::consensus_decode)?;
+        transaction.testnet = testnet;
+
+        let remaining = bytes.len() - length;
+        if remaining > 0 {
+            return Err(TransactionError::TrailingBytes(remaining));
         }
 
-        // serialize the locktime
-        let locktime = self.locktime.to_le_bytes().iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
-        serialized_tx.push_str(&locktime);
-
-        serialized_tx
-    }
-
-    pub fn parse(raw: &str, testnet: bool) -> Result<Transaction, TransactionError> {
-        let mut data_count = 0;
-        // Parse the version from the transactiob, first 4 bytes
-        let tx_bytes = Rc::new(Integer::from_str_radix(
-            raw, 
-            16)
-            .unwrap()
-            .to_digits::<u8>(rug::integer::Order::MsfBe));
-
-        let version_len = 4;
-        let version = Version::from_vec(&tx_bytes[data_count..version_len]);
-        data_count += version_len;
-
-        // Estimate the transaction input, the next character after the version
-        // First, let's determine the length of the input
-        let (input_byte_count, input_count) = utils::parse_varints(&tx_bytes, data_count);
-        data_count += input_byte_count;
-
-        let mut transactions = vec![];
-        // loop through the available inputs, based on the input count and extract each input
-        for _ in 0..input_count {
-            let mut tx_data_count = data_count;
-
-            // the input starts with the prev_tx_id: 32 bytes
-            let mut prev_tx_id = tx_bytes[tx_data_count..(tx_data_count + 32)].to_vec();
-            prev_tx_id.reverse();
-            tx_data_count += 32;
-
-            // this is followed by the prev_tx_index: 4 bytes
-            let prev_tx_index_bytes = &tx_bytes[(tx_data_count)..(tx_data_count + 4)];
-            tx_data_count += 4;
-
-
-            // decode the variable-length scriptsig
-            let (scriptsig_byte_count, scriptsig_length) = utils::parse_varints(&tx_bytes, tx_data_count);
-            let scriptsig = &tx_bytes[(tx_data_count)..(tx_data_count + scriptsig_length as usize + 1)];
-            tx_data_count += scriptsig_byte_count + scriptsig_length as usize;
-
-            // the sequence will take up 4 bytes
-            let sequence = &tx_bytes[(tx_data_count)..(tx_data_count + 4)];
-            tx_data_count += 4;
-
-            let previous_output = PrevOutput {
-                txid: prev_tx_id.iter().map(|byte| format!("{:02x}", byte)).collect::<String>(),
-                index: PrevOutput::parse_index(prev_tx_index_bytes),
-            };
-            let transaction = TxIn::new(
-                previous_output, 
-                Some(scriptsig.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()), 
-                Sequence::from_bytes(sequence)
-            );
-            transactions.push(transaction);
-
-            tx_data_count -= data_count;
-
-            data_count += tx_data_count;
+        Ok(transaction)
+    }
+
+    /// Parses a hex encoded raw transaction
+    pub fn parse_hex(raw: &str, testnet: bool) -> Result<Transaction, TransactionError> {
+        let bytes = from_hex(raw).map_err(|_| TransactionError::FailedToDecodeTX)?;
+        Self::parse(&bytes, testnet)
+    }
+}
+
+/// Transactions with witnesses use the BIP144 format: the version is followed by the 0x00 marker
+/// and the 0x01 flag, and the witness of each input comes after the outputs
+impl ConsensusEncode for Transaction {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        if !self.has_witness() {
+            return self.encode_legacy(writer);
         }
 
+        let mut written = self.version.consensus_encode(writer)?;
+        written += [SEGWIT_MARKER, SEGWIT_FLAG].consensus_encode(writer)?;
+        written += self.inputs.consensus_encode(writer)?;
+        written += self.outputs.consensus_encode(writer)?;
+        for input in &self.inputs {
+            written += input.witness.consensus_encode(writer)?;
+        }
+        Ok(written + self.locktime.consensus_encode(writer)?)
+    }
+}
 
-        // parse the tx outputs
-        let mut outputs = vec![];
-        let (_, output_count) = utils::parse_varints(&tx_bytes, data_count);
-        for _ in 0..output_count {
-            let mut tx_data_count = data_count;
-
-            // The output amount is 8 bytes
-            let output_amount = &tx_bytes[(tx_data_count + 1)..(tx_data_count + 9)];
-            let value = u64::from_le_bytes([
-                output_amount[0],
-                output_amount[1],
-                output_amount[2],
-                output_amount[3],
-                output_amount[4],
-                output_amount[5],
-                output_amount[6],
-                output_amount[7],
-            ]);
-            tx_data_count += 9;
-
-            // The scriptpubkey is variable length, let's decode the length
-            let (scriptpubkey_byte_count, scriptpubkey_length) = utils::parse_varints(&tx_bytes, tx_data_count);
-            let scriptpubkey = &tx_bytes[(tx_data_count)..(tx_data_count + scriptpubkey_length as usize + scriptpubkey_byte_count)];
-            tx_data_count += scriptpubkey_byte_count + scriptpubkey_length as usize;
-
-            let output = TxOut::new(
-                value, 
-                scriptpubkey.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()
-            );
-            outputs.push(output);
-            tx_data_count -= data_count;
-            data_count += tx_data_count - 1;
+/// Decoded transactions are marked as mainnet, use `parse_stream` to choose the network
+impl ConsensusDecode for Transaction {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let version = Version::consensus_decode(reader)?;
+
+        // the segwit marker reads as an empty input list
+        let mut inputs = Vec::<TxIn>::consensus_decode(reader)?;
+        let segwit = inputs.is_empty();
+        if segwit {
+            if u8::consensus_decode(reader)? != SEGWIT_FLAG {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown segwit flag"));
+            }
+            inputs = Vec::<TxIn>::consensus_decode(reader)?;
         }
 
-        // decode the locktime: 4 bytes
-        let locktime_bytes = &tx_bytes[(data_count + 1)..(data_count + 5)];
-        let mut locktime = [0u8; 4];
-        locktime[..locktime_bytes.len()].copy_from_slice(locktime_bytes);
+        let outputs = Vec::<TxOut>::consensus_decode(reader)?;
+
+        if segwit {
+            for input in &mut inputs {
+                input.witness = Witness::consensus_decode(reader)?;
+            }
+            // the witness format is only allowed when there is a witness to write
+            if inputs.iter().all(|input| input.witness.is_empty()) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "superfluous witness data"));
+            }
+        }
 
         Ok(Transaction {
             version,
-            inputs: transactions,
+            inputs,
             outputs,
-            locktime: u32::from_le_bytes(locktime),
-            testnet,
+            locktime: LockTime::consensus_decode(reader)?,
+            testnet: false,
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use ec_cryptography::helper::to_hex;
+    use scripts::Script;
+
     use super::*;
+    use crate::{fixtures::SEGWIT_TX, utils::TxFetcher};
+
+    /// The first transaction between two people, from block 170
+    const BLOCK_170_TX: &str = "0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
+
+    const MULTI_INPUT_TX: &str = "010000000456919960ac691763688d3d3bcea9ad6ecaf875df5339e148a1fc61c6ed7a069e010000006a47304402204585bcdef85e6b1c6af5c2669d4830ff86e42dd205c0e089bc2a821657e951c002201024a10366077f87d6bce1f7100ad8cfa8a064b39d4e8fe4ea13a7b71aa8180f012102f0da57e85eec2934a82a585ea337ce2f4998b50ae699dd79f5880e253dafafb7feffffffeb8f51f4038dc17e6313cf831d4f02281c2a468bde0fafd37f1bf882729e7fd3000000006a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937feffffff567bf40595119d1bb8a3037c356efd56170b64cbcc160fb028fa10704b45d775000000006a47304402204c7c7818424c7f7911da6cddc59655a70af1cb5eaf17c69dadbfc74ffa0b662f02207599e08bc8023693ad4e9527dc42c34210f7a7d1d1ddfc8492b654a11e7620a0012102158b46fbdff65d0172b7989aec8850aa0dae49abfb84c81ae6e5b251a58ace5cfeffffffd63a5e6c16e620f86f375925b21cabaf736c779f88fd04dcad51d26690f7f345010000006a47304402200633ea0d3314bea0d95b3cd8dadb2ef79ea8331ffe1e61f762c0f6daea0fabde022029f23b3e9c30f080446150b23852028751635dcee2be669c2a1686a4b5edf304012103ffd6f4a67e94aba353a00882e563ff2722eb4cff0ad6006e86ee20dfe7520d55feffffff0251430f00000000001976a914ab0c0b2e98b1ab6dbf67d4750b0a56244948a87988ac005a6202000000001976a9143c82d7df364eb6c75be8c80df2b3eda8db57397088ac46430600";
 
     fn raw_tx() -> &'static str {
-        "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600"
+        fixtures::RAW_TX
     }
 
     #[test]
     fn test_parse_version() {
-        let transaction = Transaction::parse(raw_tx(), true);
+        let transaction = Transaction::parse_hex(raw_tx(), true);
         assert!(transaction.is_ok(), "Transaction parse should succeed");
         assert_eq!(transaction.unwrap().version, Version::new(1));
+
+        // all 4 bytes of the version are kept, not just the first
+        let raw = format!("02010000{}", &raw_tx()[8..]);
+        let transaction = Transaction::parse_hex(&raw, true).unwrap();
+        assert_eq!(transaction.version.to_u32(), 0x102);
+        assert_eq!(to_hex(&transaction.serialize()), raw);
     }
 
     #[test]
     fn test_parse_inputs() {        
-        let transaction = Transaction::parse(raw_tx(), true);
+        let transaction = Transaction::parse_hex(raw_tx(), true);
         assert!(transaction.is_ok(), "Transaction parse should succeed");
         let tx = transaction.unwrap();
         
         assert_eq!(tx.inputs.len(), 1);
         let input = &tx.inputs[0];
-        assert_eq!(input.previous_output.txid, "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81");
-        assert_eq!(input.previous_output.index, 0);
-        assert_eq!(input.script_sig, Some("6b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a".to_string()));
+        assert_eq!(input.prev_txid.to_string(), "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81");
+        assert_eq!(input.prev_index, 0);
+        assert_eq!(input.script_sig.serialize(), "6b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a".to_string());
         assert_eq!(input.sequence.0, 0xfffffffe);
     }
 
     #[test]
     fn test_parse_outputs() {        
-        let transaction = Transaction::parse(raw_tx(), true);
+        let transaction = Transaction::parse_hex(raw_tx(), true);
         assert!(transaction.is_ok(), "Transaction parse should succeed");
 
         let transaction = transaction.unwrap();
         assert_eq!(transaction.outputs.len(), 2);
-        assert_eq!(transaction.outputs[0].value, 32454049);
-        assert_eq!(transaction.outputs[0].script_pubkey, "1976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac".to_string());
+        assert_eq!(transaction.outputs[0].value, Amount::from_sat(32454049));
+        assert_eq!(transaction.outputs[0].script_pubkey.serialize(), "1976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac".to_string());
         
-        assert_eq!(transaction.outputs[1].value, 10011545);
-        assert_eq!(transaction.outputs[1].script_pubkey, "1976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac".to_string());
+        assert_eq!(transaction.outputs[1].value, Amount::from_sat(10011545));
+        assert_eq!(transaction.outputs[1].script_pubkey.serialize(), "1976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac".to_string());
     }
 
     #[test]
     fn test_parse_tx_locktime() {
-        let transaction = Transaction::parse(raw_tx(), true);
+        let transaction = Transaction::parse_hex(raw_tx(), true);
         assert!(transaction.is_ok(), "Transaction parse should succeed");
 
         let transaction = transaction.unwrap();
-        assert_eq!(transaction.locktime, 410393);
+        assert_eq!(transaction.locktime, LockTime::Blocks(410393));
+    }
+
+    #[test]
+    fn test_parse_stream() {
+        let mut bytes = from_hex(raw_tx()).unwrap();
+        bytes.extend_from_slice(&[0xde, 0xad]);
+
+        let mut reader = io::Cursor::new(bytes);
+        let tx = Transaction::parse_stream(&mut reader, true).unwrap();
+        assert_eq!(tx.version, Version::new(1));
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.inputs[0].prev_txid.to_string(), "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81");
+        assert_eq!(tx.inputs[0].sequence.0, 0xfffffffe);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].value, Amount::from_sat(10011545));
+        assert_eq!(tx.outputs[1].script_pubkey.serialize(), "1976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac".to_string());
+        assert_eq!(tx.locktime, LockTime::Blocks(410393));
+
+        // the bytes after the transaction are left in the stream
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![0xde, 0xad]);
+
+        // inputs and outputs read and write the same way on their own
+        let mut input = vec![];
+        tx.inputs[0].serialize_stream(&mut input).unwrap();
+        assert_eq!(TxIn::parse_stream(&mut io::Cursor::new(input)).unwrap().serialize(), tx.inputs[0].serialize());
+        let mut output = vec![];
+        tx.outputs[1].serialize_stream(&mut output).unwrap();
+        assert_eq!(TxOut::parse_stream(&mut io::Cursor::new(output)).unwrap().serialize(), tx.outputs[1].serialize());
+
+        let mut serialized = vec![];
+        tx.serialize_stream(&mut serialized).unwrap();
+        assert_eq!(to_hex(&serialized), raw_tx());
+        assert_eq!(tx.consensus_serialize(), from_hex(raw_tx()).unwrap());
+    }
+
+    #[test]
+    fn test_parse_stream_truncated() {
+        let bytes = from_hex(raw_tx()).unwrap();
+        let mut reader = io::Cursor::new(&bytes[..bytes.len() - 1]);
+        assert!(matches!(
+            Transaction::parse_stream(&mut reader, true),
+            Err(TransactionError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_mainnet_transactions() {
+        let raw = from_hex(BLOCK_170_TX).unwrap();
+        let tx = Transaction::parse(&raw, false).unwrap();
+
+        assert_eq!(tx.version, Version::new(1));
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.inputs[0].prev_txid.to_string(), "0437cd7f8525ceed2324359c2d0ba26006d92d856a9c20fa0241106ee5a597c9");
+        assert_eq!(tx.inputs[0].prev_index, 0);
+        assert_eq!(tx.inputs[0].sequence.0, 0xffffffff);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[0].value, Amount::from_sat(1_000_000_000));
+        assert_eq!(tx.outputs[1].value, Amount::from_sat(4_000_000_000));
+        assert_eq!(tx.locktime, LockTime::Blocks(0));
+
+        // a transaction with several inputs
+        let raw = from_hex(MULTI_INPUT_TX).unwrap();
+        let tx = Transaction::parse(&raw, false).unwrap();
+
+        assert_eq!(tx.inputs.len(), 4);
+        assert_eq!(tx.inputs[3].prev_txid.to_string(), "45f3f79066d251addc04fd889f776c73afab1cb22559376ff820e6166c5e3ad6");
+        assert_eq!(tx.inputs[3].prev_index, 1);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].value, Amount::from_sat(40000000));
+        assert_eq!(tx.locktime, LockTime::Blocks(410438));
+    }
+
+    #[test]
+    fn test_txid() {
+        let tx = Transaction::parse_hex(raw_tx(), false).unwrap();
+        assert_eq!(tx.txid().to_string(), "452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03");
+        assert_eq!(tx.id(), "452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03");
+        assert_eq!(tx.hash(), tx.txid().as_bytes().to_vec());
+
+        let tx = Transaction::parse_hex(BLOCK_170_TX, false).unwrap();
+        assert_eq!(tx.txid(), "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16".parse().unwrap());
+    }
+
+    #[test]
+    fn test_wtxid() {
+        // without witnesses both ids hash the same bytes
+        let tx = Transaction::parse_hex(BLOCK_170_TX, false).unwrap();
+        assert_eq!(tx.serialize_legacy(), tx.serialize());
+        assert_eq!(tx.wtxid().as_bytes(), tx.txid().as_bytes());
+        assert_eq!(tx.wtxid().to_string(), "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16");
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        for raw in [raw_tx(), BLOCK_170_TX, MULTI_INPUT_TX] {
+            let bytes = from_hex(raw).unwrap();
+            let tx = Transaction::parse(&bytes, false).unwrap();
+            assert_eq!(tx.serialize(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_serialize_large_counts() {
+        // counts of 253 and more take 3 bytes
+        let mut tx = Transaction::parse_hex(raw_tx(), false).unwrap();
+        let output = tx.outputs.pop().unwrap();
+        tx.outputs = (0..253).map(|_| TxOut::new(output.value, output.script_pubkey.clone())).collect();
+
+        let serialized = tx.serialize();
+        let output_count_start = serialized.len() - 4 - 253 * (8 + 26) - 3;
+        assert_eq!(serialized[output_count_start..output_count_start + 3], [0xfd, 0xfd, 0x00]);
+
+        let parsed = Transaction::parse(&serialized, false).unwrap();
+        assert_eq!(parsed.outputs.len(), 253);
+        assert_eq!(parsed.serialize(), serialized);
+    }
+
+    #[test]
+    fn test_parse_invalid_transactions() {
+        let mut raw = from_hex(raw_tx()).unwrap();
+        let truncated = &raw[..raw.len() - 2];
+        assert!(matches!(
+            Transaction::parse(truncated, false),
+            Err(TransactionError::Parse(ParseError { offset, .. })) if offset == truncated.len()
+        ));
+        // every shorter prefix is an error too, rather than a panic
+        assert!((0..raw.len()).all(|length| Transaction::parse(&raw[..length], false).is_err()));
+
+        raw.push(0x00);
+        assert!(matches!(Transaction::parse(&raw, false), Err(TransactionError::TrailingBytes(1))));
+
+        assert!(matches!(Transaction::parse_hex("01000", false), Err(TransactionError::FailedToDecodeTX)));
     }
 
     #[test]
     fn test_tx_fee() {
-        let transaction = Transaction::parse(raw_tx(), false);
-        assert!(transaction.is_ok(), "Transaction parse should succeed");
+        let tx = Transaction::parse_hex(raw_tx(), false).unwrap();
+        let prev_txid = tx.inputs[0].prev_txid;
 
-        let tx = transaction.unwrap();
-        let fee = tx.fee();
-        assert_eq!(fee, 40000);
+        let mut utxos = HashMap::new();
+        assert!(matches!(tx.fee(&utxos), Err(TransactionError::MissingPrevout(0))));
+
+        utxos.insert((prev_txid, 0), TxOut::new(Amount::from_sat(42505594), Script::default()));
+        assert_eq!(tx.fee(&utxos).unwrap(), Amount::from_sat(40000));
+
+        // spending more than the inputs hold
+        utxos.insert((prev_txid, 0), TxOut::new(Amount::from_sat(42465593), Script::default()));
+        assert!(matches!(tx.fee(&utxos), Err(TransactionError::NegativeFee(Amount::ONE_SAT))));
+
+        utxos.insert((prev_txid, 0), TxOut::new(Amount::from_sat(u64::MAX), Script::default()));
+        let mut tx = tx;
+        tx.outputs.push(TxOut::new(Amount::from_sat(u64::MAX), Script::default()));
+        assert!(matches!(tx.fee(&utxos), Err(TransactionError::ValueOverflow)));
+    }
+
+    #[test]
+    fn test_parse_segwit() {
+        let tx = Transaction::parse_hex(SEGWIT_TX, false).unwrap();
+        assert!(tx.has_witness());
+        assert_eq!(tx.inputs.len(), 2);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.locktime, LockTime::Blocks(17));
+
+        // the first input spends a P2PK output, the second a P2WPKH one
+        assert!(tx.inputs[0].witness.is_empty());
+        assert_eq!(tx.inputs[1].witness.len(), 2);
+        assert_eq!(tx.inputs[1].witness[0].len(), 0x47);
+        assert_eq!(
+            to_hex(&tx.inputs[1].witness[1]),
+            "025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357"
+        );
+
+        assert_eq!(to_hex(&tx.serialize()), SEGWIT_TX);
+        assert_eq!(tx.id(), "e8151a2af31c368a35053ddd4bdb285a8595c769a3ad83e0fa02314a602d4609");
+        assert_eq!(tx.wtxid().to_string(), "c36c38370907df2324d9ce9d149d191192f338b37665a82e78e76a12c909b762");
+    }
 
-        let raw_tx = "010000000456919960ac691763688d3d3bcea9ad6ecaf875df5339e148a1fc61c6ed7a069e010000006a47304402204585bcdef85e6b1c6af5c2669d4830ff86e42dd205c0e089bc2a821657e951c002201024a10366077f87d6bce1f7100ad8cfa8a064b39d4e8fe4ea13a7b71aa8180f012102f0da57e85eec2934a82a585ea337ce2f4998b50ae699dd79f5880e253dafafb7feffffffeb8f51f4038dc17e6313cf831d4f02281c2a468bde0fafd37f1bf882729e7fd3000000006a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937feffffff567bf40595119d1bb8a3037c356efd56170b64cbcc160fb028fa10704b45d775000000006a47304402204c7c7818424c7f7911da6cddc59655a70af1cb5eaf17c69dadbfc74ffa0b662f02207599e08bc8023693ad4e9527dc42c34210f7a7d1d1ddfc8492b654a11e7620a0012102158b46fbdff65d0172b7989aec8850aa0dae49abfb84c81ae6e5b251a58ace5cfeffffffd63a5e6c16e620f86f375925b21cabaf736c779f88fd04dcad51d26690f7f345010000006a47304402200633ea0d3314bea0d95b3cd8dadb2ef79ea8331ffe1e61f762c0f6daea0fabde022029f23b3e9c30f080446150b23852028751635dcee2be669c2a1686a4b5edf304012103ffd6f4a67e94aba353a00882e563ff2722eb4cff0ad6006e86ee20dfe7520d55feffffff0251430f00000000001976a914ab0c0b2e98b1ab6dbf67d4750b0a56244948a87988ac005a6202000000001976a9143c82d7df364eb6c75be8c80df2b3eda8db57397088ac46430600";
-        let tx = Transaction::parse(raw_tx, false);
+    #[test]
+    fn test_parse_segwit_errors() {
+        let raw = from_hex(SEGWIT_TX).unwrap();
+
+        let mut bad_flag = raw.clone();
+        bad_flag[5] = 0x02;
+        // the error is reported just after the flag
+        assert!(matches!(
+            Transaction::parse(&bad_flag, false),
+            Err(TransactionError::Parse(ParseError { offset: 6, .. }))
+        ));
+
+        // the segwit format with only empty witnesses is rejected
+        let mut tx = Transaction::parse(&raw, false).unwrap();
+        tx.inputs[1].witness = Witness::new();
+        let legacy = tx.serialize();
+        let (body, locktime) = legacy.split_at(legacy.len() - 4);
+        let mut no_witness = [&body[..4], &[0x00, 0x01], &body[4..], &[0x00, 0x00], locktime].concat();
+        assert!(matches!(Transaction::parse(&no_witness, false), Err(TransactionError::Parse(_))));
+
+        no_witness.truncate(no_witness.len() - 6);
+        assert!(Transaction::parse(&no_witness, false).is_err());
+    }
 
-        assert!(tx.is_ok(), "Transaction parse should succeed");
-        let tx = tx.unwrap();
-        let fee = tx.fee();
-        assert_eq!(fee, 140500);
+    #[test]
+    fn test_segwit_weight() {
+        let tx = Transaction::parse_hex(SEGWIT_TX, false).unwrap();
 
+        // 233 bytes without witness data, 343 with it
+        assert_eq!(tx.serialize_legacy().len(), 233);
+        assert_eq!(tx.weight(), 233 * 3 + 343);
+        assert_eq!(tx.vsize(), 261);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_weight() {
+        let tx = Transaction::parse_hex(raw_tx(), false).unwrap();
+        let size = raw_tx().len() as u64 / 2;
+
+        // without witnesses every byte weighs 4
+        assert_eq!(tx.weight(), size * 4);
+        assert_eq!(tx.vsize(), size);
+        assert_eq!(tx.fee_rate(Amount::from_sat(40000)), 40000.0 / size as f64);
+    }
+
+    /// Looks up the previous outputs with a block explorer
+    #[test]
+    #[ignore = "needs network access"]
+    fn test_tx_fee_from_explorer() {
+        for (raw, fee) in [(raw_tx(), 40000), (MULTI_INPUT_TX, 140500)] {
+            let tx = Transaction::parse_hex(raw, false).unwrap();
+
+            let mut utxos = HashMap::new();
+            let mut tx_fetcher = TxFetcher::new(false);
+            for input in &tx.inputs {
+                let prev_txid = input.prev_txid;
+                let prev_tx = tx_fetcher.fetch(&prev_txid, false).unwrap();
+                utxos.insert((prev_txid, input.prev_index), prev_tx.outputs[input.prev_index as usize].clone());
+            }
+
+            assert_eq!(tx.fee(&utxos).unwrap(), Amount::from_sat(fee));
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::io::{self, Read, Write};
+
+use ec_cryptography::consensus::{ConsensusDecode, ConsensusEncode};
+use serde::{Deserialize, Serialize};
+
+use crate::{input::Sequence, Transaction, TransactionError};
+
+/// Locktimes below this are block heights, the rest are unix timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LockTimeError {
+    /// Heights must be below 500,000,000, or they would be read as a timestamp
+    InvalidHeight(u32),
+    /// Timestamps must be at least 500,000,000, or they would be read as a height
+    InvalidTime(u32),
+}
+
+/// The earliest block a transaction can be included in (absolute locktime),
+/// given either as a block height or as a unix timestamp compared against the median time past
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LockTime {
+    Blocks(u32),
+    Seconds(u32),
+}
+
+impl Default for LockTime {
+    /// No lock: a locktime of 0
+    fn default() -> Self {
+        LockTime::Blocks(0)
+    }
+}
+
+impl LockTime {
+    /// Reads the locktime field of a transaction (or the argument of OP_CHECKLOCKTIMEVERIFY)
+    pub fn from_consensus(locktime: u32) -> LockTime {
+        if locktime < LOCKTIME_THRESHOLD {
+            LockTime::Blocks(locktime)
+        } else {
+            LockTime::Seconds(locktime)
+        }
+    }
+
+    pub fn from_height(height: u32) -> Result<LockTime, LockTimeError> {
+        if height < LOCKTIME_THRESHOLD {
+            Ok(LockTime::Blocks(height))
+        } else {
+            Err(LockTimeError::InvalidHeight(height))
+        }
+    }
+
+    pub fn from_time(time: u32) -> Result<LockTime, LockTimeError> {
+        if time >= LOCKTIME_THRESHOLD {
+            Ok(LockTime::Seconds(time))
+        } else {
+            Err(LockTimeError::InvalidTime(time))
+        }
+    }
+
+    /// The value of the locktime field
+    pub fn to_consensus_u32(self) -> u32 {
+        match self {
+            LockTime::Blocks(height) => height,
+            LockTime::Seconds(time) => time,
+        }
+    }
+
+    /// Whether both are heights or both are timestamps
+    pub fn is_same_unit(self, other: LockTime) -> bool {
+        matches!(
+            (self, other),
+            (LockTime::Blocks(_), LockTime::Blocks(_)) | (LockTime::Seconds(_), LockTime::Seconds(_))
+        )
+    }
+
+    /// Whether the lock has expired for a block at `height` whose previous block has median time past `mtp`
+    pub fn is_satisfied_by(self, height: u32, mtp: u32) -> bool {
+        match self {
+            LockTime::Blocks(locktime) => locktime < height,
+            LockTime::Seconds(locktime) => locktime < mtp,
+        }
+    }
+
+    /// Whether a transaction with this locktime passes OP_CHECKLOCKTIMEVERIFY `required` (BIP65):
+    /// it must be of the same unit and at least as late
+    pub fn satisfies_cltv(self, required: LockTime) -> bool {
+        self.is_same_unit(required) && required.to_consensus_u32() <= self.to_consensus_u32()
+    }
+}
+
+impl ConsensusEncode for LockTime {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        self.to_consensus_u32().consensus_encode(writer)
+    }
+}
+
+impl ConsensusDecode for LockTime {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(LockTime::from_consensus(u32::consensus_decode(reader)?))
+    }
+}
+
+impl Transaction {
+    pub fn locktime(&self) -> LockTime {
+        self.locktime
+    }
+
+    pub fn set_locktime(&mut self, locktime: LockTime) {
+        self.locktime = locktime;
+    }
+
+    /// Whether the transaction can be included in a block at `height`, whose previous block
+    /// has median time past `mtp`. The locktime is ignored when every input has a final sequence.
+    pub fn is_final(&self, height: u32, mtp: u32) -> bool {
+        self.locktime.to_consensus_u32() == 0
+            || self.locktime.is_satisfied_by(height, mtp)
+            || self.inputs.iter().all(|input| input.sequence == Sequence::MAX)
+    }
+
+    /// Whether the relative locks of all inputs (BIP68) have expired. `ages` has, for each input,
+    /// the number of blocks and the difference in median time past (in seconds) between the block
+    /// the transaction goes in and the one that confirmed the output it spends.
+    /// Transactions before version 2 have no relative locks.
+    pub fn relative_locks_satisfied(&self, ages: &[(u32, u32)]) -> Result<bool, TransactionError> {
+        if ages.len() != self.inputs.len() {
+            return Err(TransactionError::PrevoutsLengthMismatch(ages.len()));
+        }
+        if self.version.to_u32() < 2 {
+            return Ok(true);
+        }
+
+        Ok(self.inputs.iter().zip(ages).all(|(input, &(blocks, seconds))| {
+            input
+                .sequence
+                .relative_locktime()
+                .is_none_or(|lock| lock.is_satisfied_by(blocks, seconds))
+        }))
+    }
+
+    /// Sets a locktime that lets the input at `input_index` pass OP_CHECKLOCKTIMEVERIFY `required`.
+    /// CLTV fails on final inputs, so a final sequence is lowered to 0xfffffffe.
+    pub fn set_cltv_locktime(&mut self, input_index: usize, required: LockTime) {
+        self.locktime = required;
+        if let Some(input) = self.inputs.get_mut(input_index) {
+            if input.sequence == Sequence::MAX {
+                input.sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::RAW_TX, version::Version};
+
+    #[test]
+    fn test_units() {
+        assert_eq!(LockTime::from_consensus(499_999_999), LockTime::Blocks(499_999_999));
+        assert_eq!(LockTime::from_consensus(500_000_000), LockTime::Seconds(500_000_000));
+
+        assert_eq!(LockTime::from_height(500_000_000), Err(LockTimeError::InvalidHeight(500_000_000)));
+        assert_eq!(LockTime::from_time(1), Err(LockTimeError::InvalidTime(1)));
+        assert_eq!(LockTime::from_time(1_700_000_000).unwrap().to_consensus_u32(), 1_700_000_000);
+    }
+
+    #[test]
+    fn test_cltv() {
+        let height = LockTime::from_height(800_000).unwrap();
+        let time = LockTime::from_time(1_700_000_000).unwrap();
+
+        assert!(height.satisfies_cltv(LockTime::Blocks(800_000)));
+        assert!(height.satisfies_cltv(LockTime::Blocks(799_999)));
+        assert!(!height.satisfies_cltv(LockTime::Blocks(800_001)));
+        // comparing heights and timestamps always fails
+        assert!(!time.satisfies_cltv(height));
+        assert!(!height.satisfies_cltv(time));
+    }
+
+    #[test]
+    fn test_is_final() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        assert_eq!(tx.locktime(), LockTime::Blocks(410393));
+
+        // the lock is the last height the transaction can't be mined at
+        assert!(!tx.is_final(410393, 0));
+        assert!(tx.is_final(410394, 0));
+
+        tx.set_locktime(LockTime::from_time(1_700_000_000).unwrap());
+        assert!(!tx.is_final(u32::MAX, 1_700_000_000));
+        assert!(tx.is_final(0, 1_700_000_001));
+
+        tx.set_locktime(LockTime::default());
+        assert!(tx.is_final(0, 0));
+
+        // final sequences disable the locktime
+        tx.set_locktime(LockTime::Blocks(410393));
+        tx.inputs[0].sequence = Sequence::MAX;
+        assert!(tx.is_final(0, 0));
+    }
+
+    #[test]
+    fn test_relative_locks() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        tx.inputs[0].sequence = Sequence::from_height(10);
+
+        // version 1 transactions aren't subject to BIP68
+        assert!(tx.relative_locks_satisfied(&[(0, 0)]).unwrap());
+
+        tx.version = Version::new(2);
+        assert!(!tx.relative_locks_satisfied(&[(9, u32::MAX)]).unwrap());
+        assert!(tx.relative_locks_satisfied(&[(10, 0)]).unwrap());
+
+        tx.inputs[0].sequence = Sequence::from_512_second_intervals(1);
+        assert!(!tx.relative_locks_satisfied(&[(100, 511)]).unwrap());
+        assert!(tx.relative_locks_satisfied(&[(0, 512)]).unwrap());
+
+        // the original sequence has the disable flag set
+        tx.inputs[0].sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+        assert!(tx.relative_locks_satisfied(&[(0, 0)]).unwrap());
+
+        assert!(matches!(tx.relative_locks_satisfied(&[]), Err(TransactionError::PrevoutsLengthMismatch(0))));
+    }
+
+    #[test]
+    fn test_set_cltv_locktime() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        tx.inputs[0].sequence = Sequence::MAX;
+
+        tx.set_cltv_locktime(0, LockTime::Blocks(500));
+        assert_eq!(tx.locktime(), LockTime::Blocks(500));
+        assert_eq!(tx.inputs[0].sequence, Sequence::ENABLE_LOCKTIME_NO_RBF);
+        assert!(tx.locktime().satisfies_cltv(LockTime::Blocks(500)));
+        assert!(tx.serialize().ends_with(&500u32.to_le_bytes()));
+    }
+}
@@ -0,0 +1,241 @@
+use scripts::{Command, Script, ScriptType};
+
+use crate::{
+    policy::is_witness_program,
+    utxo::UtxoProvider,
+    Transaction, TransactionError,
+};
+
+/// Half the order of secp256k1: an s above it has a twin, n - s, that is just as valid
+const HALF_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x5d, 0x57, 0x6e,
+    0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// A way someone other than the signers can change a signed transaction's script_sigs,
+/// and so its txid, while it stays valid
+#[derive(Debug, PartialEq, Eq)]
+pub enum Malleability {
+    /// A signature in the script_sig of the input with this index isn't strict DER (BIP66)
+    NonDerSignature(usize),
+    /// A signature in the script_sig of the input with this index has an s in the upper half
+    /// of the order, which can be swapped for n - s (BIP62)
+    HighS(usize),
+    /// The script_sig of the input with this index does more than push data
+    NotPushOnly(usize),
+    /// A push in the script_sig of the input with this index doesn't use the shortest encoding
+    NonMinimalPush(usize),
+    /// The script_sig of the input with this index pushes more items than the output it spends needs,
+    /// the extra ones can be changed or dropped
+    ExtraPushes(usize),
+}
+
+impl Transaction {
+    /// Lists what a third party could change in the script_sigs without invalidating the transaction.
+    /// Witnesses aren't checked: changing them changes the wtxid, not the txid.
+    ///
+    /// The outputs spent are looked up in `utxos`, to know how many items their script_sigs need.
+    /// Pushes that start like a DER signature (0x30) are checked as signatures.
+    pub fn malleability(&self, utxos: &impl UtxoProvider) -> Result<Vec<Malleability>, TransactionError> {
+        let mut malleabilities = vec![];
+
+        for (index, input) in self.inputs.iter().enumerate() {
+            let prevout = utxos
+                .get_output(&input.prev_txid, input.prev_index)
+                .ok_or(TransactionError::MissingPrevout(index))?;
+            let commands = input.script_sig.commands();
+
+            let pushes: Vec<&[u8]> = commands
+                .iter()
+                .filter_map(|command| match command {
+                    Command::Push(data) => Some(data.as_slice()),
+                    _ => None,
+                })
+                .collect();
+            for signature in pushes.iter().filter(|push| push.first() == Some(&0x30)) {
+                if !is_strict_der(signature) {
+                    malleabilities.push(Malleability::NonDerSignature(index));
+                } else if is_high_s(signature) {
+                    malleabilities.push(Malleability::HighS(index));
+                }
+            }
+
+            // OP_0 and OP_1NEGATE to OP_16 push numbers
+            let push_only = commands
+                .iter()
+                .all(|command| matches!(command, Command::Push(_) | Command::Op(0x00 | 0x4f..=0x60)));
+            if !push_only {
+                malleabilities.push(Malleability::NotPushOnly(index));
+            }
+            if Script::from_commands(commands.to_vec()).as_bytes() != input.script_sig.as_bytes() {
+                malleabilities.push(Malleability::NonMinimalPush(index));
+            }
+
+            let expected = expected_pushes(&prevout.script_pubkey, &input.script_sig);
+            if expected.is_some_and(|expected| commands.len() > expected) {
+                malleabilities.push(Malleability::ExtraPushes(index));
+            }
+        }
+
+        Ok(malleabilities)
+    }
+
+    /// Whether the txid is settled once the transaction is signed, as every input is a segwit spend:
+    /// it has a witness, and a script_sig that is empty or only pushes the witness program of a P2SH
+    /// output. Protocols that spend outputs of unconfirmed transactions, like payment channels, rely on it.
+    pub fn has_witness_independent_txid(&self) -> bool {
+        self.inputs.iter().all(|input| {
+            let script_sig = input.script_sig.as_bytes();
+            let wrapped_program = match input.script_sig.commands() {
+                [Command::Push(program)] => is_witness_program(program) && script_sig[1..] == program[..],
+                _ => false,
+            };
+
+            !input.witness.is_empty() && (script_sig.is_empty() || wrapped_program)
+        })
+    }
+}
+
+/// The number of items the script_sig spending the output needs, for the types where that's fixed
+fn expected_pushes(script_pubkey: &Script, script_sig: &Script) -> Option<usize> {
+    match script_pubkey.classify() {
+        ScriptType::P2PK => Some(1),
+        ScriptType::P2PKH => Some(2),
+        // the dummy element OP_CHECKMULTISIG pops, then the signatures
+        ScriptType::Multisig { required, .. } => Some(1 + required as usize),
+        ScriptType::P2WPKH | ScriptType::P2WSH | ScriptType::P2TR => Some(0),
+        ScriptType::P2SH => match script_sig.commands().last() {
+            Some(Command::Push(redeem_script)) if is_witness_program(redeem_script) => Some(1),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether the signature, with its hash type byte, follows the strict DER rules of BIP66
+fn is_strict_der(signature: &[u8]) -> bool {
+    // 0x30, the length, then 0x02, length and value for r and s
+    let length = signature.len();
+    if !(9..=73).contains(&length) || signature[0] != 0x30 || signature[1] as usize != length - 3 {
+        return false;
+    }
+
+    let r_length = signature[3] as usize;
+    if 5 + r_length >= length {
+        return false;
+    }
+    let s_length = signature[5 + r_length] as usize;
+    if r_length + s_length + 7 != length {
+        return false;
+    }
+
+    let is_valid_integer = |start: usize, integer_length: usize| {
+        // positive, and without padding unless the next byte would make it negative
+        signature[start - 2] == 0x02
+            && integer_length > 0
+            && signature[start] & 0x80 == 0
+            && !(integer_length > 1 && signature[start] == 0x00 && signature[start + 1] & 0x80 == 0)
+    };
+    is_valid_integer(4, r_length) && is_valid_integer(6 + r_length, s_length)
+}
+
+/// Whether s is in the upper half of the order, for a strict DER signature
+fn is_high_s(signature: &[u8]) -> bool {
+    let r_length = signature[3] as usize;
+    let s_length = signature[5 + r_length] as usize;
+    let s = &signature[6 + r_length..6 + r_length + s_length];
+
+    // drop the padding byte, then compare as 32-byte big-endian numbers
+    let s = s.strip_prefix(&[0x00]).unwrap_or(s);
+    if s.len() > 32 {
+        return true;
+    }
+    let mut padded = [0u8; 32];
+    padded[32 - s.len()..].copy_from_slice(s);
+    padded > HALF_ORDER
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ec_cryptography::helper::from_hex;
+
+    use super::*;
+    use crate::{amount::Amount, fixtures::RAW_TX, output::TxOut, txid::Txid, witness::Witness};
+
+    /// The P2PKH spend of chapter 5, with the output it spends
+    fn p2pkh_spend() -> (Transaction, HashMap<(Txid, u32), TxOut>) {
+        let tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        let script_pubkey = Script::from_hex("76a914a802fc56c704ce87c42d7c92eb75e7896bdc41ae88ac").unwrap();
+        let utxos = HashMap::from([((tx.inputs[0].prev_txid, 0), TxOut::new(Amount::from_sat(42505594), script_pubkey))]);
+        (tx, utxos)
+    }
+
+    #[test]
+    fn test_malleability() {
+        let (mut tx, utxos) = p2pkh_spend();
+        assert_eq!(tx.malleability(&utxos).unwrap(), vec![]);
+        assert!(!tx.has_witness_independent_txid());
+
+        let commands = tx.inputs[0].script_sig.commands().to_vec();
+        let (Command::Push(signature), Command::Push(sec)) = (&commands[0], &commands[1]) else {
+            panic!("expected a signature and a key");
+        };
+
+        // an extra push in front still leaves the signature and key on top of the stack
+        let mut extra = vec![Command::Op(0x51)];
+        extra.extend(commands.clone());
+        tx.inputs[0].script_sig = Script::from_commands(extra);
+        assert_eq!(tx.malleability(&utxos).unwrap(), vec![Malleability::ExtraPushes(0)]);
+
+        // the signature pushed with OP_PUSHDATA1 instead of its length
+        let mut bytes = vec![0x4c, signature.len() as u8];
+        bytes.extend_from_slice(signature);
+        bytes.push(sec.len() as u8);
+        bytes.extend_from_slice(sec);
+        tx.inputs[0].script_sig = Script::from_bytes(bytes);
+        assert_eq!(tx.malleability(&utxos).unwrap(), vec![Malleability::NonMinimalPush(0)]);
+
+        tx.inputs[0].script_sig = Script::from_commands(vec![Command::Op(0x61), Command::Push(sec.clone())]);
+        assert_eq!(tx.malleability(&utxos).unwrap(), vec![Malleability::NotPushOnly(0)]);
+
+        assert!(matches!(tx.malleability(&HashMap::new()), Err(TransactionError::MissingPrevout(0))));
+    }
+
+    #[test]
+    fn test_signature_encoding() {
+        // the low-s signature above and its n - s twin
+        let low_s = from_hex("3045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01").unwrap();
+        let high_s = from_hex("3046022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f0221008567926aa391f34ca2bb95762c0a9efdc5d6e66ead8586d44b97c1febfd4e55401").unwrap();
+        assert!(is_strict_der(&low_s) && !is_high_s(&low_s));
+        assert!(is_strict_der(&high_s) && is_high_s(&high_s));
+
+        // r padded with a zero byte it doesn't need
+        let padded_r = from_hex("304602220000ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01").unwrap();
+        assert!(!is_strict_der(&padded_r));
+        // the wrong total length
+        let mut wrong_length = low_s.clone();
+        wrong_length[1] += 1;
+        assert!(!is_strict_der(&wrong_length));
+    }
+
+    #[test]
+    fn test_witness_independent_txid() {
+        let (mut tx, _) = p2pkh_spend();
+        tx.inputs[0].witness = Witness::p2wpkh(vec![0x30; 72], vec![0x02; 33]);
+        // the script_sig isn't cleared
+        assert!(!tx.has_witness_independent_txid());
+
+        tx.inputs[0].script_sig = Script::default();
+        assert!(tx.has_witness_independent_txid());
+
+        // P2SH-P2WPKH
+        let redeem_script = from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        tx.inputs[0].script_sig = Script::from_commands(vec![Command::Push(redeem_script)]);
+        assert!(tx.has_witness_independent_txid());
+
+        tx.inputs[0].witness = Witness::new();
+        assert!(!tx.has_witness_independent_txid());
+    }
+}
@@ -1,59 +1,131 @@
-use serde::{Deserialize};
+use std::io::{self, Read, Write};
 
-use crate::utils::parse_varints;
+use ec_cryptography::{
+    consensus::{ConsensusDecode, ConsensusEncode},
+    helper::to_hex,
+};
+use scripts::{Command, Script};
 
-#[derive(Default, Debug, Deserialize)]
+use crate::{amount::Amount, decode_prefix, policy::MAX_OP_RETURN_DATA, ParseError, TransactionError};
+
+const OP_RETURN: u8 = 0x6a;
+
+#[derive(Default, Debug, Clone)]
 pub struct TxOut {
-    pub value: u64,
-    pub script_pubkey: String,
+    pub value: Amount,
+    pub script_pubkey: Script,
 }
 
 impl TxOut {
-    pub fn new(value: u64, script_pubkey: String) -> TxOut {
+    pub fn new(value: Amount, script_pubkey: Script) -> TxOut {
         TxOut {
             value,
             script_pubkey,
         }
     }
 
+    /// An unspendable output of no value that embeds the data: OP_RETURN followed by a push of it.
+    /// Nodes only relay those with up to MAX_OP_RETURN_DATA bytes, so more is refused.
+    pub fn new_op_return(data: &[u8]) -> Result<TxOut, TransactionError> {
+        if data.len() > MAX_OP_RETURN_DATA {
+            return Err(TransactionError::OpReturnTooLarge(data.len()));
+        }
+
+        let script_pubkey = Script::from_commands(vec![Command::Op(OP_RETURN), Command::Push(data.to_vec())]);
+        Ok(TxOut::new(Amount::ZERO, script_pubkey))
+    }
+
+    /// The weight the output adds to a transaction, four times its size as outputs have no witness
+    pub fn weight(&self) -> u64 {
+        (8 + self.script_pubkey.serialized_size()) as u64 * 4
+    }
+
     pub fn serialize(&self) -> String {
         let mut serialized = String::from("");
 
         // serialize the amount
-        let value = self.value
-            .to_le_bytes()
-            .iter()
-            .map(|byte| format!("{:02x}", byte))
-            .collect::<String>();
-        serialized.push_str(&value);
+        serialized.push_str(&to_hex(&self.value.to_sat().to_le_bytes()));
 
         // serialize the script_pubkey
-        serialized.push_str(&self.script_pubkey);
+        serialized.push_str(&self.script_pubkey.serialize());
 
         serialized
     }
 
-    pub fn parse_from_bytes(bytes: &[u8]) -> Vec<TxOut> {
-        let mut txs = vec![];
-        let mut i = 0;
-        while i < bytes.len() {
-            let value = u64::from_le_bytes([
-                bytes[i],
-                bytes[i + 1],
-                bytes[i + 2],
-                bytes[i + 3],
-                bytes[i + 4],
-                bytes[i + 5],
-                bytes[i + 6],
-                bytes[i + 7],
-            ]);
-            i += 8;
-            let (byte_count, script_pubkey_length) = parse_varints(&bytes, i);
-            i += byte_count;
-            let script_pubkey = hex::encode(&bytes[i..(i + script_pubkey_length as usize)]);
-            i += script_pubkey_length as usize;
-            txs.push(TxOut::new(value, script_pubkey));
-        }
-        txs
+    /// Parses `count` outputs from the start of the bytes.
+    /// Returns them with the number of bytes they took up, as they are followed by the locktime
+    /// (and the witnesses for segwit transactions).
+    pub fn parse(bytes: &[u8], count: usize) -> Result<(Vec<TxOut>, usize), ParseError> {
+        decode_prefix(bytes, |cursor| (0..count).map(|_| TxOut::consensus_decode(cursor)).collect())
     }
-}
\ No newline at end of file
+
+    /// Reads an output from a stream: an 8-byte little-endian amount followed by the script_pubkey
+    pub fn parse_stream<R: Read>(reader: &mut R) -> io::Result<TxOut> {
+        TxOut::consensus_decode(reader)
+    }
+
+    /// Writes the output to a stream in the same format `parse_stream` reads
+    pub fn serialize_stream<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.consensus_encode(writer).map(|_| ())
+    }
+}
+
+impl ConsensusEncode for TxOut {
+    /// An 8-byte little-endian amount followed by the script_pubkey
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.value.consensus_encode(writer)? + self.script_pubkey.consensus_encode(writer)?)
+    }
+}
+
+impl ConsensusDecode for TxOut {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let value = Amount::consensus_decode(reader)?;
+        let script_pubkey = Script::consensus_decode(reader)?;
+
+        Ok(TxOut::new(value, script_pubkey))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::from_hex;
+
+    use super::*;
+    use crate::policy::MAX_OP_RETURN_RELAY;
+
+    #[test]
+    fn test_parse_with_count() {
+        // two outputs followed by the locktime
+        let bytes = from_hex("a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600").unwrap();
+
+        let (outputs, consumed) = TxOut::parse(&bytes, 2).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].value, Amount::from_sat(32454049));
+        assert_eq!(outputs[1].script_pubkey.to_hex(), "76a9141c4bc762dd5423e332166702cb75f40df79fea1288ac");
+        assert_eq!(consumed, bytes.len() - 4);
+        assert_eq!(bytes[consumed..], [0x19, 0x43, 0x06, 0x00]);
+
+        let (outputs, consumed) = TxOut::parse(&bytes, 1).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(consumed, 34);
+        assert_eq!(outputs[0].weight(), 34 * 4);
+
+        // the third output would start where the bytes end
+        assert_eq!(TxOut::parse(&bytes, 3).unwrap_err().offset, bytes.len());
+    }
+
+    #[test]
+    fn test_new_op_return() {
+        let output = TxOut::new_op_return(b"hello").unwrap();
+        assert_eq!(output.value, Amount::ZERO);
+        assert_eq!(output.script_pubkey.to_hex(), "6a0568656c6c6f");
+        assert_eq!(output.dust_threshold(), Amount::ZERO);
+
+        // past 75 bytes the push needs OP_PUSHDATA1
+        let output = TxOut::new_op_return(&[0xab; 80]).unwrap();
+        assert_eq!(output.script_pubkey.as_bytes()[..3], [0x6a, 0x4c, 80]);
+        assert_eq!(output.script_pubkey.len(), MAX_OP_RETURN_RELAY);
+
+        assert!(matches!(TxOut::new_op_return(&[0; 81]), Err(TransactionError::OpReturnTooLarge(81))));
+    }
+}
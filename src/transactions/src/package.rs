@@ -0,0 +1,129 @@
+use crate::{amount::Amount, output::TxOut, txid::Txid, utxo::UtxoProvider, Transaction, TransactionError};
+
+/// A transaction and a child spending one of its outputs. Miners consider them together,
+/// so a child paying a high fee can get a parent that pays too little mined (child pays for parent).
+#[derive(Debug, Clone)]
+pub struct TxPackage {
+    pub parent: Transaction,
+    pub child: Transaction,
+}
+
+impl TxPackage {
+    pub fn new(parent: Transaction, child: Transaction) -> Result<TxPackage, TransactionError> {
+        let parent_txid = parent.txid();
+        if !child.inputs.iter().any(|input| input.prev_txid == parent_txid) {
+            return Err(TransactionError::NotAChild);
+        }
+
+        Ok(TxPackage { parent, child })
+    }
+
+    pub fn weight(&self) -> u64 {
+        self.parent.weight() + self.child.weight()
+    }
+
+    /// The virtual sizes of both transactions added up, as Core counts packages
+    pub fn vsize(&self) -> u64 {
+        self.parent.vsize() + self.child.vsize()
+    }
+
+    /// The fees of both transactions. The outputs the parent spends, and those the child spends
+    /// other than the parent's, are looked up in `utxos`.
+    pub fn fee(&self, utxos: &impl UtxoProvider) -> Result<Amount, TransactionError> {
+        let parent_fee = self.parent.fee(utxos)?;
+        let with_parent = WithParent { parent_txid: self.parent.txid(), parent: &self.parent, utxos };
+        let child_fee = self.child.fee(&with_parent)?;
+
+        parent_fee.checked_add(child_fee).ok_or(TransactionError::ValueOverflow)
+    }
+
+    /// The feerate in sat/vB of the package as a whole, which is what the child pays for its parent
+    pub fn fee_rate(&self, utxos: &impl UtxoProvider) -> Result<f64, TransactionError> {
+        Ok(self.fee(utxos)?.to_sat() as f64 / self.vsize() as f64)
+    }
+
+    /// The fee the child has to pay for the package to reach `feerate` sat/vB, given what the parent pays
+    pub fn child_fee_for(&self, feerate: u64, utxos: &impl UtxoProvider) -> Result<Amount, TransactionError> {
+        let package_fee = Amount::from_sat(feerate)
+            .checked_mul(self.vsize())
+            .ok_or(TransactionError::ValueOverflow)?;
+        let parent_fee = self.parent.fee(utxos)?;
+
+        Ok(package_fee.checked_sub(parent_fee).unwrap_or(Amount::ZERO))
+    }
+}
+
+/// The outputs of the parent, on top of the ones `utxos` knows about
+struct WithParent<'a, P: UtxoProvider> {
+    parent_txid: Txid,
+    parent: &'a Transaction,
+    utxos: &'a P,
+}
+
+impl<P: UtxoProvider> UtxoProvider for WithParent<'_, P> {
+    fn get_output(&self, txid: &Txid, index: u32) -> Option<TxOut> {
+        if *txid == self.parent_txid {
+            self.parent.outputs.get(index as usize).cloned()
+        } else {
+            self.utxos.get_output(txid, index)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use scripts::Script;
+
+    use super::*;
+    use crate::{
+        fixtures::RAW_TX,
+        input::{OutPoint, Sequence, TxIn},
+    };
+
+    /// The parent pays 40,000 sats, and the child spending its second output 20,000
+    fn package() -> (TxPackage, HashMap<(Txid, u32), TxOut>) {
+        let parent = Transaction::parse_hex(RAW_TX, false).unwrap();
+        let utxos = HashMap::from([(
+            (parent.inputs[0].prev_txid, 0),
+            TxOut::new(Amount::from_sat(42505594), Script::default()),
+        )]);
+
+        let input = TxIn::from_outpoint(OutPoint::new(parent.txid(), 1), Sequence::MAX);
+        let output = TxOut::new(Amount::from_sat(10011545 - 20000), parent.outputs[1].script_pubkey.clone());
+        let child = Transaction { inputs: vec![input], outputs: vec![output], ..Default::default() };
+
+        (TxPackage::new(parent, child).unwrap(), utxos)
+    }
+
+    #[test]
+    fn test_package_fee_rate() {
+        let (package, utxos) = package();
+        assert_eq!(package.vsize(), package.parent.vsize() + package.child.vsize());
+        assert_eq!(package.weight(), package.parent.weight() + package.child.weight());
+
+        assert_eq!(package.fee(&utxos).unwrap(), Amount::from_sat(60000));
+        assert_eq!(package.fee_rate(&utxos).unwrap(), 60000.0 / package.vsize() as f64);
+        assert!(package.fee_rate(&utxos).unwrap() > package.parent.fee_rate(Amount::from_sat(40000)));
+
+        assert!(matches!(package.fee(&HashMap::new()), Err(TransactionError::MissingPrevout(0))));
+    }
+
+    #[test]
+    fn test_child_fee_for() {
+        let (package, utxos) = package();
+        let vsize = package.vsize();
+
+        assert_eq!(package.child_fee_for(200, &utxos).unwrap(), Amount::from_sat(200 * vsize - 40000));
+        // the parent alone pays enough
+        assert_eq!(package.child_fee_for(1, &utxos).unwrap(), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_not_a_child() {
+        let (package, _) = package();
+        let unrelated = package.parent.clone();
+        assert!(matches!(TxPackage::new(package.parent, unrelated), Err(TransactionError::NotAChild)));
+    }
+}
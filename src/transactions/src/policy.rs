@@ -0,0 +1,205 @@
+use ec_cryptography::consensus::ConsensusEncode;
+use scripts::{Command, ScriptType};
+
+use crate::{amount::Amount, output::TxOut, Transaction};
+
+/// The heaviest transaction nodes relay, a tenth of a block
+pub const MAX_STANDARD_TX_WEIGHT: u64 = 400_000;
+/// Transactions of 64 bytes or less without witnesses could pass for an inner node of the merkle tree
+pub const MIN_STANDARD_TX_NONWITNESS_SIZE: usize = 65;
+/// Large enough for a P2SH spend of a 15-of-15 multisig with compressed keys
+pub const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1650;
+/// The largest OP_RETURN script_pubkey relayed: OP_RETURN, a PUSHDATA1 and 80 bytes of data
+pub const MAX_OP_RETURN_RELAY: usize = 83;
+/// The most data an OP_RETURN output can carry and still be relayed
+pub const MAX_OP_RETURN_DATA: usize = MAX_OP_RETURN_RELAY - 3;
+/// Bare multisig outputs may have at most this many keys
+pub const MAX_BARE_MULTISIG_KEYS: u8 = 3;
+/// The highest standard version, 3 being the TRUC transactions of BIP431
+pub const MAX_STANDARD_VERSION: u32 = 3;
+/// The feerate (sat/vB) below which spending an output costs more than it's worth
+pub const DUST_RELAY_FEE_RATE: u64 = 3;
+
+const OP_RETURN: u8 = 0x6a;
+const OP_16: u8 = 0x60;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PolicyViolation {
+    /// The version is 0 or above MAX_STANDARD_VERSION
+    Version(u32),
+    /// The transaction weighs more than MAX_STANDARD_TX_WEIGHT
+    TxTooLarge(u64),
+    /// Without its witnesses the transaction is smaller than MIN_STANDARD_TX_NONWITNESS_SIZE
+    TxTooSmall(usize),
+    /// The script_sig of the input with this index is larger than MAX_STANDARD_SCRIPTSIG_SIZE
+    ScriptSigTooLarge(usize),
+    /// The script_sig of the input with this index does more than push data
+    ScriptSigNotPushOnly(usize),
+    /// The script_pubkey of the output with this index isn't a standard type
+    NonstandardScriptPubKey(usize),
+    /// The output with this index is a bare multisig with more than MAX_BARE_MULTISIG_KEYS keys
+    BareMultisigTooLarge(usize),
+    /// The OP_RETURN output with this index is larger than MAX_OP_RETURN_RELAY
+    NullDataTooLarge(usize),
+    /// More than one output is an OP_RETURN
+    MultipleNullData,
+    /// The output with this index is worth less than spending it would cost
+    Dust(usize),
+}
+
+/// Whether the script_pubkey is a segwit output: a version opcode followed by a 2 to 40-byte program
+pub(crate) fn is_witness_program(bytes: &[u8]) -> bool {
+    match bytes {
+        [0x00 | 0x51..=OP_16, length, ..] => bytes.len() == 2 + *length as usize && (2..=40).contains(length),
+        _ => false,
+    }
+}
+
+impl TxOut {
+    /// The smallest value the output can have without being dust (Core's GetDustThreshold): the fee,
+    /// at DUST_RELAY_FEE_RATE, for the output itself and for an input spending it.
+    /// That is 546 satoshis for P2PKH and 294 for P2WPKH. OP_RETURN outputs can't be spent, so they can be worth 0.
+    pub fn dust_threshold(&self) -> Amount {
+        let script = self.script_pubkey.as_bytes();
+        if script.first() == Some(&OP_RETURN) {
+            return Amount::ZERO;
+        }
+
+        // the outpoint, script_sig length and sequence, then a signature and a key in the witness or the script_sig
+        let spend_size = if is_witness_program(script) { 32 + 4 + 1 + 107 / 4 + 4 } else { 32 + 4 + 1 + 107 + 4 };
+        let size = self.consensus_serialize().len() as u64 + spend_size;
+        Amount::from_sat(size * DUST_RELAY_FEE_RATE)
+    }
+
+    pub fn is_dust(&self) -> bool {
+        self.value < self.dust_threshold()
+    }
+}
+
+impl Transaction {
+    /// Checks the rules nodes apply before relaying a transaction, on top of consensus (Core's IsStandardTx):
+    /// its version, size and weight, that the script_sigs only push data, and that the outputs are of
+    /// a standard type, with at most one OP_RETURN and no dust.
+    ///
+    /// Like `verify`, every rule broken is returned. The spent outputs and the witnesses aren't checked.
+    pub fn check_standard(&self) -> Result<(), Vec<PolicyViolation>> {
+        let mut violations = vec![];
+
+        let version = self.version.to_u32();
+        if version == 0 || version > MAX_STANDARD_VERSION {
+            violations.push(PolicyViolation::Version(version));
+        }
+        let weight = self.weight();
+        if weight > MAX_STANDARD_TX_WEIGHT {
+            violations.push(PolicyViolation::TxTooLarge(weight));
+        }
+        let size = self.serialize_legacy().len();
+        if size < MIN_STANDARD_TX_NONWITNESS_SIZE {
+            violations.push(PolicyViolation::TxTooSmall(size));
+        }
+
+        for (index, input) in self.inputs.iter().enumerate() {
+            if input.script_sig.len() > MAX_STANDARD_SCRIPTSIG_SIZE {
+                violations.push(PolicyViolation::ScriptSigTooLarge(index));
+            }
+            // OP_0, the pushes and OP_1NEGATE to OP_16
+            let push_only = input
+                .script_sig
+                .commands()
+                .iter()
+                .all(|command| matches!(command, Command::Push(_) | Command::Op(0x00 | 0x4f..=OP_16)));
+            if !push_only {
+                violations.push(PolicyViolation::ScriptSigNotPushOnly(index));
+            }
+        }
+
+        let mut null_data_outputs = 0;
+        for (index, output) in self.outputs.iter().enumerate() {
+            match output.script_pubkey.classify() {
+                ScriptType::NonStandard => violations.push(PolicyViolation::NonstandardScriptPubKey(index)),
+                ScriptType::NullData => {
+                    null_data_outputs += 1;
+                    if output.script_pubkey.len() > MAX_OP_RETURN_RELAY {
+                        violations.push(PolicyViolation::NullDataTooLarge(index));
+                    }
+                }
+                ScriptType::Multisig { keys, .. } if keys > MAX_BARE_MULTISIG_KEYS => {
+                    violations.push(PolicyViolation::BareMultisigTooLarge(index));
+                }
+                _ => {}
+            }
+            if output.is_dust() {
+                violations.push(PolicyViolation::Dust(index));
+            }
+        }
+        if null_data_outputs > 1 {
+            violations.push(PolicyViolation::MultipleNullData);
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scripts::Script;
+
+    use super::*;
+    use crate::{fixtures::RAW_TX, version::Version};
+
+    fn output(value: u64, script_pubkey: &str) -> TxOut {
+        TxOut::new(Amount::from_sat(value), Script::from_hex(script_pubkey).unwrap())
+    }
+
+    #[test]
+    fn test_dust_threshold() {
+        assert_eq!(output(0, "76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").dust_threshold(), Amount::from_sat(546));
+        assert_eq!(output(0, "00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").dust_threshold(), Amount::from_sat(294));
+        assert_eq!(output(0, "6a0b68656c6c6f20776f726c64").dust_threshold(), Amount::ZERO);
+
+        assert!(output(293, "00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").is_dust());
+        assert!(!output(294, "00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").is_dust());
+    }
+
+    #[test]
+    fn test_check_standard() {
+        let tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        assert_eq!(tx.check_standard(), Ok(()));
+
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        tx.version = Version::new(4);
+        // OP_DUP in the script_sig
+        tx.inputs[0].script_sig = Script::from_hex("017676").unwrap();
+        tx.outputs = vec![
+            output(1000, "51"),
+            output(100, "76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac"),
+            output(0, &format!("6a4c51{}", "00".repeat(81))),
+            output(0, "6a0b68656c6c6f20776f726c64"),
+            // 1-of-4
+            output(1000, &format!("51{}54ae", "2103935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b67".repeat(4))),
+        ];
+
+        assert_eq!(
+            tx.check_standard(),
+            Err(vec![
+                PolicyViolation::Version(4),
+                PolicyViolation::ScriptSigNotPushOnly(0),
+                PolicyViolation::NonstandardScriptPubKey(0),
+                PolicyViolation::Dust(1),
+                PolicyViolation::NullDataTooLarge(2),
+                PolicyViolation::BareMultisigTooLarge(4),
+                PolicyViolation::MultipleNullData,
+            ])
+        );
+
+        let mut tiny = Transaction::parse_hex(RAW_TX, false).unwrap();
+        tiny.inputs[0].script_sig = Script::default();
+        tiny.outputs.truncate(1);
+        tiny.outputs[0] = output(1000, "6a");
+        assert_eq!(tiny.check_standard(), Err(vec![PolicyViolation::TxTooSmall(61)]));
+    }
+}
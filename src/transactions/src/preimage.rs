@@ -0,0 +1,281 @@
+use ec_cryptography::{
+    helper::{hash160, hash256, Hasher},
+    serializer::parse_sec,
+    verify_schnorr, Signature,
+};
+use rug::{integer::Order, Integer};
+use scripts::{Command, Script};
+
+use crate::{
+    sighash::SighashType, sign::p2wpkh_script, utxo::UtxoProvider, witness::Witness, Transaction, TransactionError,
+};
+
+/// How the output an input spends is signed. It picks the preimage format, and where the signature goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpendKind {
+    /// A legacy preimage, and a script_sig with the signature
+    P2pk,
+    /// A legacy preimage, and a script_sig with the signature and the key
+    P2pkh,
+    /// A BIP143 preimage, and a witness with the signature and the key
+    P2wpkh,
+    /// As P2WPKH, with the witness program pushed by the script_sig
+    P2shP2wpkh,
+    /// A BIP341 preimage, and a witness with the signature alone
+    P2trKeyPath,
+}
+
+/// What an external signer, like a hardware wallet or an HSM, needs to sign an input:
+/// the exact message, and what to sign it with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SighashPreimage {
+    pub input_index: usize,
+    pub spend_kind: SpendKind,
+    /// The key expected to sign: its SEC for ECDSA spends, the x-only output key for taproot
+    pub public_key: Vec<u8>,
+    /// `None` is SIGHASH_DEFAULT, which only taproot has
+    pub sighash_type: Option<SighashType>,
+    /// The legacy, BIP143 or BIP341 message
+    pub preimage: Vec<u8>,
+}
+
+impl SighashPreimage {
+    /// The hash that gets signed: the hash256 of the preimage for ECDSA, its TapSighash tagged hash for taproot
+    pub fn sighash(&self) -> [u8; 32] {
+        let hash = match self.spend_kind {
+            SpendKind::P2trKeyPath => Hasher::tagged("TapSighash").chain(&self.preimage).finalize(),
+            _ => hash256(&self.preimage),
+        };
+        hash.try_into().expect("the sighash is 32 bytes")
+    }
+}
+
+impl Transaction {
+    /// Exports what `public_key` has to sign to spend the input, instead of signing it here.
+    /// Once signed, [`Transaction::add_signature`] puts the signature in the transaction.
+    ///
+    /// The output spent is looked up in `utxos` (all of them for taproot) and must pay to the key:
+    /// to its SEC or the hash of it, or to it as the output key of a taproot output.
+    /// A `sighash_type` of `None` is SIGHASH_DEFAULT for taproot, and ALL for the others.
+    pub fn sighash_preimage(
+        &self,
+        input_index: usize,
+        public_key: &[u8],
+        sighash_type: Option<SighashType>,
+        utxos: &impl UtxoProvider,
+    ) -> Result<SighashPreimage, TransactionError> {
+        let input = self
+            .inputs
+            .get(input_index)
+            .ok_or(TransactionError::InputIndexOutOfRange(input_index))?;
+        let prevout = utxos
+            .get_output(&input.prev_txid, input.prev_index)
+            .ok_or(TransactionError::MissingPrevout(input_index))?;
+        let script_pubkey = &prevout.script_pubkey;
+        let key_hash = hash160(public_key);
+
+        let spend_kind = match script_pubkey.as_bytes() {
+            [0x41 | 0x21, key @ .., 0xac] if key == public_key => SpendKind::P2pk,
+            [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] if *hash == key_hash => SpendKind::P2pkh,
+            [0x00, 0x14, hash @ ..] if *hash == key_hash && public_key.len() == 33 => SpendKind::P2wpkh,
+            [0xa9, 0x14, hash @ .., 0x87] if *hash == hash160(&p2wpkh_script(&key_hash)) && public_key.len() == 33 => {
+                SpendKind::P2shP2wpkh
+            }
+            [0x51, 0x20, output_key @ ..] if output_key == public_key => SpendKind::P2trKeyPath,
+            _ => return Err(TransactionError::KeyMismatch),
+        };
+
+        let (sighash_type, preimage) = match spend_kind {
+            SpendKind::P2pk | SpendKind::P2pkh => {
+                let sighash_type = sighash_type.unwrap_or(SighashType::All);
+                (Some(sighash_type), self.legacy_preimage(input_index, script_pubkey, sighash_type)?)
+            }
+            SpendKind::P2wpkh | SpendKind::P2shP2wpkh => {
+                let sighash_type = sighash_type.unwrap_or(SighashType::All);
+                let script_code = Script::p2pkh(key_hash.as_slice().try_into().expect("hash160 is 20 bytes"));
+                let preimage = self.segwit_v0_preimage(input_index, &script_code, prevout.value, sighash_type)?;
+                (Some(sighash_type), preimage)
+            }
+            SpendKind::P2trKeyPath => {
+                let prevouts = self.spent_outputs(utxos)?;
+                (sighash_type, self.taproot_preimage(input_index, &prevouts, sighash_type, None, None)?)
+            }
+        };
+
+        Ok(SighashPreimage {
+            input_index,
+            spend_kind,
+            public_key: public_key.to_vec(),
+            sighash_type,
+            preimage,
+        })
+    }
+
+    /// Puts the signature an external signer made for `preimage` in the script_sig or witness of its input.
+    /// `signature` is DER for ECDSA and 64 bytes for Schnorr, without the hash type byte, which gets appended.
+    ///
+    /// The signature is checked against the key and the sighash first, so a bad one never gets in.
+    pub fn add_signature(&mut self, preimage: &SighashPreimage, signature: &[u8]) -> Result<(), TransactionError> {
+        if preimage.input_index >= self.inputs.len() {
+            return Err(TransactionError::InputIndexOutOfRange(preimage.input_index));
+        }
+        let sighash = preimage.sighash();
+        let public_key = preimage.public_key.clone();
+
+        let is_valid = match preimage.spend_kind {
+            SpendKind::P2trKeyPath => match (public_key.as_slice().try_into(), signature.try_into()) {
+                (Ok(key), Ok(signature)) => verify_schnorr(key, &sighash, signature),
+                _ => false,
+            },
+            _ => match (Signature::parse_der(signature), parse_sec(&public_key)) {
+                (Some(signature), Some(point)) => {
+                    signature.verify(&point, Integer::from_digits(&sighash, Order::MsfBe))
+                }
+                _ => false,
+            },
+        };
+        if !is_valid {
+            return Err(TransactionError::InvalidSignature);
+        }
+
+        let mut sig = signature.to_vec();
+        if let Some(sighash_type) = preimage.sighash_type {
+            sig.push(sighash_type.to_u32() as u8);
+        }
+
+        let input = &mut self.inputs[preimage.input_index];
+        match preimage.spend_kind {
+            SpendKind::P2pk => input.script_sig = Script::from_commands(vec![Command::Push(sig)]),
+            SpendKind::P2pkh => {
+                input.script_sig = Script::from_commands(vec![Command::Push(sig), Command::Push(public_key)]);
+            }
+            SpendKind::P2wpkh => input.witness = Witness::p2wpkh(sig, public_key),
+            SpendKind::P2shP2wpkh => {
+                let program = p2wpkh_script(&hash160(&public_key));
+                input.script_sig = Script::from_commands(vec![Command::Push(program)]);
+                input.witness = Witness::p2wpkh(sig, public_key);
+            }
+            SpendKind::P2trKeyPath => input.witness = Witness::p2tr_key_spend(sig),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ec_cryptography::{helper::from_hex, traits::Serializer, PrivateKey};
+
+    use super::*;
+    use crate::{
+        amount::Amount,
+        input::{OutPoint, Sequence, TxIn},
+        output::TxOut,
+        sign::KeyStore,
+        txid::Txid,
+    };
+
+    /// Inputs spending P2PKH to key 1, P2WPKH to key 2, P2SH-P2WPKH to key 3 and taproot to key 4
+    fn unsigned_tx() -> (Transaction, HashMap<(Txid, u32), TxOut>) {
+        let script_pubkeys = [
+            "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac",
+            "001406afd46bcdfd22ef94ac122aa11f241244a37ecc",
+            "a91469ea5ff598a286f418ae77503ce85d83da4ae88e87",
+            "51209317856ed22b3699e792f38ad803f9f3fafabf70a2c8983af715592242c29ca8",
+        ];
+        let mut tx = Transaction::default();
+        let mut utxos = HashMap::new();
+        for (index, script_pubkey) in script_pubkeys.iter().enumerate() {
+            let outpoint = OutPoint::new(Txid::from_bytes([index as u8 + 1; 32]), 0);
+            tx.inputs.push(TxIn::from_outpoint(outpoint, Sequence::MAX));
+            let prevout = TxOut::new(Amount::from_sat(100_000), Script::from_hex(script_pubkey).unwrap());
+            utxos.insert((outpoint.txid, 0), prevout);
+        }
+        tx.outputs.push(TxOut::new(Amount::from_sat(350_000), Script::from_hex(script_pubkeys[0]).unwrap()));
+        (tx, utxos)
+    }
+
+    #[test]
+    fn test_external_signing() {
+        let (mut tx, utxos) = unsigned_tx();
+        let mut signed_here = tx.clone();
+        let mut keys = KeyStore::new();
+
+        for secret in 1..=4 {
+            let key = PrivateKey::new(Integer::from(secret));
+            keys.add_key(PrivateKey::new(Integer::from(secret)));
+            let index = secret as usize - 1;
+
+            // the signer only sees the preimage, and hashes it itself
+            if index == 3 {
+                let tweaked_key = key.tap_tweak(None);
+                let preimage = tx.sighash_preimage(index, &tweaked_key.x_only_public_key(), None, &utxos).unwrap();
+                assert_eq!(preimage.spend_kind, SpendKind::P2trKeyPath);
+                let signature = tweaked_key.sign_schnorr(&preimage.sighash(), &[0; 32]);
+                tx.add_signature(&preimage, &signature).unwrap();
+            } else {
+                let sec = from_hex(&key.point().sec(true)).unwrap();
+                let preimage = tx.sighash_preimage(index, &sec, None, &utxos).unwrap();
+                assert_eq!(preimage.sighash_type, Some(SighashType::All));
+                let signature = key.sign(Integer::from_digits(&preimage.sighash(), Order::MsfBe));
+                tx.add_signature(&preimage, &signature.der_bytes()).unwrap();
+            }
+            assert!(tx.verify_input(index, &utxos).is_ok());
+        }
+
+        // the same signatures as signing in this crate
+        signed_here.sign_all(&keys, &utxos).unwrap();
+        assert_eq!(tx.serialize(), signed_here.serialize());
+    }
+
+    #[test]
+    fn test_preimage_formats() {
+        let (tx, utxos) = unsigned_tx();
+        let key = PrivateKey::new(Integer::from(1));
+        let sec = from_hex(&key.point().sec(true)).unwrap();
+        let script_pubkey = &utxos[&(tx.inputs[0].prev_txid, 0)].script_pubkey;
+
+        let preimage = tx.sighash_preimage(0, &sec, Some(SighashType::Single), &utxos).unwrap();
+        assert_eq!(preimage.spend_kind, SpendKind::P2pkh);
+        // the legacy preimage ends with the 4-byte hash type
+        assert_eq!(preimage.preimage[preimage.preimage.len() - 4..], [0x03, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            preimage.sighash().to_vec(),
+            tx.sig_hash(0, script_pubkey, SighashType::Single).unwrap()
+        );
+
+        // without an output, legacy SIGHASH_SINGLE signs the number 1 and has no preimage, BIP143 just leaves it out
+        let mut no_outputs = tx.clone();
+        no_outputs.outputs.clear();
+        assert!(matches!(
+            no_outputs.sighash_preimage(0, &sec, Some(SighashType::Single), &utxos),
+            Err(TransactionError::SingleWithoutOutput(0))
+        ));
+        let sec = from_hex(&PrivateKey::new(Integer::from(2)).point().sec(true)).unwrap();
+        let preimage = no_outputs.sighash_preimage(1, &sec, Some(SighashType::Single), &utxos).unwrap();
+        assert_eq!(preimage.spend_kind, SpendKind::P2wpkh);
+    }
+
+    #[test]
+    fn test_external_signing_errors() {
+        let (mut tx, utxos) = unsigned_tx();
+        let key = PrivateKey::new(Integer::from(1));
+        let sec = from_hex(&key.point().sec(true)).unwrap();
+
+        // key 1 doesn't pay to the P2WPKH output
+        assert!(matches!(tx.sighash_preimage(1, &sec, None, &utxos), Err(TransactionError::KeyMismatch)));
+        assert!(matches!(tx.sighash_preimage(4, &sec, None, &utxos), Err(TransactionError::InputIndexOutOfRange(4))));
+        assert!(matches!(
+            tx.sighash_preimage(0, &sec, None, &HashMap::new()),
+            Err(TransactionError::MissingPrevout(0))
+        ));
+
+        // a signature of another hash never gets in
+        let preimage = tx.sighash_preimage(0, &sec, None, &utxos).unwrap();
+        let signature = key.sign(Integer::from(42)).der_bytes();
+        assert!(matches!(tx.add_signature(&preimage, &signature), Err(TransactionError::InvalidSignature)));
+        assert!(matches!(tx.add_signature(&preimage, &[0x30]), Err(TransactionError::InvalidSignature)));
+        assert!(tx.inputs[0].script_sig.is_empty());
+    }
+}
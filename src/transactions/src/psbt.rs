@@ -0,0 +1,603 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{self, Cursor, Read, Write},
+    str::FromStr,
+};
+
+use ec_cryptography::{
+    consensus::{ConsensusDecode, ConsensusEncode},
+    serializer::{decode_base64, encode_base64, Base64Error},
+};
+use scripts::Script;
+
+use crate::{output::TxOut, witness::Witness, Transaction, TransactionError};
+
+mod finalize;
+mod sign;
+
+/// Every PSBT starts with "psbt" followed by 0xff
+pub const PSBT_MAGIC: [u8; 5] = *b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_GLOBAL_XPUB: u8 = 0x01;
+const PSBT_GLOBAL_VERSION: u8 = 0xfb;
+
+const PSBT_IN_NON_WITNESS_UTXO: u8 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u8 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u8 = 0x03;
+const PSBT_IN_REDEEM_SCRIPT: u8 = 0x04;
+const PSBT_IN_WITNESS_SCRIPT: u8 = 0x05;
+const PSBT_IN_BIP32_DERIVATION: u8 = 0x06;
+const PSBT_IN_FINAL_SCRIPTSIG: u8 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+
+const PSBT_OUT_REDEEM_SCRIPT: u8 = 0x00;
+const PSBT_OUT_WITNESS_SCRIPT: u8 = 0x01;
+const PSBT_OUT_BIP32_DERIVATION: u8 = 0x02;
+
+/// The length of a serialized extended public key, the key data of a global xpub
+const XPUB_LENGTH: usize = 78;
+
+#[derive(Debug)]
+pub enum PsbtError {
+    /// The data doesn't start with "psbt" and 0xff
+    InvalidMagic,
+    /// The data ended before the last map did
+    Io(io::Error),
+    /// The text isn't valid base64
+    Base64(Base64Error),
+    /// The unsigned transaction or a non-witness UTXO couldn't be parsed
+    Transaction(TransactionError),
+    /// The same key appears twice in a map
+    DuplicateKey(Vec<u8>),
+    /// The global map has no unsigned transaction
+    MissingUnsignedTx,
+    /// The unsigned transaction has a script_sig or a witness
+    UnsignedTxHasScripts,
+    /// Only version 0 PSBTs are supported
+    UnsupportedVersion(u32),
+    /// The key data doesn't fit the key type
+    InvalidKey(Vec<u8>),
+    /// The value stored under this key is malformed
+    InvalidValue(Vec<u8>),
+    /// Bytes were left over after the last output map
+    TrailingBytes(usize),
+    /// The non-witness UTXO of the input with this index isn't the transaction it spends from
+    UtxoMismatch(usize),
+    /// The redeem or witness script of the input with this index doesn't hash to the output it spends
+    ScriptMismatch(usize),
+    /// The input asks for a signature hash type that isn't one of the six standard ones
+    UnsupportedSighash(u32),
+    /// PSBTs can only be combined if they are for the same unsigned transaction
+    DifferentTransactions,
+    /// The input with this index lacks signatures or scripts, or spends a script the finalizer doesn't know
+    CannotFinalize(usize),
+    /// The input with this index has no final script_sig or witness yet
+    NotFinalized(usize),
+}
+
+impl From<io::Error> for PsbtError {
+    fn from(error: io::Error) -> Self {
+        PsbtError::Io(error)
+    }
+}
+
+impl From<TransactionError> for PsbtError {
+    fn from(error: TransactionError) -> Self {
+        PsbtError::Transaction(error)
+    }
+}
+
+impl From<Base64Error> for PsbtError {
+    fn from(error: Base64Error) -> Self {
+        PsbtError::Base64(error)
+    }
+}
+
+/// Where a key comes from (BIP32): the fingerprint of the master key and the derivation path.
+/// Hardened indexes have the top bit set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySource {
+    pub fingerprint: [u8; 4],
+    pub path: Vec<u32>,
+}
+
+impl KeySource {
+    /// The fingerprint followed by each index as 4 little-endian bytes
+    pub fn parse(bytes: &[u8]) -> Option<KeySource> {
+        if bytes.len() < 4 || !bytes.len().is_multiple_of(4) {
+            return None;
+        }
+
+        let fingerprint = bytes[..4].try_into().unwrap();
+        let path = bytes[4..]
+            .chunks(4)
+            .map(|index| u32::from_le_bytes(index.try_into().unwrap()))
+            .collect();
+        Some(KeySource { fingerprint, path })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = self.fingerprint.to_vec();
+        for index in &self.path {
+            bytes.extend(index.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// What the signers and finalizer need to know about an input
+#[derive(Debug, Clone, Default)]
+pub struct PsbtInput {
+    /// The whole transaction holding the output spent, for legacy inputs
+    pub non_witness_utxo: Option<Transaction>,
+    /// The output spent, for segwit inputs
+    pub witness_utxo: Option<TxOut>,
+    /// Signatures (with their hash type byte) by SEC public key
+    pub partial_sigs: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub sighash_type: Option<u32>,
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    /// The keys involved in the input, by SEC public key
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeySource>,
+    pub final_script_sig: Option<Script>,
+    pub final_script_witness: Option<Witness>,
+    /// Pairs of key types this implementation doesn't know, kept so they survive a round-trip
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// What is known about an output, mostly so a signer can check the change goes back to it
+#[derive(Debug, Clone, Default)]
+pub struct PsbtOutput {
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeySource>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// A partially signed transaction (BIP174): the unsigned transaction and a map of key-value pairs
+/// for the whole transaction, one for each input and one for each output.
+/// It can be passed between wallets as bytes or as base64.
+#[derive(Debug, Clone)]
+pub struct Psbt {
+    pub unsigned_tx: Transaction,
+    pub version: u32,
+    /// The master keys of the signers, by serialized extended public key
+    pub xpubs: BTreeMap<Vec<u8>, KeySource>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PsbtOutput>,
+}
+
+/// A key (its type and key data) with its value
+type KeyValuePair = (Vec<u8>, Vec<u8>);
+
+/// Reads the key-value pairs of a map up to the 0x00 that ends it
+fn read_map<R: Read>(reader: &mut R) -> Result<Vec<KeyValuePair>, PsbtError> {
+    let mut pairs: Vec<KeyValuePair> = vec![];
+    loop {
+        let key = Vec::<u8>::consensus_decode(reader)?;
+        if key.is_empty() {
+            return Ok(pairs);
+        }
+
+        let value = Vec::<u8>::consensus_decode(reader)?;
+        if pairs.iter().any(|(other, _)| *other == key) {
+            return Err(PsbtError::DuplicateKey(key));
+        }
+        pairs.push((key, value));
+    }
+}
+
+/// Writes a pair whose key is the key type followed by the key data
+fn write_pair<W: Write>(writer: &mut W, key_type: u8, key_data: &[u8], value: &[u8]) -> io::Result<()> {
+    let mut key = vec![key_type];
+    key.extend_from_slice(key_data);
+
+    key.consensus_encode(writer)?;
+    value.to_vec().consensus_encode(writer)?;
+    Ok(())
+}
+
+fn write_unknown<W: Write>(writer: &mut W, unknown: &BTreeMap<Vec<u8>, Vec<u8>>) -> io::Result<()> {
+    for (key, value) in unknown {
+        key.consensus_encode(writer)?;
+        value.consensus_encode(writer)?;
+    }
+    Ok(())
+}
+
+/// Most key types carry no key data
+fn no_key_data(key: &[u8]) -> Result<(), PsbtError> {
+    match key.len() {
+        1 => Ok(()),
+        _ => Err(PsbtError::InvalidKey(key.to_vec())),
+    }
+}
+
+/// The key data of partial signatures and derivations is a compressed or uncompressed SEC public key
+fn public_key(key: &[u8]) -> Result<Vec<u8>, PsbtError> {
+    match key.len() - 1 {
+        33 | 65 => Ok(key[1..].to_vec()),
+        _ => Err(PsbtError::InvalidKey(key.to_vec())),
+    }
+}
+
+fn key_source(key: &[u8], value: &[u8]) -> Result<KeySource, PsbtError> {
+    KeySource::parse(value).ok_or_else(|| PsbtError::InvalidValue(key.to_vec()))
+}
+
+/// Sighash types and the version are 4 little-endian bytes
+fn u32_value(key: &[u8], value: &[u8]) -> Result<u32, PsbtError> {
+    let bytes = value.try_into().map_err(|_| PsbtError::InvalidValue(key.to_vec()))?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+impl PsbtInput {
+    fn decode<R: Read>(reader: &mut R) -> Result<PsbtInput, PsbtError> {
+        let mut input = PsbtInput::default();
+
+        for (key, value) in read_map(reader)? {
+            match key[0] {
+                PSBT_IN_NON_WITNESS_UTXO => {
+                    no_key_data(&key)?;
+                    input.non_witness_utxo = Some(Transaction::parse(&value, false)?);
+                }
+                PSBT_IN_WITNESS_UTXO => {
+                    no_key_data(&key)?;
+                    let output = TxOut::consensus_deserialize(&value).map_err(|_| PsbtError::InvalidValue(key.clone()))?;
+                    input.witness_utxo = Some(output);
+                }
+                PSBT_IN_PARTIAL_SIG => {
+                    input.partial_sigs.insert(public_key(&key)?, value);
+                }
+                PSBT_IN_SIGHASH_TYPE => {
+                    no_key_data(&key)?;
+                    input.sighash_type = Some(u32_value(&key, &value)?);
+                }
+                PSBT_IN_REDEEM_SCRIPT => {
+                    no_key_data(&key)?;
+                    input.redeem_script = Some(Script::from_bytes(value));
+                }
+                PSBT_IN_WITNESS_SCRIPT => {
+                    no_key_data(&key)?;
+                    input.witness_script = Some(Script::from_bytes(value));
+                }
+                PSBT_IN_BIP32_DERIVATION => {
+                    input.bip32_derivation.insert(public_key(&key)?, key_source(&key, &value)?);
+                }
+                PSBT_IN_FINAL_SCRIPTSIG => {
+                    no_key_data(&key)?;
+                    input.final_script_sig = Some(Script::from_bytes(value));
+                }
+                PSBT_IN_FINAL_SCRIPTWITNESS => {
+                    no_key_data(&key)?;
+                    let witness = Witness::consensus_deserialize(&value).map_err(|_| PsbtError::InvalidValue(key.clone()))?;
+                    input.final_script_witness = Some(witness);
+                }
+                _ => {
+                    input.unknown.insert(key, value);
+                }
+            }
+        }
+
+        Ok(input)
+    }
+
+    /// The pairs are written in order of key type, then the unknown ones
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if let Some(tx) = &self.non_witness_utxo {
+            write_pair(writer, PSBT_IN_NON_WITNESS_UTXO, &[], &tx.serialize())?;
+        }
+        if let Some(output) = &self.witness_utxo {
+            write_pair(writer, PSBT_IN_WITNESS_UTXO, &[], &output.consensus_serialize())?;
+        }
+        for (public_key, signature) in &self.partial_sigs {
+            write_pair(writer, PSBT_IN_PARTIAL_SIG, public_key, signature)?;
+        }
+        if let Some(sighash_type) = self.sighash_type {
+            write_pair(writer, PSBT_IN_SIGHASH_TYPE, &[], &sighash_type.to_le_bytes())?;
+        }
+        if let Some(script) = &self.redeem_script {
+            write_pair(writer, PSBT_IN_REDEEM_SCRIPT, &[], script.as_bytes())?;
+        }
+        if let Some(script) = &self.witness_script {
+            write_pair(writer, PSBT_IN_WITNESS_SCRIPT, &[], script.as_bytes())?;
+        }
+        for (public_key, source) in &self.bip32_derivation {
+            write_pair(writer, PSBT_IN_BIP32_DERIVATION, public_key, &source.serialize())?;
+        }
+        if let Some(script) = &self.final_script_sig {
+            write_pair(writer, PSBT_IN_FINAL_SCRIPTSIG, &[], script.as_bytes())?;
+        }
+        if let Some(witness) = &self.final_script_witness {
+            write_pair(writer, PSBT_IN_FINAL_SCRIPTWITNESS, &[], &witness.consensus_serialize())?;
+        }
+        write_unknown(writer, &self.unknown)?;
+
+        0u8.consensus_encode(writer)?;
+        Ok(())
+    }
+}
+
+impl PsbtOutput {
+    fn decode<R: Read>(reader: &mut R) -> Result<PsbtOutput, PsbtError> {
+        let mut output = PsbtOutput::default();
+
+        for (key, value) in read_map(reader)? {
+            match key[0] {
+                PSBT_OUT_REDEEM_SCRIPT => {
+                    no_key_data(&key)?;
+                    output.redeem_script = Some(Script::from_bytes(value));
+                }
+                PSBT_OUT_WITNESS_SCRIPT => {
+                    no_key_data(&key)?;
+                    output.witness_script = Some(Script::from_bytes(value));
+                }
+                PSBT_OUT_BIP32_DERIVATION => {
+                    output.bip32_derivation.insert(public_key(&key)?, key_source(&key, &value)?);
+                }
+                _ => {
+                    output.unknown.insert(key, value);
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if let Some(script) = &self.redeem_script {
+            write_pair(writer, PSBT_OUT_REDEEM_SCRIPT, &[], script.as_bytes())?;
+        }
+        if let Some(script) = &self.witness_script {
+            write_pair(writer, PSBT_OUT_WITNESS_SCRIPT, &[], script.as_bytes())?;
+        }
+        for (public_key, source) in &self.bip32_derivation {
+            write_pair(writer, PSBT_OUT_BIP32_DERIVATION, public_key, &source.serialize())?;
+        }
+        write_unknown(writer, &self.unknown)?;
+
+        0u8.consensus_encode(writer)?;
+        Ok(())
+    }
+}
+
+impl Psbt {
+    /// Starts a PSBT for a transaction with empty script_sigs and witnesses, with nothing known about
+    /// its inputs and outputs yet
+    pub fn from_unsigned_tx(unsigned_tx: Transaction) -> Result<Psbt, PsbtError> {
+        if unsigned_tx.inputs.iter().any(|input| !input.script_sig.is_empty() || !input.witness.is_empty()) {
+            return Err(PsbtError::UnsignedTxHasScripts);
+        }
+
+        Ok(Psbt {
+            inputs: vec![PsbtInput::default(); unsigned_tx.inputs.len()],
+            outputs: vec![PsbtOutput::default(); unsigned_tx.outputs.len()],
+            unsigned_tx,
+            version: 0,
+            xpubs: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+        })
+    }
+
+    /// Parses the binary format: the magic, the global map, then a map for each input and each output
+    /// of the unsigned transaction. Each map is a series of varint prefixed keys and values ended by 0x00.
+    pub fn parse(bytes: &[u8]) -> Result<Psbt, PsbtError> {
+        if !bytes.starts_with(&PSBT_MAGIC) {
+            return Err(PsbtError::InvalidMagic);
+        }
+        let mut reader = Cursor::new(&bytes[PSBT_MAGIC.len()..]);
+
+        let mut unsigned_tx = None;
+        let mut version = None;
+        let mut xpubs = BTreeMap::new();
+        let mut unknown = BTreeMap::new();
+
+        for (key, value) in read_map(&mut reader)? {
+            match key[0] {
+                PSBT_GLOBAL_UNSIGNED_TX => {
+                    no_key_data(&key)?;
+                    unsigned_tx = Some(Transaction::parse(&value, false)?);
+                }
+                PSBT_GLOBAL_XPUB => {
+                    if key.len() != 1 + XPUB_LENGTH {
+                        return Err(PsbtError::InvalidKey(key));
+                    }
+                    xpubs.insert(key[1..].to_vec(), key_source(&key, &value)?);
+                }
+                PSBT_GLOBAL_VERSION => {
+                    no_key_data(&key)?;
+                    version = Some(u32_value(&key, &value)?);
+                }
+                _ => {
+                    unknown.insert(key, value);
+                }
+            }
+        }
+
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx.ok_or(PsbtError::MissingUnsignedTx)?)?;
+        psbt.version = version.unwrap_or(0);
+        if psbt.version != 0 {
+            return Err(PsbtError::UnsupportedVersion(psbt.version));
+        }
+        psbt.xpubs = xpubs;
+        psbt.unknown = unknown;
+
+        for input in psbt.inputs.iter_mut() {
+            *input = PsbtInput::decode(&mut reader)?;
+        }
+        for output in psbt.outputs.iter_mut() {
+            *output = PsbtOutput::decode(&mut reader)?;
+        }
+
+        let remaining = bytes.len() - PSBT_MAGIC.len() - reader.position() as usize;
+        if remaining > 0 {
+            return Err(PsbtError::TrailingBytes(remaining));
+        }
+
+        Ok(psbt)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = PSBT_MAGIC.to_vec();
+        self.encode(&mut bytes).expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_pair(writer, PSBT_GLOBAL_UNSIGNED_TX, &[], &self.unsigned_tx.serialize())?;
+        for (xpub, source) in &self.xpubs {
+            write_pair(writer, PSBT_GLOBAL_XPUB, xpub, &source.serialize())?;
+        }
+        // version 0 is the default and is left out, as other implementations do
+        if self.version != 0 {
+            write_pair(writer, PSBT_GLOBAL_VERSION, &[], &self.version.to_le_bytes())?;
+        }
+        write_unknown(writer, &self.unknown)?;
+        0u8.consensus_encode(writer)?;
+
+        for input in &self.inputs {
+            input.encode(writer)?;
+        }
+        for output in &self.outputs {
+            output.encode(writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn from_base64(text: &str) -> Result<Psbt, PsbtError> {
+        Psbt::parse(&decode_base64(text.trim())?)
+    }
+
+    /// The base64 encoding most wallets use to pass PSBTs around
+    pub fn to_base64(&self) -> String {
+        encode_base64(&self.serialize())
+    }
+}
+
+impl Display for Psbt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_base64())
+    }
+}
+
+impl FromStr for Psbt {
+    type Err = PsbtError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Psbt::from_base64(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::{from_hex, to_hex};
+
+    use super::*;
+    use crate::amount::Amount;
+
+    /// The P2PKH example of BIP174: one input with its non-witness UTXO, and two outputs
+    const BIP174_PSBT: &str = "cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA4fUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAAAAAAA";
+
+    const PUBLIC_KEY: &str = "03d2e15674941bad4a996372cb87e1856d3652606d98562fe39c5e9e7e413f2105";
+
+    #[test]
+    fn test_parse_bip174_example() {
+        let psbt: Psbt = BIP174_PSBT.parse().unwrap();
+        assert_eq!(psbt.version, 0);
+        assert_eq!(psbt.unsigned_tx.inputs.len(), 1);
+        assert_eq!(psbt.unsigned_tx.outputs.len(), 2);
+        assert_eq!(psbt.outputs.len(), 2);
+
+        // the non-witness UTXO is the transaction the input spends
+        let prevout = psbt.unsigned_tx.inputs[0].outpoint();
+        let previous_tx = psbt.inputs[0].non_witness_utxo.as_ref().unwrap();
+        assert_eq!(previous_tx.txid(), prevout.txid);
+        assert!(psbt.inputs[0].witness_utxo.is_none());
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+
+        assert_eq!(psbt.to_base64(), BIP174_PSBT);
+        assert_eq!(psbt.to_string(), BIP174_PSBT);
+    }
+
+    #[test]
+    fn test_round_trip_all_fields() {
+        let mut psbt = Psbt::from_base64(BIP174_PSBT).unwrap();
+        let public_key = from_hex(PUBLIC_KEY).unwrap();
+        let source = KeySource { fingerprint: [0xd9, 0x0c, 0x6a, 0x4f], path: vec![0x8000_0000, 0, 5] };
+
+        psbt.xpubs.insert(vec![0x04; XPUB_LENGTH], source.clone());
+        psbt.unknown.insert(vec![0xf0, 0x01], vec![0xab]);
+
+        let input = &mut psbt.inputs[0];
+        input.witness_utxo = Some(psbt.unsigned_tx.outputs[1].clone());
+        input.partial_sigs.insert(public_key.clone(), vec![0x30, 0x01]);
+        input.sighash_type = Some(1);
+        input.redeem_script = Some(Script::from_hex("0014d0c59903c5bac2868760e90fd521a4665aa76520").unwrap());
+        input.witness_script = Some(Script::from_hex("51").unwrap());
+        input.bip32_derivation.insert(public_key.clone(), source.clone());
+        input.final_script_sig = Some(Script::from_hex("00").unwrap());
+        input.final_script_witness = Some(Witness::p2wpkh(vec![0x30], public_key.clone()));
+        input.unknown.insert(vec![0x99], vec![]);
+
+        let output = &mut psbt.outputs[1];
+        output.redeem_script = Some(Script::from_hex("52").unwrap());
+        output.witness_script = Some(Script::from_hex("53").unwrap());
+        output.bip32_derivation.insert(public_key.clone(), source.clone());
+
+        let bytes = psbt.serialize();
+        let parsed = Psbt::parse(&bytes).unwrap();
+        assert_eq!(parsed.serialize(), bytes);
+
+        assert_eq!(parsed.xpubs[&vec![0x04; XPUB_LENGTH]], source);
+        assert_eq!(parsed.unknown[&vec![0xf0, 0x01]], vec![0xab]);
+        let input = &parsed.inputs[0];
+        assert_eq!(input.witness_utxo.as_ref().unwrap().value, Amount::from_sat(100_000_000));
+        assert_eq!(input.partial_sigs[&public_key], vec![0x30, 0x01]);
+        assert_eq!(input.sighash_type, Some(1));
+        assert_eq!(input.witness_script.as_ref().unwrap().to_hex(), "51");
+        assert_eq!(input.bip32_derivation[&public_key], source);
+        assert_eq!(input.final_script_witness.as_ref().unwrap().len(), 2);
+        assert_eq!(parsed.outputs[1].bip32_derivation[&public_key].path, vec![0x8000_0000, 0, 5]);
+        assert!(parsed.outputs[0].redeem_script.is_none());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = Psbt::from_base64(BIP174_PSBT).unwrap().serialize();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[4] = 0x00;
+        assert!(matches!(Psbt::parse(&bad_magic), Err(PsbtError::InvalidMagic)));
+
+        // the last output map is missing
+        assert!(matches!(Psbt::parse(&bytes[..bytes.len() - 1]), Err(PsbtError::Io(_))));
+        assert!(matches!(Psbt::parse(&[bytes.clone(), vec![0x00]].concat()), Err(PsbtError::TrailingBytes(1))));
+
+        // a global map without the unsigned transaction
+        assert!(matches!(Psbt::parse(&from_hex("70736274ff00").unwrap()), Err(PsbtError::MissingUnsignedTx)));
+
+        // a global map with the unsigned transaction twice
+        let tx = Psbt::from_base64(BIP174_PSBT).unwrap().unsigned_tx.serialize();
+        let mut duplicate = PSBT_MAGIC.to_vec();
+        write_pair(&mut duplicate, PSBT_GLOBAL_UNSIGNED_TX, &[], &tx).unwrap();
+        write_pair(&mut duplicate, PSBT_GLOBAL_UNSIGNED_TX, &[], &tx).unwrap();
+        assert!(matches!(Psbt::parse(&duplicate), Err(PsbtError::DuplicateKey(key)) if key == vec![0x00]));
+
+        // the unsigned transaction key has no key data
+        let mut key_data = PSBT_MAGIC.to_vec();
+        write_pair(&mut key_data, PSBT_GLOBAL_UNSIGNED_TX, &[0x01], &tx).unwrap();
+        key_data.push(0x00);
+        assert!(matches!(Psbt::parse(&key_data), Err(PsbtError::InvalidKey(_))));
+
+        let mut signed = Psbt::from_base64(BIP174_PSBT).unwrap().unsigned_tx;
+        signed.inputs[0].script_sig = Script::from_hex("00").unwrap();
+        assert!(matches!(Psbt::from_unsigned_tx(signed), Err(PsbtError::UnsignedTxHasScripts)));
+
+        assert!(matches!(Psbt::from_base64("not base64!"), Err(PsbtError::Base64(_))));
+        assert_eq!(to_hex(&PSBT_MAGIC), "70736274ff");
+    }
+}
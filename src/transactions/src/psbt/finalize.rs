@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+
+use ec_cryptography::helper::hash160;
+use scripts::{Command, Script};
+
+use super::{Psbt, PsbtError, PsbtInput, PsbtOutput};
+use crate::{witness::Witness, Transaction};
+
+const OP_DUP: u8 = 0x76;
+const OP_EQUALVERIFY: u8 = 0x88;
+const OP_HASH160: u8 = 0xa9;
+const OP_CHECKSIG: u8 = 0xac;
+
+/// Adds the entries of `other` whose keys aren't in `map` yet
+fn merge_maps<V>(map: &mut BTreeMap<Vec<u8>, V>, other: BTreeMap<Vec<u8>, V>) {
+    for (key, value) in other {
+        map.entry(key).or_insert(value);
+    }
+}
+
+impl PsbtInput {
+    /// Whether the input has its final script_sig or witness
+    pub fn is_finalized(&self) -> bool {
+        self.final_script_sig.is_some() || self.final_script_witness.is_some()
+    }
+
+    fn combine(&mut self, other: PsbtInput) {
+        self.non_witness_utxo = self.non_witness_utxo.take().or(other.non_witness_utxo);
+        self.witness_utxo = self.witness_utxo.take().or(other.witness_utxo);
+        merge_maps(&mut self.partial_sigs, other.partial_sigs);
+        self.sighash_type = self.sighash_type.or(other.sighash_type);
+        self.redeem_script = self.redeem_script.take().or(other.redeem_script);
+        self.witness_script = self.witness_script.take().or(other.witness_script);
+        merge_maps(&mut self.bip32_derivation, other.bip32_derivation);
+        self.final_script_sig = self.final_script_sig.take().or(other.final_script_sig);
+        self.final_script_witness = self.final_script_witness.take().or(other.final_script_witness);
+        merge_maps(&mut self.unknown, other.unknown);
+    }
+}
+
+impl PsbtOutput {
+    fn combine(&mut self, other: PsbtOutput) {
+        self.redeem_script = self.redeem_script.take().or(other.redeem_script);
+        self.witness_script = self.witness_script.take().or(other.witness_script);
+        merge_maps(&mut self.bip32_derivation, other.bip32_derivation);
+        merge_maps(&mut self.unknown, other.unknown);
+    }
+}
+
+impl Psbt {
+    /// Merges in what another signer added to the same PSBT (the combiner role of BIP174):
+    /// signatures, scripts, derivations and UTXOs. Where both have a value, this PSBT's is kept.
+    pub fn combine(&mut self, other: Psbt) -> Result<(), PsbtError> {
+        if self.unsigned_tx.txid() != other.unsigned_tx.txid() {
+            return Err(PsbtError::DifferentTransactions);
+        }
+
+        merge_maps(&mut self.xpubs, other.xpubs);
+        merge_maps(&mut self.unknown, other.unknown);
+        for (input, other) in self.inputs.iter_mut().zip(other.inputs) {
+            input.combine(other);
+        }
+        for (output, other) in self.outputs.iter_mut().zip(other.outputs) {
+            output.combine(other);
+        }
+        Ok(())
+    }
+
+    /// Builds the final script_sig and witness of every input from its partial signatures (the finalizer role).
+    /// Inputs already finalized are skipped, and it stops at the first input that can't be finalized.
+    pub fn finalize(&mut self) -> Result<(), PsbtError> {
+        for index in 0..self.inputs.len() {
+            if !self.inputs[index].is_finalized() {
+                self.finalize_input(index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the final script_sig and witness of an input, then drops what only the signers needed.
+    ///
+    /// P2PK, P2PKH and multisig scripts are supported, bare or wrapped in P2SH, P2WSH or both,
+    /// and so is P2WPKH, native or wrapped in P2SH.
+    pub fn finalize_input(&mut self, index: usize) -> Result<(), PsbtError> {
+        let cannot_finalize = || PsbtError::CannotFinalize(index);
+        let spent = self.spent_output(index)?.ok_or_else(cannot_finalize)?;
+        let input = &self.inputs[index];
+
+        // the script_sig of a P2SH spend ends with the redeem script
+        let mut script_sig = vec![];
+        let mut script = &spent.script_pubkey;
+        if let [OP_HASH160, 0x14, .., 0x87] = script.as_bytes() {
+            let redeem_script = input.redeem_script.as_ref().ok_or_else(cannot_finalize)?;
+            script_sig.push(redeem_script.as_bytes().to_vec());
+            script = redeem_script;
+        }
+
+        let witness = match script.as_bytes() {
+            [0x00, 0x14, hash @ ..] if hash.len() == 20 => {
+                let (key, signature) = input
+                    .partial_sigs
+                    .iter()
+                    .find(|(key, _)| hash160(key) == hash)
+                    .ok_or_else(cannot_finalize)?;
+                Witness::p2wpkh(signature.clone(), key.clone())
+            }
+            [0x00, 0x20, ..] if script.len() == 34 => {
+                let witness_script = input.witness_script.as_ref().ok_or_else(cannot_finalize)?;
+                let mut stack = satisfy(witness_script, &input.partial_sigs).ok_or_else(cannot_finalize)?;
+                stack.push(witness_script.as_bytes().to_vec());
+                Witness::from(stack)
+            }
+            _ => {
+                let stack = satisfy(script, &input.partial_sigs).ok_or_else(cannot_finalize)?;
+                script_sig = [stack, script_sig].concat();
+                Witness::new()
+            }
+        };
+
+        let input = &mut self.inputs[index];
+        input.final_script_sig =
+            (!script_sig.is_empty()).then(|| Script::from_commands(script_sig.into_iter().map(Command::Push).collect()));
+        input.final_script_witness = (!witness.is_empty()).then_some(witness);
+
+        input.partial_sigs.clear();
+        input.sighash_type = None;
+        input.redeem_script = None;
+        input.witness_script = None;
+        input.bip32_derivation.clear();
+        Ok(())
+    }
+
+    /// The signed transaction, once every input is finalized (the extractor role)
+    pub fn extract_tx(&self) -> Result<Transaction, PsbtError> {
+        let mut tx = self.unsigned_tx.clone();
+
+        for (index, (tx_input, input)) in tx.inputs.iter_mut().zip(&self.inputs).enumerate() {
+            if !input.is_finalized() {
+                return Err(PsbtError::NotFinalized(index));
+            }
+            tx_input.script_sig = input.final_script_sig.clone().unwrap_or_default();
+            tx_input.witness = input.final_script_witness.clone().unwrap_or_default();
+        }
+        Ok(tx)
+    }
+}
+
+/// The stack items that satisfy a P2PK, P2PKH or multisig script with the signatures available
+fn satisfy(script: &Script, partial_sigs: &BTreeMap<Vec<u8>, Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    match script.commands() {
+        [Command::Push(key), Command::Op(OP_CHECKSIG)] => Some(vec![partial_sigs.get(key)?.clone()]),
+        [Command::Op(OP_DUP), Command::Op(OP_HASH160), Command::Push(hash), Command::Op(OP_EQUALVERIFY), Command::Op(OP_CHECKSIG)] => {
+            let (key, signature) = partial_sigs.iter().find(|(key, _)| hash160(key) == *hash)?;
+            Some(vec![signature.clone(), key.clone()])
+        }
+        _ => script.multisig_inputs(partial_sigs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::{
+        helper::from_hex,
+        traits::Serializer,
+        PrivateKey,
+    };
+    use rug::Integer;
+
+    use super::*;
+    use crate::{
+        amount::Amount,
+        input::{OutPoint, Sequence, TxIn},
+        output::TxOut,
+    };
+
+    fn sec(private_key: &PrivateKey) -> Vec<u8> {
+        from_hex(&private_key.point().sec(true)).unwrap()
+    }
+
+    /// A transaction spending each of the outputs of `previous_tx`
+    fn spending_tx(previous_tx: &Transaction) -> Transaction {
+        let inputs = (0..previous_tx.outputs.len() as u32)
+            .map(|vout| TxIn::from_outpoint(OutPoint::new(previous_tx.txid(), vout), Sequence::MAX))
+            .collect();
+        let script_pubkey = Script::from_hex("76a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac").unwrap();
+
+        Transaction { inputs, outputs: vec![TxOut::new(Amount::from_sat(10_000), script_pubkey)], ..Default::default() }
+    }
+
+    #[test]
+    fn test_multisig_workflow() {
+        let keys = [PrivateKey::new(Integer::from(8675309)), PrivateKey::new(Integer::from(8675310))];
+        // 2 of 2 multisig in P2WSH
+        let witness_script = Script::multisig(2, &[sec(&keys[0]), sec(&keys[1])]).unwrap();
+        let p2wsh = witness_script.to_p2wsh();
+        let previous_tx = Transaction { outputs: vec![TxOut::new(Amount::from_sat(20_000), p2wsh)], ..Default::default() };
+
+        let mut psbt = Psbt::from_unsigned_tx(spending_tx(&previous_tx)).unwrap();
+        psbt.inputs[0].witness_utxo = Some(previous_tx.outputs[0].clone());
+        psbt.inputs[0].witness_script = Some(witness_script.clone());
+
+        // each signer works on its own copy
+        let mut second = psbt.clone();
+        assert_eq!(second.sign(&keys[1]).unwrap(), vec![0]);
+        assert_eq!(psbt.sign(&keys[0]).unwrap(), vec![0]);
+        assert!(matches!(psbt.extract_tx(), Err(PsbtError::NotFinalized(0))));
+        assert!(matches!(psbt.clone().finalize(), Err(PsbtError::CannotFinalize(0))));
+
+        psbt.combine(second.clone()).unwrap();
+        let signatures = [
+            psbt.inputs[0].partial_sigs[&sec(&keys[0])].clone(),
+            psbt.inputs[0].partial_sigs[&sec(&keys[1])].clone(),
+        ];
+        psbt.finalize().unwrap();
+
+        let input = &psbt.inputs[0];
+        assert!(input.final_script_sig.is_none());
+        assert_eq!(
+            input.final_script_witness,
+            Some(Witness::from(vec![
+                vec![],
+                signatures[0].clone(),
+                signatures[1].clone(),
+                witness_script.as_bytes().to_vec()
+            ]))
+        );
+        assert!(input.partial_sigs.is_empty());
+        assert!(input.witness_script.is_none());
+        assert!(input.witness_utxo.is_some());
+
+        let tx = psbt.extract_tx().unwrap();
+        assert!(tx.has_witness());
+        assert!(tx.inputs[0].script_sig.is_empty());
+        assert_eq!(tx.inputs[0].witness.len(), 4);
+        assert_eq!(tx.txid(), psbt.unsigned_tx.txid());
+
+        second.unsigned_tx.inputs[0].prev_index = 1;
+        assert!(matches!(psbt.combine(second), Err(PsbtError::DifferentTransactions)));
+    }
+
+    #[test]
+    fn test_finalize_p2pkh_and_p2sh_p2wpkh() {
+        let private_key = PrivateKey::new(Integer::from(8675309));
+        let key_hash = hash160(&sec(&private_key));
+        let p2pkh = Script::from_bytes([[OP_DUP, OP_HASH160, 0x14].as_slice(), &key_hash, &[OP_EQUALVERIFY, OP_CHECKSIG]].concat());
+        let redeem_script = Script::from_bytes([[0x00, 0x14].as_slice(), &key_hash].concat());
+        let p2sh = Script::from_bytes([[OP_HASH160, 0x14].as_slice(), &hash160(redeem_script.as_bytes()), &[0x87]].concat());
+
+        let previous_tx = Transaction {
+            outputs: vec![TxOut::new(Amount::from_sat(50_000), p2pkh.clone()), TxOut::new(Amount::from_sat(40_000), p2sh)],
+            ..Default::default()
+        };
+        let tx = spending_tx(&previous_tx);
+
+        let mut psbt = Psbt::from_unsigned_tx(tx.clone()).unwrap();
+        psbt.inputs[0].non_witness_utxo = Some(previous_tx.clone());
+        psbt.inputs[1].witness_utxo = Some(previous_tx.outputs[1].clone());
+        psbt.inputs[1].redeem_script = Some(redeem_script.clone());
+        assert_eq!(psbt.sign(&private_key).unwrap(), vec![0, 1]);
+        let signature = psbt.inputs[1].partial_sigs[&sec(&private_key)].clone();
+
+        psbt.finalize().unwrap();
+        let signed = psbt.extract_tx().unwrap();
+
+        // the same script_sig sign_input builds
+        let mut expected = tx;
+        expected.sign_input(0, &private_key, &p2pkh).unwrap();
+        assert_eq!(signed.inputs[0].script_sig, expected.inputs[0].script_sig);
+        assert!(signed.inputs[0].witness.is_empty());
+
+        assert_eq!(
+            signed.inputs[1].script_sig,
+            Script::from_commands(vec![Command::Push(redeem_script.as_bytes().to_vec())])
+        );
+        assert_eq!(signed.inputs[1].witness, Witness::p2wpkh(signature, sec(&private_key)));
+    }
+}
@@ -0,0 +1,260 @@
+use ec_cryptography::{
+    helper::{from_hex, hash160, sha256},
+    traits::Serializer,
+    PrivateKey,
+};
+use rug::{integer::Order, Integer};
+use scripts::{Command, Script};
+
+use super::{Psbt, PsbtError, PsbtInput};
+use crate::{output::TxOut, sighash::SighashType};
+
+/// The script a signature commits to
+enum ScriptCode {
+    Legacy(Script),
+    SegwitV0(Script),
+}
+
+impl Psbt {
+    /// Adds a signature by the key to every input it can sign (the signer role of BIP174),
+    /// returning the indexes of the inputs signed. Nothing is finalized.
+    ///
+    /// The output spent comes from the non_witness_utxo, which must be the transaction the input
+    /// spends from, or else the witness_utxo (only for segwit spends, as legacy signatures don't commit
+    /// to the amount). P2SH and P2WSH spends need their redeem or witness script.
+    /// The key signs P2PKH and P2WPKH outputs paying to its hash, and scripts that contain it.
+    /// When an input lists BIP32 derivations, only the form of the key (compressed or not) listed there is used.
+    /// Inputs that are finalized or that the key is not part of are skipped.
+    pub fn sign(&mut self, private_key: &PrivateKey) -> Result<Vec<usize>, PsbtError> {
+        let mut signed = vec![];
+
+        for index in 0..self.inputs.len() {
+            let input = &self.inputs[index];
+            if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+                continue;
+            }
+            let Some(spent) = self.spent_output(index)? else {
+                continue;
+            };
+            let sighash_type = match input.sighash_type {
+                Some(value) => SighashType::from_u32(value).ok_or(PsbtError::UnsupportedSighash(value))?,
+                None => SighashType::All,
+            };
+
+            let (sec, z) = match script_code(input, index, &spent.script_pubkey)? {
+                Some(ScriptCode::SegwitV0(script_code)) => {
+                    let Some(sec) = signing_sec(input, private_key, &script_code, true) else {
+                        continue;
+                    };
+                    (sec, self.unsigned_tx.segwit_v0_sig_hash(index, &script_code, spent.value, sighash_type)?)
+                }
+                Some(ScriptCode::Legacy(script_code)) if input.non_witness_utxo.is_some() => {
+                    let Some(sec) = signing_sec(input, private_key, &script_code, false) else {
+                        continue;
+                    };
+                    (sec, self.unsigned_tx.sig_hash(index, &script_code, sighash_type)?)
+                }
+                _ => continue,
+            };
+
+            // the hash type is appended to the DER signature
+            let mut signature = private_key.sign(Integer::from_digits(&z, Order::MsfBe)).der_bytes();
+            signature.push(sighash_type.to_u32() as u8);
+
+            self.inputs[index].partial_sigs.insert(sec, signature);
+            signed.push(index);
+        }
+
+        Ok(signed)
+    }
+
+    /// The output the input at `index` spends, if the PSBT has it
+    pub(super) fn spent_output(&self, index: usize) -> Result<Option<TxOut>, PsbtError> {
+        let input = &self.inputs[index];
+        let Some(previous_tx) = &input.non_witness_utxo else {
+            return Ok(input.witness_utxo.clone());
+        };
+
+        let prevout = self.unsigned_tx.inputs[index].outpoint();
+        if previous_tx.txid() != prevout.txid {
+            return Err(PsbtError::UtxoMismatch(index));
+        }
+        previous_tx
+            .outputs
+            .get(prevout.vout as usize)
+            .cloned()
+            .map(Some)
+            .ok_or(PsbtError::UtxoMismatch(index))
+    }
+}
+
+/// Resolves P2SH and P2WSH through the input's scripts, and P2WPKH to its P2PKH script code.
+/// None for outputs that can't be signed with ECDSA, like taproot, or when a script is missing.
+fn script_code(input: &PsbtInput, index: usize, script_pubkey: &Script) -> Result<Option<ScriptCode>, PsbtError> {
+    let mut script = script_pubkey;
+    if let [0xa9, 0x14, hash @ .., 0x87] = script_pubkey.as_bytes() {
+        let Some(redeem_script) = &input.redeem_script else {
+            return Ok(None);
+        };
+        if hash160(redeem_script.as_bytes()) != hash {
+            return Err(PsbtError::ScriptMismatch(index));
+        }
+        script = redeem_script;
+    }
+
+    match script.as_bytes() {
+        [0x00, 0x14, hash @ ..] if hash.len() == 20 => {
+            let p2pkh = Script::p2pkh(hash.try_into().expect("the key hash is 20 bytes"));
+            Ok(Some(ScriptCode::SegwitV0(p2pkh)))
+        }
+        [0x00, 0x20, hash @ ..] if hash.len() == 32 => {
+            let Some(witness_script) = &input.witness_script else {
+                return Ok(None);
+            };
+            if sha256(witness_script.as_bytes()) != hash {
+                return Err(PsbtError::ScriptMismatch(index));
+            }
+            Ok(Some(ScriptCode::SegwitV0(witness_script.clone())))
+        }
+        // other witness programs
+        [0x00 | 0x51..=0x60, length, program @ ..] if *length as usize == program.len() && (2..=40).contains(length) => {
+            Ok(None)
+        }
+        _ => Ok(Some(ScriptCode::Legacy(script.clone()))),
+    }
+}
+
+/// The SEC encoding of the key that the script code pays to or contains.
+/// Segwit only allows compressed keys.
+fn signing_sec(input: &PsbtInput, private_key: &PrivateKey, script_code: &Script, is_segwit: bool) -> Option<Vec<u8>> {
+    let forms: &[bool] = if is_segwit { &[true] } else { &[true, false] };
+
+    forms.iter().find_map(|is_compressed| {
+        let sec = from_hex(&private_key.point().sec(*is_compressed)).ok()?;
+        if !input.bip32_derivation.is_empty() && !input.bip32_derivation.contains_key(&sec) {
+            return None;
+        }
+
+        let pays_to_key = match script_code.as_bytes() {
+            [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] if hash.len() == 20 => hash160(&sec) == hash,
+            _ => script_code.commands().contains(&Command::Push(sec.clone())),
+        };
+        pays_to_key.then_some(sec)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::to_hex;
+
+    use super::*;
+    use crate::{
+        amount::Amount,
+        fixtures::BIP143_UNSIGNED_TX,
+        input::{OutPoint, Sequence, TxIn},
+        psbt::KeySource,
+        Transaction,
+    };
+
+    fn bip143_key() -> PrivateKey {
+        PrivateKey::new(Integer::from_str_radix("619c335025c7f4012e556c2a58b2506e30b8511b53ade95ea316fd8c3286feb9", 16).unwrap())
+    }
+
+    #[test]
+    fn test_sign_p2wpkh() {
+        let tx = Transaction::parse_hex(BIP143_UNSIGNED_TX, false).unwrap();
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        let script_pubkey = Script::from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        psbt.inputs[1].witness_utxo = Some(TxOut::new(Amount::from_sat(600_000_000), script_pubkey));
+
+        assert_eq!(psbt.sign(&bip143_key()).unwrap(), vec![1]);
+
+        // the signature of the BIP143 example, as the nonce is deterministic
+        let public_key = from_hex("025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357").unwrap();
+        assert_eq!(
+            to_hex(&psbt.inputs[1].partial_sigs[&public_key]),
+            "304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee01"
+        );
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+        assert!(psbt.inputs[1].final_script_witness.is_none());
+
+        // another key isn't part of the input
+        assert!(psbt.sign(&PrivateKey::new(Integer::from(8675309))).unwrap().is_empty());
+
+        // neither is a key whose derivation isn't listed
+        psbt.inputs[1].partial_sigs.clear();
+        let source = KeySource { fingerprint: [0; 4], path: vec![] };
+        psbt.inputs[1].bip32_derivation.insert(vec![0x02; 33], source);
+        assert!(psbt.sign(&bip143_key()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sign_p2pkh_and_p2wsh() {
+        let private_key = PrivateKey::new(Integer::from(8675309));
+        let sec = from_hex(&private_key.point().sec(true)).unwrap();
+        let p2pkh = Script::from_hex("76a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac").unwrap();
+        // 1 of 1 multisig
+        let witness_script = Script::from_commands(vec![
+            Command::Op(0x51),
+            Command::Push(sec.clone()),
+            Command::Op(0x51),
+            Command::Op(0xae),
+        ]);
+        let p2wsh = Script::from_bytes([[0x00, 0x20].as_slice(), &sha256(witness_script.as_bytes())].concat());
+
+        let previous_tx = Transaction {
+            outputs: vec![TxOut::new(Amount::from_sat(50_000), p2pkh.clone()), TxOut::new(Amount::from_sat(40_000), p2wsh)],
+            ..Default::default()
+        };
+        let tx = Transaction {
+            inputs: (0..2)
+                .map(|vout| TxIn::from_outpoint(OutPoint::new(previous_tx.txid(), vout), Sequence::MAX))
+                .collect(),
+            outputs: vec![TxOut::new(Amount::from_sat(80_000), p2pkh.clone())],
+            ..Default::default()
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx.clone()).unwrap();
+        psbt.inputs[0].non_witness_utxo = Some(previous_tx.clone());
+        psbt.inputs[1].witness_utxo = Some(previous_tx.outputs[1].clone());
+        psbt.inputs[1].witness_script = Some(witness_script);
+        assert_eq!(psbt.sign(&private_key).unwrap(), vec![0, 1]);
+
+        // the same signature sign_input puts in the script_sig
+        let mut signed = tx;
+        signed.sign_input(0, &private_key, &p2pkh).unwrap();
+        let script_sig_signature = match &signed.inputs[0].script_sig.commands()[0] {
+            Command::Push(signature) => signature.clone(),
+            command => panic!("unexpected {:?}", command),
+        };
+        assert_eq!(psbt.inputs[0].partial_sigs[&sec], script_sig_signature);
+        assert!(psbt.inputs[1].partial_sigs.contains_key(&sec));
+
+        // finalized inputs are left alone
+        psbt.inputs[0].partial_sigs.clear();
+        psbt.inputs[0].final_script_sig = Some(Script::from_hex("00").unwrap());
+        assert_eq!(psbt.sign(&private_key).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_sign_errors() {
+        let tx = Transaction::parse_hex(BIP143_UNSIGNED_TX, false).unwrap();
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+
+        // a transaction that isn't the one spent from
+        psbt.inputs[0].non_witness_utxo = Some(Transaction::default());
+        assert!(matches!(psbt.sign(&bip143_key()), Err(PsbtError::UtxoMismatch(0))));
+        psbt.inputs[0].non_witness_utxo = None;
+
+        // a redeem script that doesn't match the P2SH output
+        let p2sh = Script::from_hex("a914748284390f9e263a4b766a75d0633c50426eb87587").unwrap();
+        psbt.inputs[1].witness_utxo = Some(TxOut::new(Amount::from_sat(1_000), p2sh));
+        psbt.inputs[1].redeem_script = Some(Script::from_hex("51").unwrap());
+        assert!(matches!(psbt.sign(&bip143_key()), Err(PsbtError::ScriptMismatch(1))));
+
+        let script_pubkey = Script::from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        psbt.inputs[1].witness_utxo = Some(TxOut::new(Amount::from_sat(600_000_000), script_pubkey));
+        psbt.inputs[1].sighash_type = Some(0x04);
+        assert!(matches!(psbt.sign(&bip143_key()), Err(PsbtError::UnsupportedSighash(0x04))));
+    }
+}
@@ -0,0 +1,131 @@
+use scripts::Script;
+
+use crate::{amount::Amount, input::Sequence, utxo::UtxoProvider, witness::Witness, Transaction, TransactionError};
+
+/// The minimum feerate (sat/vB) a replacement has to pay for its own size on top of the fee it replaces (BIP125 rule 4)
+pub const INCREMENTAL_RELAY_FEE: u64 = 1;
+
+impl Transaction {
+    /// Whether the transaction opts in to replace-by-fee (BIP125): an input has a sequence below 0xfffffffe
+    pub fn signals_rbf(&self) -> bool {
+        self.inputs.iter().any(|input| input.sequence.is_rbf())
+    }
+
+    /// Builds a replacement that pays `new_feerate` sat/vB by taking the extra fee from the output at
+    /// `change_index`. The fee also grows by at least the incremental relay fee for its size, as BIP125 requires.
+    ///
+    /// The replacement spends the same outputs (looked up in `utxos`), with its script_sigs and witnesses
+    /// cleared: every input has to be signed again.
+    pub fn bump_fee(
+        &self,
+        utxos: &impl UtxoProvider,
+        new_feerate: u64,
+        change_index: usize,
+    ) -> Result<Transaction, TransactionError> {
+        if !self.signals_rbf() {
+            return Err(TransactionError::NotReplaceable);
+        }
+        if change_index >= self.outputs.len() {
+            return Err(TransactionError::OutputIndexOutOfRange(change_index));
+        }
+
+        let old_fee = self.fee(utxos)?;
+        let vsize = self.vsize();
+
+        let new_fee = Amount::from_sat(new_feerate)
+            .checked_mul(vsize)
+            .ok_or(TransactionError::ValueOverflow)?
+            .max(old_fee + Amount::from_sat(INCREMENTAL_RELAY_FEE * vsize));
+        let extra_fee = new_fee - old_fee;
+
+        let mut replacement = self.clone();
+        let change = &mut replacement.outputs[change_index];
+        change.value = change
+            .value
+            .checked_sub(extra_fee)
+            .ok_or_else(|| TransactionError::InsufficientChange(extra_fee - change.value))?;
+
+        for input in &mut replacement.inputs {
+            input.script_sig = Script::default();
+            input.witness = Witness::default();
+        }
+
+        Ok(replacement)
+    }
+}
+
+impl Sequence {
+    /// Whether an input with this sequence makes its transaction replaceable
+    pub fn is_rbf(self) -> bool {
+        self.0 < Sequence::ENABLE_LOCKTIME_NO_RBF.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{fixtures::RAW_TX, output::TxOut, txid::Txid};
+
+    /// The transaction, signaling RBF, with the output it spends
+    fn replaceable_tx() -> (Transaction, HashMap<(Txid, u32), TxOut>) {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        tx.inputs[0].sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+
+        let prev_txid = tx.inputs[0].prev_txid;
+        let utxos = HashMap::from([((prev_txid, 0), TxOut::new(Amount::from_sat(42505594), Script::default()))]);
+        (tx, utxos)
+    }
+
+    #[test]
+    fn test_signals_rbf() {
+        let (mut tx, _) = replaceable_tx();
+        assert!(tx.signals_rbf());
+
+        tx.inputs[0].sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+        assert!(!tx.signals_rbf());
+        tx.inputs[0].sequence = Sequence::MAX;
+        assert!(!tx.signals_rbf());
+    }
+
+    #[test]
+    fn test_bump_fee() {
+        let (tx, utxos) = replaceable_tx();
+        let size = tx.vsize();
+        assert_eq!(tx.fee(&utxos).unwrap(), Amount::from_sat(40000));
+
+        let replacement = tx.bump_fee(&utxos, 200, 1).unwrap();
+        assert_eq!(replacement.fee(&utxos).unwrap(), Amount::from_sat(200 * size));
+        assert_eq!(replacement.outputs[1].value, Amount::from_sat(10011545 - (200 * size - 40000)));
+        assert_eq!(replacement.outputs[0].value, tx.outputs[0].value);
+        assert!(replacement.inputs[0].script_sig.is_empty());
+        assert!(replacement.signals_rbf());
+
+        // a lower feerate still has to pay for the replacement's own size
+        let replacement = tx.bump_fee(&utxos, 1, 1).unwrap();
+        assert_eq!(replacement.fee(&utxos).unwrap(), Amount::from_sat(40000 + size));
+
+        // the witnesses of segwit inputs no longer sign the replacement either
+        let (mut tx, utxos) = replaceable_tx();
+        tx.inputs[0].witness = Witness::p2wpkh(vec![0x30; 72], vec![0x02; 33]);
+        let replacement = tx.bump_fee(&utxos, 200, 1).unwrap();
+        assert!(replacement.inputs[0].witness.is_empty());
+        assert!(!replacement.has_witness());
+    }
+
+    #[test]
+    fn test_bump_fee_errors() {
+        let (mut tx, utxos) = replaceable_tx();
+
+        assert!(matches!(tx.bump_fee(&utxos, 200, 2), Err(TransactionError::OutputIndexOutOfRange(2))));
+        assert!(matches!(
+            tx.bump_fee(&utxos, 1_000_000, 1),
+            Err(TransactionError::InsufficientChange(_))
+        ));
+        assert!(matches!(tx.bump_fee(&HashMap::new(), 200, 1), Err(TransactionError::MissingPrevout(0))));
+
+        tx.inputs[0].sequence = Sequence::MAX;
+        assert!(matches!(tx.bump_fee(&utxos, 200, 1), Err(TransactionError::NotReplaceable)));
+    }
+}
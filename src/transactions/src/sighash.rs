@@ -0,0 +1,624 @@
+use std::io::{self, Write};
+
+use ec_cryptography::{
+    consensus::{ConsensusEncode, VarInt},
+    helper::{hash256, sha256, Hasher},
+};
+
+use scripts::Script;
+
+pub use scripts::interpreter::{tap_leaf_hash, TapLeafSpend, TAPROOT_ANNEX_PREFIX, TAPSCRIPT_LEAF_VERSION};
+
+use crate::{
+    amount::Amount,
+    input::{Sequence, TxIn},
+    output::TxOut,
+    Transaction, TransactionError,
+};
+
+const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+/// Which parts of a transaction a signature commits to.
+///
+/// The base type picks the outputs: all of them, none of them, or only the one with the
+/// same index as the signed input. ANYONECANPAY additionally limits the inputs to the signed one,
+/// so others can add inputs without invalidating the signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SighashType {
+    All,
+    None,
+    Single,
+    AllPlusAnyoneCanPay,
+    NonePlusAnyoneCanPay,
+    SinglePlusAnyoneCanPay,
+}
+
+impl SighashType {
+    /// The value appended to the signature and to the signed message
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            SighashType::All => 0x01,
+            SighashType::None => 0x02,
+            SighashType::Single => 0x03,
+            SighashType::AllPlusAnyoneCanPay => 0x81,
+            SighashType::NonePlusAnyoneCanPay => 0x82,
+            SighashType::SinglePlusAnyoneCanPay => 0x83,
+        }
+    }
+
+    /// Reads the hash type the way consensus does: only the low 5 bits and the ANYONECANPAY bit count,
+    /// and unknown base types behave like ALL.
+    /// Note that the full value, not the normalized one, is what gets hashed.
+    pub fn from_consensus(value: u32) -> SighashType {
+        let anyone_can_pay = value & SIGHASH_ANYONECANPAY != 0;
+
+        match (value & 0x1f, anyone_can_pay) {
+            (0x02, false) => SighashType::None,
+            (0x03, false) => SighashType::Single,
+            (0x02, true) => SighashType::NonePlusAnyoneCanPay,
+            (0x03, true) => SighashType::SinglePlusAnyoneCanPay,
+            (_, false) => SighashType::All,
+            (_, true) => SighashType::AllPlusAnyoneCanPay,
+        }
+    }
+
+    /// Reads one of the six standard hash types, rejecting anything else
+    pub fn from_u32(value: u32) -> Option<SighashType> {
+        let sighash_type = Self::from_consensus(value);
+        (sighash_type.to_u32() == value).then_some(sighash_type)
+    }
+
+    pub fn is_anyone_can_pay(&self) -> bool {
+        self.to_u32() & SIGHASH_ANYONECANPAY != 0
+    }
+
+    /// The hash type without the ANYONECANPAY bit
+    pub fn base(&self) -> SighashType {
+        Self::from_consensus(self.to_u32() & !SIGHASH_ANYONECANPAY)
+    }
+}
+
+/// The hashes BIP143 shares between all inputs of a transaction.
+///
+/// Legacy signature hashing rehashes the whole transaction for every input, so its cost grows
+/// quadratically with the number of inputs. Computing these once avoids that.
+/// They are only valid as long as the transaction's inputs and outputs don't change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegwitV0Cache {
+    /// hash256 of the outpoints of all inputs
+    pub hash_prevouts: [u8; 32],
+    /// hash256 of the sequences of all inputs
+    pub hash_sequence: [u8; 32],
+    /// hash256 of all outputs
+    pub hash_outputs: [u8; 32],
+}
+
+impl SegwitV0Cache {
+    pub fn new(tx: &Transaction) -> Result<SegwitV0Cache, TransactionError> {
+        let mut prevouts = vec![];
+        let mut sequences = vec![];
+        for input in tx.inputs.iter() {
+            input.encode_outpoint(&mut prevouts)?;
+            input.sequence.0.consensus_encode(&mut sequences)?;
+        }
+
+        let mut outputs = vec![];
+        for output in tx.outputs.iter() {
+            output.consensus_encode(&mut outputs)?;
+        }
+
+        Ok(SegwitV0Cache {
+            hash_prevouts: hash256_array(&prevouts),
+            hash_sequence: hash256_array(&sequences),
+            hash_outputs: hash256_array(&outputs),
+        })
+    }
+}
+
+fn hash256_array(data: &[u8]) -> [u8; 32] {
+    hash256(data).try_into().expect("hash256 is 32 bytes")
+}
+
+impl Transaction {
+    /// The legacy signature hash of an input: the hash256 of a modified copy of the transaction
+    /// in which the signed input carries the script_pubkey it spends.
+    ///
+    /// For SIGHASH_SINGLE without a matching output this returns the number 1, as consensus does
+    /// (the "one-hash bug"), so such signatures commit to nothing at all.
+    pub fn sig_hash(
+        &self,
+        input_index: usize,
+        prev_script_pubkey: &Script,
+        sighash_type: SighashType,
+    ) -> Result<Vec<u8>, TransactionError> {
+        if input_index >= self.inputs.len() {
+            return Err(TransactionError::InputIndexOutOfRange(input_index));
+        }
+
+        let base = sighash_type.base();
+        if base == SighashType::Single && input_index >= self.outputs.len() {
+            let mut one = vec![0u8; 32];
+            one[0] = 0x01;
+            return Ok(one);
+        }
+
+        Ok(hash256(&self.legacy_preimage(input_index, prev_script_pubkey, sighash_type)?))
+    }
+
+    /// The message `sig_hash` hashes, for signers that hash it themselves.
+    /// SIGHASH_SINGLE without a matching output has no message, it signs the number 1.
+    pub fn legacy_preimage(
+        &self,
+        input_index: usize,
+        prev_script_pubkey: &Script,
+        sighash_type: SighashType,
+    ) -> Result<Vec<u8>, TransactionError> {
+        if input_index >= self.inputs.len() {
+            return Err(TransactionError::InputIndexOutOfRange(input_index));
+        }
+        if sighash_type.base() == SighashType::Single && input_index >= self.outputs.len() {
+            return Err(TransactionError::SingleWithoutOutput(input_index));
+        }
+
+        let mut message = vec![];
+        self.write_sig_hash_message(&mut message, input_index, prev_script_pubkey, sighash_type)?;
+        Ok(message)
+    }
+
+    /// The BIP143 signature hash of a segwit v0 input.
+    ///
+    /// `script_code` is the script being executed: for P2WPKH that is
+    /// the P2PKH script of the key hash, for P2WSH the witness script. Unlike the legacy hash,
+    /// this commits to the `amount` spent, so a signer can't be tricked about the fee.
+    pub fn segwit_v0_sig_hash(
+        &self,
+        input_index: usize,
+        script_code: &Script,
+        amount: Amount,
+        sighash_type: SighashType,
+    ) -> Result<Vec<u8>, TransactionError> {
+        let cache = SegwitV0Cache::new(self)?;
+        self.segwit_v0_sig_hash_with_cache(&cache, input_index, script_code, amount, sighash_type)
+    }
+
+    /// Same as `segwit_v0_sig_hash`, reusing hashes computed once for all inputs
+    pub fn segwit_v0_sig_hash_with_cache(
+        &self,
+        cache: &SegwitV0Cache,
+        input_index: usize,
+        script_code: &Script,
+        amount: Amount,
+        sighash_type: SighashType,
+    ) -> Result<Vec<u8>, TransactionError> {
+        let message = self.segwit_v0_message(cache, input_index, script_code, amount, sighash_type)?;
+        Ok(hash256(&message))
+    }
+
+    /// The message `segwit_v0_sig_hash` hashes, for signers that hash it themselves
+    pub fn segwit_v0_preimage(
+        &self,
+        input_index: usize,
+        script_code: &Script,
+        amount: Amount,
+        sighash_type: SighashType,
+    ) -> Result<Vec<u8>, TransactionError> {
+        self.segwit_v0_message(&SegwitV0Cache::new(self)?, input_index, script_code, amount, sighash_type)
+    }
+
+    fn segwit_v0_message(
+        &self,
+        cache: &SegwitV0Cache,
+        input_index: usize,
+        script_code: &Script,
+        amount: Amount,
+        sighash_type: SighashType,
+    ) -> Result<Vec<u8>, TransactionError> {
+        let input = self
+            .inputs
+            .get(input_index)
+            .ok_or(TransactionError::InputIndexOutOfRange(input_index))?;
+        let base = sighash_type.base();
+        let anyone_can_pay = sighash_type.is_anyone_can_pay();
+
+        // fields that are not signed are replaced with zeros
+        let hash_prevouts = if anyone_can_pay { [0u8; 32] } else { cache.hash_prevouts };
+        let hash_sequence = if anyone_can_pay || base != SighashType::All {
+            [0u8; 32]
+        } else {
+            cache.hash_sequence
+        };
+        let hash_outputs = match base {
+            SighashType::All => cache.hash_outputs,
+            // there is no one-hash bug here, a missing output just isn't signed
+            SighashType::Single if input_index < self.outputs.len() => {
+                hash256_array(&self.outputs[input_index].consensus_serialize())
+            }
+            _ => [0u8; 32],
+        };
+
+        let mut message = vec![];
+        self.version.consensus_encode(&mut message)?;
+        hash_prevouts.consensus_encode(&mut message)?;
+        hash_sequence.consensus_encode(&mut message)?;
+        input.encode_outpoint(&mut message)?;
+        script_code.consensus_encode(&mut message)?;
+        amount.consensus_encode(&mut message)?;
+        input.sequence.0.consensus_encode(&mut message)?;
+        hash_outputs.consensus_encode(&mut message)?;
+        self.locktime.consensus_encode(&mut message)?;
+        sighash_type.to_u32().consensus_encode(&mut message)?;
+
+        Ok(message)
+    }
+
+    /// The BIP341 signature hash of a taproot input.
+    ///
+    /// Taproot signatures commit to every output being spent, so `prevouts` must hold them all,
+    /// in input order. A `sighash_type` of `None` is SIGHASH_DEFAULT: it signs like ALL, but the
+    /// signature is 64 bytes without a hash type byte. `annex` is the last witness item when it
+    /// starts with 0x50, and `leaf` is set for script path spends.
+    pub fn taproot_sig_hash(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOut],
+        sighash_type: Option<SighashType>,
+        annex: Option<&[u8]>,
+        leaf: Option<TapLeafSpend>,
+    ) -> Result<Vec<u8>, TransactionError> {
+        let message = self.taproot_preimage(input_index, prevouts, sighash_type, annex, leaf)?;
+        Ok(Hasher::tagged("TapSighash").chain(&message).finalize())
+    }
+
+    /// The message `taproot_sig_hash` hashes with the TapSighash tag, for signers that hash it themselves
+    pub fn taproot_preimage(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOut],
+        sighash_type: Option<SighashType>,
+        annex: Option<&[u8]>,
+        leaf: Option<TapLeafSpend>,
+    ) -> Result<Vec<u8>, TransactionError> {
+        let input = self
+            .inputs
+            .get(input_index)
+            .ok_or(TransactionError::InputIndexOutOfRange(input_index))?;
+        if prevouts.len() != self.inputs.len() {
+            return Err(TransactionError::PrevoutsLengthMismatch(prevouts.len()));
+        }
+        if annex.is_some_and(|annex| annex.first() != Some(&TAPROOT_ANNEX_PREFIX)) {
+            return Err(TransactionError::InvalidAnnex);
+        }
+
+        let (hash_type, base, anyone_can_pay) = match sighash_type {
+            Some(sighash_type) => (sighash_type.to_u32() as u8, sighash_type.base(), sighash_type.is_anyone_can_pay()),
+            None => (0x00, SighashType::All, false),
+        };
+        if base == SighashType::Single && input_index >= self.outputs.len() {
+            return Err(TransactionError::SingleWithoutOutput(input_index));
+        }
+
+        // the epoch, reserved for future versions of the message
+        let mut message = vec![0x00, hash_type];
+        self.version.consensus_encode(&mut message)?;
+        self.locktime.consensus_encode(&mut message)?;
+
+        // unlike BIP143, these are single sha256 hashes
+        if !anyone_can_pay {
+            let mut prevout_bytes = vec![];
+            let mut amounts = vec![];
+            let mut script_pubkeys = vec![];
+            let mut sequences = vec![];
+            for (input, prevout) in self.inputs.iter().zip(prevouts) {
+                input.encode_outpoint(&mut prevout_bytes)?;
+                prevout.value.consensus_encode(&mut amounts)?;
+                prevout.script_pubkey.consensus_encode(&mut script_pubkeys)?;
+                input.sequence.0.consensus_encode(&mut sequences)?;
+            }
+
+            message.extend_from_slice(&sha256(&prevout_bytes));
+            message.extend_from_slice(&sha256(&amounts));
+            message.extend_from_slice(&sha256(&script_pubkeys));
+            message.extend_from_slice(&sha256(&sequences));
+        }
+        if base == SighashType::All {
+            let mut outputs = vec![];
+            for output in self.outputs.iter() {
+                output.consensus_encode(&mut outputs)?;
+            }
+            message.extend_from_slice(&sha256(&outputs));
+        }
+
+        // twice the extension flag, which is 1 for tapscript spends, plus whether there is an annex
+        let spend_type = 2 * leaf.is_some() as u8 + annex.is_some() as u8;
+        spend_type.consensus_encode(&mut message)?;
+
+        if anyone_can_pay {
+            let prevout = &prevouts[input_index];
+            input.encode_outpoint(&mut message)?;
+            prevout.value.consensus_encode(&mut message)?;
+            prevout.script_pubkey.consensus_encode(&mut message)?;
+            input.sequence.0.consensus_encode(&mut message)?;
+        } else {
+            (input_index as u32).consensus_encode(&mut message)?;
+        }
+
+        if let Some(annex) = annex {
+            let mut annex_bytes = vec![];
+            VarInt(annex.len() as u64).consensus_encode(&mut annex_bytes)?;
+            annex_bytes.extend_from_slice(annex);
+            message.extend_from_slice(&sha256(&annex_bytes));
+        }
+        if base == SighashType::Single {
+            message.extend_from_slice(&sha256(&self.outputs[input_index].consensus_serialize()));
+        }
+
+        if let Some(leaf) = leaf {
+            message.extend_from_slice(&leaf.leaf_hash);
+            // the key version, 0 for BIP340 keys
+            0u8.consensus_encode(&mut message)?;
+            leaf.codesep_pos.consensus_encode(&mut message)?;
+        }
+
+        Ok(message)
+    }
+
+    fn write_sig_hash_message<W: Write>(
+        &self,
+        writer: &mut W,
+        input_index: usize,
+        prev_script_pubkey: &Script,
+        sighash_type: SighashType,
+    ) -> io::Result<()> {
+        let base = sighash_type.base();
+        self.version.consensus_encode(writer)?;
+
+        // only the signed input gets a script, the others are blanked out. With NONE and SINGLE,
+        // the other inputs' sequences are not signed either, so they can be updated
+        let inputs = self.inputs.iter().enumerate().filter(|(index, _)| {
+            !sighash_type.is_anyone_can_pay() || *index == input_index
+        });
+        let inputs = inputs
+            .map(|(index, input)| {
+                let signed = index == input_index;
+                let script_sig = if signed { prev_script_pubkey.clone() } else { Script::default() };
+                let sequence = if signed || base == SighashType::All { input.sequence.0 } else { 0 };
+
+                TxIn::new(input.prev_txid, input.prev_index, script_sig, Sequence::new(sequence))
+            })
+            .collect::<Vec<TxIn>>();
+        inputs.consensus_encode(writer)?;
+
+        let outputs = match base {
+            SighashType::None => vec![],
+            // outputs before the signed one are replaced with empty outputs of value -1
+            SighashType::Single => self.outputs[..=input_index]
+                .iter()
+                .enumerate()
+                .map(|(index, output)| {
+                    if index == input_index {
+                        output.clone()
+                    } else {
+                        TxOut::new(Amount::from_sat(u64::MAX), Script::default())
+                    }
+                })
+                .collect(),
+            _ => self.outputs.clone(),
+        };
+        outputs.consensus_encode(writer)?;
+
+        self.locktime.consensus_encode(writer)?;
+        sighash_type.to_u32().consensus_encode(writer)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::to_hex;
+
+    use super::*;
+    use crate::fixtures::{BIP143_UNSIGNED_TX, RAW_TX};
+
+    fn prev_script_pubkey() -> Script {
+        Script::from_hex("76a914a802fc56c704ce87c42d7c92eb75e7896bdc41ae88ac").unwrap()
+    }
+
+    fn add_input(tx: &mut Transaction, sequence: u32) {
+        tx.inputs.push(TxIn::new(
+            "0437cd7f8525ceed2324359c2d0ba26006d92d856a9c20fa0241106ee5a597c9".parse().unwrap(),
+            1,
+            Script::default(),
+            Sequence::new(sequence),
+        ));
+    }
+
+    #[test]
+    fn test_sighash_type_values() {
+        for value in [0x01, 0x02, 0x03, 0x81, 0x82, 0x83] {
+            assert_eq!(SighashType::from_u32(value).unwrap().to_u32(), value);
+        }
+        assert_eq!(SighashType::from_u32(0x00), None);
+        assert_eq!(SighashType::from_u32(0x04), None);
+
+        assert_eq!(SighashType::from_consensus(0x00), SighashType::All);
+        assert_eq!(SighashType::from_consensus(0x43), SighashType::Single);
+        assert_eq!(SighashType::from_consensus(0x84), SighashType::AllPlusAnyoneCanPay);
+
+        assert!(SighashType::NonePlusAnyoneCanPay.is_anyone_can_pay());
+        assert_eq!(SighashType::SinglePlusAnyoneCanPay.base(), SighashType::Single);
+        assert_eq!(SighashType::All.base(), SighashType::All);
+    }
+
+    #[test]
+    fn test_sighash_all() {
+        let tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        let hash = tx.sig_hash(0, &prev_script_pubkey(), SighashType::All).unwrap();
+        assert_eq!(to_hex(&hash), "27e0c5994dec7824e56dec6b2fcb342eb7cdb0d0957c2fce9882f715e85d81a6");
+
+        assert!(matches!(
+            tx.sig_hash(1, &prev_script_pubkey(), SighashType::All),
+            Err(TransactionError::InputIndexOutOfRange(1))
+        ));
+    }
+
+    #[test]
+    fn test_sighash_none_and_single() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        add_input(&mut tx, 0xffffffff);
+
+        let all = tx.sig_hash(0, &prev_script_pubkey(), SighashType::All).unwrap();
+        let none = tx.sig_hash(0, &prev_script_pubkey(), SighashType::None).unwrap();
+        let single = tx.sig_hash(0, &prev_script_pubkey(), SighashType::Single).unwrap();
+
+        // NONE ignores all outputs, SINGLE only the ones after the signed input
+        tx.outputs[1].value += Amount::ONE_SAT;
+        assert_ne!(tx.sig_hash(0, &prev_script_pubkey(), SighashType::All).unwrap(), all);
+        assert_eq!(tx.sig_hash(0, &prev_script_pubkey(), SighashType::None).unwrap(), none);
+        assert_eq!(tx.sig_hash(0, &prev_script_pubkey(), SighashType::Single).unwrap(), single);
+        assert_ne!(tx.sig_hash(1, &prev_script_pubkey(), SighashType::Single).unwrap(), single);
+
+        // neither signs the sequence of the other inputs
+        tx.inputs[1].sequence = Sequence::new(0);
+        assert_eq!(tx.sig_hash(0, &prev_script_pubkey(), SighashType::None).unwrap(), none);
+        assert_eq!(tx.sig_hash(0, &prev_script_pubkey(), SighashType::Single).unwrap(), single);
+    }
+
+    #[test]
+    fn test_sighash_anyone_can_pay() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        let hash = tx.sig_hash(0, &prev_script_pubkey(), SighashType::AllPlusAnyoneCanPay).unwrap();
+        assert_ne!(hash, tx.sig_hash(0, &prev_script_pubkey(), SighashType::All).unwrap());
+
+        // other inputs can be added without invalidating the signature
+        add_input(&mut tx, 0xfffffffe);
+        assert_eq!(tx.sig_hash(0, &prev_script_pubkey(), SighashType::AllPlusAnyoneCanPay).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_sighash_single_bug() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        add_input(&mut tx, 0xffffffff);
+        add_input(&mut tx, 0xffffffff);
+
+        // input 2 has no matching output
+        let mut one = vec![0u8; 32];
+        one[0] = 0x01;
+        assert_eq!(tx.sig_hash(2, &prev_script_pubkey(), SighashType::Single).unwrap(), one);
+        assert_eq!(tx.sig_hash(2, &prev_script_pubkey(), SighashType::SinglePlusAnyoneCanPay).unwrap(), one);
+        assert_ne!(tx.sig_hash(1, &prev_script_pubkey(), SighashType::Single).unwrap(), one);
+    }
+
+    #[test]
+    fn test_segwit_v0_sighash() {
+        // native P2WPKH example from BIP143
+        let script_code = &Script::from_hex("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap();
+        let tx = Transaction::parse_hex(BIP143_UNSIGNED_TX, false).unwrap();
+
+        let cache = SegwitV0Cache::new(&tx).unwrap();
+        assert_eq!(to_hex(&cache.hash_prevouts), "96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37");
+        assert_eq!(to_hex(&cache.hash_sequence), "52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b");
+        assert_eq!(to_hex(&cache.hash_outputs), "863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5");
+
+        let hash = tx.segwit_v0_sig_hash(1, script_code, Amount::from_sat(600_000_000), SighashType::All).unwrap();
+        assert_eq!(to_hex(&hash), "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670");
+        assert_eq!(
+            tx.segwit_v0_sig_hash_with_cache(&cache, 1, script_code, Amount::from_sat(600_000_000), SighashType::All).unwrap(),
+            hash
+        );
+
+        // the amount is signed
+        assert_ne!(tx.segwit_v0_sig_hash(1, script_code, Amount::from_sat(600_000_001), SighashType::All).unwrap(), hash);
+        assert!(matches!(
+            tx.segwit_v0_sig_hash(2, script_code, Amount::from_sat(600_000_000), SighashType::All),
+            Err(TransactionError::InputIndexOutOfRange(2))
+        ));
+    }
+
+    #[test]
+    fn test_segwit_v0_sighash_single_without_output() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        add_input(&mut tx, 0xffffffff);
+        add_input(&mut tx, 0xffffffff);
+
+        // unlike the legacy hash, it still commits to the input being spent
+        let first = tx.segwit_v0_sig_hash(2, &prev_script_pubkey(), Amount::from_sat(1000), SighashType::Single).unwrap();
+        let mut one = vec![0u8; 32];
+        one[0] = 0x01;
+        assert_ne!(first, one);
+        assert_ne!(tx.segwit_v0_sig_hash(2, &prev_script_pubkey(), Amount::from_sat(2000), SighashType::Single).unwrap(), first);
+    }
+
+    fn p2tr_script(key_byte: u8) -> Script {
+        Script::p2tr(&[key_byte; 32])
+    }
+
+    fn taproot_prevouts() -> Vec<TxOut> {
+        vec![
+            TxOut::new(Amount::from_sat(42_000_000), p2tr_script(0x11)),
+            TxOut::new(Amount::from_sat(1000), p2tr_script(0x22)),
+        ]
+    }
+
+    #[test]
+    fn test_tap_leaf_hash() {
+        let op_true = Script::from_hex("51").unwrap();
+        let hash = tap_leaf_hash(&op_true, TAPSCRIPT_LEAF_VERSION);
+        assert_eq!(to_hex(&hash), "a85b2107f791b26a84e7586c28cec7cb61202ed3d01944d832500f363782d675");
+    }
+
+    #[test]
+    fn test_taproot_sighash() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        add_input(&mut tx, 0xffffffff);
+        let prevouts = taproot_prevouts();
+
+        let cases = [
+            (0, None, "e1fc12ad3939a09bc9c34d50919c3e4896ee1484b3deb4aa492cd0dee3cbdede"),
+            (1, Some(SighashType::All), "6d77985f793fbf596bbf7ba62ce0d3261dcd17aab9714f64e8cc122c712cf2b6"),
+            (1, Some(SighashType::NonePlusAnyoneCanPay), "a57a17988026d7f3ad29a5109f5ce62706672324254e20acc7a908b2fdc44178"),
+        ];
+        for (index, sighash_type, expected) in cases {
+            let hash = tx.taproot_sig_hash(index, &prevouts, sighash_type, None, None).unwrap();
+            assert_eq!(to_hex(&hash), expected);
+        }
+
+        let annex = [TAPROOT_ANNEX_PREFIX, 0xaa];
+        let hash = tx
+            .taproot_sig_hash(1, &prevouts, Some(SighashType::SinglePlusAnyoneCanPay), Some(&annex), None)
+            .unwrap();
+        assert_eq!(to_hex(&hash), "e0b746096a1a5749d01c25409bc6ac730561beb2321f12f4d49bf56195079ae8");
+
+        let op_true = Script::from_hex("51").unwrap();
+        let leaf = TapLeafSpend::new(tap_leaf_hash(&op_true, TAPSCRIPT_LEAF_VERSION));
+        let hash = tx.taproot_sig_hash(0, &prevouts, None, None, Some(leaf)).unwrap();
+        assert_eq!(to_hex(&hash), "30879700c77f972dd3ffc64bd69dea3e300fb7a8dff7c79a806273722dda9ab3");
+    }
+
+    #[test]
+    fn test_taproot_sighash_errors() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        add_input(&mut tx, 0xffffffff);
+        add_input(&mut tx, 0xffffffff);
+        let mut prevouts = taproot_prevouts();
+
+        assert!(matches!(
+            tx.taproot_sig_hash(0, &prevouts, None, None, None),
+            Err(TransactionError::PrevoutsLengthMismatch(2))
+        ));
+
+        prevouts.push(TxOut::new(Amount::from_sat(1000), p2tr_script(0x33)));
+        assert!(matches!(
+            tx.taproot_sig_hash(2, &prevouts, Some(SighashType::Single), None, None),
+            Err(TransactionError::SingleWithoutOutput(2))
+        ));
+        assert!(matches!(
+            tx.taproot_sig_hash(0, &prevouts, None, Some(&[0x51]), None),
+            Err(TransactionError::InvalidAnnex)
+        ));
+        assert!(matches!(
+            tx.taproot_sig_hash(3, &prevouts, None, None, None),
+            Err(TransactionError::InputIndexOutOfRange(3))
+        ));
+    }
+}
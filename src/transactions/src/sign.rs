@@ -0,0 +1,352 @@
+use ec_cryptography::{
+    helper::{from_hex, hash160},
+    traits::Serializer,
+    PrivateKey,
+};
+use rug::{integer::Order, Integer};
+use scripts::{Command, Script};
+
+use crate::{
+    output::TxOut,
+    sighash::{SegwitV0Cache, SighashType},
+    utxo::UtxoProvider,
+    witness::Witness,
+    Transaction, TransactionError,
+};
+
+/// The private keys a wallet signs with. [`Transaction::sign_all`] looks among them for
+/// the key that can spend each input.
+#[derive(Debug, Default)]
+pub struct KeyStore {
+    keys: Vec<PrivateKey>,
+}
+
+impl KeyStore {
+    pub fn new() -> KeyStore {
+        KeyStore { keys: vec![] }
+    }
+
+    pub fn add_key(&mut self, private_key: PrivateKey) {
+        self.keys.push(private_key);
+    }
+
+    /// How one of the keys can spend an output with this script_pubkey, if any can
+    fn spend(&self, script_pubkey: &Script) -> Option<Spend<'_>> {
+        self.keys.iter().find_map(|key| {
+            let compressed_sec = from_hex(&key.point().sec(true)).ok()?;
+            let key_hash = hash160(&compressed_sec);
+
+            match script_pubkey.as_bytes() {
+                [0x76, 0xa9, 0x14, .., 0x88, 0xac] => {
+                    p2pkh_sec(key, script_pubkey).map(|sec| Spend::P2pkh { key, sec })
+                }
+                [0x00, 0x14, hash @ ..] if hash == key_hash.as_slice() => {
+                    Some(Spend::P2wpkh { key, sec: compressed_sec, nested: false })
+                }
+                [0xa9, 0x14, hash @ .., 0x87] if hash == hash160(&p2wpkh_script(&key_hash)).as_slice() => {
+                    Some(Spend::P2wpkh { key, sec: compressed_sec, nested: true })
+                }
+                [0x51, 0x20, output_key @ ..] => {
+                    let tweaked_key = key.tap_tweak(None);
+                    let pays_to_key = output_key == tweaked_key.x_only_public_key().as_slice();
+                    pays_to_key.then(|| Spend::P2tr(Box::new(tweaked_key)))
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+/// How an input gets signed, with the key that signs it
+enum Spend<'a> {
+    P2pkh { key: &'a PrivateKey, sec: Vec<u8> },
+    /// P2WPKH, or P2SH-P2WPKH when `nested`, with the compressed SEC
+    P2wpkh { key: &'a PrivateKey, sec: Vec<u8>, nested: bool },
+    /// A taproot key path spend, with the tweaked key of an output that commits to no scripts (BIP86)
+    P2tr(Box<PrivateKey>),
+}
+
+impl Transaction {
+    /// Signs a P2PKH input with SIGHASH_ALL and fills in its script_sig,
+    /// so once every input is signed the transaction can be broadcast.
+    ///
+    /// `prev_script_pubkey` is the script_pubkey of the output being spent. It must pay to the hash
+    /// of the key's compressed or uncompressed SEC, which then goes in the script_sig.
+    pub fn sign_input(
+        &mut self,
+        input_index: usize,
+        private_key: &PrivateKey,
+        prev_script_pubkey: &Script,
+    ) -> Result<(), TransactionError> {
+        let sec = p2pkh_sec(private_key, prev_script_pubkey).ok_or(TransactionError::KeyMismatch)?;
+
+        let z = self.sig_hash(input_index, prev_script_pubkey, SighashType::All)?;
+        let sig = ecdsa_signature(private_key, &z);
+
+        self.inputs[input_index].script_sig = Script::p2pkh_script_sig(&sig, &sec);
+        Ok(())
+    }
+
+    /// Signs every input that a key in `keys` can spend, with SIGHASH_ALL (SIGHASH_DEFAULT for taproot),
+    /// and returns the indexes of the inputs signed. Other inputs are left for someone else to sign.
+    ///
+    /// The outputs spent are looked up in `utxos`, and each one's script_pubkey picks how its input is signed:
+    /// P2PKH gets a script_sig, P2WPKH a witness, P2SH-P2WPKH both, and taproot outputs of a key without
+    /// a script tree a key path witness. Every output must be known, as segwit and taproot signatures
+    /// commit to the amounts.
+    pub fn sign_all(&mut self, keys: &KeyStore, utxos: &impl UtxoProvider) -> Result<Vec<usize>, TransactionError> {
+        let prevouts = self.spent_outputs(utxos)?;
+        // signatures don't commit to script_sigs or witnesses, so the cache outlives the signing
+        let cache = SegwitV0Cache::new(self)?;
+
+        let mut signed = vec![];
+        for (index, prevout) in prevouts.iter().enumerate() {
+            let Some(spend) = keys.spend(&prevout.script_pubkey) else {
+                continue;
+            };
+
+            match spend {
+                Spend::P2pkh { key, sec } => {
+                    let z = self.sig_hash(index, &prevout.script_pubkey, SighashType::All)?;
+                    let sig = ecdsa_signature(key, &z);
+                    self.inputs[index].script_sig = Script::p2pkh_script_sig(&sig, &sec);
+                }
+                Spend::P2wpkh { key, sec, nested } => {
+                    // the script code of P2WPKH is the P2PKH script of the key hash
+                    let key_hash: [u8; 20] = hash160(&sec).try_into().expect("hash160 is 20 bytes");
+                    let z = self.segwit_v0_sig_hash_with_cache(
+                        &cache,
+                        index,
+                        &Script::p2pkh(&key_hash),
+                        prevout.value,
+                        SighashType::All,
+                    )?;
+
+                    let input = &mut self.inputs[index];
+                    input.witness = Witness::p2wpkh(ecdsa_signature(key, &z), sec);
+                    if nested {
+                        input.script_sig = Script::from_commands(vec![Command::Push(p2wpkh_script(&key_hash))]);
+                    }
+                }
+                Spend::P2tr(tweaked_key) => {
+                    self.inputs[index].witness = self.taproot_key_spend_witness(index, &tweaked_key, &prevouts, None)?;
+                }
+            }
+            signed.push(index);
+        }
+
+        Ok(signed)
+    }
+
+    /// Signs a taproot input through the key path (BIP341) and fills in its witness.
+    ///
+    /// `internal_key` is the key before tweaking. It gets tweaked with `merkle_root`, the root of the
+    /// output's script tree (`None` for outputs without one, as in BIP86), and the result must be the
+    /// output key of the spent script_pubkey. A `sighash_type` of `None` is SIGHASH_DEFAULT, with a
+    /// 64-byte signature; other types append their byte to it. Every output spent must be in `utxos`,
+    /// as taproot signatures commit to all of them.
+    pub fn sign_taproot_key_spend(
+        &mut self,
+        input_index: usize,
+        internal_key: &PrivateKey,
+        merkle_root: Option<&[u8; 32]>,
+        sighash_type: Option<SighashType>,
+        utxos: &impl UtxoProvider,
+    ) -> Result<(), TransactionError> {
+        if input_index >= self.inputs.len() {
+            return Err(TransactionError::InputIndexOutOfRange(input_index));
+        }
+        let prevouts = self.spent_outputs(utxos)?;
+
+        let tweaked_key = internal_key.tap_tweak(merkle_root);
+        let expected = [[0x51, 0x20].as_slice(), &tweaked_key.x_only_public_key()].concat();
+        if prevouts[input_index].script_pubkey.as_bytes() != expected {
+            return Err(TransactionError::KeyMismatch);
+        }
+
+        self.inputs[input_index].witness =
+            self.taproot_key_spend_witness(input_index, &tweaked_key, &prevouts, sighash_type)?;
+        Ok(())
+    }
+
+    /// The key path witness of a taproot input, signed with the tweaked key
+    fn taproot_key_spend_witness(
+        &self,
+        input_index: usize,
+        tweaked_key: &PrivateKey,
+        prevouts: &[TxOut],
+        sighash_type: Option<SighashType>,
+    ) -> Result<Witness, TransactionError> {
+        let z = self.taproot_sig_hash(input_index, prevouts, sighash_type, None, None)?;
+        let z: [u8; 32] = z.try_into().expect("the sighash is 32 bytes");
+        // no auxiliary randomness, so signing is deterministic like the ECDSA signatures
+        let mut sig = tweaked_key.sign_schnorr(&z, &[0; 32]).to_vec();
+        if let Some(sighash_type) = sighash_type {
+            sig.push(sighash_type.to_u32() as u8);
+        }
+
+        Ok(Witness::p2tr_key_spend(sig))
+    }
+
+    /// The outputs the inputs spend, looked up in `utxos`, in input order
+    pub(crate) fn spent_outputs(&self, utxos: &impl UtxoProvider) -> Result<Vec<TxOut>, TransactionError> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                utxos
+                    .get_output(&input.prev_txid, input.prev_index)
+                    .ok_or(TransactionError::MissingPrevout(index))
+            })
+            .collect()
+    }
+}
+
+/// The DER signature of the hash z, with the SIGHASH_ALL byte appended
+pub(crate) fn ecdsa_signature(private_key: &PrivateKey, z: &[u8]) -> Vec<u8> {
+    let mut sig = private_key.sign(Integer::from_digits(z, Order::MsfBe)).der_bytes();
+    sig.push(SighashType::All.to_u32() as u8);
+    sig
+}
+
+/// The P2WPKH script_pubkey paying to the key hash, which is also the redeem script of P2SH-P2WPKH
+pub(crate) fn p2wpkh_script(key_hash: &[u8]) -> Vec<u8> {
+    [[0x00, 0x14].as_slice(), key_hash].concat()
+}
+
+/// The SEC encoding of the key whose hash the P2PKH script pays to, if it is this key's
+fn p2pkh_sec(private_key: &PrivateKey, script_pubkey: &Script) -> Option<Vec<u8>> {
+    let key_hash = script_pubkey.p2pkh_hash()?;
+    [true, false].into_iter().find_map(|is_compressed| {
+        let sec = from_hex(&private_key.point().sec(is_compressed)).ok()?;
+        (hash160(&sec) == key_hash).then_some(sec)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ec_cryptography::helper::to_hex;
+
+    use super::*;
+    use crate::{
+        amount::Amount,
+        input::{OutPoint, Sequence, TxIn},
+        txid::Txid,
+    };
+
+    const UNSIGNED_TX: &str = "010000000199a24308080ab26e6fb65c4eccfadf76749bb5bfa8cb08f291320b3c21e56f0d0d00000000ffffffff02408af701000000001976a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac80969800000000001976a914507b27411ccf7f16f10297de6cef3f291623eddf88ac00000000";
+
+    fn prev_script_pubkey() -> Script {
+        Script::from_hex("76a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac").unwrap()
+    }
+
+    #[test]
+    fn test_sign_input() {
+        let private_key = PrivateKey::new(Integer::from(8675309));
+        let mut tx = Transaction::parse_hex(UNSIGNED_TX, true).unwrap();
+
+        tx.sign_input(0, &private_key, &prev_script_pubkey()).unwrap();
+        assert_eq!(
+            to_hex(&tx.serialize()),
+            "010000000199a24308080ab26e6fb65c4eccfadf76749bb5bfa8cb08f291320b3c21e56f0d0d0000006b4830450221008ed46aa2cf12d6d81065bfabe903670165b538f65ee9a3385e6327d80c66d3b502203124f804410527497329ec4715e18558082d489b218677bd029e7fa306a72236012103935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b67ffffffff02408af701000000001976a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac80969800000000001976a914507b27411ccf7f16f10297de6cef3f291623eddf88ac00000000"
+        );
+    }
+
+    #[test]
+    fn test_sign_input_with_wrong_key() {
+        let private_key = PrivateKey::new(Integer::from(8675310));
+        let mut tx = Transaction::parse_hex(UNSIGNED_TX, true).unwrap();
+
+        assert!(matches!(
+            tx.sign_input(0, &private_key, &prev_script_pubkey()),
+            Err(TransactionError::KeyMismatch)
+        ));
+        assert!(matches!(
+            tx.sign_input(1, &PrivateKey::new(Integer::from(8675309)), &prev_script_pubkey()),
+            Err(TransactionError::InputIndexOutOfRange(1))
+        ));
+    }
+
+    #[test]
+    fn test_sign_all() {
+        let mut keys = KeyStore::new();
+        for secret in 1..=4 {
+            keys.add_key(PrivateKey::new(Integer::from(secret)));
+        }
+
+        // P2PKH to key 1, P2WPKH to key 2, P2SH-P2WPKH to key 3, taproot to key 4, and a P2WPKH of no key
+        let script_pubkeys = [
+            "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac",
+            "001406afd46bcdfd22ef94ac122aa11f241244a37ecc",
+            "a91469ea5ff598a286f418ae77503ce85d83da4ae88e87",
+            "51209317856ed22b3699e792f38ad803f9f3fafabf70a2c8983af715592242c29ca8",
+            "00140000000000000000000000000000000000000000",
+        ];
+        let mut tx = Transaction::default();
+        let mut utxos = HashMap::new();
+        for (index, script_pubkey) in script_pubkeys.iter().enumerate() {
+            let outpoint = OutPoint::new(Txid::from_bytes([index as u8 + 1; 32]), 0);
+            tx.inputs.push(TxIn::from_outpoint(outpoint, Sequence::MAX));
+            let prevout = TxOut::new(Amount::from_sat(100_000), Script::from_hex(script_pubkey).unwrap());
+            utxos.insert((outpoint.txid, 0), prevout);
+        }
+        tx.outputs.push(TxOut::new(Amount::from_sat(450_000), prev_script_pubkey()));
+
+        let unsigned = tx.clone();
+        assert_eq!(tx.sign_all(&keys, &utxos).unwrap(), vec![0, 1, 2, 3]);
+        // the signatures check out against the legacy, BIP143 and BIP341 sighashes
+        assert_eq!(
+            to_hex(&tx.serialize()),
+            "000000000001050101010101010101010101010101010101010101010101010101010101010101000000006b483045022100f37f4d80c8ef854c589977a8b1206ce6662b56a6190eee3cfeb2c3c7781696a002200c5d33a9a1fe51c21ccc1cfb0a75cee7dbda915f41dc69291716387960a4667201210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ffffffff02020202020202020202020202020202020202020202020202020202020202020000000000ffffffff030303030303030303030303030303030303030303030303030303030303030300000000171600147dd65592d0ab2fe0d0257d571abf032cd9db93dcffffffff04040404040404040404040404040404040404040404040404040404040404040000000000ffffffff05050505050505050505050505050505050505050505050505050505050505050000000000ffffffff01d0dd0600000000001976a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac000247304402203c1ce592d712bc43cdc668b1fd16e48b4a2cd583aca7701821b4e3389b57a209022069eb4c1fc86dd4ace49b01cf2443e3d2f2fda779da1d8ee47092a53b42e9b341012102c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee502483045022100c47f27dea9cce849995342d32671389a5a76b06f7d3ec35a3a7a69895afc33b502203e341ef45831207241d14158d1702788b955bda2994e0909883fc35d112d0110012102f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f90140b0ce0caab1ce2a42c9fa36fdf01a9dec608a98eb3617469d980d72c0c62a8b456728e2313d17702b3f3a41a9ae899c834295f7ef4427e4fbdf9405f2a16723500000000000"
+        );
+        assert!(tx.inputs[4].witness.is_empty());
+
+        // every spent output has to be known
+        let mut tx = unsigned;
+        utxos.remove(&(Txid::from_bytes([5; 32]), 0));
+        assert!(matches!(tx.sign_all(&keys, &utxos), Err(TransactionError::MissingPrevout(4))));
+        assert!(tx.inputs[0].script_sig.is_empty());
+    }
+
+    #[test]
+    fn test_sign_taproot_key_spend() {
+        let internal_key = PrivateKey::new(Integer::from(4));
+        let merkle_root = [7u8; 32];
+        let output_key = internal_key.tap_tweak(Some(&merkle_root)).x_only_public_key();
+
+        let outpoint = OutPoint::new(Txid::from_bytes([1; 32]), 0);
+        let script_pubkey = Script::from_bytes([[0x51, 0x20].as_slice(), &output_key].concat());
+        let utxos = HashMap::from([((outpoint.txid, 0), TxOut::new(Amount::from_sat(100_000), script_pubkey))]);
+        let mut tx = Transaction {
+            inputs: vec![TxIn::from_outpoint(outpoint, Sequence::MAX)],
+            outputs: vec![TxOut::new(Amount::from_sat(90_000), prev_script_pubkey())],
+            ..Default::default()
+        };
+
+        // SIGHASH_DEFAULT signatures are 64 bytes, others carry the hash type
+        tx.sign_taproot_key_spend(0, &internal_key, Some(&merkle_root), None, &utxos).unwrap();
+        assert_eq!(tx.inputs[0].witness[0].len(), 64);
+        assert!(tx.verify_input(0, &utxos).is_ok());
+
+        tx.sign_taproot_key_spend(0, &internal_key, Some(&merkle_root), Some(SighashType::All), &utxos).unwrap();
+        assert_eq!(tx.inputs[0].witness[0].len(), 65);
+        assert_eq!(tx.inputs[0].witness[0][64], 0x01);
+        assert!(tx.verify_input(0, &utxos).is_ok());
+
+        // the output commits to a script tree, so the untweaked BIP86 key doesn't match
+        assert!(matches!(
+            tx.sign_taproot_key_spend(0, &internal_key, None, None, &utxos),
+            Err(TransactionError::KeyMismatch)
+        ));
+        assert!(matches!(
+            tx.sign_taproot_key_spend(1, &internal_key, None, None, &utxos),
+            Err(TransactionError::InputIndexOutOfRange(1))
+        ));
+        assert!(matches!(
+            tx.sign_taproot_key_spend(0, &internal_key, None, None, &HashMap::new()),
+            Err(TransactionError::MissingPrevout(0))
+        ));
+    }
+}
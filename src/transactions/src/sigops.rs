@@ -0,0 +1,99 @@
+use crate::{utxo::UtxoProvider, Transaction, TransactionError};
+
+/// The most the signature operations of a block can cost (BIP141)
+pub const MAX_BLOCK_SIGOPS_COST: usize = 80_000;
+/// Signature operations outside witnesses cost this much, the way their bytes weigh four times as much
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+/// The most the signature operations of a transaction nodes relay can cost, a fifth of a block's
+pub const MAX_STANDARD_TX_SIGOPS_COST: usize = MAX_BLOCK_SIGOPS_COST / 5;
+
+impl Transaction {
+    /// The signature operations of the script_sigs and the script_pubkeys of the outputs, counted
+    /// without looking at what the inputs spend (Core's GetLegacySigOpCount)
+    pub fn legacy_sigop_count(&self) -> usize {
+        let script_sigs = self.inputs.iter().map(|input| input.script_sig.sigop_count(false));
+        let script_pubkeys = self.outputs.iter().map(|output| output.script_pubkey.sigop_count(false));
+        script_sigs.chain(script_pubkeys).sum()
+    }
+
+    /// What the signature operations of the transaction cost against MAX_BLOCK_SIGOPS_COST
+    /// (Core's GetTransactionSigOpCost): legacy ones and those of P2SH redeem scripts cost WITNESS_SCALE_FACTOR,
+    /// those of witnesses one. The outputs the inputs spend are looked up in `utxos`, except for a coinbase,
+    /// which spends none.
+    pub fn sigop_cost(&self, utxos: &impl UtxoProvider) -> Result<usize, TransactionError> {
+        let legacy_cost = self.legacy_sigop_count() * WITNESS_SCALE_FACTOR;
+        if self.is_coinbase() {
+            return Ok(legacy_cost);
+        }
+
+        let prevouts = self.spent_outputs(utxos)?;
+        let cost = self.inputs.iter().zip(&prevouts).fold(legacy_cost, |cost, (input, prevout)| {
+            let script_pubkey = &prevout.script_pubkey;
+            cost + script_pubkey.p2sh_sigop_count(&input.script_sig) * WITNESS_SCALE_FACTOR
+                + script_pubkey.witness_sigop_count(&input.script_sig, input.witness.items())
+        });
+        Ok(cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use scripts::{Command, Script};
+
+    use super::*;
+    use crate::{
+        amount::Amount,
+        coinbase::COINBASE_PREV_INDEX,
+        input::{OutPoint, Sequence, TxIn},
+        output::TxOut,
+        txid::Txid,
+        witness::Witness,
+    };
+
+    const KEY: &str = "2103935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b67";
+
+    #[test]
+    fn test_sigop_cost() {
+        let p2pkh = Script::from_hex("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").unwrap();
+        let p2wpkh = Script::from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        let multisig = Script::from_hex(&format!("52{}53ae", KEY.repeat(3))).unwrap();
+        let signatures = vec![Command::Op(0x00), Command::Push(vec![0x30; 71]), Command::Push(vec![0x30; 71])];
+
+        // P2PKH, P2SH 2-of-3, P2WPKH and P2WSH 2-of-3 spends, paying to a P2PKH output
+        let mut tx = Transaction::default();
+        let mut utxos = HashMap::new();
+        let script_pubkeys = [p2pkh.clone(), multisig.to_p2sh(), p2wpkh, multisig.to_p2wsh()];
+        for (index, script_pubkey) in script_pubkeys.into_iter().enumerate() {
+            let outpoint = OutPoint::new(Txid::from_bytes([index as u8 + 1; 32]), 0);
+            tx.inputs.push(TxIn::from_outpoint(outpoint, Sequence::MAX));
+            utxos.insert((outpoint.txid, 0), TxOut::new(Amount::from_sat(100_000), script_pubkey));
+        }
+        let p2pkh_inputs = vec![Command::Push(vec![0x30; 71]), Command::Push(vec![0x02; 33])];
+        tx.inputs[0].script_sig = Script::from_commands(p2pkh_inputs);
+        tx.inputs[1].script_sig = Script::p2sh_script_sig(signatures, &multisig);
+        tx.inputs[2].witness = Witness::p2wpkh(vec![0x30; 71], vec![0x02; 33]);
+        let witness = vec![vec![], vec![0x30; 71], vec![0x30; 71], multisig.as_bytes().to_vec()];
+        tx.inputs[3].witness = Witness::from(witness);
+        tx.outputs.push(TxOut::new(Amount::from_sat(390_000), p2pkh.clone()));
+
+        // the legacy count only sees the P2PKH output, the multisig is in a push
+        assert_eq!(tx.legacy_sigop_count(), 1);
+        // 4 for the output, 12 for the redeem script, 1 and 3 in the witnesses
+        assert_eq!(tx.sigop_cost(&utxos).unwrap(), 20);
+        assert!(matches!(tx.sigop_cost(&HashMap::new()), Err(TransactionError::MissingPrevout(0))));
+
+        // a bare multisig output counts 20 keys
+        tx.outputs.push(TxOut::new(Amount::from_sat(1000), multisig.clone()));
+        assert_eq!(tx.sigop_cost(&utxos).unwrap(), 20 + 80);
+
+        let script_sig = Script::from_hex("03a0bb0d").unwrap();
+        let coinbase = Transaction {
+            inputs: vec![TxIn::new(Txid::default(), COINBASE_PREV_INDEX, script_sig, Sequence::MAX)],
+            outputs: vec![TxOut::new(Amount::from_sat(625_000_000), p2pkh)],
+            ..Transaction::default()
+        };
+        assert_eq!(coinbase.sigop_cost(&HashMap::new()).unwrap(), 4);
+    }
+}
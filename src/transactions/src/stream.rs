@@ -0,0 +1,296 @@
+use std::io::{self, BufRead};
+
+use ec_cryptography::{
+    consensus::{ConsensusDecode, VarInt},
+    helper::{hash256, HashFunction, Hasher},
+};
+use scripts::Script;
+
+use crate::{
+    amount::Amount,
+    input::{Sequence, TxIn},
+    locktime::LockTime,
+    output::TxOut,
+    txid::{Txid, Wtxid},
+    version::Version,
+    witness::Witness,
+    ParseError, Transaction, TransactionError, SEGWIT_FLAG,
+};
+
+/// Reads transactions one after the other from a stream, such as the transactions of a block file
+/// or a socket, without loading the whole stream first.
+/// Iteration stops at the end of the stream, or after the first error, as the position in the stream is lost.
+pub struct TransactionStream<R> {
+    reader: R,
+    testnet: bool,
+    failed: bool,
+}
+
+impl<R: BufRead> TransactionStream<R> {
+    pub fn new(reader: R, testnet: bool) -> TransactionStream<R> {
+        TransactionStream { reader, testnet, failed: false }
+    }
+
+    /// Gives back the reader, positioned after the last transaction read
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: BufRead> Iterator for TransactionStream<R> {
+    type Item = Result<Transaction, TransactionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let result = match self.reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => Transaction::parse_stream(&mut self.reader, self.testnet),
+            Err(error) => Err(error.into()),
+        };
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// An input whose scripts and witness borrow the bytes it was parsed from
+#[derive(Debug, Clone)]
+pub struct TxInRef<'a> {
+    /// The previous txid in internal byte order, as serialized
+    pub prev_txid: &'a [u8; 32],
+    pub prev_index: u32,
+    pub script_sig: &'a [u8],
+    pub sequence: u32,
+    pub witness: Vec<&'a [u8]>,
+}
+
+/// An output whose script_pubkey borrows the bytes it was parsed from
+#[derive(Debug, Clone, Copy)]
+pub struct TxOutRef<'a> {
+    pub value: Amount,
+    pub script_pubkey: &'a [u8],
+}
+
+/// A transaction parsed without copying its scripts, witnesses or hashes out of the bytes,
+/// e.g. to scan the transactions of a block for the outputs of interest.
+/// `to_transaction` makes an owned copy for the ones worth keeping.
+#[derive(Debug, Clone)]
+pub struct TransactionRef<'a> {
+    pub version: u32,
+    pub inputs: Vec<TxInRef<'a>>,
+    pub outputs: Vec<TxOutRef<'a>>,
+    pub locktime: u32,
+    /// The serialized transaction
+    bytes: &'a [u8],
+    /// Where the outputs end, and the witnesses begin if there are any
+    outputs_end: usize,
+    segwit: bool,
+}
+
+/// Borrows `length` bytes off the front of the slice. If there aren't that many, it's left empty,
+/// as reading from a slice does.
+fn take<'a>(rest: &mut &'a [u8], length: u64) -> io::Result<&'a [u8]> {
+    if (rest.len() as u64) < length {
+        *rest = &rest[rest.len()..];
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the data ends in the middle of a field"));
+    }
+
+    let (taken, tail) = rest.split_at(length as usize);
+    *rest = tail;
+    Ok(taken)
+}
+
+/// Borrows a varint prefixed byte string
+fn take_var_bytes<'a>(rest: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let VarInt(length) = VarInt::consensus_decode(rest)?;
+    take(rest, length)
+}
+
+impl<'a> TransactionRef<'a> {
+    /// Parses the transaction at the start of the bytes, in the legacy or the segwit format.
+    /// Returns it with the number of bytes it took up, so the next transaction can be parsed from there.
+    pub fn parse(bytes: &'a [u8]) -> Result<(TransactionRef<'a>, usize), TransactionError> {
+        let mut rest = bytes;
+        TransactionRef::decode(bytes, &mut rest)
+            .map_err(|error| ParseError { offset: bytes.len() - rest.len(), error }.into())
+    }
+
+    /// Parses the transaction at the start of `bytes`, taking what it reads off the front of `rest`
+    fn decode(bytes: &'a [u8], rest: &mut &'a [u8]) -> io::Result<(TransactionRef<'a>, usize)> {
+        let version = u32::consensus_decode(rest)?;
+
+        // the segwit marker reads as an empty input list
+        let VarInt(mut input_count) = VarInt::consensus_decode(rest)?;
+        let segwit = input_count == 0;
+        if segwit {
+            if u8::consensus_decode(rest)? != SEGWIT_FLAG {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown segwit flag"));
+            }
+            input_count = VarInt::consensus_decode(rest)?.0;
+        }
+
+        let mut inputs = vec![];
+        for _ in 0..input_count {
+            inputs.push(TxInRef {
+                prev_txid: take(rest, 32)?.try_into().expect("took 32 bytes"),
+                prev_index: u32::consensus_decode(rest)?,
+                script_sig: take_var_bytes(rest)?,
+                sequence: u32::consensus_decode(rest)?,
+                witness: vec![],
+            });
+        }
+
+        let VarInt(output_count) = VarInt::consensus_decode(rest)?;
+        let mut outputs = vec![];
+        for _ in 0..output_count {
+            outputs.push(TxOutRef {
+                value: Amount::consensus_decode(rest)?,
+                script_pubkey: take_var_bytes(rest)?,
+            });
+        }
+        let outputs_end = bytes.len() - rest.len();
+
+        if segwit {
+            for input in &mut inputs {
+                let VarInt(item_count) = VarInt::consensus_decode(rest)?;
+                for _ in 0..item_count {
+                    input.witness.push(take_var_bytes(rest)?);
+                }
+            }
+            if inputs.iter().all(|input| input.witness.is_empty()) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "superfluous witness data"));
+            }
+        }
+
+        let locktime = u32::consensus_decode(rest)?;
+        let length = bytes.len() - rest.len();
+
+        let transaction = TransactionRef {
+            version,
+            inputs,
+            outputs,
+            locktime,
+            bytes: &bytes[..length],
+            outputs_end,
+            segwit,
+        };
+        Ok((transaction, length))
+    }
+
+    /// The bytes the transaction was parsed from
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The txid, hashing the serialization without witnesses straight from the bytes
+    pub fn txid(&self) -> Txid {
+        let hash = if self.segwit {
+            // the version, then everything from the inputs to the end of the outputs, then the locktime
+            Hasher::new(HashFunction::Hash256)
+                .chain(&self.bytes[..4])
+                .chain(&self.bytes[6..self.outputs_end])
+                .chain(&self.bytes[self.bytes.len() - 4..])
+                .finalize()
+        } else {
+            hash256(self.bytes)
+        };
+        Txid::from_bytes(hash.try_into().expect("hash256 is 32 bytes"))
+    }
+
+    pub fn wtxid(&self) -> Wtxid {
+        Wtxid::from_bytes(hash256(self.bytes).try_into().expect("hash256 is 32 bytes"))
+    }
+
+    /// An owned copy of the transaction
+    pub fn to_transaction(&self, testnet: bool) -> Transaction {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| {
+                let script_sig = Script::from_bytes(input.script_sig.to_vec());
+                let mut tx_input =
+                    TxIn::new(Txid::from_bytes(*input.prev_txid), input.prev_index, script_sig, Sequence::new(input.sequence));
+                tx_input.witness = Witness::from(input.witness.iter().map(|item| item.to_vec()).collect::<Vec<_>>());
+                tx_input
+            })
+            .collect();
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|output| TxOut::new(output.value, Script::from_bytes(output.script_pubkey.to_vec())))
+            .collect();
+
+        Transaction {
+            version: Version::new(self.version),
+            inputs,
+            outputs,
+            locktime: LockTime::from_consensus(self.locktime),
+            testnet,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use ec_cryptography::helper::from_hex;
+
+    use super::*;
+    use crate::fixtures::{RAW_TX, SEGWIT_TX};
+
+    /// Both transactions one after the other, as in a block
+    fn concatenated() -> Vec<u8> {
+        [from_hex(RAW_TX).unwrap(), from_hex(SEGWIT_TX).unwrap()].concat()
+    }
+
+    #[test]
+    fn test_parse_borrowed() {
+        let bytes = concatenated();
+
+        let (legacy, consumed) = TransactionRef::parse(&bytes).unwrap();
+        assert_eq!(consumed, RAW_TX.len() / 2);
+        let (segwit, _) = TransactionRef::parse(&bytes[consumed..]).unwrap();
+
+        for (borrowed, raw) in [(legacy, RAW_TX), (segwit, SEGWIT_TX)] {
+            let owned = Transaction::parse_hex(raw, false).unwrap();
+            assert_eq!(borrowed.txid(), owned.txid());
+            assert_eq!(borrowed.wtxid(), owned.wtxid());
+            assert_eq!(borrowed.to_transaction(false).serialize(), owned.serialize());
+            assert_eq!(borrowed.as_bytes(), owned.serialize());
+        }
+
+        // the scripts point into the parsed bytes
+        let (segwit, _) = TransactionRef::parse(&bytes[consumed..]).unwrap();
+        assert!(bytes.as_ptr_range().contains(&segwit.inputs[0].script_sig.as_ptr()));
+        assert!(bytes.as_ptr_range().contains(&segwit.outputs[1].script_pubkey.as_ptr()));
+        assert_eq!(segwit.inputs[1].witness.len(), 2);
+        assert_eq!(segwit.outputs[0].value, Amount::from_sat(112340000));
+        assert_eq!(segwit.locktime, 17);
+
+        assert!(matches!(
+            TransactionRef::parse(&bytes[..100]),
+            Err(TransactionError::Parse(ParseError { offset: 100, .. }))
+        ));
+        assert!((0..consumed).all(|length| TransactionRef::parse(&bytes[..length]).is_err()));
+    }
+
+    #[test]
+    fn test_stream() {
+        let bytes = concatenated();
+        let stream = TransactionStream::new(BufReader::new(Cursor::new(bytes.clone())), true);
+
+        let transactions = stream.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert!(transactions[1].has_witness());
+        assert!(transactions[0].testnet);
+
+        // a truncated stream gives an error, then stops
+        let mut stream = TransactionStream::new(Cursor::new(&bytes[..bytes.len() - 1]), false);
+        assert!(stream.next().unwrap().is_ok());
+        assert!(matches!(stream.next(), Some(Err(TransactionError::Io(_)))));
+        assert!(stream.next().is_none());
+    }
+}
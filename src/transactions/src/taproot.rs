@@ -0,0 +1,261 @@
+use ec_cryptography::{helper::Hasher, tap_tweak_public_key, PrivateKey};
+use scripts::{interpreter::TAPROOT_LEAF_MASK, Script};
+
+use crate::{
+    sighash::{tap_leaf_hash, SighashType, TapLeafSpend, TAPSCRIPT_LEAF_VERSION},
+    utxo::UtxoProvider,
+    witness::Witness,
+    Transaction, TransactionError,
+};
+
+pub use scripts::interpreter::TAPROOT_CONTROL_MAX_NODE_COUNT;
+
+/// The last witness item of a taproot script path spend (BIP341). It proves that the leaf script
+/// is in the script tree the output key commits to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlBlock {
+    pub leaf_version: u8,
+    /// Whether the output key has an odd y, which the x-only key of the script_pubkey leaves out
+    pub output_key_parity: bool,
+    pub internal_key: [u8; 32],
+    /// The hashes next to the path from the leaf up to the root of the tree, starting at the leaf
+    pub merkle_path: Vec<[u8; 32]>,
+}
+
+impl ControlBlock {
+    /// The control block for spending `leaf_script` from the output of `internal_key`, whose tree
+    /// the leaf is in with `merkle_path`. None if the internal key isn't on the curve.
+    pub fn new(
+        internal_key: [u8; 32],
+        leaf_script: &Script,
+        leaf_version: u8,
+        merkle_path: Vec<[u8; 32]>,
+    ) -> Option<ControlBlock> {
+        let mut control_block = ControlBlock {
+            leaf_version,
+            output_key_parity: false,
+            internal_key,
+            merkle_path,
+        };
+        let merkle_root = control_block.merkle_root(leaf_script);
+        let (_, parity) = tap_tweak_public_key(&internal_key, Some(&merkle_root))?;
+        control_block.output_key_parity = parity;
+        Some(control_block)
+    }
+
+    /// Parses the control block from its witness item: 33 bytes and up to 128 hashes of 32 bytes
+    pub fn parse(bytes: &[u8]) -> Result<ControlBlock, TransactionError> {
+        let invalid = || TransactionError::InvalidControlBlock(bytes.len());
+        let Some(path_length) = bytes.len().checked_sub(33) else {
+            return Err(invalid());
+        };
+        if !path_length.is_multiple_of(32) || path_length / 32 > TAPROOT_CONTROL_MAX_NODE_COUNT {
+            return Err(invalid());
+        }
+
+        Ok(ControlBlock {
+            leaf_version: bytes[0] & TAPROOT_LEAF_MASK,
+            output_key_parity: bytes[0] & 1 == 1,
+            internal_key: bytes[1..33].try_into().expect("checked the length"),
+            merkle_path: bytes[33..]
+                .chunks_exact(32)
+                .map(|hash| hash.try_into().expect("chunks of 32 bytes"))
+                .collect(),
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = vec![self.leaf_version | self.output_key_parity as u8];
+        bytes.extend_from_slice(&self.internal_key);
+        for hash in &self.merkle_path {
+            bytes.extend_from_slice(hash);
+        }
+        bytes
+    }
+
+    /// The root of the script tree, hashing the leaf up the merkle path
+    pub fn merkle_root(&self, leaf_script: &Script) -> [u8; 32] {
+        self.merkle_path
+            .iter()
+            .fold(tap_leaf_hash(leaf_script, self.leaf_version), |hash, sibling| {
+                tap_branch_hash(&hash, sibling)
+            })
+    }
+
+    /// Whether the leaf script is in the tree `output_key` commits to: tweaking the internal key with
+    /// the merkle root must give the output key, with the parity the control block claims
+    pub fn verify(&self, leaf_script: &Script, output_key: &[u8; 32]) -> bool {
+        let merkle_root = self.merkle_root(leaf_script);
+        tap_tweak_public_key(&self.internal_key, Some(&merkle_root))
+            .is_some_and(|(key, parity)| key == *output_key && parity == self.output_key_parity)
+    }
+}
+
+/// The BIP341 hash of a branch of the script tree. The two hashes are sorted first,
+/// so proofs don't have to say which side each one is on.
+pub fn tap_branch_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    let hash = Hasher::tagged("TapBranch").chain(left).chain(right).finalize();
+    hash.try_into().expect("sha256 is 32 bytes")
+}
+
+impl Transaction {
+    /// The BIP342 Schnorr signature, by a key the tapscript `leaf_script` checks, for spending
+    /// the input through that leaf. It goes among the script inputs of [`Transaction::spend_taproot_leaf`].
+    ///
+    /// A `sighash_type` of `None` is SIGHASH_DEFAULT, with a 64-byte signature; other types append
+    /// their byte to it. Every output spent must be in `utxos`, as taproot signatures commit to all of them.
+    pub fn sign_tapscript(
+        &self,
+        input_index: usize,
+        private_key: &PrivateKey,
+        leaf_script: &Script,
+        sighash_type: Option<SighashType>,
+        utxos: &impl UtxoProvider,
+    ) -> Result<Vec<u8>, TransactionError> {
+        let prevouts = self.spent_outputs(utxos)?;
+        let leaf = TapLeafSpend::new(tap_leaf_hash(leaf_script, TAPSCRIPT_LEAF_VERSION));
+
+        let z = self.taproot_sig_hash(input_index, &prevouts, sighash_type, None, Some(leaf))?;
+        let z: [u8; 32] = z.try_into().expect("the sighash is 32 bytes");
+        let mut sig = private_key.sign_schnorr(&z, &[0; 32]).to_vec();
+        if let Some(sighash_type) = sighash_type {
+            sig.push(sighash_type.to_u32() as u8);
+        }
+        Ok(sig)
+    }
+
+    /// Spends a taproot input through a leaf of its script tree: the witness is `script_inputs`
+    /// (bottom of the stack first), then the leaf script, then the control block.
+    ///
+    /// The control block is checked against the output key of the spent script_pubkey, looked up in `utxos`,
+    /// so a wrong merkle path or internal key is a `KeyMismatch` here rather than an invalid transaction later.
+    pub fn spend_taproot_leaf(
+        &mut self,
+        input_index: usize,
+        script_inputs: Vec<Vec<u8>>,
+        leaf_script: &Script,
+        control_block: &ControlBlock,
+        utxos: &impl UtxoProvider,
+    ) -> Result<(), TransactionError> {
+        let input = self
+            .inputs
+            .get(input_index)
+            .ok_or(TransactionError::InputIndexOutOfRange(input_index))?;
+        let prevout = utxos
+            .get_output(&input.prev_txid, input.prev_index)
+            .ok_or(TransactionError::MissingPrevout(input_index))?;
+
+        let [0x51, 0x20, output_key @ ..] = prevout.script_pubkey.as_bytes() else {
+            return Err(TransactionError::KeyMismatch);
+        };
+        let output_key = output_key.try_into().map_err(|_| TransactionError::KeyMismatch)?;
+        if !control_block.verify(leaf_script, output_key) {
+            return Err(TransactionError::KeyMismatch);
+        }
+
+        self.inputs[input_index].witness = Witness::p2tr_script_spend(script_inputs, leaf_script, control_block);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ec_cryptography::{
+        helper::{from_hex, to_hex},
+        verify_schnorr,
+    };
+    use rug::Integer;
+
+    use super::*;
+    use crate::{
+        amount::Amount,
+        input::{OutPoint, Sequence, TxIn},
+        output::TxOut,
+        txid::Txid,
+    };
+
+    #[test]
+    fn test_control_block() {
+        // a single leaf tree of the BIP341 wallet test vectors
+        let internal_key: [u8; 32] = from_hex("187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let leaf_script =
+            Script::from_hex("20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac").unwrap();
+        let output_key: [u8; 32] = from_hex("147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3")
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        let control_block = ControlBlock::new(internal_key, &leaf_script, TAPSCRIPT_LEAF_VERSION, vec![]).unwrap();
+        assert_eq!(
+            to_hex(&control_block.serialize()),
+            "c1187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27"
+        );
+        assert!(control_block.verify(&leaf_script, &output_key));
+        assert_eq!(ControlBlock::parse(&control_block.serialize()).unwrap(), control_block);
+
+        // the other parity, or another script, isn't what the output key commits to
+        let mut flipped = control_block.clone();
+        flipped.output_key_parity = false;
+        assert!(!flipped.verify(&leaf_script, &output_key));
+        assert!(!control_block.verify(&Script::from_hex("51").unwrap(), &output_key));
+
+        assert!(matches!(ControlBlock::parse(&[0xc0; 32]), Err(TransactionError::InvalidControlBlock(32))));
+        assert!(matches!(ControlBlock::parse(&[0xc0; 34]), Err(TransactionError::InvalidControlBlock(34))));
+        assert!(ControlBlock::parse(&vec![0xc0; 33 + 129 * 32]).is_err());
+    }
+
+    #[test]
+    fn test_spend_taproot_leaf() {
+        // a tree of two leaves: a key 5 checksig, and OP_TRUE
+        let leaf_key = PrivateKey::new(Integer::from(5));
+        let checksig = Script::from_bytes([[0x20].as_slice(), &leaf_key.x_only_public_key(), &[0xac]].concat());
+        let op_true = Script::from_hex("51").unwrap();
+        let internal_key = PrivateKey::new(Integer::from(4)).x_only_public_key();
+
+        let merkle_root = tap_branch_hash(
+            &tap_leaf_hash(&checksig, TAPSCRIPT_LEAF_VERSION),
+            &tap_leaf_hash(&op_true, TAPSCRIPT_LEAF_VERSION),
+        );
+        let (output_key, _) = tap_tweak_public_key(&internal_key, Some(&merkle_root)).unwrap();
+        let path = vec![tap_leaf_hash(&op_true, TAPSCRIPT_LEAF_VERSION)];
+        let control_block = ControlBlock::new(internal_key, &checksig, TAPSCRIPT_LEAF_VERSION, path).unwrap();
+        assert_eq!(control_block.merkle_root(&checksig), merkle_root);
+
+        let outpoint = OutPoint::new(Txid::from_bytes([1; 32]), 0);
+        let script_pubkey = Script::p2tr(&output_key);
+        let prevout = TxOut::new(Amount::from_sat(100_000), script_pubkey);
+        let utxos = HashMap::from([((outpoint.txid, 0), prevout.clone())]);
+        let mut tx = Transaction {
+            inputs: vec![TxIn::from_outpoint(outpoint, Sequence::MAX)],
+            outputs: vec![TxOut::new(Amount::from_sat(90_000), Script::default())],
+            ..Default::default()
+        };
+
+        let sig = tx.sign_tapscript(0, &leaf_key, &checksig, None, &utxos).unwrap();
+        tx.spend_taproot_leaf(0, vec![sig.clone()], &checksig, &control_block, &utxos).unwrap();
+        assert_eq!(tx.inputs[0].witness.len(), 3);
+        assert_eq!(tx.inputs[0].witness.tapscript(), Some(checksig.as_bytes()));
+
+        // the signature commits to the leaf
+        let leaf = TapLeafSpend::new(tap_leaf_hash(&checksig, TAPSCRIPT_LEAF_VERSION));
+        let z: [u8; 32] = tx.taproot_sig_hash(0, &[prevout], None, None, Some(leaf)).unwrap().try_into().unwrap();
+        assert!(verify_schnorr(&leaf_key.x_only_public_key(), &z, sig.as_slice().try_into().unwrap()));
+
+        // the control block of the other leaf doesn't prove this one
+        let wrong = ControlBlock::new(internal_key, &op_true, TAPSCRIPT_LEAF_VERSION, vec![]).unwrap();
+        assert!(matches!(
+            tx.spend_taproot_leaf(0, vec![], &op_true, &wrong, &utxos),
+            Err(TransactionError::KeyMismatch)
+        ));
+        assert!(matches!(
+            tx.spend_taproot_leaf(1, vec![], &checksig, &control_block, &utxos),
+            Err(TransactionError::InputIndexOutOfRange(1))
+        ));
+    }
+}
@@ -0,0 +1,34 @@
+use ec_cryptography::hash_newtype;
+
+hash_newtype!(
+    /// A transaction id: the hash256 of the transaction serialized without witnesses
+    Txid
+);
+
+hash_newtype!(
+    /// A witness transaction id (BIP141): the hash256 of the transaction serialized with its witnesses.
+    /// For transactions without witnesses it is the same as the txid.
+    Wtxid
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_order() {
+        let display = "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81";
+        let txid: Txid = display.parse().unwrap();
+
+        assert_eq!(txid.as_bytes()[0], 0x81);
+        assert_eq!(txid.as_bytes()[31], 0xd1);
+        assert_eq!(txid.to_string(), display);
+        assert_eq!(Txid::from_bytes(*txid.as_bytes()), txid);
+        assert_eq!(format!("{:?}", txid), format!("Txid({})", display));
+
+        let wtxid: Wtxid = display.parse().unwrap();
+        assert_eq!(wtxid.as_bytes(), txid.as_bytes());
+
+        assert!("d1c789".parse::<Txid>().is_err());
+    }
+}
@@ -1,48 +1,36 @@
-use std::{collections::HashMap, rc::Rc};
-
-use rug::Integer;
-use hex::ToHex;
-
-use crate::Transaction;
-
-pub fn parse_varints(bytes: &[u8], init_count: usize) -> (usize, u64) {
-    let (byte_count, length) = match bytes[init_count] {
-        0x00..=0xfc => {
-            (1, bytes[init_count] as u64)
-        },
-        0xfd => {
-            // if startsWith 'fd', then the next 2 bytes is the length
-            let length = Integer::from_digits(
-                &bytes[(init_count + 1_usize)..(init_count + 3_usize)],
-                rug::integer::Order::LsfLe
-            );
-            (3, length.to_u64().unwrap())
-        },
-        0xfe => {
-            let mut padded = [0u8; 8];
-            let length = &bytes[(init_count + 1_usize)..(init_count + 5_usize)];
-            padded[..length.len()].copy_from_slice(&length);
-            (4, u64::from_le_bytes(padded))
-        },
-        0xff => {
-            let length = Integer::from_digits(&bytes[(init_count + 1_usize)..(init_count + 9_usize)], rug::integer::Order::LsfLe);
-            (8, length.to_u64().unwrap())
-        },
-    };
-
-    (byte_count, length)
+use std::{collections::HashMap, fs, io::{self, Read, Write}, path::PathBuf, rc::Rc};
+
+use ec_cryptography::{
+    consensus::{ConsensusDecode, ConsensusEncode, VarInt},
+    helper::{from_hex, HexError},
+};
+
+use crate::{decode_prefix, txid::Txid, ParseError, Transaction, TransactionError};
+
+/// Reads the varint at `init_count`, returning the number of bytes it takes up and its value.
+/// A varint cut off by the end of the bytes is an error.
+pub fn parse_varints(bytes: &[u8], init_count: usize) -> Result<(usize, u64), ParseError> {
+    let rest = bytes.get(init_count..).unwrap_or_default();
+    let (VarInt(value), byte_count) = decode_prefix(rest, VarInt::consensus_decode).map_err(|error| ParseError {
+        offset: init_count + error.offset,
+        error: error.error,
+    })?;
+
+    Ok((byte_count, value))
 }
 
-pub fn encode_varints(length: u64) -> Vec<u8> {
+/// Encodes an integer as a varint: values below 0xfd take a single byte,
+/// larger values are prefixed with 0xfd, 0xfe or 0xff followed by 2, 4 or 8 little-endian bytes.
+pub fn encode_varint(length: u64) -> Vec<u8> {
     if length < 0xfd {
         vec![length as u8]
     } else if length < 0x10000 {
         let mut bytes = vec![0xfd];
-        bytes.extend_from_slice(&length.to_le_bytes());
+        bytes.extend_from_slice(&(length as u16).to_le_bytes());
         bytes
     } else if length < 0x100000000 {
         let mut bytes = vec![0xfe];
-        bytes.extend_from_slice(&length.to_le_bytes());
+        bytes.extend_from_slice(&(length as u32).to_le_bytes());
         bytes
     } else {
         let mut bytes = vec![0xff];
@@ -51,8 +39,58 @@ pub fn encode_varints(length: u64) -> Vec<u8> {
     }
 }
 
+/// Reads a varint from a stream, consuming only the bytes that belong to it.
+pub fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    VarInt::consensus_decode(reader).map(|VarInt(value)| value)
+}
+
+/// Writes a varint to a stream, returning the number of bytes written.
+pub fn write_varint<W: Write>(writer: &mut W, length: u64) -> io::Result<usize> {
+    VarInt(length).consensus_encode(writer)
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    /// None of the block explorers could be reached, or they all returned an error status
+    Http(reqwest::Error),
+    /// The disk cache could not be read or written
+    Io(io::Error),
+    /// The raw transaction is not valid hex
+    InvalidHex(HexError),
+    /// The raw transaction could not be parsed
+    Transaction(TransactionError),
+    /// The transaction received hashes to a different txid than the one requested
+    TxidMismatch(Txid),
+    /// The fetched transaction has no output with this index
+    MissingOutput(u32),
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(error: reqwest::Error) -> Self {
+        FetchError::Http(error)
+    }
+}
+
+impl From<io::Error> for FetchError {
+    fn from(error: io::Error) -> Self {
+        FetchError::Io(error)
+    }
+}
+
+impl From<TransactionError> for FetchError {
+    fn from(error: TransactionError) -> Self {
+        FetchError::Transaction(error)
+    }
+}
+
+/// Looks up raw transactions by txid with the mempool.space API, falling back to blockstream.info.
+///
+/// Fetched transactions are kept in memory, and with `with_cache_dir` also written to disk
+/// as `<txid>.hex` files so later runs don't hit the network.
 pub struct TxFetcher {
-    cache: HashMap<String, Transaction>,
+    cache: HashMap<Txid, Transaction>,
+    cache_dir: Option<PathBuf>,
+    client: reqwest::blocking::Client,
     testnet: bool,
 }
 
@@ -60,48 +98,223 @@ impl TxFetcher {
     pub fn new(testnet: bool) -> TxFetcher {
         TxFetcher {
             cache: HashMap::new(),
+            cache_dir: None,
+            client: reqwest::blocking::Client::new(),
             testnet,
         }
     }
+
+    /// Stores the raw transactions in `cache_dir`, creating it on the first write
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> TxFetcher {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// The base URL of the preferred block explorer API
     pub fn get_url(&self) -> &str {
+        self.endpoints()[0]
+    }
+
+    fn endpoints(&self) -> [&'static str; 2] {
         if self.testnet {
-            "https://blockstream.info/testnet/api/"
+            ["https://mempool.space/testnet/api/", "https://blockstream.info/testnet/api/"]
         } else {
-            "https://blockstream.info/api/"
+            ["https://mempool.space/api/", "https://blockstream.info/api/"]
+        }
+    }
+
+    /// The transaction with the given id, from the cache if we have it.
+    /// `fresh` skips both caches and downloads it again.
+    pub fn fetch(&mut self, txid: &Txid, fresh: bool) -> Result<&Transaction, FetchError> {
+        if fresh || !self.cache.contains_key(txid) {
+            let (raw, downloaded) = match self.read_cached(txid, fresh)? {
+                Some(raw) => (raw, false),
+                None => (self.download(txid)?, true),
+            };
+
+            let tx = parse_fetched(&raw, self.testnet)?;
+            if tx.txid() != *txid {
+                return Err(FetchError::TxidMismatch(tx.txid()));
+            }
+            // only a response that checks out is kept on disk
+            if downloaded {
+                self.write_cached(txid, &raw)?;
+            }
+
+            self.cache.insert(*txid, tx);
         }
+
+        Ok(&self.cache[txid])
     }
 
-    pub fn fetch(&mut self, tx_id: String, fresh: bool) -> &Transaction {
-        if fresh || !self.cache.contains_key(&tx_id) {
-            let tx_url = format!("{}tx/{}/hex", self.get_url(), tx_id);
-            let response = reqwest::blocking::get(
-                tx_url,
-            ).unwrap().text().unwrap();
-            println!("Response: {:?}", response);
-
-            let mut tx = Transaction::default();
-
-            tx.testnet = self.testnet;
-            let raw_bytes = Integer::from_str_radix(response.as_str(), 16).unwrap().to_digits::<u8>(rug::integer::Order::MsfBe);
-            if raw_bytes[4] == 0x00 {
-                let starter_bytes = &raw_bytes[..4];
-                starter_bytes.to_vec().copy_from_slice(&raw_bytes[6..]);
-                let bytes = starter_bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
-                tx = Transaction::parse(bytes.as_str(), self.testnet).unwrap();
-                tx.locktime = Integer::from_digits(&starter_bytes[(starter_bytes.len() - 4)..], rug::integer::Order::LsfLe).to_u32().unwrap();
-            } else {
-                tx = Transaction::parse(response.as_str(), self.testnet).unwrap();
+    fn download(&self, txid: &Txid) -> Result<String, FetchError> {
+        let mut last_error = None;
+        for url in self.endpoints() {
+            let response = self
+                .client
+                .get(format!("{}tx/{}/hex", url, txid))
+                .send()
+                .and_then(|response| response.error_for_status())
+                .and_then(|response| response.text());
+
+            match response {
+                Ok(raw) => return Ok(raw.trim().to_string()),
+                Err(error) => last_error = Some(error),
             }
+        }
 
-            println!("Transaction: {:?}", tx.id());
+        Err(FetchError::Http(last_error.expect("there is at least one endpoint")))
+    }
 
-            // if tx.id() != tx_id.clone() {
-            //     panic!("Transaction ID does not match");
-            // }
+    fn cache_path(&self, txid: &Txid) -> Option<PathBuf> {
+        self.cache_dir.as_ref().map(|dir| dir.join(format!("{}.hex", txid)))
+    }
 
-            self.cache.insert(tx_id.clone(), tx);
+    fn read_cached(&self, txid: &Txid, fresh: bool) -> io::Result<Option<String>> {
+        match self.cache_path(txid) {
+            Some(path) if !fresh && path.exists() => Ok(Some(fs::read_to_string(path)?.trim().to_string())),
+            _ => Ok(None),
         }
-        
-        self.cache.get(&tx_id).unwrap()
     }
-}
\ No newline at end of file
+
+    fn write_cached(&self, txid: &Txid, raw: &str) -> io::Result<()> {
+        if let (Some(dir), Some(path)) = (&self.cache_dir, self.cache_path(txid)) {
+            fs::create_dir_all(dir)?;
+            fs::write(path, raw)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a raw transaction from a block explorer
+fn parse_fetched(raw: &str, testnet: bool) -> Result<Transaction, FetchError> {
+    let bytes = from_hex(raw).map_err(FetchError::InvalidHex)?;
+    Ok(Transaction::parse(&bytes, testnet)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::to_hex;
+
+    use super::*;
+    use crate::{fixtures::RAW_TX, locktime::LockTime, witness::Witness};
+
+    #[test]
+    fn test_encode_varint() {
+        assert_eq!(encode_varint(0x00), vec![0x00]);
+        assert_eq!(encode_varint(0xfc), vec![0xfc]);
+        assert_eq!(encode_varint(0xfd), vec![0xfd, 0xfd, 0x00]);
+        assert_eq!(encode_varint(0xffff), vec![0xfd, 0xff, 0xff]);
+        assert_eq!(encode_varint(0x10000), vec![0xfe, 0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(encode_varint(0xffffffff), vec![0xfe, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(
+            encode_varint(0x100000000),
+            vec![0xff, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_varint_round_trip() {
+        let values = [0, 1, 0xfc, 0xfd, 0x1234, 0xffff, 0x10000, 0xdeadbeef, 0x100000000, u64::MAX];
+
+        for value in values {
+            let encoded = encode_varint(value);
+            let (byte_count, decoded) = parse_varints(&encoded, 0).unwrap();
+
+            assert_eq!(decoded, value);
+            assert_eq!(byte_count, encoded.len());
+        }
+    }
+
+    #[test]
+    fn test_parse_varints_truncated() {
+        let bytes = [0x01, 0xfe, 0x00, 0x00, 0x01, 0x00];
+        assert_eq!(parse_varints(&bytes, 1).unwrap(), (5, 0x10000));
+        assert_eq!(parse_varints(&bytes, 0).unwrap(), (1, 1));
+
+        // the four bytes after 0xfe are cut off, or the varint starts past the end
+        assert_eq!(parse_varints(&bytes[..4], 1).unwrap_err().offset, 4);
+        assert_eq!(parse_varints(&bytes, 6).unwrap_err().offset, 6);
+        assert_eq!(parse_varints(&bytes, 10).unwrap_err().offset, 10);
+    }
+
+    #[test]
+    fn test_varint_stream() {
+        let mut stream = vec![];
+        for value in [0xfc, 0x1234, 0xdeadbeef, u64::MAX] {
+            write_varint(&mut stream, value).unwrap();
+        }
+        // trailing data must be left in the stream
+        stream.push(0x2a);
+
+        let mut reader = io::Cursor::new(stream);
+        assert_eq!(read_varint(&mut reader).unwrap(), 0xfc);
+        assert_eq!(read_varint(&mut reader).unwrap(), 0x1234);
+        assert_eq!(read_varint(&mut reader).unwrap(), 0xdeadbeef);
+        assert_eq!(read_varint(&mut reader).unwrap(), u64::MAX);
+        assert_eq!(read_varint(&mut reader).unwrap(), 0x2a);
+        assert!(read_varint(&mut reader).is_err());
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tx-fetcher-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_fetch_from_disk_cache() {
+        let dir = cache_dir("hit");
+        let txid = Transaction::parse_hex(RAW_TX, false).unwrap().txid();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("{}.hex", txid)), format!("{}\n", RAW_TX)).unwrap();
+
+        let mut tx_fetcher = TxFetcher::new(false).with_cache_dir(&dir);
+        let tx = tx_fetcher.fetch(&txid, false).unwrap();
+        assert_eq!(tx.txid(), txid);
+        assert_eq!(tx.locktime, LockTime::Blocks(410393));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fetch_checks_txid() {
+        let dir = cache_dir("mismatch");
+        let txid = Txid::from_bytes([1; 32]);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("{}.hex", txid)), RAW_TX).unwrap();
+
+        let mut tx_fetcher = TxFetcher::new(false).with_cache_dir(&dir);
+        assert!(matches!(tx_fetcher.fetch(&txid, false), Err(FetchError::TxidMismatch(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_fetched_segwit() {
+        let legacy = from_hex(RAW_TX).unwrap();
+
+        // add the marker and flag, and a witness with a single item before the locktime
+        let locktime_at = legacy.len() - 4;
+        let segwit = [&legacy[..4], &[0x00, 0x01], &legacy[4..locktime_at], &[0x01, 0x02, 0xab, 0xcd], &legacy[locktime_at..]].concat();
+
+        let tx = parse_fetched(&to_hex(&segwit), false).unwrap();
+        assert_eq!(tx.txid(), Transaction::parse(&legacy, false).unwrap().txid());
+        assert_eq!(tx.locktime, LockTime::Blocks(410393));
+        assert_eq!(tx.inputs[0].witness, Witness::from(vec![vec![0xab, 0xcd]]));
+    }
+
+    /// Downloads the transaction and keeps the raw hex in the cache directory
+    #[test]
+    #[ignore = "needs network access"]
+    fn test_fetch_from_explorer() {
+        let dir = cache_dir("download");
+        let txid: Txid = "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81".parse().unwrap();
+
+        let mut tx_fetcher = TxFetcher::new(false).with_cache_dir(&dir);
+        assert_eq!(tx_fetcher.fetch(&txid, false).unwrap().txid(), txid);
+        assert!(dir.join(format!("{}.hex", txid)).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
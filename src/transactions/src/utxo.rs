@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use crate::{amount::Amount, input::OutPoint, output::TxOut, txid::Txid};
+
+/// The weight of an input before it's signed: the outpoint, sequence and the empty script_sig's length byte
+pub const UNSIGNED_INPUT_WEIGHT: u64 = (32 + 4 + 4 + 1) * 4;
+
+/// A source of the outputs that transaction inputs spend, e.g. a wallet's UTXO set,
+/// an indexer, or a block explorer
+pub trait UtxoProvider {
+    /// The output with the given index of the transaction `txid`, if it is known
+    fn get_output(&self, txid: &Txid, index: u32) -> Option<TxOut>;
+}
+
+/// Outputs keyed by their outpoint, handy for tests and for wallets that hold their UTXOs in memory
+impl UtxoProvider for HashMap<(Txid, u32), TxOut> {
+    fn get_output(&self, txid: &Txid, index: u32) -> Option<TxOut> {
+        self.get(&(*txid, index)).cloned()
+    }
+}
+
+impl UtxoProvider for [Utxo] {
+    fn get_output(&self, txid: &Txid, index: u32) -> Option<TxOut> {
+        let outpoint = OutPoint::new(*txid, index);
+        self.iter().find(|utxo| utxo.outpoint == outpoint).map(|utxo| utxo.output.clone())
+    }
+}
+
+/// An unspent output: where it is and what it holds
+#[derive(Debug, Clone)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub output: TxOut,
+}
+
+impl Utxo {
+    pub fn new(outpoint: OutPoint, output: TxOut) -> Utxo {
+        Utxo { outpoint, output }
+    }
+
+    pub fn value(&self) -> Amount {
+        self.output.value
+    }
+
+    /// The weight the script_sig and witness add to the input once it's signed, for the single key output
+    /// types (P2PKH, P2WPKH and taproot key path spends). It assumes 72-byte DER signatures and compressed keys,
+    /// so estimates are never short. Witnesses include their item count.
+    pub fn satisfaction_weight(&self) -> Option<u64> {
+        let script = self.output.script_pubkey.as_bytes();
+        match script {
+            // the signature and the public key, each with its push opcode
+            [0x76, 0xa9, 0x14, .., 0x88, 0xac] if script.len() == 25 => Some((1 + 72 + 1 + 33) * 4),
+            [0x00, 0x14, ..] if script.len() == 22 => Some(1 + 1 + 72 + 1 + 33),
+            [0x51, 0x20, ..] if script.len() == 34 => Some(1 + 1 + 64),
+            _ => None,
+        }
+    }
+
+    /// Whether spending the output puts data in the witness
+    pub fn is_witness(&self) -> bool {
+        matches!(self.output.script_pubkey.as_bytes().first(), Some(0x00 | 0x51..=0x60))
+    }
+
+    /// The weight of an input spending this output once it's signed
+    pub fn input_weight(&self) -> Option<u64> {
+        self.satisfaction_weight().map(|weight| UNSIGNED_INPUT_WEIGHT + weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scripts::Script;
+
+    use super::*;
+
+    fn utxo(script_pubkey: &str) -> Utxo {
+        let outpoint = OutPoint::new(Txid::from_bytes([1; 32]), 0);
+        Utxo::new(outpoint, TxOut::new(Amount::from_sat(1000), Script::from_hex(script_pubkey).unwrap()))
+    }
+
+    #[test]
+    fn test_input_weight() {
+        // the usual 148, 68 and 57.5 vbytes
+        let p2pkh = utxo("76a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac");
+        assert_eq!(p2pkh.input_weight(), Some(148 * 4));
+        assert!(!p2pkh.is_witness());
+
+        let p2wpkh = utxo("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1");
+        assert_eq!(p2wpkh.input_weight(), Some(68 * 4));
+        assert!(p2wpkh.is_witness());
+
+        let p2tr = utxo(&format!("5120{}", "ab".repeat(32)));
+        assert_eq!(p2tr.input_weight(), Some(230));
+
+        assert_eq!(utxo("a914748284390f9e263a4b766a75d0633c50426eb87587").input_weight(), None);
+    }
+
+    #[test]
+    fn test_utxo_provider() {
+        let utxos = [utxo("51")];
+        let txid = Txid::from_bytes([1; 32]);
+
+        assert_eq!(utxos.get_output(&txid, 0).unwrap().value, Amount::from_sat(1000));
+        assert!(utxos.get_output(&txid, 1).is_none());
+    }
+}
@@ -0,0 +1,870 @@
+use std::collections::HashSet;
+
+use ec_cryptography::{helper::hash160, serializer::parse_sec, verify_schnorr, Signature};
+use rug::{integer::Order, Integer};
+use scripts::{
+    interpreter::{ScriptError, SigVersion, SignatureChecker},
+    Command, Script,
+};
+
+use crate::{
+    amount::Amount,
+    input::Sequence,
+    locktime::LockTime,
+    output::TxOut,
+    sighash::{SegwitV0Cache, SighashType, TapLeafSpend, TAPROOT_ANNEX_PREFIX},
+    taproot::ControlBlock,
+    utxo::UtxoProvider,
+    Transaction, TransactionError,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The transaction has no inputs
+    NoInputs,
+    /// The transaction has no outputs
+    NoOutputs,
+    /// The input with this index spends the same output as an earlier input
+    DuplicateInput(usize),
+    /// The output spent by the input with this index could not be found
+    MissingPrevout(usize),
+    /// The output with this index is worth more than `Amount::MAX_MONEY`
+    OutputValueOutOfRange(usize),
+    /// The outputs, or the outputs spent, are together worth more than `Amount::MAX_MONEY`
+    TotalValueOutOfRange,
+    /// The outputs spend more than the inputs hold, by this much
+    NegativeFee(Amount),
+    /// The script_sig of a coinbase must be 2 to 100 bytes long, this one has this many
+    InvalidCoinbaseLength(usize),
+}
+
+/// Why the signature of an input doesn't check out
+#[derive(Debug, PartialEq, Eq)]
+pub enum InputError {
+    /// The transaction has no input with this index
+    InputIndexOutOfRange(usize),
+    /// The output spent by the input with this index could not be found
+    MissingPrevout(usize),
+    /// The script_sig or witness doesn't hold the items spending the output takes
+    MalformedSpend,
+    /// The public key isn't a valid SEC encoding
+    InvalidPublicKey,
+    /// The public key or the redeem script doesn't hash to what the output pays to
+    KeyMismatch,
+    /// The signature isn't strict DER (BIP66)
+    InvalidDer,
+    /// The hash type byte isn't a defined one, or can't be used for this input
+    InvalidHashType(u8),
+    /// A Schnorr signature is 64 bytes, or 65 with a hash type byte, not this many
+    InvalidSignatureLength(usize),
+    /// The signature doesn't match the public key and the signature hash
+    SignatureMismatch,
+    /// Running the scripts of the spend failed, for outputs without a single key
+    Script(ScriptError),
+}
+
+/// Checks the signatures and locks of a script run for one input of a transaction:
+/// signatures against its legacy, BIP143 or BIP341 signature hash, taproot control blocks against the
+/// output key, OP_CHECKLOCKTIMEVERIFY against the transaction's locktime and OP_CHECKSEQUENCEVERIFY
+/// against the input's sequence
+#[derive(Debug, Clone)]
+pub struct TransactionChecker<'a> {
+    tx: &'a Transaction,
+    input_index: usize,
+    /// The outputs the inputs spend, in input order
+    prevouts: Vec<TxOut>,
+    cache: SegwitV0Cache,
+}
+
+impl<'a> TransactionChecker<'a> {
+    /// A checker for the input with this index. The outputs the transaction spends are looked up
+    /// in `utxos`, as segwit signatures commit to the amounts.
+    pub fn new(
+        tx: &'a Transaction,
+        input_index: usize,
+        utxos: &impl UtxoProvider,
+    ) -> Result<TransactionChecker<'a>, TransactionError> {
+        if input_index >= tx.inputs.len() {
+            return Err(TransactionError::InputIndexOutOfRange(input_index));
+        }
+        let prevouts = tx.spent_outputs(utxos)?;
+        let cache = SegwitV0Cache::new(tx)?;
+        Ok(TransactionChecker { tx, input_index, prevouts, cache })
+    }
+}
+
+impl SignatureChecker for TransactionChecker<'_> {
+    fn check_signature(
+        &self,
+        signature: &[u8],
+        public_key: &[u8],
+        script_code: &Script,
+        sig_version: SigVersion,
+    ) -> bool {
+        // taproot signatures are Schnorr, the interpreter checks them with `check_schnorr_signature`
+        if matches!(sig_version, SigVersion::Taproot | SigVersion::Tapscript) {
+            return false;
+        }
+        check_ecdsa(signature, public_key, |sighash_type| match sig_version {
+            SigVersion::Base => self.tx.sig_hash(self.input_index, script_code, sighash_type),
+            _ => {
+                let amount = self.prevouts[self.input_index].value;
+                self.tx.segwit_v0_sig_hash_with_cache(&self.cache, self.input_index, script_code, amount, sighash_type)
+            }
+        })
+        .is_ok()
+    }
+
+    fn check_schnorr_signature(
+        &self,
+        signature: &[u8],
+        public_key: &[u8; 32],
+        annex: Option<&[u8]>,
+        leaf: Option<TapLeafSpend>,
+    ) -> bool {
+        check_schnorr(signature, public_key, |sighash_type| {
+            self.tx.taproot_sig_hash(self.input_index, &self.prevouts, sighash_type, annex, leaf)
+        })
+        .is_ok()
+    }
+
+    fn check_taproot_commitment(&self, control_block: &[u8], leaf_script: &Script, output_key: &[u8; 32]) -> bool {
+        ControlBlock::parse(control_block).is_ok_and(|control_block| control_block.verify(leaf_script, output_key))
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        let Ok(lock_time) = u32::try_from(lock_time) else {
+            return false;
+        };
+        // a final input turns the transaction's locktime off, so it proves nothing
+        self.tx.inputs[self.input_index].sequence != Sequence::MAX
+            && self.tx.locktime.satisfies_cltv(LockTime::from_consensus(lock_time))
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+        // only the bits of a sequence count, the ones above are ignored
+        let Some(required) = Sequence::new(sequence as u32).relative_locktime() else {
+            return false;
+        };
+        // relative locks only apply from version 2 on, and not to inputs that disable them
+        self.tx.version.to_u32() >= 2
+            && self.tx.inputs[self.input_index]
+                .sequence
+                .relative_locktime()
+                .is_some_and(|lock| lock.satisfies_csv(required))
+    }
+}
+
+impl Transaction {
+    /// Checks the transaction the way a node does before accepting it, with the outputs it spends
+    /// looked up in `utxos`: it must have inputs and outputs, spend no output twice, and pay a fee of
+    /// at least zero without any amount going above `Amount::MAX_MONEY`. A coinbase spends nothing, so only
+    /// the length of its script_sig is checked instead.
+    ///
+    /// Every failure is returned, not just the first. Scripts aren't evaluated here, `verify_input`
+    /// checks the signatures and scripts of the inputs.
+    pub fn verify(&self, utxos: &impl UtxoProvider) -> Result<(), Vec<VerifyError>> {
+        let mut errors = vec![];
+
+        if self.inputs.is_empty() {
+            errors.push(VerifyError::NoInputs);
+        }
+        if self.outputs.is_empty() {
+            errors.push(VerifyError::NoOutputs);
+        }
+
+        let mut output_value = Amount::ZERO;
+        for (index, output) in self.outputs.iter().enumerate() {
+            if output.value > Amount::MAX_MONEY {
+                errors.push(VerifyError::OutputValueOutOfRange(index));
+            }
+            output_value = output_value.saturating_add(output.value);
+        }
+        if output_value > Amount::MAX_MONEY {
+            errors.push(VerifyError::TotalValueOutOfRange);
+        }
+
+        if self.is_coinbase() {
+            let length = self.inputs[0].script_sig.len();
+            if !(2..=100).contains(&length) {
+                errors.push(VerifyError::InvalidCoinbaseLength(length));
+            }
+            return if errors.is_empty() { Ok(()) } else { Err(errors) };
+        }
+
+        let mut spent = HashSet::new();
+        let mut input_value = Some(Amount::ZERO);
+        for (index, input) in self.inputs.iter().enumerate() {
+            if !spent.insert(input.outpoint()) {
+                errors.push(VerifyError::DuplicateInput(index));
+            }
+
+            match utxos.get_output(&input.prev_txid, input.prev_index) {
+                Some(prev_output) => input_value = input_value.map(|value| value.saturating_add(prev_output.value)),
+                None => {
+                    errors.push(VerifyError::MissingPrevout(index));
+                    input_value = None;
+                }
+            }
+        }
+
+        // the fee is only known once every spent output is
+        if let Some(input_value) = input_value {
+            if input_value > Amount::MAX_MONEY {
+                errors.push(VerifyError::TotalValueOutOfRange);
+            } else if output_value <= Amount::MAX_MONEY && output_value > input_value {
+                errors.push(VerifyError::NegativeFee(output_value - input_value));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl Transaction {
+    /// Checks the signature of the input with this index against the output it spends, looked up in `utxos`.
+    /// The signature and public key are taken from the script_sig or witness, and the signature hash
+    /// is computed the way the output type calls for: legacy for P2PK and P2PKH, BIP143 for P2WPKH and
+    /// P2SH-P2WPKH, and BIP341 for taproot key path spends, which needs every output the transaction spends.
+    /// Any other output, and taproot script path spends, are checked by running the scripts with
+    /// `Script::verify_spend`.
+    pub fn verify_input(&self, input_index: usize, utxos: &impl UtxoProvider) -> Result<(), InputError> {
+        let input = self
+            .inputs
+            .get(input_index)
+            .ok_or(InputError::InputIndexOutOfRange(input_index))?;
+        let prevout = utxos
+            .get_output(&input.prev_txid, input.prev_index)
+            .ok_or(InputError::MissingPrevout(input_index))?;
+        let script_pubkey = &prevout.script_pubkey;
+        let script_sig = input.script_sig.commands();
+        let witness: Vec<&[u8]> = input.witness.iter().collect();
+
+        match script_pubkey.as_bytes() {
+            [push @ (0x21 | 0x41), key @ .., 0xac] if key.len() == *push as usize => {
+                let [Command::Push(sig)] = script_sig else {
+                    return Err(InputError::MalformedSpend);
+                };
+                check_ecdsa(sig, key, |sighash_type| self.sig_hash(input_index, script_pubkey, sighash_type))
+            }
+            [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] if hash.len() == 20 => {
+                let [Command::Push(sig), Command::Push(sec)] = script_sig else {
+                    return Err(InputError::MalformedSpend);
+                };
+                if hash160(sec) != hash {
+                    return Err(InputError::KeyMismatch);
+                }
+                check_ecdsa(sig, sec, |sighash_type| self.sig_hash(input_index, script_pubkey, sighash_type))
+            }
+            [0x00, 0x14, hash @ ..] if hash.len() == 20 => {
+                if !script_sig.is_empty() {
+                    return Err(InputError::MalformedSpend);
+                }
+                self.check_p2wpkh(input_index, hash, &witness, prevout.value)
+            }
+            [0xa9, 0x14, hash @ .., 0x87] if hash.len() == 20 => {
+                // only P2SH-P2WPKH, whose redeem script is the P2WPKH script_pubkey
+                let [Command::Push(redeem_script)] = script_sig else {
+                    return Err(InputError::MalformedSpend);
+                };
+                let [0x00, 0x14, key_hash @ ..] = redeem_script.as_slice() else {
+                    return self.verify_input_script(input_index, script_pubkey, utxos);
+                };
+                if key_hash.len() != 20 {
+                    return self.verify_input_script(input_index, script_pubkey, utxos);
+                }
+                if hash160(redeem_script) != hash {
+                    return Err(InputError::KeyMismatch);
+                }
+                self.check_p2wpkh(input_index, key_hash, &witness, prevout.value)
+            }
+            [0x51, 0x20, output_key @ ..] if output_key.len() == 32 => {
+                if !script_sig.is_empty() {
+                    return Err(InputError::MalformedSpend);
+                }
+                // an annex is the last of at least two items
+                let (annex, items) = match witness.split_last() {
+                    Some((last, rest)) if !rest.is_empty() && last.first() == Some(&TAPROOT_ANNEX_PREFIX) => {
+                        (Some(*last), rest)
+                    }
+                    _ => (None, witness.as_slice()),
+                };
+                let [sig] = items else {
+                    return self.verify_input_script(input_index, script_pubkey, utxos);
+                };
+
+                let prevouts = self
+                    .inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(index, input)| {
+                        utxos
+                            .get_output(&input.prev_txid, input.prev_index)
+                            .ok_or(InputError::MissingPrevout(index))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let public_key = output_key.try_into().expect("the output key is 32 bytes");
+                check_schnorr(sig, public_key, |sighash_type| {
+                    self.taproot_sig_hash(input_index, &prevouts, sighash_type, annex, None)
+                })
+            }
+            _ => self.verify_input_script(input_index, script_pubkey, utxos),
+        }
+    }
+
+    /// Checks the input by running its script_sig and witness against the `script_pubkey` it spends
+    fn verify_input_script(
+        &self,
+        input_index: usize,
+        script_pubkey: &Script,
+        utxos: &impl UtxoProvider,
+    ) -> Result<(), InputError> {
+        let checker = TransactionChecker::new(self, input_index, utxos).map_err(|error| match error {
+            TransactionError::MissingPrevout(index) => InputError::MissingPrevout(index),
+            _ => InputError::InputIndexOutOfRange(input_index),
+        })?;
+        let input = &self.inputs[input_index];
+        Script::verify_spend(&input.script_sig, script_pubkey, input.witness.items(), &checker).map_err(InputError::Script)
+    }
+
+    /// Checks the signature of every input with `verify_input`, returning the index and error of each one that fails
+    pub fn verify_inputs(&self, utxos: &impl UtxoProvider) -> Result<(), Vec<(usize, InputError)>> {
+        let errors: Vec<_> = (0..self.inputs.len())
+            .filter_map(|index| self.verify_input(index, utxos).err().map(|error| (index, error)))
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// `verify_inputs`, with the inputs checked across threads. The errors come back in input order,
+    /// the same as checking them one after the other.
+    #[cfg(feature = "rayon")]
+    pub fn par_verify(&self, utxos: &(impl UtxoProvider + Sync)) -> Result<(), Vec<(usize, InputError)>> {
+        use rayon::prelude::*;
+
+        let errors: Vec<_> = (0..self.inputs.len())
+            .into_par_iter()
+            .filter_map(|index| self.verify_input(index, utxos).err().map(|error| (index, error)))
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks the witness of a P2WPKH spend, a signature and the key whose hash is `key_hash`
+    fn check_p2wpkh(
+        &self,
+        input_index: usize,
+        key_hash: &[u8],
+        witness: &[&[u8]],
+        amount: Amount,
+    ) -> Result<(), InputError> {
+        let [sig, sec] = witness else {
+            return Err(InputError::MalformedSpend);
+        };
+        if hash160(sec) != key_hash {
+            return Err(InputError::KeyMismatch);
+        }
+
+        // the script code is the P2PKH script of the key hash
+        let script_code = Script::p2pkh(key_hash.try_into().expect("P2WPKH key hashes are 20 bytes"));
+        check_ecdsa(sig, sec, |sighash_type| self.segwit_v0_sig_hash(input_index, &script_code, amount, sighash_type))
+    }
+}
+
+/// Checks a DER signature followed by its hash type byte against the SEC public key,
+/// with `sig_hash` computing the hash it signs for that hash type
+fn check_ecdsa(
+    sig: &[u8],
+    sec: &[u8],
+    sig_hash: impl FnOnce(SighashType) -> Result<Vec<u8>, TransactionError>,
+) -> Result<(), InputError> {
+    let (&hash_type, der) = sig.split_last().ok_or(InputError::InvalidDer)?;
+    let signature = Signature::parse_der(der).ok_or(InputError::InvalidDer)?;
+    let sighash_type = SighashType::from_u32(hash_type as u32).ok_or(InputError::InvalidHashType(hash_type))?;
+    let public_key = parse_sec(sec).ok_or(InputError::InvalidPublicKey)?;
+
+    let z = sig_hash(sighash_type).map_err(|_| InputError::InvalidHashType(hash_type))?;
+    if signature.verify(&public_key, Integer::from_digits(&z, Order::MsfBe)) {
+        Ok(())
+    } else {
+        Err(InputError::SignatureMismatch)
+    }
+}
+
+/// Checks a BIP340 signature, 64 bytes or 65 with a hash type byte, against the signature hash
+/// `sig_hash` computes for its hash type, None being SIGHASH_DEFAULT
+fn check_schnorr(
+    sig: &[u8],
+    public_key: &[u8; 32],
+    sig_hash: impl FnOnce(Option<SighashType>) -> Result<Vec<u8>, TransactionError>,
+) -> Result<(), InputError> {
+    let (signature, sighash_type) = match sig.len() {
+        64 => (sig, None),
+        65 => {
+            let hash_type = sig[64];
+            // SIGHASH_DEFAULT is only implied by leaving the byte out
+            let sighash_type = SighashType::from_u32(hash_type as u32)
+                .filter(|_| hash_type != 0)
+                .ok_or(InputError::InvalidHashType(hash_type))?;
+            (&sig[..64], Some(sighash_type))
+        }
+        length => return Err(InputError::InvalidSignatureLength(length)),
+    };
+
+    let z = sig_hash(sighash_type).map_err(|_| InputError::InvalidHashType(sig.get(64).copied().unwrap_or(0)))?;
+    let z: [u8; 32] = z.try_into().expect("the sighash is 32 bytes");
+    let signature = signature.try_into().expect("the signature is 64 bytes");
+    if verify_schnorr(public_key, &z, signature) {
+        Ok(())
+    } else {
+        Err(InputError::SignatureMismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ec_cryptography::{helper::from_hex, tap_tweak_public_key, traits::Serializer, PrivateKey};
+    use scripts::{
+        interpreter::encode_num,
+        Opcode,
+    };
+
+    use super::*;
+    use crate::{
+        coinbase::COINBASE_PREV_INDEX,
+        fixtures::RAW_TX,
+        input::{OutPoint, Sequence, TxIn},
+        output::TxOut,
+        sighash::TAPSCRIPT_LEAF_VERSION,
+        sign::{ecdsa_signature, KeyStore},
+        txid::Txid,
+        version::Version,
+        witness::Witness,
+    };
+
+    fn utxos(tx: &Transaction, value: u64) -> HashMap<(Txid, u32), TxOut> {
+        let prev_txid = tx.inputs[0].prev_txid;
+        HashMap::from([((prev_txid, 0), TxOut::new(Amount::from_sat(value), Script::default()))])
+    }
+
+    #[test]
+    fn test_verify() {
+        let tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        assert_eq!(tx.verify(&utxos(&tx, 42505594)), Ok(()));
+
+        assert_eq!(tx.verify(&HashMap::new()), Err(vec![VerifyError::MissingPrevout(0)]));
+        assert_eq!(tx.verify(&utxos(&tx, 42465593)), Err(vec![VerifyError::NegativeFee(Amount::ONE_SAT)]));
+        assert_eq!(tx.verify(&utxos(&tx, Amount::MAX_MONEY.to_sat() + 1)), Err(vec![VerifyError::TotalValueOutOfRange]));
+    }
+
+    #[test]
+    fn test_verify_reports_every_failure() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        let utxos = utxos(&tx, 42505594);
+
+        tx.inputs.push(tx.inputs[0].clone());
+        tx.outputs[1].value = Amount::MAX_MONEY + Amount::ONE_SAT;
+        assert_eq!(
+            tx.verify(&utxos),
+            Err(vec![
+                VerifyError::OutputValueOutOfRange(1),
+                VerifyError::TotalValueOutOfRange,
+                VerifyError::DuplicateInput(1),
+            ])
+        );
+
+        let empty = Transaction::default();
+        assert_eq!(empty.verify(&utxos), Err(vec![VerifyError::NoInputs, VerifyError::NoOutputs]));
+    }
+
+    #[test]
+    fn test_verify_coinbase() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        tx.inputs[0].prev_txid = Txid::default();
+        tx.inputs[0].prev_index = COINBASE_PREV_INDEX;
+        tx.inputs[0].script_sig = Script::from_hex("03a0bb0d").unwrap();
+
+        // there is nothing to look up
+        assert_eq!(tx.verify(&HashMap::new()), Ok(()));
+
+        tx.inputs[0].script_sig = Script::from_hex("51").unwrap();
+        assert_eq!(tx.verify(&HashMap::new()), Err(vec![VerifyError::InvalidCoinbaseLength(1)]));
+    }
+
+    /// The output the input of RAW_TX spends, paying to the hash of the key in its script_sig
+    fn p2pkh_utxos(tx: &Transaction) -> HashMap<(Txid, u32), TxOut> {
+        let script_pubkey = Script::from_hex("76a914a802fc56c704ce87c42d7c92eb75e7896bdc41ae88ac").unwrap();
+        HashMap::from([((tx.inputs[0].prev_txid, 0), TxOut::new(Amount::from_sat(42505594), script_pubkey))])
+    }
+
+    #[test]
+    fn test_verify_input_p2pkh() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        let mut utxos = p2pkh_utxos(&tx);
+        assert_eq!(tx.verify_input(0, &utxos), Ok(()));
+        assert_eq!(tx.verify_input(1, &utxos), Err(InputError::InputIndexOutOfRange(1)));
+        assert_eq!(tx.verify_input(0, &HashMap::new()), Err(InputError::MissingPrevout(0)));
+
+        tx.outputs[0].value = Amount::from_sat(1);
+        assert_eq!(tx.verify_input(0, &utxos), Err(InputError::SignatureMismatch));
+
+        let other_key_hash = Script::from_hex("76a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac").unwrap();
+        utxos.get_mut(&(tx.inputs[0].prev_txid, 0)).unwrap().script_pubkey = other_key_hash;
+        assert_eq!(tx.verify_input(0, &utxos), Err(InputError::KeyMismatch));
+    }
+
+    #[test]
+    fn test_verify_input_errors() {
+        let mut tx = Transaction::parse_hex(RAW_TX, false).unwrap();
+        let utxos = p2pkh_utxos(&tx);
+        let commands = tx.inputs[0].script_sig.commands().to_vec();
+        let (Command::Push(sig), Command::Push(sec)) = (&commands[0], &commands[1]) else {
+            panic!("expected a signature and a key");
+        };
+
+        let mut unknown_hash_type = sig.clone();
+        *unknown_hash_type.last_mut().unwrap() = 0x04;
+        tx.inputs[0].script_sig = Script::from_commands(vec![Command::Push(unknown_hash_type), Command::Push(sec.clone())]);
+        assert_eq!(tx.verify_input(0, &utxos), Err(InputError::InvalidHashType(0x04)));
+
+        let mut not_der = sig.clone();
+        not_der[0] = 0x31;
+        tx.inputs[0].script_sig = Script::from_commands(vec![Command::Push(not_der), Command::Push(sec.clone())]);
+        assert_eq!(tx.verify_input(0, &utxos), Err(InputError::InvalidDer));
+
+        tx.inputs[0].script_sig = Script::from_commands(vec![Command::Push(sig.clone())]);
+        assert_eq!(tx.verify_input(0, &utxos), Err(InputError::MalformedSpend));
+
+        // other outputs run their scripts
+        let bare_script = Script::from_hex("51").unwrap();
+        let utxos = HashMap::from([((tx.inputs[0].prev_txid, 0), TxOut::new(Amount::ZERO, bare_script))]);
+        assert_eq!(tx.verify_input(0, &utxos), Ok(()));
+        let bare_script = Script::from_hex("6a").unwrap();
+        let utxos = HashMap::from([((tx.inputs[0].prev_txid, 0), TxOut::new(Amount::ZERO, bare_script))]);
+        assert_eq!(tx.verify_input(0, &utxos), Err(InputError::Script(ScriptError::OpReturn)));
+
+        // a P2PK push opcode that doesn't match the key length is not P2PK
+        let short_key = Script::from_hex(&format!("41{}ac", "02".repeat(33))).unwrap();
+        let utxos = HashMap::from([((tx.inputs[0].prev_txid, 0), TxOut::new(Amount::ZERO, short_key))]);
+        assert_eq!(tx.verify_input(0, &utxos), Err(InputError::Script(ScriptError::InvalidPush)));
+
+        // neither is a 51 20 script_pubkey of another length taproot
+        tx.inputs[0].script_sig = Script::default();
+        tx.inputs[0].witness = Witness::from(vec![vec![0x30; 64]]);
+        let not_taproot = Script::from_hex(&format!("5120{}75", "01".repeat(32))).unwrap();
+        let utxos = HashMap::from([((tx.inputs[0].prev_txid, 0), TxOut::new(Amount::ZERO, not_taproot))]);
+        assert_eq!(tx.verify_input(0, &utxos), Err(InputError::Script(ScriptError::WitnessUnexpected)));
+    }
+
+    #[test]
+    fn test_verify_signed_inputs() {
+        let mut keys = KeyStore::new();
+        for secret in 1..=4 {
+            keys.add_key(PrivateKey::new(rug::Integer::from(secret)));
+        }
+
+        // P2PKH, P2WPKH, P2SH-P2WPKH and taproot outputs of keys 1 to 4, then a P2WPKH of no key
+        let script_pubkeys = [
+            "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac",
+            "001406afd46bcdfd22ef94ac122aa11f241244a37ecc",
+            "a91469ea5ff598a286f418ae77503ce85d83da4ae88e87",
+            "51209317856ed22b3699e792f38ad803f9f3fafabf70a2c8983af715592242c29ca8",
+            "00140000000000000000000000000000000000000000",
+        ];
+        let mut tx = Transaction::default();
+        let mut utxos = HashMap::new();
+        for (index, script_pubkey) in script_pubkeys.iter().enumerate() {
+            let outpoint = OutPoint::new(Txid::from_bytes([index as u8 + 1; 32]), 0);
+            tx.inputs.push(TxIn::from_outpoint(outpoint, Sequence::MAX));
+            let prevout = TxOut::new(Amount::from_sat(100_000), Script::from_hex(script_pubkey).unwrap());
+            utxos.insert((outpoint.txid, 0), prevout);
+        }
+        tx.outputs.push(TxOut::new(Amount::from_sat(450_000), Script::from_hex(script_pubkeys[0]).unwrap()));
+        tx.sign_all(&keys, &utxos).unwrap();
+
+        for index in 0..4 {
+            assert_eq!(tx.verify_input(index, &utxos), Ok(()));
+        }
+        assert_eq!(tx.verify_input(4, &utxos), Err(InputError::MalformedSpend));
+        assert_eq!(tx.verify_inputs(&utxos), Err(vec![(4, InputError::MalformedSpend)]));
+        #[cfg(feature = "rayon")]
+        assert_eq!(tx.par_verify(&utxos), tx.verify_inputs(&utxos));
+
+        // the segwit and taproot signatures commit to the amount spent
+        utxos.get_mut(&(Txid::from_bytes([2; 32]), 0)).unwrap().value = Amount::from_sat(100_001);
+        assert_eq!(tx.verify_input(0, &utxos), Ok(()));
+        assert_eq!(tx.verify_input(1, &utxos), Err(InputError::SignatureMismatch));
+        assert_eq!(tx.verify_input(3, &utxos), Err(InputError::SignatureMismatch));
+        let errors = vec![
+            (1, InputError::SignatureMismatch),
+            (3, InputError::SignatureMismatch),
+            (4, InputError::MalformedSpend),
+        ];
+        assert_eq!(tx.verify_inputs(&utxos), Err(errors));
+        #[cfg(feature = "rayon")]
+        assert_eq!(tx.par_verify(&utxos), tx.verify_inputs(&utxos));
+
+        // a 65-byte Schnorr signature can't have a zero hash type
+        let mut sig = tx.inputs[3].witness.iter().next().unwrap().to_vec();
+        sig.push(0x00);
+        tx.inputs[3].witness = Witness::p2tr_key_spend(sig);
+        assert_eq!(tx.verify_input(3, &utxos), Err(InputError::InvalidHashType(0)));
+    }
+
+    /// The output the first input of `tx` spends, paying to `script_pubkey`
+    fn spent_output(tx: &Transaction, script_pubkey: &Script) -> HashMap<(Txid, u32), TxOut> {
+        let prevout = TxOut::new(Amount::from_sat(100_000), script_pubkey.clone());
+        HashMap::from([((tx.inputs[0].prev_txid, tx.inputs[0].prev_index), prevout)])
+    }
+
+    #[test]
+    fn test_verify_script_segwit() {
+        let mut keys = KeyStore::new();
+        keys.add_key(PrivateKey::new(rug::Integer::from(2)));
+        keys.add_key(PrivateKey::new(rug::Integer::from(3)));
+
+        // P2WPKH and P2SH-P2WPKH outputs of keys 2 and 3
+        let script_pubkeys = [
+            Script::from_hex("001406afd46bcdfd22ef94ac122aa11f241244a37ecc").unwrap(),
+            Script::from_hex("a91469ea5ff598a286f418ae77503ce85d83da4ae88e87").unwrap(),
+        ];
+        let mut tx = Transaction::default();
+        let mut utxos = HashMap::new();
+        for (index, script_pubkey) in script_pubkeys.iter().enumerate() {
+            let outpoint = OutPoint::new(Txid::from_bytes([index as u8 + 1; 32]), 0);
+            tx.inputs.push(TxIn::from_outpoint(outpoint, Sequence::MAX));
+            utxos.insert((outpoint.txid, 0), TxOut::new(Amount::from_sat(100_000), script_pubkey.clone()));
+        }
+        tx.outputs.push(TxOut::new(Amount::from_sat(190_000), Script::default()));
+        tx.sign_all(&keys, &utxos).unwrap();
+
+        let witnesses: Vec<Vec<Vec<u8>>> =
+            tx.inputs.iter().map(|input| input.witness.iter().map(<[u8]>::to_vec).collect()).collect();
+        for (index, script_pubkey) in script_pubkeys.iter().enumerate() {
+            let checker = TransactionChecker::new(&tx, index, &utxos).unwrap();
+            let script_sig = &tx.inputs[index].script_sig;
+            assert_eq!(Script::verify_spend(script_sig, script_pubkey, &witnesses[index], &checker), Ok(()));
+        }
+
+        // the signatures commit to the amount
+        let mut changed = utxos.clone();
+        changed.get_mut(&(Txid::from_bytes([1; 32]), 0)).unwrap().value = Amount::from_sat(100_001);
+        let checker = TransactionChecker::new(&tx, 0, &changed).unwrap();
+        let result = Script::verify_spend(&Script::default(), &script_pubkeys[0], &witnesses[0], &checker);
+        assert_eq!(result, Err(ScriptError::EvalFalse));
+
+        // a native witness spend takes an empty script_sig, and the witness can't go elsewhere
+        let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+        let script_sig = Script::from_hex("51").unwrap();
+        let result = Script::verify_spend(&script_sig, &script_pubkeys[0], &witnesses[0], &checker);
+        assert_eq!(result, Err(ScriptError::WitnessMalleated));
+        let result = Script::verify_spend(&Script::default(), &script_pubkeys[0], &witnesses[0][..1], &checker);
+        assert_eq!(result, Err(ScriptError::WitnessProgramMismatch));
+        let result = Script::verify_spend(&script_sig, &Script::from_hex("51").unwrap(), &witnesses[0], &checker);
+        assert_eq!(result, Err(ScriptError::WitnessUnexpected));
+    }
+
+    #[test]
+    fn test_verify_script_p2wsh() {
+        let key = PrivateKey::new(rug::Integer::from(5));
+        let witness_script = Script::from_commands(vec![
+            Command::Push(from_hex(&key.point().sec(true)).unwrap()),
+            Command::Op(Opcode::OpCheckSig.to_u8()),
+        ]);
+
+        // bare and nested in P2SH
+        for (script_pubkey, script_sig) in [
+            (witness_script.to_p2wsh(), Script::default()),
+            (witness_script.to_p2wsh().to_p2sh(), Script::p2sh_script_sig(vec![], &witness_script.to_p2wsh())),
+        ] {
+            let mut tx = Transaction::default();
+            tx.inputs.push(TxIn::from_outpoint(OutPoint::new(Txid::from_bytes([1; 32]), 0), Sequence::MAX));
+            tx.outputs.push(TxOut::new(Amount::from_sat(90_000), Script::default()));
+            let utxos = spent_output(&tx, &script_pubkey);
+
+            let z = tx.segwit_v0_sig_hash(0, &witness_script, Amount::from_sat(100_000), SighashType::All).unwrap();
+            let witness = Script::p2wsh_witness(vec![ecdsa_signature(&key, &z)], &witness_script);
+            tx.inputs[0].script_sig = script_sig.clone();
+            tx.inputs[0].witness = Witness::from(witness.clone());
+
+            let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+            assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &witness, &checker), Ok(()));
+            // outputs without a single key run their scripts
+            assert_eq!(tx.verify_input(0, &utxos), Ok(()));
+
+            tx.outputs[0].value = Amount::from_sat(90_001);
+            let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+            let result = Script::verify_spend(&script_sig, &script_pubkey, &witness, &checker);
+            assert_eq!(result, Err(ScriptError::EvalFalse));
+            assert_eq!(tx.verify_input(0, &utxos), Err(InputError::Script(ScriptError::EvalFalse)));
+        }
+    }
+
+    #[test]
+    fn test_verify_script_taproot() {
+        // a key path spend of key 4, with no script tree
+        let mut keys = KeyStore::new();
+        keys.add_key(PrivateKey::new(rug::Integer::from(4)));
+        let output_key = PrivateKey::new(rug::Integer::from(4)).tap_tweak(None).x_only_public_key();
+        let script_pubkey = Script::p2tr(&output_key);
+
+        let mut tx = Transaction::default();
+        tx.inputs.push(TxIn::from_outpoint(OutPoint::new(Txid::from_bytes([1; 32]), 0), Sequence::MAX));
+        tx.outputs.push(TxOut::new(Amount::from_sat(90_000), Script::default()));
+        let utxos = spent_output(&tx, &script_pubkey);
+        tx.sign_all(&keys, &utxos).unwrap();
+        let witness: Vec<Vec<u8>> = tx.inputs[0].witness.iter().map(<[u8]>::to_vec).collect();
+
+        let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+        assert_eq!(Script::verify_spend(&Script::default(), &script_pubkey, &witness, &checker), Ok(()));
+        let mut changed = tx.clone();
+        changed.outputs[0].value = Amount::from_sat(90_001);
+        let checker = TransactionChecker::new(&changed, 0, &utxos).unwrap();
+        let result = Script::verify_spend(&Script::default(), &script_pubkey, &witness, &checker);
+        assert_eq!(result, Err(ScriptError::SchnorrSig));
+
+        // a script path spend of <key 5> OP_CHECKSIG, the only leaf of internal key 4
+        let leaf_key = PrivateKey::new(rug::Integer::from(5));
+        let leaf_script = Script::from_commands(vec![
+            Command::Push(leaf_key.x_only_public_key().to_vec()),
+            Command::Op(Opcode::OpCheckSig.to_u8()),
+        ]);
+        let internal_key = PrivateKey::new(rug::Integer::from(4)).x_only_public_key();
+        let control_block = ControlBlock::new(internal_key, &leaf_script, TAPSCRIPT_LEAF_VERSION, vec![]).unwrap();
+        let output_key = tap_tweak_public_key(&internal_key, Some(&control_block.merkle_root(&leaf_script))).unwrap().0;
+        let script_pubkey = Script::p2tr(&output_key);
+        let utxos = spent_output(&tx, &script_pubkey);
+
+        let sig = tx.sign_tapscript(0, &leaf_key, &leaf_script, None, &utxos).unwrap();
+        tx.spend_taproot_leaf(0, vec![sig], &leaf_script, &control_block, &utxos).unwrap();
+        let witness: Vec<Vec<u8>> = tx.inputs[0].witness.iter().map(<[u8]>::to_vec).collect();
+        let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+        assert_eq!(Script::verify_spend(&Script::default(), &script_pubkey, &witness, &checker), Ok(()));
+
+        // the control block has to commit to the leaf, and the signature to the transaction
+        let other_leaf = Script::from_hex("51").unwrap();
+        let witness = vec![vec![], other_leaf.as_bytes().to_vec(), control_block.serialize()];
+        let result = Script::verify_spend(&Script::default(), &script_pubkey, &witness, &checker);
+        assert_eq!(result, Err(ScriptError::WitnessProgramMismatch));
+        tx.outputs[0].value = Amount::from_sat(90_001);
+        let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+        let witness: Vec<Vec<u8>> = tx.inputs[0].witness.iter().map(<[u8]>::to_vec).collect();
+        let result = Script::verify_spend(&Script::default(), &script_pubkey, &witness, &checker);
+        assert_eq!(result, Err(ScriptError::SchnorrSig));
+    }
+
+    #[test]
+    fn test_verify_p2sh_multisig() {
+        // the book's 2-of-2 P2SH spend, checked by running its scripts
+        let raw = "0100000001868278ed6ddfb6c1ed3ad5f8181eb0c7a385aa0836f01d5e4789e6bd304d87221a000000db00483045022100dc92655fe37036f47756db8102e0d7d5e28b3beb83a8fef4f5dc0559bddfb94e02205a36d4e4e6c7fcd16658c50783e00c341609977aed3ad00937bf4ee942a8993701483045022100da6bee3c93766232079a01639d07fa869598749729ae323eab8eef53577d611b02207bef15429dcadce2121ea07f233115c6f09034c0be68db99980b9a6c5e75402201475221022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb702103b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb7152aeffffffff04d3b11400000000001976a914904a49878c0adfc3aa05de7afad2cc15f483a56a88ac7f400900000000001976a914418327e3f3dda4cf5b9089325a4b95abdfa0334088ac722c0c00000000001976a914ba35042cfe9fc66fd35ac2224eebdafd1028ad2788acdc4ace020000000017a91474d691da1574e6b3c192ecfb52cc8984ee7b6c568700000000";
+        let mut tx = Transaction::parse_hex(raw, false).unwrap();
+        let script_pubkey = Script::from_hex("a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687").unwrap();
+        let script_sig = tx.inputs[0].script_sig.clone();
+        let utxos = spent_output(&tx, &script_pubkey);
+
+        let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Ok(()));
+
+        tx.outputs[0].value = Amount::from_sat(1);
+        let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Err(ScriptError::EvalFalse));
+    }
+
+    #[test]
+    fn test_check_lock_time() {
+        let key = PrivateKey::new(rug::Integer::from(1));
+        let sec = from_hex(&key.point().sec(true)).unwrap();
+        // <500> OP_CHECKLOCKTIMEVERIFY OP_DROP <key> OP_CHECKSIG
+        let script_pubkey = Script::from_commands(vec![
+            Command::Push(encode_num(500)),
+            Command::Op(Opcode::OpCheckLockTimeVerify.to_u8()),
+            Command::Op(Opcode::OpDrop.to_u8()),
+            Command::Push(sec),
+            Command::Op(Opcode::OpCheckSig.to_u8()),
+        ]);
+
+        let mut tx = Transaction::default();
+        tx.inputs.push(TxIn::from_outpoint(OutPoint::new(Txid::from_bytes([1; 32]), 0), Sequence::MAX));
+        tx.outputs.push(TxOut::new(Amount::from_sat(1000), Script::default()));
+        tx.set_cltv_locktime(0, LockTime::Blocks(500));
+        let utxos = spent_output(&tx, &script_pubkey);
+        let z = tx.sig_hash(0, &script_pubkey, SighashType::All).unwrap();
+        let script_sig = Script::from_commands(vec![Command::Push(ecdsa_signature(&key, &z))]);
+        let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Ok(()));
+
+        // too early, a timestamp instead of a height, and a final input
+        let locks = [
+            (LockTime::Blocks(499), Sequence::ENABLE_LOCKTIME_NO_RBF),
+            (LockTime::Seconds(500_000_000), Sequence::ENABLE_LOCKTIME_NO_RBF),
+            (LockTime::Blocks(500), Sequence::MAX),
+        ];
+        for (locktime, sequence) in locks {
+            let mut tx = tx.clone();
+            tx.locktime = locktime;
+            tx.inputs[0].sequence = sequence;
+            let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+            let result = Script::verify_spend(&script_sig, &script_pubkey, &[], &checker);
+            assert_eq!(result, Err(ScriptError::UnsatisfiedLockTime));
+        }
+
+        // a later locktime passes the lock, but isn't what the signature signed
+        tx.locktime = LockTime::Blocks(600);
+        let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Err(ScriptError::EvalFalse));
+        assert!(matches!(TransactionChecker::new(&tx, 1, &utxos), Err(TransactionError::InputIndexOutOfRange(1))));
+    }
+
+    #[test]
+    fn test_check_sequence() {
+        // the refund path of a contract: <10 blocks> OP_CHECKSEQUENCEVERIFY OP_DROP <key> OP_CHECKSIG
+        let key = PrivateKey::new(rug::Integer::from(1));
+        let script_pubkey = Script::from_commands(vec![
+            Command::Push(encode_num(10)),
+            Command::Op(Opcode::OpCheckSequenceVerify.to_u8()),
+            Command::Op(Opcode::OpDrop.to_u8()),
+            Command::Push(from_hex(&key.point().sec(true)).unwrap()),
+            Command::Op(Opcode::OpCheckSig.to_u8()),
+        ]);
+
+        let mut tx = Transaction { version: Version::new(2), ..Default::default() };
+        let outpoint = OutPoint::new(Txid::from_bytes([1; 32]), 0);
+        tx.inputs.push(TxIn::from_outpoint(outpoint, Sequence::from_height(10)));
+        tx.outputs.push(TxOut::new(Amount::from_sat(1000), Script::default()));
+        let utxos = spent_output(&tx, &script_pubkey);
+        let z = tx.sig_hash(0, &script_pubkey, SighashType::All).unwrap();
+        let script_sig = Script::from_commands(vec![Command::Push(ecdsa_signature(&key, &z))]);
+        let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+        assert_eq!(Script::verify_spend(&script_sig, &script_pubkey, &[], &checker), Ok(()));
+
+        // too soon, a time instead of blocks, a disabled lock, and a version without relative locks
+        let locks = [
+            (2, Sequence::from_height(9)),
+            (2, Sequence::from_512_second_intervals(10)),
+            (2, Sequence::ENABLE_LOCKTIME_NO_RBF),
+            (1, Sequence::from_height(10)),
+        ];
+        for (version, sequence) in locks {
+            let mut tx = tx.clone();
+            tx.version = Version::new(version);
+            tx.inputs[0].sequence = sequence;
+            let checker = TransactionChecker::new(&tx, 0, &utxos).unwrap();
+            let result = Script::verify_spend(&script_sig, &script_pubkey, &[], &checker);
+            assert_eq!(result, Err(ScriptError::UnsatisfiedLockTime));
+        }
+    }
+}
@@ -1,9 +1,11 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, io::{self, Read, Write}};
+
+use ec_cryptography::consensus::{ConsensusDecode, ConsensusEncode};
 
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Serialize, Clone, PartialEq, Eq, Deserialize)]
-pub struct Version(u8);
+pub struct Version(u32);
 
 impl Debug for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -12,15 +14,33 @@ impl Debug for Version {
 }
 
 impl Version {
-    pub fn new(version: u8) -> Version {
+    pub fn new(version: u32) -> Version {
         Version(version)
     }
 
-    pub fn parse(&self) -> String {
-        format!("{:08b}", (self.0 << 6) | 0)
+    /// The version from its little-endian bytes, of which there are 4 in a serialized transaction
+    pub fn from_vec(version: &[u8]) -> Version {
+        Version(version.iter().take(4).rev().fold(0, |version, byte| version << 8 | *byte as u32))
     }
 
-    pub fn from_vec(version: &[u8]) -> Version {
-        Version(version[0])
+    pub fn to_u32(&self) -> u32 {
+        self.0
+    }
+
+    /// The version as the 4 little-endian bytes that start a serialized transaction
+    pub fn to_le_bytes(&self) -> [u8; 4] {
+        self.0.to_le_bytes()
+    }
+}
+
+impl ConsensusEncode for Version {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        self.to_le_bytes().consensus_encode(writer)
+    }
+}
+
+impl ConsensusDecode for Version {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Version(u32::consensus_decode(reader)?))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,178 @@
+use std::{
+    io::{self, Read, Write},
+    ops::Index,
+};
+
+use ec_cryptography::{
+    consensus::{ConsensusDecode, ConsensusEncode},
+    helper::to_hex,
+};
+use scripts::Script;
+use serde::Deserialize;
+
+use crate::{decode_prefix, sighash::TAPROOT_ANNEX_PREFIX, taproot::ControlBlock, ParseError};
+
+/// The witness of an input (BIP141): a stack of byte strings that takes the place of the
+/// script_sig for segwit outputs. The first item is at the bottom of the stack.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub struct Witness(Vec<Vec<u8>>);
+
+impl Witness {
+    pub fn new() -> Witness {
+        Witness(vec![])
+    }
+
+    /// The witness that spends a P2WPKH output: the signature (with its hash type byte) and the SEC public key
+    pub fn p2wpkh(signature: Vec<u8>, public_key: Vec<u8>) -> Witness {
+        Witness(vec![signature, public_key])
+    }
+
+    /// The witness of a taproot key path spend: a single 64-byte Schnorr signature,
+    /// or 65 bytes when the hash type isn't SIGHASH_DEFAULT
+    pub fn p2tr_key_spend(signature: Vec<u8>) -> Witness {
+        Witness(vec![signature])
+    }
+
+    /// The witness of a taproot script path spend: the inputs of the leaf script,
+    /// then the script itself and the control block proving it's in the output's script tree
+    pub fn p2tr_script_spend(script_inputs: Vec<Vec<u8>>, leaf_script: &Script, control_block: &ControlBlock) -> Witness {
+        let mut items = script_inputs;
+        items.push(leaf_script.as_bytes().to_vec());
+        items.push(control_block.serialize());
+        Witness(items)
+    }
+
+    /// Pushes an item on top of the stack
+    pub fn push(&mut self, item: Vec<u8>) {
+        self.0.push(item);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn items(&self) -> &[Vec<u8>] {
+        &self.0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.0.iter().map(Vec::as_slice)
+    }
+
+    /// The item on top of the stack
+    pub fn last(&self) -> Option<&[u8]> {
+        self.0.last().map(Vec::as_slice)
+    }
+
+    /// The annex of a taproot spend (BIP341): the last item, if it starts with 0x50 and isn't the only one
+    pub fn taproot_annex(&self) -> Option<&[u8]> {
+        match self.0.as_slice() {
+            [_, .., last] if last.first() == Some(&TAPROOT_ANNEX_PREFIX) => Some(last),
+            _ => None,
+        }
+    }
+
+    /// The leaf script of a taproot script path spend: the item before the control block
+    /// (and before the annex, if any). None for key path spends, which have a single item.
+    pub fn tapscript(&self) -> Option<&[u8]> {
+        let items = match self.taproot_annex() {
+            Some(_) => &self.0[..self.0.len() - 1],
+            None => &self.0[..],
+        };
+
+        match items {
+            [.., script, _control_block] => Some(script),
+            _ => None,
+        }
+    }
+
+    /// Parses a witness at the start of the bytes.
+    /// Returns it with the number of bytes it took up, as the next input's witness follows.
+    pub fn parse(bytes: &[u8]) -> Result<(Witness, usize), ParseError> {
+        decode_prefix(bytes, Witness::consensus_decode)
+    }
+
+    pub fn serialize(&self) -> String {
+        to_hex(&self.consensus_serialize())
+    }
+}
+
+impl From<Vec<Vec<u8>>> for Witness {
+    fn from(items: Vec<Vec<u8>>) -> Self {
+        Witness(items)
+    }
+}
+
+impl Index<usize> for Witness {
+    type Output = [u8];
+
+    fn index(&self, index: usize) -> &[u8] {
+        &self.0[index]
+    }
+}
+
+impl ConsensusEncode for Witness {
+    /// The varint item count, then each item with its varint length
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        self.0.consensus_encode(writer)
+    }
+}
+
+impl ConsensusDecode for Witness {
+    fn consensus_decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Witness(Vec::<Vec<u8>>::consensus_decode(reader)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec_cryptography::helper::from_hex;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_and_serialize() {
+        let raw = "0247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357";
+        let mut bytes = from_hex(raw).unwrap();
+        // the next witness is not consumed
+        bytes.push(0x00);
+
+        let (witness, consumed) = Witness::parse(&bytes).unwrap();
+        assert_eq!(consumed, bytes.len() - 1);
+        assert_eq!(witness.len(), 2);
+        assert_eq!(witness[0].len(), 0x47);
+        assert_eq!(witness.last().unwrap().len(), 33);
+        assert_eq!(witness, Witness::p2wpkh(witness[0].to_vec(), witness[1].to_vec()));
+
+        assert_eq!(witness.serialize(), raw);
+        assert_eq!(Witness::new().serialize(), "00");
+        assert!(Witness::parse(&from_hex("0201aa").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_taproot_items() {
+        let key_spend = Witness::p2tr_key_spend(vec![0x11; 64]);
+        assert_eq!(key_spend.taproot_annex(), None);
+        assert_eq!(key_spend.tapscript(), None);
+
+        // a lone item is never an annex
+        assert_eq!(Witness::from(vec![vec![TAPROOT_ANNEX_PREFIX]]).taproot_annex(), None);
+
+        let mut key_spend_with_annex = key_spend.clone();
+        key_spend_with_annex.push(vec![TAPROOT_ANNEX_PREFIX, 0x01]);
+        assert_eq!(key_spend_with_annex.taproot_annex(), Some(&[TAPROOT_ANNEX_PREFIX, 0x01][..]));
+        assert_eq!(key_spend_with_annex.tapscript(), None);
+
+        let script_spend = Witness::from(vec![vec![0x22; 64], vec![0x51], vec![0xc0; 33]]);
+        assert_eq!(script_spend.tapscript(), Some(&[0x51][..]));
+
+        let mut script_spend_with_annex = script_spend.clone();
+        script_spend_with_annex.push(vec![TAPROOT_ANNEX_PREFIX]);
+        assert_eq!(script_spend_with_annex.tapscript(), Some(&[0x51][..]));
+        assert_eq!(script_spend_with_annex.iter().count(), 4);
+    }
+}